@@ -0,0 +1,543 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Verifiable key-value REST service
+//! Serves namespaced key-values stored in GroveDB over plain HTTP/1.1, every
+//! response reports the cost of the operations it ran. Values are proved
+//! against the root hash and proofs can be sent back to be verified.
+//!
+//! Run with `cargo run -p grovedb --example rest_service --features
+//! rest_example -- <db path> <address>`, then for instance:
+//!
+//! ```text
+//! curl -X PUT --data-binary 'hello' localhost:8080/kv/greetings/en
+//! curl localhost:8080/kv/greetings/en
+//! curl localhost:8080/kv/greetings
+//! curl localhost:8080/prove/greetings/en
+//! curl -X POST --data-binary '<proof>' localhost:8080/verify/greetings/en
+//! curl -X POST --data '{"namespace": "greetings", "puts": {"fr": "bonjour"}, "deletes": ["en"]}' localhost:8080/batch
+//! curl -X DELETE localhost:8080/kv/greetings/fr
+//! ```
+//!
+//! Keys are the bytes of the URL path segments, values are returned hex
+//! encoded. Writes run in a transaction which is committed once all of them
+//! succeeded.
+
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use costs::OperationCost;
+use grovedb::{
+    batch::GroveDbOp, query_result_type::QueryResultType, Element, Error, GroveDb, PathQuery,
+    Query, Transaction,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Root tree holding one subtree per namespace
+const KV_ROOT: &[u8] = b"kv";
+
+/// Largest request body accepted
+const MAX_BODY_LENGTH: usize = 1 << 20;
+
+/// HTTP request, reduced to what the service needs
+#[derive(Debug)]
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+/// HTTP response with a JSON body
+#[derive(Debug)]
+struct Response {
+    status: u16,
+    body: Value,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Response { status: 200, body }
+    }
+
+    fn error(status: u16, message: impl ToString) -> Self {
+        Response {
+            status,
+            body: json!({ "error": message.to_string() }),
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+impl From<Error> for Response {
+    fn from(error: Error) -> Self {
+        let status = match error {
+            Error::PathKeyNotFound(_)
+            | Error::PathNotFound(_)
+            | Error::PathParentLayerNotFound(_) => 404,
+            Error::InvalidInput(_) | Error::InvalidProof(_) => 400,
+            _ => 500,
+        };
+        Response::error(status, error)
+    }
+}
+
+/// Body of a batch request
+#[derive(Debug, Deserialize)]
+struct BatchRequest {
+    namespace: String,
+    #[serde(default)]
+    puts: BTreeMap<String, String>,
+    #[serde(default)]
+    deletes: Vec<String>,
+}
+
+fn cost_json(cost: &OperationCost) -> Value {
+    json!({
+        "seek_count": cost.seek_count,
+        "added_bytes": cost.storage_cost.added_bytes,
+        "replaced_bytes": cost.storage_cost.replaced_bytes,
+        "removed_bytes": cost.storage_cost.removed_bytes.total_removed_bytes(),
+        "loaded_bytes": cost.storage_loaded_bytes,
+        "hash_node_calls": cost.hash_node_calls,
+    })
+}
+
+fn namespace_path(namespace: &str) -> Vec<Vec<u8>> {
+    vec![KV_ROOT.to_vec(), namespace.as_bytes().to_vec()]
+}
+
+/// Key-value service over a GroveDB
+struct Service {
+    db: GroveDb,
+}
+
+impl Service {
+    /// Opens the service, creating its root tree if needed
+    fn open(path: &str) -> Result<Self, Error> {
+        let db = GroveDb::open(path)?;
+        if db.get_raw_optional([], KV_ROOT, None).unwrap()?.is_none() {
+            db.insert([], KV_ROOT, Element::empty_tree(), None, None)
+                .unwrap()?;
+        }
+        Ok(Service { db })
+    }
+
+    fn handle(&self, request: &Request) -> Response {
+        let segments: Vec<&str> = request
+            .path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        let result = match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["kv", namespace, key]) => self.get(namespace, key),
+            ("PUT", ["kv", namespace, key]) => self.put(namespace, key, &request.body),
+            ("DELETE", ["kv", namespace, key]) => self.delete(namespace, key),
+            ("GET", ["kv", namespace]) => self.list(namespace),
+            ("GET", ["prove", namespace, key]) => self.prove(namespace, key),
+            ("POST", ["verify", namespace, key]) => self.verify(namespace, key, &request.body),
+            ("POST", ["batch"]) => self.batch(&request.body),
+            ("GET" | "PUT" | "DELETE" | "POST", _) => {
+                return Response::error(404, "unknown route");
+            }
+            _ => return Response::error(405, "unsupported method"),
+        };
+        result.unwrap_or_else(Response::from)
+    }
+
+    fn get(&self, namespace: &str, key: &str) -> Result<Response, Error> {
+        let mut cost = OperationCost::default();
+        let element = self
+            .db
+            .get([KV_ROOT, namespace.as_bytes()], key.as_bytes(), None)
+            .unwrap_add_cost(&mut cost)?;
+        let value = match element {
+            Element::Item(value, _) => value,
+            _ => return Err(Error::InvalidInput("key doesn't hold a value")),
+        };
+        Ok(Response::ok(json!({
+            "value": hex::encode(value),
+            "cost": cost_json(&cost),
+        })))
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> Result<Response, Error> {
+        let mut cost = OperationCost::default();
+        let transaction = self.db.start_transaction();
+        self.ensure_namespace(namespace, &transaction, &mut cost)?;
+        self.db
+            .insert(
+                [KV_ROOT, namespace.as_bytes()],
+                key.as_bytes(),
+                Element::new_item(value.to_vec()),
+                None,
+                Some(&transaction),
+            )
+            .unwrap_add_cost(&mut cost)?;
+        self.commit(transaction, cost)
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<Response, Error> {
+        let mut cost = OperationCost::default();
+        let transaction = self.db.start_transaction();
+        self.db
+            .delete(
+                [KV_ROOT, namespace.as_bytes()],
+                key.as_bytes(),
+                None,
+                Some(&transaction),
+            )
+            .unwrap_add_cost(&mut cost)?;
+        self.commit(transaction, cost)
+    }
+
+    fn list(&self, namespace: &str) -> Result<Response, Error> {
+        let mut cost = OperationCost::default();
+        let mut query = Query::new();
+        query.insert_all();
+        let (elements, _) = self
+            .db
+            .query_raw(
+                &PathQuery::new_unsized(namespace_path(namespace), query),
+                true,
+                QueryResultType::QueryKeyElementPairResultType,
+                None,
+            )
+            .unwrap_add_cost(&mut cost)?;
+        let entries: Vec<Value> = elements
+            .to_key_elements()
+            .into_iter()
+            .filter_map(|(key, element)| match element {
+                Element::Item(value, _) => Some(json!({
+                    "key": String::from_utf8_lossy(&key),
+                    "value": hex::encode(value),
+                })),
+                _ => None,
+            })
+            .collect();
+        Ok(Response::ok(json!({
+            "entries": entries,
+            "cost": cost_json(&cost),
+        })))
+    }
+
+    fn prove(&self, namespace: &str, key: &str) -> Result<Response, Error> {
+        let mut cost = OperationCost::default();
+        let path_query =
+            PathQuery::new_single_key(namespace_path(namespace), key.as_bytes().to_vec());
+        let proof = self
+            .db
+            .prove_query(&path_query)
+            .unwrap_add_cost(&mut cost)?;
+        let root_hash = self.db.root_hash(None).unwrap_add_cost(&mut cost)?;
+        Ok(Response::ok(json!({
+            "proof": hex::encode(proof),
+            "root_hash": hex::encode(root_hash),
+            "cost": cost_json(&cost),
+        })))
+    }
+
+    fn verify(&self, namespace: &str, key: &str, body: &[u8]) -> Result<Response, Error> {
+        let proof = std::str::from_utf8(body)
+            .ok()
+            .and_then(|proof| hex::decode(proof.trim()).ok())
+            .ok_or(Error::InvalidInput("proof should be hex encoded"))?;
+        let path_query =
+            PathQuery::new_single_key(namespace_path(namespace), key.as_bytes().to_vec());
+        let (root_hash, results) = GroveDb::verify_query(&proof, &path_query)?;
+        let value = results
+            .into_iter()
+            .find_map(|(_, _, element)| match element {
+                Some(Element::Item(value, _)) => Some(hex::encode(value)),
+                _ => None,
+            });
+        let current_root_hash = self.db.root_hash(None).unwrap()?;
+        Ok(Response::ok(json!({
+            "root_hash": hex::encode(root_hash),
+            "value": value,
+            "matches_current_root": root_hash == current_root_hash,
+        })))
+    }
+
+    fn batch(&self, body: &[u8]) -> Result<Response, Error> {
+        let request: BatchRequest = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(error) => return Ok(Response::error(400, error)),
+        };
+        let mut cost = OperationCost::default();
+        let transaction = self.db.start_transaction();
+        self.ensure_namespace(&request.namespace, &transaction, &mut cost)?;
+        let path = namespace_path(&request.namespace);
+        let ops = request
+            .puts
+            .into_iter()
+            .map(|(key, value)| {
+                GroveDbOp::insert_op(
+                    path.clone(),
+                    key.into_bytes(),
+                    Element::new_item(value.into_bytes()),
+                )
+            })
+            .chain(
+                request
+                    .deletes
+                    .into_iter()
+                    .map(|key| GroveDbOp::delete_op(path.clone(), key.into_bytes())),
+            )
+            .collect();
+        self.db
+            .apply_batch(ops, None, Some(&transaction))
+            .unwrap_add_cost(&mut cost)?;
+        self.commit(transaction, cost)
+    }
+
+    fn ensure_namespace(
+        &self,
+        namespace: &str,
+        transaction: &Transaction,
+        cost: &mut OperationCost,
+    ) -> Result<(), Error> {
+        let existing = self
+            .db
+            .get_raw_optional([KV_ROOT], namespace.as_bytes(), Some(transaction))
+            .unwrap_add_cost(cost)?;
+        if existing.is_none() {
+            self.db
+                .insert(
+                    [KV_ROOT],
+                    namespace.as_bytes(),
+                    Element::empty_tree(),
+                    None,
+                    Some(transaction),
+                )
+                .unwrap_add_cost(cost)?;
+        }
+        Ok(())
+    }
+
+    fn commit(&self, transaction: Transaction, mut cost: OperationCost) -> Result<Response, Error> {
+        self.db
+            .commit_transaction(transaction)
+            .unwrap_add_cost(&mut cost)?;
+        let root_hash = self.db.root_hash(None).unwrap_add_cost(&mut cost)?;
+        Ok(Response::ok(json!({
+            "root_hash": hex::encode(root_hash),
+            "cost": cost_json(&cost),
+        })))
+    }
+}
+
+/// Reads a request, only the request line and the content length header are
+/// looked at
+fn read_request(stream: &mut TcpStream) -> Result<Request, String> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader
+        .read_line(&mut request_line)
+        .map_err(|error| error.to_string())?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_owned(), path.to_owned()),
+        _ => return Err("malformed request line".to_owned()),
+    };
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader
+            .read_line(&mut header)
+            .map_err(|error| error.to_string())?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| "malformed content length".to_owned())?;
+            }
+        }
+    }
+    if content_length > MAX_BODY_LENGTH {
+        return Err("request body too large".to_owned());
+    }
+
+    let mut body = vec![0; content_length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|error| error.to_string())?;
+    Ok(Request { method, path, body })
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> std::io::Result<()> {
+    let body = response.body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: \
+         close\r\n\r\n{}",
+        response.status,
+        response.reason(),
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// Serves connections one at a time, each carrying a single request
+fn serve(service: &Service, listener: TcpListener) -> std::io::Result<()> {
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        let response = match read_request(&mut stream) {
+            Ok(request) => service.handle(&request),
+            Err(message) => Response::error(400, message),
+        };
+        write_response(&mut stream, &response)?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let db_path = args
+        .next()
+        .unwrap_or_else(|| "grovedb_rest_example".to_owned());
+    let address = args.next().unwrap_or_else(|| "127.0.0.1:8080".to_owned());
+
+    let service = Service::open(&db_path).expect("should open database");
+    let listener = TcpListener::bind(&address).expect("should bind address");
+    println!("serving {} on http://{}", db_path, address);
+    serve(&service, listener).expect("should serve requests");
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn request(method: &str, path: &str, body: &[u8]) -> Request {
+        Request {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            body: body.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_insert_query_prove_verify() {
+        let dir = TempDir::new().unwrap();
+        let service = Service::open(dir.path().to_str().unwrap()).expect("should open service");
+
+        let response = service.handle(&request("PUT", "/kv/greetings/en", b"hello"));
+        assert_eq!(response.status, 200);
+        assert!(response.body["cost"]["added_bytes"].as_u64().unwrap() > 0);
+
+        let response = service.handle(&request("GET", "/kv/greetings/en", b""));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body["value"], hex::encode(b"hello"));
+
+        let response = service.handle(&request(
+            "POST",
+            "/batch",
+            br#"{"namespace": "greetings", "puts": {"fr": "bonjour"}, "deletes": ["en"]}"#,
+        ));
+        assert_eq!(response.status, 200);
+        let response = service.handle(&request("GET", "/kv/greetings/en", b""));
+        assert_eq!(response.status, 404);
+        let response = service.handle(&request("GET", "/kv/greetings", b""));
+        assert_eq!(
+            response.body["entries"],
+            json!([{ "key": "fr", "value": hex::encode(b"bonjour") }])
+        );
+
+        let proved = service.handle(&request("GET", "/prove/greetings/fr", b""));
+        assert_eq!(proved.status, 200);
+        let proof = proved.body["proof"].as_str().unwrap().as_bytes();
+        let verified = service.handle(&request("POST", "/verify/greetings/fr", proof));
+        assert_eq!(verified.status, 200);
+        assert_eq!(verified.body["root_hash"], proved.body["root_hash"]);
+        assert_eq!(verified.body["value"], hex::encode(b"bonjour"));
+        assert_eq!(verified.body["matches_current_root"], true);
+
+        // a failing batch leaves nothing behind
+        let response = service.handle(&request(
+            "POST",
+            "/batch",
+            br#"{"namespace": "greetings", "puts": {"es": "hola"}, "deletes": ["es"]}"#,
+        ));
+        assert_eq!(response.status, 500);
+        let response = service.handle(&request("GET", "/kv/greetings/es", b""));
+        assert_eq!(response.status, 404);
+        let response = service.handle(&request("DELETE", "/kv/greetings/fr", b""));
+        assert_eq!(response.status, 200);
+        let verified = service.handle(&request("POST", "/verify/greetings/fr", proof));
+        assert_eq!(verified.body["matches_current_root"], false);
+    }
+
+    #[test]
+    fn test_serves_over_http() {
+        let dir = TempDir::new().unwrap();
+        let service = Service::open(dir.path().to_str().unwrap()).expect("should open service");
+        let listener = TcpListener::bind("127.0.0.1:0").expect("should bind");
+        let address = listener.local_addr().unwrap();
+
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).expect("should connect");
+            stream
+                .write_all(b"PUT /kv/greetings/en HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello")
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+        let (mut stream, _) = listener.accept().expect("should accept");
+        let request = read_request(&mut stream).expect("should read request");
+        write_response(&mut stream, &service.handle(&request)).expect("should respond");
+        drop(stream);
+
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        let body: Value = serde_json::from_str(body).unwrap();
+        assert!(body["root_hash"].is_string());
+    }
+}
@@ -0,0 +1,358 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Async facade
+//! Async versions of the main GroveDb operations for async servers. Blocking
+//! work runs on a dedicated pool of worker threads, the returned futures are
+//! completed by the workers, so any executor can await them.
+
+use std::{
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    pin::Pin,
+    sync::{mpsc, Arc, Mutex},
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+use costs::{CostResult, CostsExt};
+
+use crate::{
+    active_cost_constants,
+    batch::{BatchApplyOptions, GroveDbOp},
+    query_result_type::{QueryResultElements, QueryResultType},
+    with_cost_constants, Element, Error, GroveDb, PathQuery,
+};
+
+/// Work executed on a worker thread
+type Job = Box<dyn FnOnce(&GroveDb) + Send>;
+
+/// State shared by a blocking future and the worker completing it
+struct Completion<T> {
+    result: Option<T>,
+    waker: Option<Waker>,
+}
+
+impl<T> Completion<T> {
+    fn shared() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Completion {
+            result: None,
+            waker: None,
+        }))
+    }
+}
+
+/// Future of a result produced on a worker thread
+pub struct BlockingFuture<T> {
+    completion: Arc<Mutex<Completion<T>>>,
+}
+
+impl<T> Future for BlockingFuture<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut completion = self.completion.lock().expect("completion lock is poisoned");
+        match completion.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                completion.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// GroveDb handle whose operations are async, the database is shared by a
+/// pool of worker threads running the blocking work.
+///
+/// Transactions borrow the database, so they can't be moved between threads.
+/// Transactional work is run as a whole on a worker with
+/// [`AsyncGroveDb::run`].
+#[derive(Clone)]
+pub struct AsyncGroveDb {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl AsyncGroveDb {
+    /// Opens GroveDb on a pool of `worker_threads` threads
+    pub async fn open(path: impl Into<PathBuf>, worker_threads: usize) -> Result<Self, Error> {
+        let path = path.into();
+        let completion = Completion::shared();
+        let opening = completion.clone();
+        thread::spawn(move || {
+            let result = panic::catch_unwind(|| GroveDb::open(path))
+                .unwrap_or(Err(Error::InternalError("opening GroveDb panicked")))
+                .map(|db| Self::new(db, worker_threads));
+            Self::complete(&opening, result);
+        });
+        BlockingFuture { completion }.await
+    }
+
+    /// Shares an already opened GroveDb with a pool of `worker_threads`
+    /// threads
+    pub fn new(db: GroveDb, worker_threads: usize) -> Self {
+        let (jobs, receiver) = mpsc::channel();
+        Self::spawn_workers(Arc::new(db), receiver, worker_threads);
+        Self { jobs }
+    }
+
+    fn spawn_workers(db: Arc<GroveDb>, receiver: mpsc::Receiver<Job>, worker_threads: usize) {
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..worker_threads.max(1) {
+            let db = db.clone();
+            let receiver = receiver.clone();
+            thread::spawn(move || loop {
+                // workers stop once every handle was dropped
+                let job = match receiver.lock().expect("jobs lock is poisoned").recv() {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+                job(&db);
+            });
+        }
+    }
+
+    fn complete<T>(completion: &Mutex<Completion<T>>, result: T) {
+        let mut completion = completion.lock().expect("completion lock is poisoned");
+        completion.result = Some(result);
+        if let Some(waker) = completion.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Runs blocking work on a worker thread. Work that panics completes
+    /// with an error, the worker keeps running the next jobs. Costs of the
+    /// work are calculated from the cost constants active on the thread
+    /// submitting it.
+    pub fn run<T, F>(&self, f: F) -> BlockingFuture<Result<T, Error>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&GroveDb) -> T + Send + 'static,
+    {
+        let completion = Completion::shared();
+        let job_completion = completion.clone();
+        let cost_constants = active_cost_constants();
+        self.jobs
+            .send(Box::new(move |db| {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    with_cost_constants(cost_constants, || f(db))
+                }))
+                .map_err(|_| Error::InternalError("async job panicked"));
+                Self::complete(&job_completion, result);
+            }))
+            .expect("workers live as long as any handle");
+        BlockingFuture { completion }
+    }
+
+    /// Runs blocking work with costs on a worker thread
+    async fn run_with_cost<T, F>(&self, f: F) -> CostResult<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&GroveDb) -> CostResult<T, Error> + Send + 'static,
+    {
+        self.run(f)
+            .await
+            .unwrap_or_else(|e| Err(e).wrap_with_cost(Default::default()))
+    }
+
+    /// Get an element from the backing store
+    pub async fn get(&self, path: Vec<Vec<u8>>, key: Vec<u8>) -> CostResult<Element, Error> {
+        self.run_with_cost(move |db| {
+            db.get(path.iter().map(|segment| segment.as_slice()), &key, None)
+        })
+        .await
+    }
+
+    /// Queries the backing store
+    pub async fn query(
+        &self,
+        path_query: PathQuery,
+        allow_cache: bool,
+        result_type: QueryResultType,
+    ) -> CostResult<(QueryResultElements, u16), Error> {
+        self.run_with_cost(move |db| db.query(&path_query, allow_cache, result_type, None))
+            .await
+    }
+
+    /// Generates a proof for the path query
+    pub async fn prove_query(&self, path_query: PathQuery) -> CostResult<Vec<u8>, Error> {
+        self.run_with_cost(move |db| db.prove_query(&path_query))
+            .await
+    }
+
+    /// Applies a batch of operations
+    pub async fn apply_batch(
+        &self,
+        ops: Vec<GroveDbOp>,
+        batch_apply_options: Option<BatchApplyOptions>,
+    ) -> CostResult<(), Error> {
+        self.run_with_cost(move |db| db.apply_batch(ops, batch_apply_options, None))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        task::{Context, Poll, Wake},
+        thread::{self, Thread},
+    };
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{tests::TEST_LEAF, CostConstants, Query, COST_CONSTANTS};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Minimal executor, so tests don't need an async runtime
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_async_operations() {
+        let tmp_dir = TempDir::new().unwrap();
+        block_on(async {
+            let db = AsyncGroveDb::open(tmp_dir.path(), 2)
+                .await
+                .expect("should open");
+            db.apply_batch(
+                vec![
+                    GroveDbOp::insert_op(vec![], TEST_LEAF.to_vec(), Element::empty_tree()),
+                    GroveDbOp::insert_op(
+                        vec![TEST_LEAF.to_vec()],
+                        b"key".to_vec(),
+                        Element::new_item(b"value".to_vec()),
+                    ),
+                ],
+                None,
+            )
+            .await
+            .unwrap()
+            .expect("should apply batch");
+
+            let element = db
+                .get(vec![TEST_LEAF.to_vec()], b"key".to_vec())
+                .await
+                .unwrap()
+                .expect("should get");
+            assert_eq!(element, Element::new_item(b"value".to_vec()));
+
+            let mut query = Query::new();
+            query.insert_all();
+            let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+            let (elements, _) = db
+                .query(
+                    path_query.clone(),
+                    true,
+                    QueryResultType::QueryElementResultType,
+                )
+                .await
+                .unwrap()
+                .expect("should query");
+            assert_eq!(elements.len(), 1);
+
+            let proof = db
+                .prove_query(path_query.clone())
+                .await
+                .unwrap()
+                .expect("should prove");
+            let root_hash = db
+                .run(|db| db.root_hash(None))
+                .await
+                .expect("should run")
+                .unwrap()
+                .expect("should get root hash");
+            let (verified_hash, _) =
+                GroveDb::verify_query(&proof, &path_query).expect("should verify");
+            assert_eq!(verified_hash, root_hash);
+        });
+    }
+
+    #[test]
+    fn test_panicking_job_fails_its_future_only() {
+        let tmp_dir = TempDir::new().unwrap();
+        block_on(async {
+            let db = AsyncGroveDb::open(tmp_dir.path(), 1)
+                .await
+                .expect("should open");
+            let result = db.run::<(), _>(|_| panic!("job failure")).await;
+            assert!(matches!(result, Err(Error::InternalError(_))));
+
+            let root_hash = db
+                .run(|db| db.root_hash(None))
+                .await
+                .expect("worker should survive the panic")
+                .unwrap()
+                .expect("should get root hash");
+            assert_eq!(root_hash.len(), 32);
+        });
+    }
+
+    #[test]
+    fn test_jobs_use_the_cost_constants_of_the_submitting_thread() {
+        let tmp_dir = TempDir::new().unwrap();
+        let overridden = CostConstants {
+            key_prefix_size: 20,
+            ..COST_CONSTANTS
+        };
+        block_on(async {
+            let db = AsyncGroveDb::open(tmp_dir.path(), 1)
+                .await
+                .expect("should open");
+            let worker_constants =
+                with_cost_constants(overridden, || db.run(|_| active_cost_constants()))
+                    .await
+                    .expect("should run");
+            assert_eq!(worker_constants, overridden);
+
+            let worker_constants = db
+                .run(|_| active_cost_constants())
+                .await
+                .expect("should run");
+            assert_eq!(worker_constants, COST_CONSTANTS);
+        });
+    }
+}
@@ -149,7 +149,7 @@ where
             let op_cost = OperationCost::default();
             let op_result = match &op.op {
                 Op::Insert { element } | Op::Replace { element } | Op::Patch { element, .. } => {
-                    if let Element::Tree(..) = element {
+                    if let Element::Tree(..) | Element::OrderedTree(..) = element {
                         cost_return_on_error!(&mut cost, merk_tree_cache.insert(&op, false));
                     } else if let Element::SumTree(..) = element {
                         cost_return_on_error!(&mut cost, merk_tree_cache.insert(&op, true));
@@ -162,6 +162,9 @@ where
                         "replace and insert tree hash are internal operations only",
                     ))
                 }
+                Op::Rekey { .. } => Err(Error::InvalidBatchOperation(
+                    "rekey operations need the database to be resolved",
+                )),
             };
             if op_result.is_err() {
                 return Err(op_result.err().unwrap()).wrap_with_cost(op_cost);
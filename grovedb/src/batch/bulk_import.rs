@@ -0,0 +1,389 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Bulk import
+//! Applies a potentially huge stream of batch operations in chunks, each chunk
+//! being a batch committed in its own transaction. The operations iterator is
+//! only advanced once the previous chunk is committed and the progress callback
+//! returned, so a slow consumer of the progress naturally holds the import
+//! back. Imports with an id record a checkpoint together with every chunk and
+//! can be resumed from the last committed chunk after an interruption.
+
+#[cfg(feature = "full")]
+use std::collections::BTreeMap;
+
+#[cfg(feature = "full")]
+use bincode::Options;
+#[cfg(feature = "full")]
+use costs::{
+    cost_return_on_error, cost_return_on_error_no_add, CostResult, CostsExt, OperationCost,
+};
+#[cfg(feature = "full")]
+use merk::CryptoHash;
+#[cfg(feature = "full")]
+use storage::StorageContext;
+
+#[cfg(feature = "full")]
+use crate::{
+    batch::{BatchApplyOptions, GroveDbOp},
+    operations::metadata::{get_internal_meta, put_internal_meta},
+    util::meta_storage_context_optional_tx,
+    Error, GroveDb, TransactionArg,
+};
+
+#[cfg(feature = "full")]
+/// Meta storage key under which the checkpoints of unfinished bulk imports
+/// are kept
+pub(crate) const BULK_IMPORT_CHECKPOINTS_KEY: &[u8] = b"bulk_import_checkpoints";
+
+#[cfg(feature = "full")]
+/// Operations applied and bytes written of unfinished bulk imports by id
+type BulkImportCheckpoints = BTreeMap<Vec<u8>, (u64, u64)>;
+
+#[cfg(feature = "full")]
+/// Default number of operations applied per transaction
+pub const DEFAULT_BULK_IMPORT_CHUNK_SIZE: usize = 10_000;
+
+#[cfg(feature = "full")]
+#[derive(Debug, Clone)]
+/// Bulk import options
+pub struct BulkImportOptions {
+    /// Number of operations applied and committed per transaction
+    pub chunk_size: usize,
+    /// Id under which the progress is checkpointed, imports without an id
+    /// can't be resumed
+    pub import_id: Option<Vec<u8>>,
+    /// Options used to apply every chunk
+    pub batch_apply_options: Option<BatchApplyOptions>,
+}
+
+#[cfg(feature = "full")]
+impl Default for BulkImportOptions {
+    fn default() -> Self {
+        BulkImportOptions {
+            chunk_size: DEFAULT_BULK_IMPORT_CHUNK_SIZE,
+            import_id: None,
+            batch_apply_options: None,
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Progress of a bulk import, as of the last committed chunk
+pub struct BulkImportProgress {
+    /// Number of operations applied
+    pub ops_applied: u64,
+    /// Number of bytes added or replaced in storage
+    pub bytes_written: u64,
+    /// Root hash of the database
+    pub root_hash: CryptoHash,
+}
+
+#[cfg(feature = "full")]
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::default()
+        .with_varint_encoding()
+        .reject_trailing_bytes()
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Returns the progress recorded for an unfinished bulk import, `None` if
+    /// the import didn't commit any chunk yet or is finished
+    pub fn bulk_import_checkpoint(
+        &self,
+        import_id: &[u8],
+        transaction: TransactionArg,
+    ) -> CostResult<Option<BulkImportProgress>, Error> {
+        let mut cost = OperationCost::default();
+        let checkpoints =
+            cost_return_on_error!(&mut cost, self.bulk_import_checkpoints(transaction));
+        let (ops_applied, bytes_written) = match checkpoints.get(import_id) {
+            Some(checkpoint) => *checkpoint,
+            None => return Ok(None).wrap_with_cost(cost),
+        };
+        let root_hash = cost_return_on_error!(&mut cost, self.root_hash(transaction));
+        Ok(Some(BulkImportProgress {
+            ops_applied,
+            bytes_written,
+            root_hash,
+        }))
+        .wrap_with_cost(cost)
+    }
+
+    /// Applies the operations in chunks of `chunk_size`, every chunk in its
+    /// own transaction, calling `on_progress` after every committed chunk.
+    /// An import with an id that was interrupted resumes after its last
+    /// committed chunk, in which case the same operations have to be passed
+    /// again from the start.
+    pub fn bulk_import<I, F>(
+        &self,
+        ops: I,
+        options: Option<BulkImportOptions>,
+        mut on_progress: F,
+    ) -> CostResult<BulkImportProgress, Error>
+    where
+        I: IntoIterator<Item = GroveDbOp>,
+        F: FnMut(&BulkImportProgress),
+    {
+        let mut cost = OperationCost::default();
+        let options = options.unwrap_or_default();
+        if options.chunk_size == 0 {
+            return Err(Error::InvalidInput(
+                "bulk import chunk size must be positive",
+            ))
+            .wrap_with_cost(cost);
+        }
+
+        let resumed = match &options.import_id {
+            Some(import_id) => {
+                cost_return_on_error!(&mut cost, self.bulk_import_checkpoint(import_id, None))
+            }
+            None => None,
+        };
+        let mut progress = match resumed {
+            Some(progress) => progress,
+            None => BulkImportProgress {
+                ops_applied: 0,
+                bytes_written: 0,
+                root_hash: cost_return_on_error!(&mut cost, self.root_hash(None)),
+            },
+        };
+
+        let mut ops = ops.into_iter().skip(progress.ops_applied as usize);
+        loop {
+            let chunk: Vec<GroveDbOp> = ops.by_ref().take(options.chunk_size).collect();
+            if chunk.is_empty() {
+                break;
+            }
+            let chunk_len = chunk.len() as u64;
+
+            let transaction = self.start_transaction();
+            let mut chunk_cost = OperationCost::default();
+            let result = self
+                .apply_batch(
+                    chunk,
+                    options.batch_apply_options.clone(),
+                    Some(&transaction),
+                )
+                .unwrap_add_cost(&mut chunk_cost);
+            cost += chunk_cost.clone();
+            cost_return_on_error_no_add!(&cost, result);
+
+            progress.ops_applied += chunk_len;
+            progress.bytes_written += chunk_cost.storage_cost.added_bytes as u64
+                + chunk_cost.storage_cost.replaced_bytes as u64;
+
+            if let Some(import_id) = &options.import_id {
+                let mut checkpoints = cost_return_on_error!(
+                    &mut cost,
+                    self.bulk_import_checkpoints(Some(&transaction))
+                );
+                checkpoints.insert(
+                    import_id.clone(),
+                    (progress.ops_applied, progress.bytes_written),
+                );
+                cost_return_on_error!(
+                    &mut cost,
+                    self.write_bulk_import_checkpoints(&checkpoints, Some(&transaction))
+                );
+            }
+            cost_return_on_error!(&mut cost, self.commit_transaction(transaction));
+
+            progress.root_hash = cost_return_on_error!(&mut cost, self.root_hash(None));
+            on_progress(&progress);
+        }
+
+        if let Some(import_id) = &options.import_id {
+            let mut checkpoints =
+                cost_return_on_error!(&mut cost, self.bulk_import_checkpoints(None));
+            if checkpoints.remove(import_id).is_some() {
+                cost_return_on_error!(
+                    &mut cost,
+                    self.write_bulk_import_checkpoints(&checkpoints, None)
+                );
+            }
+        }
+
+        Ok(progress).wrap_with_cost(cost)
+    }
+
+    fn bulk_import_checkpoints(
+        &self,
+        transaction: TransactionArg,
+    ) -> CostResult<BulkImportCheckpoints, Error> {
+        let mut cost = OperationCost::default();
+        let maybe_bytes = meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            let meta_storage = meta_storage.unwrap_add_cost(&mut cost);
+            cost_return_on_error!(
+                &mut cost,
+                get_internal_meta(&meta_storage, BULK_IMPORT_CHECKPOINTS_KEY)
+            )
+        });
+        match maybe_bytes {
+            Some(bytes) => bincode_options().deserialize(&bytes).map_err(|_| {
+                Error::CorruptedData("bulk import checkpoints are corrupted".to_owned())
+            }),
+            None => Ok(BulkImportCheckpoints::new()),
+        }
+        .wrap_with_cost(cost)
+    }
+
+    fn write_bulk_import_checkpoints(
+        &self,
+        checkpoints: &BulkImportCheckpoints,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+        let bytes = cost_return_on_error_no_add!(
+            &cost,
+            bincode_options().serialize(checkpoints).map_err(|_| {
+                Error::CorruptedData("unable to serialize bulk import checkpoints".to_owned())
+            })
+        );
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            let meta_storage = meta_storage.unwrap_add_cost(&mut cost);
+            if checkpoints.is_empty() {
+                meta_storage
+                    .delete_meta(BULK_IMPORT_CHECKPOINTS_KEY, None)
+                    .map_err(Error::StorageError)
+            } else {
+                put_internal_meta(&meta_storage, BULK_IMPORT_CHECKPOINTS_KEY, bytes)
+            }
+        })
+        .add_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::{make_test_grovedb, TEST_LEAF},
+        Element,
+    };
+
+    fn item_ops(count: u32) -> Vec<GroveDbOp> {
+        (0..count)
+            .map(|i| {
+                GroveDbOp::insert_op(
+                    vec![TEST_LEAF.to_vec()],
+                    i.to_be_bytes().to_vec(),
+                    Element::new_item(i.to_be_bytes().to_vec()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bulk_import_reports_progress_per_chunk() {
+        let db = make_test_grovedb();
+        let mut reported = vec![];
+        let progress = db
+            .bulk_import(
+                item_ops(25),
+                Some(BulkImportOptions {
+                    chunk_size: 10,
+                    ..Default::default()
+                }),
+                |progress| reported.push(*progress),
+            )
+            .unwrap()
+            .expect("should import");
+
+        assert_eq!(
+            reported
+                .iter()
+                .map(|progress| progress.ops_applied)
+                .collect::<Vec<_>>(),
+            vec![10, 20, 25]
+        );
+        assert!(reported
+            .windows(2)
+            .all(|w| w[0].bytes_written < w[1].bytes_written));
+        assert_eq!(progress.root_hash, db.root_hash(None).unwrap().unwrap());
+        assert_eq!(
+            db.get([TEST_LEAF], &24u32.to_be_bytes(), None)
+                .unwrap()
+                .expect("should get imported item"),
+            Element::new_item(24u32.to_be_bytes().to_vec())
+        );
+    }
+
+    #[test]
+    fn test_bulk_import_resumes_after_last_committed_chunk() {
+        let db = make_test_grovedb();
+        let options = BulkImportOptions {
+            chunk_size: 10,
+            import_id: Some(b"import".to_vec()),
+            ..Default::default()
+        };
+
+        let mut interrupted_ops = item_ops(25);
+        interrupted_ops[15] = GroveDbOp::insert_op(
+            vec![TEST_LEAF.to_vec(), b"missing".to_vec()],
+            b"key".to_vec(),
+            Element::new_item(b"value".to_vec()),
+        );
+        assert!(db
+            .bulk_import(interrupted_ops, Some(options.clone()), |_| {})
+            .unwrap()
+            .is_err());
+        let checkpoint = db
+            .bulk_import_checkpoint(b"import", None)
+            .unwrap()
+            .expect("should get checkpoint")
+            .expect("checkpoint should be recorded");
+        assert_eq!(checkpoint.ops_applied, 10);
+        assert!(db
+            .get([TEST_LEAF], &12u32.to_be_bytes(), None)
+            .unwrap()
+            .is_err());
+
+        let mut reported = vec![];
+        let progress = db
+            .bulk_import(item_ops(25), Some(options), |progress| {
+                reported.push(progress.ops_applied)
+            })
+            .unwrap()
+            .expect("should resume import");
+        assert_eq!(reported, vec![20, 25]);
+        assert_eq!(progress.ops_applied, 25);
+        assert!(db
+            .bulk_import_checkpoint(b"import", None)
+            .unwrap()
+            .expect("should get checkpoint")
+            .is_none());
+        assert!(db
+            .get([TEST_LEAF], &12u32.to_be_bytes(), None)
+            .unwrap()
+            .is_ok());
+    }
+}
@@ -126,6 +126,10 @@ impl Op {
                 worst_case_layer_element_estimates,
                 propagate,
             ),
+            Op::Rekey { .. } => Err(Error::NotSupported(
+                "rekey operations need the database to be resolved",
+            ))
+            .wrap_with_cost(OperationCost::default()),
         }
     }
 }
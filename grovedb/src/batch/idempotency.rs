@@ -0,0 +1,195 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Batch idempotency
+//! Batches applied with an idempotency id are recorded in the meta storage
+//! within a bounded window of the most recent ids, a replay of a recorded id is
+//! rejected with `Error::AlreadyApplied`.
+
+#[cfg(feature = "full")]
+use costs::{cost_return_on_error, CostResult, CostsExt, OperationCost};
+#[cfg(feature = "full")]
+use integer_encoding::VarInt;
+#[cfg(feature = "full")]
+use storage::{Storage, StorageBatch};
+
+#[cfg(feature = "full")]
+use crate::{
+    operations::metadata::{get_internal_meta, put_internal_meta},
+    util::meta_storage_context_optional_tx,
+    Error, GroveDb, TransactionArg,
+};
+
+#[cfg(feature = "full")]
+/// Meta storage key under which the applied batch ids are kept
+pub(crate) const APPLIED_BATCH_IDS_KEY: &[u8] = b"applied_batch_ids";
+
+#[cfg(feature = "full")]
+/// Number of most recent applied batch ids that are remembered
+pub const APPLIED_BATCH_IDS_WINDOW: usize = 1024;
+
+#[cfg(feature = "full")]
+fn serialize_batch_ids(ids: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = ids.len().encode_var_vec();
+    for id in ids {
+        bytes.extend(id.len().encode_var_vec());
+        bytes.extend_from_slice(id);
+    }
+    bytes
+}
+
+#[cfg(feature = "full")]
+fn deserialize_batch_ids(mut bytes: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+    let corrupted = || Error::CorruptedData("applied batch ids are corrupted".to_owned());
+    let (count, read) = usize::decode_var(bytes).ok_or_else(corrupted)?;
+    bytes = &bytes[read..];
+    let mut ids = Vec::with_capacity(count.min(APPLIED_BATCH_IDS_WINDOW));
+    for _ in 0..count {
+        let (len, read) = usize::decode_var(bytes).ok_or_else(corrupted)?;
+        bytes = &bytes[read..];
+        if bytes.len() < len {
+            return Err(corrupted());
+        }
+        ids.push(bytes[..len].to_vec());
+        bytes = &bytes[len..];
+    }
+    Ok(ids)
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Returns the recorded applied batch ids, oldest first
+    pub fn applied_batch_ids(
+        &self,
+        transaction: TransactionArg,
+    ) -> CostResult<Vec<Vec<u8>>, Error> {
+        let mut cost = OperationCost::default();
+        let maybe_bytes = meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            let meta_storage = meta_storage.unwrap_add_cost(&mut cost);
+            cost_return_on_error!(
+                &mut cost,
+                get_internal_meta(&meta_storage, APPLIED_BATCH_IDS_KEY)
+            )
+        });
+        match maybe_bytes {
+            Some(bytes) => deserialize_batch_ids(&bytes),
+            None => Ok(vec![]),
+        }
+        .wrap_with_cost(cost)
+    }
+
+    /// Checks that the batch id was not applied yet and records it into the
+    /// storage batch, so it is committed together with the batch itself
+    pub(crate) fn record_batch_id(
+        &self,
+        id: &[u8],
+        storage_batch: &StorageBatch,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+        let mut ids = cost_return_on_error!(&mut cost, self.applied_batch_ids(transaction));
+        if ids.iter().any(|applied_id| applied_id.as_slice() == id) {
+            return Err(Error::AlreadyApplied(id.to_vec())).wrap_with_cost(cost);
+        }
+        ids.push(id.to_vec());
+        if ids.len() > APPLIED_BATCH_IDS_WINDOW {
+            ids.drain(..ids.len() - APPLIED_BATCH_IDS_WINDOW);
+        }
+        let bytes = serialize_batch_ids(&ids);
+        if let Some(tx) = transaction {
+            let meta_storage = self
+                .db
+                .get_batch_transactional_storage_context(std::iter::empty(), storage_batch, tx)
+                .unwrap_add_cost(&mut cost);
+            put_internal_meta(&meta_storage, APPLIED_BATCH_IDS_KEY, bytes)
+        } else {
+            let meta_storage = self
+                .db
+                .get_batch_storage_context(std::iter::empty(), storage_batch)
+                .unwrap_add_cost(&mut cost);
+            put_internal_meta(&meta_storage, APPLIED_BATCH_IDS_KEY, bytes)
+        }
+        .add_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        batch::{BatchApplyOptions, GroveDbOp},
+        tests::{make_test_grovedb, TEST_LEAF},
+        Element,
+    };
+
+    #[test]
+    fn test_batch_with_idempotency_id_is_applied_once() {
+        let db = make_test_grovedb();
+        let options = BatchApplyOptions {
+            idempotency_id: Some(b"batch_1".to_vec()),
+            ..Default::default()
+        };
+        let ops = vec![GroveDbOp::insert_op(
+            vec![TEST_LEAF.to_vec()],
+            b"key".to_vec(),
+            Element::new_item(b"value".to_vec()),
+        )];
+
+        db.apply_batch(ops.clone(), Some(options.clone()), None)
+            .unwrap()
+            .expect("should apply batch");
+        let root_hash = db.root_hash(None).unwrap().unwrap();
+
+        let result = db.apply_batch(ops, Some(options), None).unwrap();
+        assert!(matches!(result, Err(Error::AlreadyApplied(id)) if id == b"batch_1"));
+        assert_eq!(db.root_hash(None).unwrap().unwrap(), root_hash);
+        assert_eq!(
+            db.applied_batch_ids(None).unwrap().unwrap(),
+            vec![b"batch_1".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_applied_batch_ids_window_is_bounded() {
+        let db = make_test_grovedb();
+        for i in 0..APPLIED_BATCH_IDS_WINDOW + 1 {
+            let storage_batch = StorageBatch::new();
+            db.record_batch_id(&(i as u32).to_be_bytes(), &storage_batch, None)
+                .unwrap()
+                .expect("should record batch id");
+            db.db
+                .commit_multi_context_batch(storage_batch, None)
+                .unwrap()
+                .expect("should commit");
+        }
+        let ids = db.applied_batch_ids(None).unwrap().unwrap();
+        assert_eq!(ids.len(), APPLIED_BATCH_IDS_WINDOW);
+        assert_eq!(ids[0], 1u32.to_be_bytes().to_vec());
+    }
+}
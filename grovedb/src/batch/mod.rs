@@ -29,14 +29,18 @@
 //! GroveDB batch operations support
 
 mod batch_structure;
+pub mod bulk_import;
 
 pub mod estimated_costs;
 
+pub mod idempotency;
+
 pub mod key_info;
 
 mod mode;
 #[cfg(test)]
 mod multi_insert_cost_tests;
+pub mod rekey;
 
 #[cfg(test)]
 mod just_in_time_cost_tests;
@@ -99,10 +103,13 @@ use crate::{
         estimated_costs::EstimatedCostsType,
         mode::BatchRunMode,
     },
-    element::{SUM_ITEM_COST_SIZE, SUM_TREE_COST_SIZE, TREE_COST_SIZE},
-    operations::get::MAX_REFERENCE_HOPS,
-    reference_path::{path_from_reference_path_type, path_from_reference_qualified_path_type},
-    Element, ElementFlags, Error, GroveDb, Transaction, TransactionArg,
+    element::{sum_item_cost_size, sum_tree_cost_size, tree_cost_size},
+    operations::{get::MAX_REFERENCE_HOPS, propagation::PendingPropagations},
+    reference_path::{
+        path_from_reference_path_type, path_from_reference_qualified_path_type,
+        PartialReferenceChain,
+    },
+    Element, ElementFlags, Error, GroveDb, KeyOrdering, Transaction, TransactionArg,
 };
 
 /// Operations
@@ -144,6 +151,8 @@ pub enum Op {
         flags: Option<ElementFlags>,
         /// Sum
         sum: Option<i64>,
+        /// Key ordering of an ordered tree
+        key_ordering: Option<KeyOrdering>,
     },
     /// Delete
     Delete,
@@ -151,6 +160,11 @@ pub enum Op {
     DeleteTree,
     /// Delete sum tree
     DeleteSumTree,
+    /// Move the element to a new key of the same subtree
+    Rekey {
+        /// New key
+        new_key: Vec<u8>,
+    },
 }
 
 impl PartialOrd for Op {
@@ -362,6 +376,7 @@ impl fmt::Debug for GroveDbOp {
                 Element::Reference(..) => "Insert Ref",
                 Element::Tree(..) => "Insert Tree",
                 Element::SumTree(..) => "Insert Sum Tree",
+                Element::OrderedTree(..) => "Insert Ordered Tree",
                 Element::SumItem(..) => "Insert Sum Item",
             },
             Op::Replace { element } => match element {
@@ -369,6 +384,7 @@ impl fmt::Debug for GroveDbOp {
                 Element::Reference(..) => "Replace Ref",
                 Element::Tree(..) => "Replace Tree",
                 Element::SumTree(..) => "Replace Sum Tree",
+                Element::OrderedTree(..) => "Replace Ordered Tree",
                 Element::SumItem(..) => "Replace Sum Item",
             },
             Op::Patch { element, .. } => match element {
@@ -376,11 +392,13 @@ impl fmt::Debug for GroveDbOp {
                 Element::Reference(..) => "Patch Ref",
                 Element::Tree(..) => "Patch Tree",
                 Element::SumTree(..) => "Patch Sum Tree",
+                Element::OrderedTree(..) => "Patch Ordered Tree",
                 Element::SumItem(..) => "Patch Sum Item",
             },
             Op::Delete => "Delete",
             Op::DeleteTree => "Delete Tree",
             Op::DeleteSumTree => "Delete Sum Tree",
+            Op::Rekey { .. } => "Rekey",
             Op::ReplaceTreeRootKey { .. } => "Replace Tree Hash and Root Key",
             Op::InsertTreeWithRootHash { .. } => "Insert Tree Hash and Root Key",
         };
@@ -659,6 +677,19 @@ trait TreeCache<G, SR> {
     ) -> CostResult<RootHashKeyAndSum, Error>;
 
     fn update_base_merk_root_key(&mut self, root_key: Option<Vec<u8>>) -> CostResult<(), Error>;
+
+    /// Returns true if the element of the subtree at `path` in its parent
+    /// already holds the given root key and sum, so that carrying up the root
+    /// hash of the subtree can be deferred. Estimated costs always propagate
+    /// to the root.
+    fn parent_element_is_accurate(
+        &mut self,
+        _path: &KeyInfoPath,
+        _root_key: &Option<Vec<u8>>,
+        _sum: Option<i64>,
+    ) -> CostResult<bool, Error> {
+        Ok(false).wrap_with_cost(OperationCost::default())
+    }
 }
 
 impl<'db, S, F> TreeCacheMerkByPath<S, F>
@@ -676,15 +707,19 @@ where
     /// insert ref_3 and another operation to change something in the
     /// reference chain in the same batch.
     /// All these has to be taken into account.
+    /// The references followed are recorded in `chain`, so that exceeding the
+    /// hop limit reports the part of the chain followed.
     fn follow_reference_get_value_hash<'a>(
         &'a mut self,
         qualified_path: &[Vec<u8>],
         ops_by_qualified_paths: &'a BTreeMap<Vec<Vec<u8>>, Op>,
         recursions_allowed: u8,
+        mut chain: PartialReferenceChain,
     ) -> CostResult<CryptoHash, Error> {
         let mut cost = OperationCost::default();
         if recursions_allowed == 0 {
-            return Err(Error::ReferenceLimit).wrap_with_cost(cost);
+            chain.next_path = Some(qualified_path.to_vec());
+            return Err(Error::ReferenceLimit(Box::new(chain))).wrap_with_cost(cost);
         }
         // If the element being referenced changes in the same batch
         // we need to set the value_hash based on the new change and not the old state.
@@ -711,13 +746,16 @@ where
                                     qualified_path
                                 )
                             );
+                            chain.visited.push(qualified_path.to_vec());
+                            chain.last_element = Some(element.clone());
                             self.follow_reference_get_value_hash(
                                 path.as_slice(),
                                 ops_by_qualified_paths,
                                 recursions_allowed - 1,
+                                chain,
                             )
                         }
-                        Element::Tree(..) | Element::SumTree(..) => {
+                        Element::Tree(..) | Element::SumTree(..) | Element::OrderedTree(..) => {
                             Err(Error::InvalidBatchOperation(
                                 "references can not point to trees being updated",
                             ))
@@ -725,7 +763,7 @@ where
                         }
                     }
                 }
-                Op::Delete | Op::DeleteTree | Op::DeleteSumTree => {
+                Op::Delete | Op::DeleteTree | Op::DeleteSumTree | Op::Rekey { .. } => {
                     Err(Error::InvalidBatchOperation(
                         "references can not point to something currently being deleted",
                     ))
@@ -805,7 +843,7 @@ where
                     })
                 );
 
-                match element {
+                match &element {
                     Element::Item(..) | Element::SumItem(..) => {
                         let serialized = cost_return_on_error_no_add!(&cost, element.serialize());
                         let val_hash = value_hash(&serialized).unwrap_add_cost(&mut cost);
@@ -814,18 +852,23 @@ where
                     Element::Reference(path, ..) => {
                         let path = cost_return_on_error_no_add!(
                             &cost,
-                            path_from_reference_qualified_path_type(path, qualified_path)
+                            path_from_reference_qualified_path_type(path.clone(), qualified_path)
                         );
+                        chain.visited.push(qualified_path.to_vec());
+                        chain.last_element = Some(element);
                         self.follow_reference_get_value_hash(
                             path.as_slice(),
                             ops_by_qualified_paths,
                             recursions_allowed - 1,
+                            chain,
                         )
                     }
-                    Element::Tree(..) | Element::SumTree(..) => Err(Error::InvalidBatchOperation(
-                        "references can not point to trees being updated",
-                    ))
-                    .wrap_with_cost(cost),
+                    Element::Tree(..) | Element::SumTree(..) | Element::OrderedTree(..) => {
+                        Err(Error::InvalidBatchOperation(
+                            "references can not point to trees being updated",
+                        ))
+                        .wrap_with_cost(cost)
+                    }
                 }
             }
         }
@@ -928,7 +971,8 @@ where
                                 self.follow_reference_get_value_hash(
                                     path_reference.as_slice(),
                                     ops_by_qualified_paths,
-                                    element_max_reference_hop.unwrap_or(MAX_REFERENCE_HOPS as u8)
+                                    element_max_reference_hop.unwrap_or(MAX_REFERENCE_HOPS as u8),
+                                    PartialReferenceChain::default()
                                 )
                             );
 
@@ -942,7 +986,7 @@ where
                                 )
                             );
                         }
-                        Element::Tree(..) | Element::SumTree(..) => {
+                        Element::Tree(..) | Element::SumTree(..) | Element::OrderedTree(..) => {
                             let merk_feature_type = cost_return_on_error!(
                                 &mut cost,
                                 element
@@ -1047,17 +1091,27 @@ where
                         )
                     );
                 }
+                Op::Rekey { .. } => {
+                    return Err(Error::CorruptedCodeExecution(
+                        "rekey operations are resolved before the batch is applied",
+                    ))
+                    .wrap_with_cost(cost);
+                }
                 Op::InsertTreeWithRootHash {
                     hash,
                     root_key,
                     flags,
                     sum,
+                    key_ordering,
                 } => {
-                    let element = match sum {
-                        None => Element::new_tree_with_flags(root_key, flags),
-                        Some(sum_value) => Element::new_sum_tree_with_flags_and_sum_value(
+                    let element = match (sum, key_ordering) {
+                        (Some(sum_value), _) => Element::new_sum_tree_with_flags_and_sum_value(
                             root_key, sum_value, flags,
                         ),
+                        (None, Some(key_ordering)) => {
+                            Element::new_ordered_tree_with_flags(root_key, key_ordering, flags)
+                        }
+                        (None, None) => Element::new_tree_with_flags(root_key, flags),
                     };
                     let merk_feature_type =
                         cost_return_on_error_no_add!(&cost, element.get_feature_type(is_sum_tree));
@@ -1114,11 +1168,13 @@ where
                                 // we need to give back the value defined cost in the case that the
                                 // new element is a tree
                                 match new_element {
-                                    Element::Tree(..) | Element::SumTree(..) => {
+                                    Element::Tree(..)
+                                    | Element::SumTree(..)
+                                    | Element::OrderedTree(..) => {
                                         let tree_cost_size = if new_element.is_sum_tree() {
-                                            SUM_TREE_COST_SIZE
+                                            sum_tree_cost_size()
                                         } else {
-                                            TREE_COST_SIZE
+                                            tree_cost_size()
                                         };
                                         let tree_value_cost = tree_cost_size
                                             + flags_len
@@ -1126,7 +1182,7 @@ where
                                         Ok((true, Some(LayeredValueDefinedCost(tree_value_cost))))
                                     }
                                     Element::SumItem(..) => {
-                                        let sum_item_value_cost = SUM_ITEM_COST_SIZE
+                                        let sum_item_value_cost = sum_item_cost_size()
                                             + flags_len
                                             + flags_len.required_space() as u32;
                                         Ok((
@@ -1172,6 +1228,42 @@ where
     fn get_batch_run_mode(&self) -> BatchRunMode {
         BatchRunMode::Execute
     }
+
+    fn parent_element_is_accurate(
+        &mut self,
+        path: &KeyInfoPath,
+        root_key: &Option<Vec<u8>>,
+        sum: Option<i64>,
+    ) -> CostResult<bool, Error> {
+        let mut cost = OperationCost::default();
+
+        let mut parent_path = path.to_path();
+        let key = match parent_path.pop() {
+            Some(key) => key,
+            None => return Ok(false).wrap_with_cost(cost),
+        };
+        let parent_merk = match self.merks.entry(parent_path) {
+            HashMapEntry::Occupied(entry) => entry.into_mut(),
+            HashMapEntry::Vacant(entry) => {
+                let merk = cost_return_on_error!(&mut cost, (self.get_merk_fn)(entry.key(), false));
+                entry.insert(merk)
+            }
+        };
+        let element = cost_return_on_error!(
+            &mut cost,
+            GroveDb::get_element_from_subtree(parent_merk, &key)
+        );
+        let parent_element_is_accurate = match element {
+            Element::Tree(stored_root_key, _) | Element::OrderedTree(stored_root_key, ..) => {
+                &stored_root_key == root_key
+            }
+            Element::SumTree(stored_root_key, stored_sum, _) => {
+                &stored_root_key == root_key && Some(stored_sum) == sum
+            }
+            _ => false,
+        };
+        Ok(parent_element_is_accurate).wrap_with_cost(cost)
+    }
 }
 
 impl GroveDb {
@@ -1181,6 +1273,7 @@ impl GroveDb {
     fn apply_batch_structure<C: TreeCache<F, SR>, F, SR>(
         batch_structure: BatchStructure<C, F, SR>,
         batch_apply_options: Option<BatchApplyOptions>,
+        mut deferred_propagations: Option<&mut PendingPropagations>,
     ) -> CostResult<Option<OpsByLevelPath>, Error>
     where
         F: FnMut(&StorageCost, Option<ElementFlags>, &mut ElementFlags) -> Result<bool, Error>,
@@ -1204,8 +1297,12 @@ impl GroveDb {
         let batch_apply_options = batch_apply_options.unwrap_or_default();
         let stop_level = batch_apply_options.batch_pause_height.unwrap_or_default() as u32;
 
-        // We will update up the tree
-        while let Some(ops_at_level) = ops_by_level_paths.remove(&current_level) {
+        // We will update up the tree, levels may be left without operations when
+        // propagations are deferred
+        loop {
+            let ops_at_level = ops_by_level_paths
+                .remove(&current_level)
+                .unwrap_or_default();
             for (path, ops_at_path) in ops_at_level.into_iter() {
                 if current_level == 0 {
                     // execute the ops at this path
@@ -1250,6 +1347,32 @@ impl GroveDb {
                         )
                     );
 
+                    if let Some(deferred_propagations) = deferred_propagations.as_deref_mut() {
+                        let parent_has_op_on_key =
+                            path.split_last().is_some_and(|(key, parent_path)| {
+                                ops_by_level_paths
+                                    .get(&(current_level - 1))
+                                    .and_then(|ops_at_level_above| {
+                                        ops_at_level_above.get(&KeyInfoPath(parent_path.to_vec()))
+                                    })
+                                    .is_some_and(|ops_on_path| ops_on_path.contains_key(key))
+                            });
+                        if !parent_has_op_on_key
+                            && cost_return_on_error!(
+                                &mut cost,
+                                merk_tree_cache.parent_element_is_accurate(
+                                    &path,
+                                    &calculated_root_key,
+                                    sum_value
+                                )
+                            )
+                        {
+                            // only the root hash changed, it's carried up on commit
+                            deferred_propagations.insert(path.to_path());
+                            continue;
+                        }
+                    }
+
                     if current_level > 0 {
                         // We need to propagate up this root hash, this means adding grove_db
                         // operations up for the level above
@@ -1297,6 +1420,21 @@ impl GroveDb {
                                                                 root_key: calculated_root_key,
                                                                 flags: flags.clone(),
                                                                 sum: None,
+                                                                key_ordering: None,
+                                                            };
+                                                    } else if let Element::OrderedTree(
+                                                        _,
+                                                        key_ordering,
+                                                        flags,
+                                                    ) = element
+                                                    {
+                                                        *mutable_occupied_entry =
+                                                            Op::InsertTreeWithRootHash {
+                                                                hash: root_hash,
+                                                                root_key: calculated_root_key,
+                                                                flags: flags.clone(),
+                                                                sum: None,
+                                                                key_ordering: Some(*key_ordering),
                                                             };
                                                     } else if let Element::SumTree(.., flags) =
                                                         element
@@ -1307,6 +1445,7 @@ impl GroveDb {
                                                                 root_key: calculated_root_key,
                                                                 flags: flags.clone(),
                                                                 sum: sum_value,
+                                                                key_ordering: None,
                                                             };
                                                     } else {
                                                         return Err(Error::InvalidBatchOperation(
@@ -1315,7 +1454,10 @@ impl GroveDb {
                                                         .wrap_with_cost(cost);
                                                     }
                                                 }
-                                                Op::Delete | Op::DeleteTree | Op::DeleteSumTree => {
+                                                Op::Delete
+                                                | Op::DeleteTree
+                                                | Op::DeleteSumTree
+                                                | Op::Rekey { .. } => {
                                                     if calculated_root_key.is_some() {
                                                         return Err(Error::InvalidBatchOperation(
                                                             "modification of tree when it will be \
@@ -1362,9 +1504,10 @@ impl GroveDb {
                 // we need to pause the batch execution
                 return Ok(Some(ops_by_level_paths)).wrap_with_cost(cost);
             }
-            if current_level > 0 {
-                current_level -= 1;
+            if current_level == 0 {
+                break;
             }
+            current_level -= 1;
         }
         Ok(None).wrap_with_cost(cost)
     }
@@ -1372,10 +1515,13 @@ impl GroveDb {
     /// Method to propagate updated subtree root hashes up to GroveDB root
     /// If the pause height is set in the batch apply options
     /// Then return the list of leftover operations
+    /// Subtrees whose root hash is left to be propagated are added to
+    /// `deferred_propagations` if given
     fn apply_body<'db, S: StorageContext<'db>>(
         &self,
         ops: Vec<GroveDbOp>,
         batch_apply_options: Option<BatchApplyOptions>,
+        deferred_propagations: Option<&mut PendingPropagations>,
         update_element_flags_function: impl FnMut(
             &StorageCost,
             Option<ElementFlags>,
@@ -1404,7 +1550,8 @@ impl GroveDb {
                 }
             )
         );
-        Self::apply_batch_structure(batch_structure, batch_apply_options).add_cost(cost)
+        Self::apply_batch_structure(batch_structure, batch_apply_options, deferred_propagations)
+            .add_cost(cost)
     }
 
     /// Method to propagate updated subtree root hashes up to GroveDB root
@@ -1444,7 +1591,7 @@ impl GroveDb {
                 }
             )
         );
-        Self::apply_batch_structure(batch_structure, batch_apply_options).add_cost(cost)
+        Self::apply_batch_structure(batch_structure, batch_apply_options, None).add_cost(cost)
     }
 
     /// Applies operations on GroveDB without batching
@@ -1455,18 +1602,26 @@ impl GroveDb {
         transaction: TransactionArg,
     ) -> CostResult<(), Error> {
         let mut cost = OperationCost::default();
+        cost_return_on_error!(&mut cost, self.check_ops_key_ordering(&ops, transaction));
+        let ops = cost_return_on_error!(&mut cost, self.resolve_rekey_ops(ops, transaction));
         for op in ops.into_iter() {
+            let overwrite = matches!(op.op, Op::Replace { .. });
             match op.op {
                 Op::Insert { element } | Op::Replace { element } => {
                     let path_slices: Vec<&[u8]> =
                         op.path.iterator().map(|p| p.as_slice()).collect();
+                    let mut insert_options = options
+                        .as_ref()
+                        .map(|o| o.as_insert_options())
+                        .unwrap_or_default();
+                    insert_options.overwrite = overwrite;
                     cost_return_on_error!(
                         &mut cost,
                         self.insert(
                             path_slices,
                             op.key.as_slice(),
                             element.to_owned(),
-                            options.clone().map(|o| o.as_insert_options()),
+                            Some(insert_options),
                             transaction,
                         )
                     );
@@ -1577,7 +1732,10 @@ impl GroveDb {
                         })
                     );
                     let is_sum_tree = element.is_sum_tree();
-                    if let Element::Tree(root_key, _) | Element::SumTree(root_key, ..) = element {
+                    if let Element::Tree(root_key, _)
+                    | Element::SumTree(root_key, ..)
+                    | Element::OrderedTree(root_key, ..) = element
+                    {
                         Merk::open_layered_with_root_key(storage, root_key, is_sum_tree)
                             .map_err(|_| {
                                 Error::CorruptedData(
@@ -1637,7 +1795,10 @@ impl GroveDb {
                 Element::get_from_storage(&parent_storage, last)
             );
             let is_sum_tree = element.is_sum_tree();
-            if let Element::Tree(root_key, _) | Element::SumTree(root_key, ..) = element {
+            if let Element::Tree(root_key, _)
+            | Element::SumTree(root_key, ..)
+            | Element::OrderedTree(root_key, ..) = element
+            {
                 Merk::open_layered_with_root_key(storage, root_key, is_sum_tree)
                     .map_err(|_| {
                         Error::CorruptedData("cannot open a subtree with given root key".to_owned())
@@ -1682,6 +1843,28 @@ impl GroveDb {
             return Ok(()).wrap_with_cost(cost);
         }
 
+        if let Some(batch_options) = batch_apply_options
+            .as_ref()
+            .filter(|batch_options| batch_options.dry_run)
+        {
+            let batch_options = BatchApplyOptions {
+                dry_run: false,
+                ..batch_options.clone()
+            };
+            return self.dry_run(transaction, |tx| {
+                self.apply_batch_with_element_flags_update(
+                    ops,
+                    Some(batch_options),
+                    update_element_flags_function,
+                    split_removal_bytes_function,
+                    Some(tx),
+                )
+            });
+        }
+
+        cost_return_on_error!(&mut cost, self.check_ops_key_ordering(&ops, transaction));
+        let ops = cost_return_on_error!(&mut cost, self.resolve_rekey_ops(ops, transaction));
+
         // Determines whether to check batch operation consistency
         // return false if the disable option is set to true, returns true for any other
         // case
@@ -1699,10 +1882,48 @@ impl GroveDb {
                 .wrap_with_cost(cost);
             }
         }
+        cost_return_on_error!(&mut cost, self.check_ops_writable(&ops, transaction));
+        cost_return_on_error!(
+            &mut cost,
+            self.check_ops_overwrite_intent(&ops, transaction)
+        );
+        let quotas = cost_return_on_error!(&mut cost, self.check_ops_quotas(&ops, transaction));
+        let reservations =
+            cost_return_on_error!(&mut cost, self.check_ops_reservations(&ops, transaction));
 
         // `StorageBatch` allows us to collect operations on different subtrees before
         // execution
         let storage_batch = StorageBatch::new();
+        let mut memory = self.reserve_memory();
+
+        if let Some(id) = batch_apply_options
+            .as_ref()
+            .and_then(|batch_options| batch_options.idempotency_id.as_ref())
+        {
+            cost_return_on_error!(
+                &mut cost,
+                self.record_batch_id(id, &storage_batch, transaction)
+            );
+        }
+        if let Some(quotas) = quotas.as_ref() {
+            cost_return_on_error!(
+                &mut cost,
+                self.record_subtree_quotas(quotas, &storage_batch, transaction)
+            );
+        }
+        if let Some(reservations) = reservations.as_ref() {
+            cost_return_on_error!(
+                &mut cost,
+                self.record_subtree_reservations(reservations, &storage_batch, transaction)
+            );
+        }
+        // the root leaf registry hash is bookkeeping of the database rather than of
+        // the batch, so its cost isn't charged
+        cost_return_on_error_no_add!(
+            &cost,
+            self.record_root_leaves_hash(&ops, &storage_batch, transaction)
+                .unwrap()
+        );
 
         // With the only one difference (if there is a transaction) do the following:
         // 2. If nothing left to do and we were on a non-leaf subtree or we're done with
@@ -1715,14 +1936,17 @@ impl GroveDb {
         // 6. Add root leaves save operation to the batch
         // 7. Apply storage_cost batch
         if let Some(tx) = transaction {
+            let mut deferred_propagations = PendingPropagations::new();
             cost_return_on_error!(
                 &mut cost,
                 self.apply_body(
                     ops,
                     batch_apply_options,
+                    (!self.is_eager_propagation()).then_some(&mut deferred_propagations),
                     update_element_flags_function,
                     split_removal_bytes_function,
                     |path, new_merk| {
+                        memory.add_open_subtree();
                         self.open_batch_transactional_merk_at_path(
                             &storage_batch,
                             path.iter().map(|x| x.as_slice()),
@@ -1733,6 +1957,8 @@ impl GroveDb {
                 )
             );
 
+            memory.set_in_flight_batch_bytes(storage_batch.size_in_bytes());
+
             // TODO: compute batch costs
             cost_return_on_error!(
                 &mut cost,
@@ -1740,20 +1966,25 @@ impl GroveDb {
                     .commit_multi_context_batch(storage_batch, Some(tx))
                     .map_err(|e| e.into())
             );
+            tx.add_pending_subtrees(deferred_propagations);
         } else {
             cost_return_on_error!(
                 &mut cost,
                 self.apply_body(
                     ops,
                     batch_apply_options,
+                    None,
                     update_element_flags_function,
                     split_removal_bytes_function,
                     |path, new_merk| {
+                        memory.add_open_subtree();
                         self.open_batch_merk_at_path(&storage_batch, path, new_merk)
                     }
                 )
             );
 
+            memory.set_in_flight_batch_bytes(storage_batch.size_in_bytes());
+
             // TODO: compute batch costs
             cost_return_on_error!(
                 &mut cost,
@@ -1762,6 +1993,8 @@ impl GroveDb {
                     .map_err(|e| e.into())
             );
         }
+        drop(memory);
+        self.enforce_memtable_budget_after_write();
         Ok(()).wrap_with_cost(cost)
     }
 
@@ -1799,11 +2032,27 @@ impl GroveDb {
         }
 
         let mut batch_apply_options = batch_apply_options.unwrap_or_default();
+        if batch_apply_options.dry_run {
+            batch_apply_options.dry_run = false;
+            return self.dry_run(transaction, |tx| {
+                self.apply_partial_batch_with_element_flags_update(
+                    ops,
+                    Some(batch_apply_options),
+                    update_element_flags_function,
+                    split_removal_bytes_function,
+                    add_on_operations,
+                    Some(tx),
+                )
+            });
+        }
         if batch_apply_options.batch_pause_height.is_none() {
             // we default to pausing at the root tree, which is the most common case
             batch_apply_options.batch_pause_height = Some(1);
         }
 
+        cost_return_on_error!(&mut cost, self.check_ops_key_ordering(&ops, transaction));
+        let ops = cost_return_on_error!(&mut cost, self.resolve_rekey_ops(ops, transaction));
+
         // Determines whether to check batch operation consistency
         // return false if the disable option is set to true, returns true for any other
         // case
@@ -1819,10 +2068,43 @@ impl GroveDb {
                 .wrap_with_cost(cost);
             }
         }
+        cost_return_on_error!(&mut cost, self.check_ops_writable(&ops, transaction));
+        cost_return_on_error!(
+            &mut cost,
+            self.check_ops_overwrite_intent(&ops, transaction)
+        );
+        let quotas = cost_return_on_error!(&mut cost, self.check_ops_quotas(&ops, transaction));
+        let reservations =
+            cost_return_on_error!(&mut cost, self.check_ops_reservations(&ops, transaction));
+        let root_ops: Vec<GroveDbOp> = ops
+            .iter()
+            .filter(|op| op.path.is_empty())
+            .cloned()
+            .collect();
 
         // `StorageBatch` allows us to collect operations on different subtrees before
         // execution
         let storage_batch = StorageBatch::new();
+        let mut memory = self.reserve_memory();
+
+        if let Some(id) = batch_apply_options.idempotency_id.as_ref() {
+            cost_return_on_error!(
+                &mut cost,
+                self.record_batch_id(id, &storage_batch, transaction)
+            );
+        }
+        if let Some(quotas) = quotas.as_ref() {
+            cost_return_on_error!(
+                &mut cost,
+                self.record_subtree_quotas(quotas, &storage_batch, transaction)
+            );
+        }
+        if let Some(reservations) = reservations.as_ref() {
+            cost_return_on_error!(
+                &mut cost,
+                self.record_subtree_reservations(reservations, &storage_batch, transaction)
+            );
+        }
 
         // With the only one difference (if there is a transaction) do the following:
         // 2. If nothing left to do and we were on a non-leaf subtree or we're done with
@@ -1840,9 +2122,11 @@ impl GroveDb {
                 self.apply_body(
                     ops,
                     Some(batch_apply_options.clone()),
+                    None,
                     &mut update_element_flags_function,
                     &mut split_removal_bytes_function,
                     |path, new_merk| {
+                        memory.add_open_subtree();
                         self.open_batch_transactional_merk_at_path(
                             &storage_batch,
                             path.iter().map(|x| x.as_slice()),
@@ -1855,6 +2139,8 @@ impl GroveDb {
             // if we paused at the root height, the left over operations would be to replace
             // a lot of leaf nodes in the root tree
 
+            memory.set_in_flight_batch_bytes(storage_batch.size_in_bytes());
+
             // let's build the write batch
             let (mut write_batch, mut pending_costs) = cost_return_on_error!(
                 &mut cost,
@@ -1874,11 +2160,26 @@ impl GroveDb {
                 &cost,
                 add_on_operations(&total_current_costs, &left_over_operations)
             );
+            cost_return_on_error!(
+                &mut cost,
+                self.check_ops_writable(&new_operations, transaction)
+            );
 
             // we are trying to finalize
             batch_apply_options.batch_pause_height = None;
 
             let continue_storage_batch = StorageBatch::new();
+            // the root leaf registry hash is bookkeeping of the database rather than
+            // of the batch, so its cost isn't charged
+            cost_return_on_error_no_add!(
+                &cost,
+                self.record_root_leaves_hash(
+                    root_ops.iter().chain(new_operations.iter()),
+                    &continue_storage_batch,
+                    transaction
+                )
+                .unwrap()
+            );
 
             cost_return_on_error!(
                 &mut cost,
@@ -1889,6 +2190,7 @@ impl GroveDb {
                     update_element_flags_function,
                     split_removal_bytes_function,
                     |path, new_merk| {
+                        memory.add_open_subtree();
                         self.open_batch_transactional_merk_at_path(
                             &continue_storage_batch,
                             path.iter().map(|x| x.as_slice()),
@@ -1922,9 +2224,11 @@ impl GroveDb {
                 self.apply_body(
                     ops,
                     Some(batch_apply_options.clone()),
+                    None,
                     &mut update_element_flags_function,
                     &mut split_removal_bytes_function,
                     |path, new_merk| {
+                        memory.add_open_subtree();
                         self.open_batch_merk_at_path(&storage_batch, path, new_merk)
                     }
                 )
@@ -1933,6 +2237,8 @@ impl GroveDb {
             // if we paused at the root height, the left over operations would be to replace
             // a lot of leaf nodes in the root tree
 
+            memory.set_in_flight_batch_bytes(storage_batch.size_in_bytes());
+
             // let's build the write batch
             let (mut write_batch, mut pending_costs) = cost_return_on_error!(
                 &mut cost,
@@ -1950,11 +2256,26 @@ impl GroveDb {
                 &cost,
                 add_on_operations(&total_current_costs, &left_over_operations)
             );
+            cost_return_on_error!(
+                &mut cost,
+                self.check_ops_writable(&new_operations, transaction)
+            );
 
             // we are trying to finalize
             batch_apply_options.batch_pause_height = None;
 
             let continue_storage_batch = StorageBatch::new();
+            // the root leaf registry hash is bookkeeping of the database rather than
+            // of the batch, so its cost isn't charged
+            cost_return_on_error_no_add!(
+                &cost,
+                self.record_root_leaves_hash(
+                    root_ops.iter().chain(new_operations.iter()),
+                    &continue_storage_batch,
+                    transaction
+                )
+                .unwrap()
+            );
 
             cost_return_on_error!(
                 &mut cost,
@@ -1965,6 +2286,7 @@ impl GroveDb {
                     update_element_flags_function,
                     split_removal_bytes_function,
                     |path, new_merk| {
+                        memory.add_open_subtree();
                         self.open_batch_merk_at_path(&continue_storage_batch, path, new_merk)
                     }
                 )
@@ -1988,6 +2310,8 @@ impl GroveDb {
                     .map_err(|e| e.into())
             );
         }
+        drop(memory);
+        self.enforce_memtable_budget_after_write();
         Ok(()).wrap_with_cost(cost)
     }
 
@@ -2032,7 +2356,7 @@ impl GroveDb {
                 );
                 cost_return_on_error!(
                     &mut cost,
-                    Self::apply_batch_structure(batch_structure, batch_apply_options)
+                    Self::apply_batch_structure(batch_structure, batch_apply_options, None)
                 );
             }
 
@@ -2050,7 +2374,7 @@ impl GroveDb {
                 );
                 cost_return_on_error!(
                     &mut cost,
-                    Self::apply_batch_structure(batch_structure, batch_apply_options)
+                    Self::apply_batch_structure(batch_structure, batch_apply_options, None)
                 );
             }
         }
@@ -2208,6 +2532,8 @@ mod tests {
                     disable_operation_consistency_check: true,
                     base_root_storage_is_free: true,
                     batch_pause_height: None,
+                    idempotency_id: None,
+                    dry_run: false,
                 }),
                 None
             )
@@ -2926,6 +3252,8 @@ mod tests {
                     disable_operation_consistency_check: false,
                     base_root_storage_is_free: true,
                     batch_pause_height: None,
+                    idempotency_id: None,
+                    dry_run: false,
                 }),
                 None
             )
@@ -2965,6 +3293,8 @@ mod tests {
                     deleting_non_empty_trees_returns_error: true,
                     base_root_storage_is_free: true,
                     batch_pause_height: None,
+                    idempotency_id: None,
+                    dry_run: false,
                 }),
                 None
             )
@@ -2998,6 +3328,8 @@ mod tests {
                     disable_operation_consistency_check: false,
                     base_root_storage_is_free: true,
                     batch_pause_height: None,
+                    idempotency_id: None,
+                    dry_run: false,
                 }),
                 None
             )
@@ -3262,9 +3594,19 @@ mod tests {
             ),
             GroveDbOp::insert_op(vec![TEST_LEAF.to_vec()], b"invalid_path".to_vec(), elem),
         ];
-        assert!(matches!(
-            db.apply_batch(batch, None, None).unwrap(),
-            Err(Error::ReferenceLimit)
-        ));
+        match db.apply_batch(batch, None, None).unwrap() {
+            Err(Error::ReferenceLimit(chain)) => {
+                assert_eq!(
+                    chain.visited,
+                    vec![vec![TEST_LEAF.to_vec(), b"key1".to_vec()]]
+                );
+                assert_eq!(
+                    chain.next_path,
+                    Some(vec![TEST_LEAF.to_vec(), b"invalid_path".to_vec()])
+                );
+                assert!(matches!(chain.last_element, Some(Element::Reference(..))));
+            }
+            _ => panic!("expected the reference limit to be exceeded"),
+        }
     }
 }
@@ -53,6 +53,11 @@ pub struct BatchApplyOptions {
     /// At what height do we want to pause applying batch operations
     /// Most of the time this should be not set
     pub batch_pause_height: Option<u8>,
+    /// Id under which the batch is recorded, a batch with an already applied
+    /// id is rejected
+    pub idempotency_id: Option<Vec<u8>>,
+    /// Apply the batch and report its costs, but roll back all of its writes
+    pub dry_run: bool,
 }
 
 #[cfg(feature = "full")]
@@ -66,6 +71,8 @@ impl Default for BatchApplyOptions {
             disable_operation_consistency_check: false,
             base_root_storage_is_free: true,
             batch_pause_height: None,
+            idempotency_id: None,
+            dry_run: false,
         }
     }
 }
@@ -79,6 +86,7 @@ impl BatchApplyOptions {
             validate_insertion_does_not_override_tree: self
                 .validate_insertion_does_not_override_tree,
             base_root_storage_is_free: self.base_root_storage_is_free,
+            overwrite: false,
         }
     }
 
@@ -0,0 +1,274 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Batch rekey
+//! Rekey operations move an element to another key of the same subtree. They
+//! are resolved against the database before the batch is applied, becoming
+//! the deletion of the old key and the insertion of the unchanged element at
+//! the new key, so the subtree is propagated only once. As they are resolved
+//! against the database, their keys can't be touched by other operations of
+//! the same batch.
+
+#[cfg(feature = "full")]
+use std::collections::BTreeSet;
+
+#[cfg(feature = "full")]
+use costs::{cost_return_on_error, CostResult, CostsExt, OperationCost};
+
+#[cfg(feature = "full")]
+use crate::{
+    batch::{key_info::KeyInfo::KnownKey, GroveDbOp, KeyInfoPath, Op},
+    Error, GroveDb, TransactionArg,
+};
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Replaces the rekey operations of a batch by the deletion of the old key
+    /// and the insertion of the moved element at the new key
+    pub(crate) fn resolve_rekey_ops(
+        &self,
+        ops: Vec<GroveDbOp>,
+        transaction: TransactionArg,
+    ) -> CostResult<Vec<GroveDbOp>, Error> {
+        let mut cost = OperationCost::default();
+
+        if !ops.iter().any(|op| matches!(op.op, Op::Rekey { .. })) {
+            return Ok(ops).wrap_with_cost(cost);
+        }
+
+        // the old and new keys of rekeys may appear only once in the batch
+        let op_keys = |op: &GroveDbOp| {
+            let path = op.path.to_path();
+            let mut keys = vec![(path.clone(), op.key.get_key_clone())];
+            if let Op::Rekey { new_key } = &op.op {
+                if new_key.as_slice() != op.key.as_slice() {
+                    keys.push((path, new_key.clone()));
+                }
+            }
+            keys
+        };
+        let rekeyed_keys: BTreeSet<(Vec<Vec<u8>>, Vec<u8>)> = ops
+            .iter()
+            .filter(|op| matches!(op.op, Op::Rekey { .. }))
+            .flat_map(op_keys)
+            .collect();
+        let mut touched_keys = BTreeSet::new();
+        for key in ops.iter().flat_map(op_keys) {
+            if rekeyed_keys.contains(&key) && !touched_keys.insert(key) {
+                return Err(Error::InvalidBatchOperation(
+                    "rekeyed keys can't be touched by other operations of the batch",
+                ))
+                .wrap_with_cost(cost);
+            }
+        }
+
+        let mut resolved_ops = Vec::with_capacity(ops.len() + 1);
+        for op in ops {
+            let new_key = match op.op {
+                Op::Rekey { new_key } => new_key,
+                _ => {
+                    resolved_ops.push(op);
+                    continue;
+                }
+            };
+            if op.key.as_slice() == new_key.as_slice() {
+                continue;
+            }
+            let path = op.path.to_path();
+            let path_slices = path.iter().map(|segment| segment.as_slice());
+
+            let element = cost_return_on_error!(
+                &mut cost,
+                self.get_raw(path_slices.clone(), op.key.as_slice(), transaction)
+            );
+            if element.is_tree() {
+                return Err(Error::NotSupported("only non tree elements can be rekeyed"))
+                    .wrap_with_cost(cost);
+            }
+            let new_key_taken = cost_return_on_error!(
+                &mut cost,
+                self.has_raw(path_slices, new_key.as_slice(), transaction)
+            );
+            if new_key_taken {
+                return Err(Error::InvalidBatchOperation(
+                    "rekey operation new key is already taken",
+                ))
+                .wrap_with_cost(cost);
+            }
+
+            resolved_ops.push(GroveDbOp {
+                path: op.path.clone(),
+                key: op.key,
+                op: Op::Delete,
+            });
+            resolved_ops.push(GroveDbOp::insert_op(path, new_key, element));
+        }
+        Ok(resolved_ops).wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+impl GroveDbOp {
+    /// A rekey op moving the element at the old key to the new key of the
+    /// same subtree, trees can't be rekeyed
+    pub fn rekey_op(path: Vec<Vec<u8>>, old_key: Vec<u8>, new_key: Vec<u8>) -> Self {
+        Self {
+            path: KeyInfoPath::from_known_owned_path(path),
+            key: KnownKey(old_key),
+            op: Op::Rekey { new_key },
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use crate::{
+        batch::GroveDbOp,
+        tests::{make_test_grovedb, TEST_LEAF},
+        Element, Error,
+    };
+
+    #[test]
+    fn test_rekey_op_moves_element_keeping_flags_and_sum() {
+        let db = make_test_grovedb();
+        let ops = vec![
+            GroveDbOp::insert_op(
+                vec![TEST_LEAF.to_vec()],
+                b"sum".to_vec(),
+                Element::new_sum_tree(None),
+            ),
+            GroveDbOp::insert_op(
+                vec![TEST_LEAF.to_vec(), b"sum".to_vec()],
+                b"a".to_vec(),
+                Element::new_sum_item_with_flags(7, Some(vec![1, 2])),
+            ),
+            GroveDbOp::insert_op(
+                vec![TEST_LEAF.to_vec(), b"sum".to_vec()],
+                b"c".to_vec(),
+                Element::new_sum_item(3),
+            ),
+        ];
+        db.apply_batch(ops, None, None)
+            .unwrap()
+            .expect("should apply batch");
+        let root_hash = db.root_hash(None).unwrap().unwrap();
+
+        let rekey = GroveDbOp::rekey_op(
+            vec![TEST_LEAF.to_vec(), b"sum".to_vec()],
+            b"a".to_vec(),
+            b"b".to_vec(),
+        );
+        db.apply_batch(vec![rekey], None, None)
+            .unwrap()
+            .expect("should rekey element");
+
+        assert!(matches!(
+            db.get([TEST_LEAF, b"sum"], b"a", None).unwrap(),
+            Err(Error::PathKeyNotFound(_))
+        ));
+        assert_eq!(
+            db.get([TEST_LEAF, b"sum"], b"b", None)
+                .unwrap()
+                .expect("should get rekeyed element"),
+            Element::new_sum_item_with_flags(7, Some(vec![1, 2]))
+        );
+        assert_eq!(
+            db.get([TEST_LEAF], b"sum", None)
+                .unwrap()
+                .expect("should get sum tree")
+                .sum_value_or_default(),
+            10
+        );
+        assert_ne!(db.root_hash(None).unwrap().unwrap(), root_hash);
+
+        let rekey_onto_taken_key = GroveDbOp::rekey_op(
+            vec![TEST_LEAF.to_vec(), b"sum".to_vec()],
+            b"b".to_vec(),
+            b"c".to_vec(),
+        );
+        assert!(matches!(
+            db.apply_batch(vec![rekey_onto_taken_key], None, None)
+                .unwrap(),
+            Err(Error::InvalidBatchOperation(_))
+        ));
+    }
+
+    #[test]
+    fn test_rekey_keys_cant_be_touched_by_other_ops() {
+        let db = make_test_grovedb();
+        db.insert(
+            [TEST_LEAF],
+            b"a",
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+        let path = vec![TEST_LEAF.to_vec()];
+
+        let batches = [
+            vec![
+                GroveDbOp::replace_op(path.clone(), b"a".to_vec(), Element::new_item(vec![1])),
+                GroveDbOp::rekey_op(path.clone(), b"a".to_vec(), b"b".to_vec()),
+            ],
+            vec![
+                GroveDbOp::rekey_op(path.clone(), b"a".to_vec(), b"b".to_vec()),
+                GroveDbOp::insert_op(path.clone(), b"b".to_vec(), Element::new_item(vec![1])),
+            ],
+            vec![
+                GroveDbOp::rekey_op(path.clone(), b"a".to_vec(), b"b".to_vec()),
+                GroveDbOp::rekey_op(path.clone(), b"b".to_vec(), b"c".to_vec()),
+            ],
+        ];
+        for ops in batches {
+            assert!(matches!(
+                db.apply_batch(ops, None, None).unwrap(),
+                Err(Error::InvalidBatchOperation(_))
+            ));
+        }
+        assert_eq!(
+            db.get([TEST_LEAF], b"a", None)
+                .unwrap()
+                .expect("should keep the element"),
+            Element::new_item(b"value".to_vec())
+        );
+
+        db.apply_batch(
+            vec![
+                GroveDbOp::rekey_op(path.clone(), b"a".to_vec(), b"b".to_vec()),
+                GroveDbOp::insert_op(path, b"c".to_vec(), Element::new_item(vec![1])),
+            ],
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should rekey next to other keys");
+    }
+}
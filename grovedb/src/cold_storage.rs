@@ -0,0 +1,372 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Cold storage
+//! A subtree can be exported to a chunk file, holding its path, root hash and
+//! the chunks a chunk producer gives for it. An archived subtree is hydrated
+//! from a chunk file in memory, without RocksDB, and proves queries on the
+//! subtree, so archival nodes can answer historical proof requests from cheap
+//! object storage. Proofs are checked against the subtree root hash, taken for
+//! instance from the state manifest of the archived state.
+
+#[cfg(feature = "full")]
+use std::io::{Read, Write};
+
+#[cfg(feature = "full")]
+use costs::{
+    cost_return_on_error, cost_return_on_error_no_add, CostResult, CostsExt, OperationCost,
+};
+#[cfg(feature = "full")]
+use integer_encoding::{VarInt, VarIntReader};
+#[cfg(feature = "full")]
+use merk::{
+    proofs::{encode_into, Decoder, Op},
+    ChunkTree,
+};
+#[cfg(any(feature = "full", feature = "verify"))]
+use merk::{verify_query, CryptoHash};
+
+#[cfg(feature = "full")]
+use crate::{util::merk_optional_tx, GroveDb, TransactionArg};
+#[cfg(any(feature = "full", feature = "verify"))]
+use crate::{Element, Error, SizedQuery};
+
+#[cfg(feature = "full")]
+/// Bytes starting every chunk file
+pub const CHUNK_FILE_MAGIC: [u8; 4] = *b"GDCF";
+
+#[cfg(feature = "full")]
+/// Version of the chunk file format
+pub const CHUNK_FILE_VERSION: u8 = 1;
+
+#[cfg(feature = "full")]
+fn write_error(error: std::io::Error) -> Error {
+    Error::CorruptedData(format!("unable to write chunk file: {}", error))
+}
+
+#[cfg(feature = "full")]
+fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> Result<(), Error> {
+    writer
+        .write_all(&bytes.len().encode_var_vec())
+        .and_then(|_| writer.write_all(bytes))
+        .map_err(write_error)
+}
+
+#[cfg(feature = "full")]
+fn read_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>, Error> {
+    let length: u64 = reader
+        .read_varint()
+        .map_err(|_| Error::CorruptedData("chunk file is truncated".to_owned()))?;
+    let mut bytes = Vec::new();
+    reader
+        .by_ref()
+        .take(length)
+        .read_to_end(&mut bytes)
+        .map_err(|_| Error::CorruptedData("chunk file is truncated".to_owned()))?;
+    if bytes.len() as u64 != length {
+        return Err(Error::CorruptedData("chunk file is truncated".to_owned()));
+    }
+    Ok(bytes)
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Writes the chunk file of the subtree at the path and returns the root
+    /// hash of the subtree
+    pub fn export_subtree_chunks<'p, P, W>(
+        &self,
+        path: P,
+        writer: &mut W,
+        transaction: TransactionArg,
+    ) -> CostResult<CryptoHash, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+        W: Write,
+    {
+        let mut cost = OperationCost::default();
+
+        let path: Vec<&[u8]> = path.into_iter().collect();
+        cost_return_on_error!(
+            &mut cost,
+            self.check_subtree_exists_path_not_found(path.iter().copied(), transaction)
+        );
+        let mut path_iter = path.iter().copied().peekable();
+        let (root_hash, chunks) =
+            merk_optional_tx!(&mut cost, self.db, path_iter, transaction, subtree, {
+                let root_hash = subtree.root_hash().unwrap_add_cost(&mut cost);
+                let chunks = if subtree.root_key().is_some() {
+                    let chunk_producer = cost_return_on_error_no_add!(
+                        &cost,
+                        subtree
+                            .chunks()
+                            .map_err(|e| Error::CorruptedData(e.to_string()))
+                    );
+                    cost_return_on_error_no_add!(
+                        &cost,
+                        chunk_producer
+                            .into_iter()
+                            .collect::<Result<Vec<Vec<Op>>, _>>()
+                            .map_err(|e| Error::CorruptedData(e.to_string()))
+                    )
+                } else {
+                    Vec::new()
+                };
+                (root_hash, chunks)
+            });
+
+        cost_return_on_error_no_add!(
+            &cost,
+            writer
+                .write_all(&CHUNK_FILE_MAGIC)
+                .and_then(|_| writer.write_all(&[CHUNK_FILE_VERSION]))
+                .and_then(|_| writer.write_all(&root_hash))
+                .and_then(|_| writer.write_all(&path.len().encode_var_vec()))
+                .map_err(write_error)
+        );
+        for segment in path {
+            cost_return_on_error_no_add!(&cost, write_bytes(writer, segment));
+        }
+        cost_return_on_error_no_add!(
+            &cost,
+            writer
+                .write_all(&chunks.len().encode_var_vec())
+                .map_err(write_error)
+        );
+        for chunk in chunks {
+            let mut bytes = Vec::new();
+            encode_into(chunk.iter(), &mut bytes);
+            cost_return_on_error_no_add!(&cost, write_bytes(writer, &bytes));
+        }
+        Ok(root_hash).wrap_with_cost(cost)
+    }
+
+    /// Checks a proof of an archived subtree against the subtree root hash
+    /// and returns the proved keys with their elements. Subqueries aren't
+    /// followed, only the subtree itself is proved.
+    pub fn verify_archived_subtree_query(
+        proof: &[u8],
+        query: &SizedQuery,
+        subtree_root_hash: CryptoHash,
+    ) -> Result<Vec<(Vec<u8>, Element)>, Error> {
+        verify_archived_subtree_query(proof, query, subtree_root_hash)
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Checks a proof of an archived subtree against the subtree root hash and
+/// returns the proved keys with their elements
+pub fn verify_archived_subtree_query(
+    proof: &[u8],
+    query: &SizedQuery,
+    subtree_root_hash: CryptoHash,
+) -> Result<Vec<(Vec<u8>, Element)>, Error> {
+    let result = verify_query(
+        proof,
+        &query.query,
+        query.limit,
+        query.offset,
+        query.query.left_to_right,
+        subtree_root_hash,
+    )
+    .unwrap()
+    .map_err(Error::MerkError)?;
+    result
+        .result_set
+        .into_iter()
+        .map(|proved| Element::deserialize(&proved.value).map(|element| (proved.key, element)))
+        .collect()
+}
+
+#[cfg(feature = "full")]
+/// A subtree hydrated in memory from its chunk file
+pub struct ArchivedSubtree {
+    path: Vec<Vec<u8>>,
+    tree: ChunkTree,
+}
+
+#[cfg(feature = "full")]
+impl ArchivedSubtree {
+    /// Reads a chunk file, verifying every chunk against the root hash it
+    /// declares. Whether that root hash is the expected one is up to the
+    /// caller, see `root_hash`.
+    pub fn read<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let mut header = [0; 5];
+        reader
+            .read_exact(&mut header)
+            .map_err(|_| Error::CorruptedData("chunk file is truncated".to_owned()))?;
+        if header[..4] != CHUNK_FILE_MAGIC {
+            return Err(Error::CorruptedData("not a chunk file".to_owned()));
+        }
+        if header[4] != CHUNK_FILE_VERSION {
+            return Err(Error::CorruptedData(format!(
+                "unsupported chunk file version {}",
+                header[4]
+            )));
+        }
+        let mut root_hash = [0; 32];
+        reader
+            .read_exact(&mut root_hash)
+            .map_err(|_| Error::CorruptedData("chunk file is truncated".to_owned()))?;
+
+        let path_length: usize = reader
+            .read_varint()
+            .map_err(|_| Error::CorruptedData("chunk file is truncated".to_owned()))?;
+        let path = (0..path_length)
+            .map(|_| read_bytes(&mut reader))
+            .collect::<Result<Vec<Vec<u8>>, Error>>()?;
+
+        let chunk_count: usize = reader
+            .read_varint()
+            .map_err(|_| Error::CorruptedData("chunk file is truncated".to_owned()))?;
+        let chunks = (0..chunk_count)
+            .map(|_| {
+                let bytes = read_bytes(&mut reader)?;
+                Decoder::new(&bytes)
+                    .collect::<Result<Vec<Op>, _>>()
+                    .map_err(Error::MerkError)
+            })
+            .collect::<Result<Vec<Vec<Op>>, Error>>()?;
+        let tree = ChunkTree::from_chunks(chunks, root_hash).map_err(Error::MerkError)?;
+
+        Ok(ArchivedSubtree { path, tree })
+    }
+
+    /// Path of the subtree
+    pub fn path(&self) -> &[Vec<u8>] {
+        &self.path
+    }
+
+    /// Root hash of the subtree
+    pub fn root_hash(&self) -> CryptoHash {
+        self.tree.root_hash()
+    }
+
+    /// Element stored under the key in the subtree
+    pub fn get(&self, key: &[u8]) -> Result<Option<Element>, Error> {
+        self.tree
+            .get(key)
+            .map_err(Error::MerkError)?
+            .map(|bytes| Element::deserialize(&bytes))
+            .transpose()
+    }
+
+    /// Proves the query on the subtree, subqueries aren't followed. The proof
+    /// is checked with `verify_archived_subtree_query`.
+    pub fn prove_query(&self, query: &SizedQuery) -> Result<Vec<u8>, Error> {
+        self.tree
+            .prove(query.query.clone(), query.limit, query.offset)
+            .unwrap()
+            .map(|result| result.proof)
+            .map_err(Error::MerkError)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        batch::GroveDbOp,
+        tests::{make_test_grovedb, TEST_LEAF},
+        Query,
+    };
+
+    #[test]
+    fn test_prove_from_chunk_file() {
+        let db = make_test_grovedb();
+        db.insert([TEST_LEAF], b"archive", Element::empty_tree(), None, None)
+            .unwrap()
+            .expect("should insert subtree");
+        // enough items for the subtree to be split into several chunks
+        let ops = (0u32..2000)
+            .map(|i| {
+                GroveDbOp::insert_op(
+                    vec![TEST_LEAF.to_vec(), b"archive".to_vec()],
+                    i.to_be_bytes().to_vec(),
+                    Element::new_item(i.to_le_bytes().to_vec()),
+                )
+            })
+            .collect();
+        db.apply_batch(ops, None, None)
+            .unwrap()
+            .expect("should insert items");
+
+        let mut file = Vec::new();
+        let root_hash = db
+            .export_subtree_chunks([TEST_LEAF, b"archive"], &mut file, None)
+            .unwrap()
+            .expect("should export chunks");
+        assert_eq!(
+            root_hash,
+            db.subtree_root_hash([TEST_LEAF, b"archive"], None)
+                .unwrap()
+                .expect("should get subtree root hash")
+        );
+
+        // the database moves on, the archive keeps the exported state
+        db.delete([TEST_LEAF, b"archive"], &7u32.to_be_bytes(), None, None)
+            .unwrap()
+            .expect("should delete item");
+
+        let archived = ArchivedSubtree::read(file.as_slice()).expect("should read chunk file");
+        assert_eq!(archived.root_hash(), root_hash);
+        assert_eq!(archived.path(), &[TEST_LEAF.to_vec(), b"archive".to_vec()]);
+        assert_eq!(
+            archived
+                .get(&7u32.to_be_bytes())
+                .expect("should get element"),
+            Some(Element::new_item(7u32.to_le_bytes().to_vec()))
+        );
+
+        let mut query = Query::new();
+        query.insert_range(5u32.to_be_bytes().to_vec()..9u32.to_be_bytes().to_vec());
+        let query = SizedQuery::new(query, Some(3), None);
+        let proof = archived.prove_query(&query).expect("should prove");
+        let proved = GroveDb::verify_archived_subtree_query(&proof, &query, root_hash)
+            .expect("proof should verify");
+        assert_eq!(
+            proved,
+            (5u32..8)
+                .map(|i| (
+                    i.to_be_bytes().to_vec(),
+                    Element::new_item(i.to_le_bytes().to_vec())
+                ))
+                .collect::<Vec<_>>()
+        );
+
+        let current_root_hash = db
+            .subtree_root_hash([TEST_LEAF, b"archive"], None)
+            .unwrap()
+            .expect("should get subtree root hash");
+        assert!(GroveDb::verify_archived_subtree_query(&proof, &query, current_root_hash).is_err());
+
+        file[10] ^= 1;
+        assert!(ArchivedSubtree::read(file.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,293 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Crash recovery
+//! Harness exercising the atomicity of batches across crashes. Each cycle
+//! applies a few random batches to a database on disk, crashes its storage at
+//! a random write batch boundary with the storage fault injector, reopens it
+//! and checks that the crashed batch was either fully applied or not at all,
+//! and that every subtree still hashes into its parent.
+
+use std::collections::BTreeSet;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use storage::rocksdb_storage::{CrashPoint, FaultInjector};
+use tempfile::TempDir;
+
+use crate::{batch::GroveDbOp, Element, Error, GroveDb, Hash};
+
+/// Number of root tree leaves the random batches write under
+const ROOT_LEAVES: usize = 3;
+/// Maximum number of batches applied in a cycle, the last one crashes
+const MAX_BATCHES_PER_CYCLE: usize = 4;
+/// Maximum number of operations of a random batch
+const MAX_OPS_PER_BATCH: usize = 32;
+/// Number of distinct item keys in a subtree, kept small for batches to
+/// replace and delete existing items
+const ITEM_KEYS: u8 = 64;
+
+impl GroveDb {
+    /// Sets an injector crashing the storage at a chosen write batch, `None`
+    /// removes it. The injector is shared by every handle of the database.
+    pub fn set_fault_injector(&self, injector: Option<FaultInjector>) {
+        self.db.set_fault_injector(injector);
+    }
+}
+
+/// Outcome of a crash and recovery cycle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrashCycle {
+    /// Batches applied during the cycle, including the crashed one if it made
+    /// it to storage
+    pub batches_applied: usize,
+    /// Point of the write batch the storage crashed at
+    pub crash_point: CrashPoint,
+    /// Whether the crashed batch was applied in a transaction
+    pub transactional: bool,
+    /// Whether the crashed batch was found applied after recovery
+    pub crashed_batch_applied: bool,
+}
+
+/// Random batch with its effects on the harness model
+struct RandomBatch {
+    ops: Vec<GroveDbOp>,
+    inserted_items: Vec<(usize, Vec<u8>)>,
+    deleted_items: Vec<(usize, Vec<u8>)>,
+    new_subtrees: Vec<Vec<Vec<u8>>>,
+}
+
+/// Crash and recovery harness over a temporary database
+pub struct CrashRecoveryHarness {
+    dir: TempDir,
+    rng: StdRng,
+    /// Paths of the subtrees batches write to
+    subtrees: Vec<Vec<Vec<u8>>>,
+    /// Items present in the database, by subtree index and key
+    items: BTreeSet<(usize, Vec<u8>)>,
+}
+
+impl CrashRecoveryHarness {
+    /// Create a harness over a fresh database, random batches are derived
+    /// from the seed so failing runs can be replayed
+    pub fn new(seed: u64) -> Result<Self, Error> {
+        let dir = TempDir::new()
+            .map_err(|_| Error::InternalError("cannot create temporary directory"))?;
+        let db = GroveDb::open(dir.path())?;
+        let subtrees: Vec<Vec<Vec<u8>>> = (0..ROOT_LEAVES)
+            .map(|leaf| vec![format!("leaf_{}", leaf).into_bytes()])
+            .collect();
+        let ops = subtrees
+            .iter()
+            .map(|path| GroveDbOp::insert_op(vec![], path[0].clone(), Element::empty_tree()))
+            .collect();
+        db.apply_batch(ops, None, None).unwrap()?;
+
+        Ok(CrashRecoveryHarness {
+            dir,
+            rng: StdRng::seed_from_u64(seed),
+            subtrees,
+            items: BTreeSet::new(),
+        })
+    }
+
+    /// Runs the given number of crash and recovery cycles, stopping at the
+    /// first one breaking atomicity
+    pub fn run(&mut self, cycles: usize) -> Result<Vec<CrashCycle>, Error> {
+        (0..cycles).map(|_| self.run_cycle()).collect()
+    }
+
+    /// Opens the database, applies random batches until the storage crashes,
+    /// then reopens it and checks the crashed batch was applied atomically
+    pub fn run_cycle(&mut self) -> Result<CrashCycle, Error> {
+        let db = GroveDb::open(self.dir.path())?;
+        let injector = FaultInjector::new();
+        let batches = self.rng.gen_range(1..=MAX_BATCHES_PER_CYCLE);
+        let crash_point = if self.rng.gen() {
+            CrashPoint::BeforeWrite
+        } else {
+            CrashPoint::AfterWrite
+        };
+        injector.crash_at(batches as u64 - 1, crash_point);
+        db.set_fault_injector(Some(injector.clone()));
+
+        for batch_index in 0..batches {
+            let batch = self.random_batch();
+            let root_hash_before = db.root_hash(None).unwrap()?;
+            let root_hash_after = Self::root_hash_after(&db, batch.ops.clone())?;
+            let transactional = self.rng.gen();
+            let result = if transactional {
+                let transaction = db.start_transaction();
+                db.apply_batch(batch.ops.clone(), None, Some(&transaction))
+                    .unwrap()
+                    .and_then(|_| db.commit_transaction(transaction).unwrap())
+            } else {
+                db.apply_batch(batch.ops.clone(), None, None).unwrap()
+            };
+
+            match result {
+                Ok(_) => self.record(batch),
+                Err(_) if injector.crashed() => {
+                    drop(db);
+                    let db = GroveDb::open(self.dir.path())?;
+                    let root_hash = db.root_hash(None).unwrap()?;
+                    let crashed_batch_applied = if root_hash == root_hash_after {
+                        true
+                    } else if root_hash == root_hash_before {
+                        false
+                    } else {
+                        return Err(Error::CorruptedData(format!(
+                            "batch {} was partially applied after crashing {:?}",
+                            batch_index, crash_point
+                        )));
+                    };
+                    let issues = db.verify_grovedb();
+                    if !issues.is_empty() {
+                        return Err(Error::CorruptedData(format!(
+                            "{} subtrees don't hash into their parent after recovery",
+                            issues.len()
+                        )));
+                    }
+                    if crashed_batch_applied {
+                        self.record(batch);
+                    }
+                    return Ok(CrashCycle {
+                        batches_applied: batch_index + usize::from(crashed_batch_applied),
+                        crash_point,
+                        transactional,
+                        crashed_batch_applied,
+                    });
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(Error::InternalError(
+            "storage didn't crash at the last batch of the cycle",
+        ))
+    }
+
+    /// Root hash the database would have after applying the operations
+    fn root_hash_after(db: &GroveDb, ops: Vec<GroveDbOp>) -> Result<Hash, Error> {
+        let transaction = db.start_transaction();
+        db.apply_batch(ops, None, Some(&transaction)).unwrap()?;
+        db.root_hash(Some(&transaction)).unwrap()
+    }
+
+    /// Random batch of item insertions, replacements and deletions, and of
+    /// new subtrees, touching every key at most once
+    fn random_batch(&mut self) -> RandomBatch {
+        let mut batch = RandomBatch {
+            ops: vec![],
+            inserted_items: vec![],
+            deleted_items: vec![],
+            new_subtrees: vec![],
+        };
+        let mut touched = BTreeSet::new();
+        for _ in 0..self.rng.gen_range(1..=MAX_OPS_PER_BATCH) {
+            let subtree = self.rng.gen_range(0..self.subtrees.len());
+            let path = self.subtrees[subtree].clone();
+            if self.rng.gen_ratio(1, 16) {
+                let key =
+                    format!("tree_{}", self.subtrees.len() + batch.new_subtrees.len()).into_bytes();
+                let mut subtree_path = path.clone();
+                subtree_path.push(key.clone());
+                batch
+                    .ops
+                    .push(GroveDbOp::insert_op(path, key, Element::empty_tree()));
+                batch.new_subtrees.push(subtree_path);
+                continue;
+            }
+            let key = format!("item_{}", self.rng.gen_range(0..ITEM_KEYS)).into_bytes();
+            if !touched.insert((subtree, key.clone())) {
+                continue;
+            }
+            if self.items.contains(&(subtree, key.clone())) && self.rng.gen_ratio(1, 3) {
+                batch.ops.push(GroveDbOp::delete_op(path, key.clone()));
+                batch.deleted_items.push((subtree, key));
+            } else {
+                let value = self.rng.gen::<[u8; 8]>().to_vec();
+                batch.ops.push(GroveDbOp::insert_op(
+                    path,
+                    key.clone(),
+                    Element::new_item(value),
+                ));
+                batch.inserted_items.push((subtree, key));
+            }
+        }
+        batch
+    }
+
+    /// Updates the model with the effects of an applied batch
+    fn record(&mut self, batch: RandomBatch) {
+        self.items.extend(batch.inserted_items);
+        for item in batch.deleted_items {
+            self.items.remove(&item);
+        }
+        self.subtrees.extend(batch.new_subtrees);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crash_recovery_cycles() {
+        let mut harness = CrashRecoveryHarness::new(7).expect("should create harness");
+        let cycles = harness
+            .run(32)
+            .expect("batches should be applied atomically");
+        assert!(cycles.iter().any(|cycle| cycle.crashed_batch_applied));
+        assert!(cycles.iter().any(|cycle| !cycle.crashed_batch_applied));
+    }
+
+    #[test]
+    fn test_writes_outside_transactions_crash() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = GroveDb::open(tmp_dir.path()).expect("should open");
+        db.insert([], b"leaf", Element::empty_tree(), None, None)
+            .unwrap()
+            .expect("should insert root leaf");
+
+        let injector = FaultInjector::new();
+        injector.crash_at(0, CrashPoint::BeforeWrite);
+        db.set_fault_injector(Some(injector.clone()));
+        assert!(db
+            .insert(
+                [b"leaf".as_slice()],
+                b"key",
+                Element::new_item(b"value".to_vec()),
+                None,
+                None,
+            )
+            .unwrap()
+            .is_err());
+        assert!(injector.crashed());
+        assert_eq!(injector.writes(), 0);
+    }
+}
@@ -33,7 +33,7 @@
 use crate::{
     element::{MaxReferenceHop, SumValue},
     reference_path::ReferencePathType,
-    Element, ElementFlags,
+    Element, ElementFlags, KeyOrdering,
 };
 
 impl Element {
@@ -50,6 +50,21 @@ impl Element {
         Element::new_tree_with_flags(Default::default(), flags)
     }
 
+    #[cfg(feature = "full")]
+    /// Set element to default empty tree with key ordering and without flags
+    pub fn empty_ordered_tree(key_ordering: KeyOrdering) -> Self {
+        Element::new_ordered_tree(Default::default(), key_ordering)
+    }
+
+    #[cfg(feature = "full")]
+    /// Set element to default empty tree with key ordering and flags
+    pub fn empty_ordered_tree_with_flags(
+        key_ordering: KeyOrdering,
+        flags: Option<ElementFlags>,
+    ) -> Self {
+        Element::new_ordered_tree_with_flags(Default::default(), key_ordering, flags)
+    }
+
     #[cfg(feature = "full")]
     /// Set element to default empty sum tree without flags
     pub fn empty_sum_tree() -> Self {
@@ -135,6 +150,22 @@ impl Element {
         Element::Tree(maybe_root_key, flags)
     }
 
+    #[cfg(feature = "full")]
+    /// Set element to a tree with key ordering and without flags
+    pub fn new_ordered_tree(maybe_root_key: Option<Vec<u8>>, key_ordering: KeyOrdering) -> Self {
+        Element::OrderedTree(maybe_root_key, key_ordering, None)
+    }
+
+    #[cfg(feature = "full")]
+    /// Set element to a tree with key ordering and flags
+    pub fn new_ordered_tree_with_flags(
+        maybe_root_key: Option<Vec<u8>>,
+        key_ordering: KeyOrdering,
+        flags: Option<ElementFlags>,
+    ) -> Self {
+        Element::OrderedTree(maybe_root_key, key_ordering, flags)
+    }
+
     #[cfg(feature = "full")]
     /// Set element to a sum tree without flags
     pub fn new_sum_tree(maybe_root_key: Option<Vec<u8>>) -> Self {
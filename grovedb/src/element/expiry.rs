@@ -0,0 +1,131 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Element expiry
+//! An element can carry an expiry epoch in its flags. Such flags start with a
+//! reserved marker followed by the big endian expiry epoch, the rest of the
+//! flags being left to the user. Since the expiry is part of the element it is
+//! covered by the proofs, so verifiers can check on their own whether a proved
+//! element is expired.
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use crate::{element::ElementFlags, Element};
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Epoch as understood by the element expiry
+pub type Epoch = u64;
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Marker opening the flags of an expiring element
+pub const EXPIRY_FLAGS_MARKER: [u8; 4] = *b"\xffexp";
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Length of the expiry prefix of the flags: marker and epoch
+const EXPIRY_PREFIX_LEN: usize = EXPIRY_FLAGS_MARKER.len() + 8;
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Builds flags expiring at the given epoch, followed by the user flags
+pub fn expiring_flags(expiry_epoch: Epoch, user_flags: Option<ElementFlags>) -> ElementFlags {
+    let user_flags = user_flags.unwrap_or_default();
+    let mut flags = Vec::with_capacity(EXPIRY_PREFIX_LEN + user_flags.len());
+    flags.extend_from_slice(&EXPIRY_FLAGS_MARKER);
+    flags.extend_from_slice(&expiry_epoch.to_be_bytes());
+    flags.extend(user_flags);
+    flags
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Splits flags into their expiry epoch and the user flags
+pub fn split_expiring_flags(flags: &[u8]) -> (Option<Epoch>, &[u8]) {
+    if flags.len() < EXPIRY_PREFIX_LEN || !flags.starts_with(&EXPIRY_FLAGS_MARKER) {
+        return (None, flags);
+    }
+    let mut epoch_bytes = [0u8; 8];
+    epoch_bytes.copy_from_slice(&flags[EXPIRY_FLAGS_MARKER.len()..EXPIRY_PREFIX_LEN]);
+    (
+        Some(Epoch::from_be_bytes(epoch_bytes)),
+        &flags[EXPIRY_PREFIX_LEN..],
+    )
+}
+
+impl Element {
+    #[cfg(any(feature = "full", feature = "verify"))]
+    /// Set element to an item expiring at the given epoch
+    pub fn new_item_with_expiry(
+        item_value: Vec<u8>,
+        expiry_epoch: Epoch,
+        flags: Option<ElementFlags>,
+    ) -> Self {
+        Element::Item(item_value, Some(expiring_flags(expiry_epoch, flags)))
+    }
+
+    #[cfg(any(feature = "full", feature = "verify"))]
+    /// Returns the expiry epoch stored in the element flags
+    pub fn expiry_epoch(&self) -> Option<Epoch> {
+        self.get_flags()
+            .as_ref()
+            .and_then(|flags| split_expiring_flags(flags).0)
+    }
+
+    #[cfg(any(feature = "full", feature = "verify"))]
+    /// Returns true if the element expires at or before the current epoch,
+    /// trees never expire
+    pub fn is_expired(&self, current_epoch: Epoch) -> bool {
+        !self.is_tree()
+            && self
+                .expiry_epoch()
+                .map(|expiry_epoch| expiry_epoch <= current_epoch)
+                .unwrap_or(false)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expiring_flags_round_trip() {
+        let flags = expiring_flags(42, Some(vec![1, 2, 3]));
+        assert_eq!(
+            split_expiring_flags(&flags),
+            (Some(42), [1, 2, 3].as_slice())
+        );
+        assert_eq!(
+            split_expiring_flags(&[1, 2, 3]),
+            (None, [1, 2, 3].as_slice())
+        );
+
+        let element = Element::new_item_with_expiry(b"value".to_vec(), 42, None);
+        assert_eq!(element.expiry_epoch(), Some(42));
+        assert!(!element.is_expired(41));
+        assert!(element.is_expired(42));
+        assert!(!Element::new_item(b"value".to_vec()).is_expired(u64::MAX));
+        assert!(!Element::Tree(None, Some(expiring_flags(1, None))).is_expired(2));
+    }
+}
@@ -42,9 +42,9 @@ use merk::{ed::Decode, tree::TreeInner};
 #[cfg(feature = "full")]
 use storage::StorageContext;
 
-use crate::element::{SUM_ITEM_COST_SIZE, SUM_TREE_COST_SIZE, TREE_COST_SIZE};
+use crate::element::{sum_item_cost_size, sum_tree_cost_size, tree_cost_size};
 #[cfg(feature = "full")]
-use crate::{Element, Error, Hash};
+use crate::{Element, Error, Hash, SubtreePath};
 
 impl Element {
     #[cfg(feature = "full")]
@@ -162,7 +162,7 @@ impl Element {
                 )
             }
             Some(Element::SumItem(_, flags)) => {
-                let cost_size = SUM_ITEM_COST_SIZE;
+                let cost_size = sum_item_cost_size();
                 let flags_len = flags.as_ref().map_or(0, |flags| {
                     let flags_len = flags.len() as u32;
                     flags_len + flags_len.required_space() as u32
@@ -175,11 +175,15 @@ impl Element {
                         false,
                     )
             }
-            Some(Element::Tree(_, flags)) | Some(Element::SumTree(_, _, flags)) => {
-                let tree_cost_size = if element.as_ref().unwrap().is_sum_tree() {
-                    SUM_TREE_COST_SIZE
-                } else {
-                    TREE_COST_SIZE
+            Some(Element::Tree(_, flags))
+            | Some(Element::SumTree(_, _, flags))
+            | Some(Element::OrderedTree(_, _, flags)) => {
+                let tree_cost_size = match element.as_ref().unwrap() {
+                    Element::SumTree(..) => sum_tree_cost_size(),
+                    Element::OrderedTree(_, key_ordering, _) => {
+                        tree_cost_size() + key_ordering.serialized_size()
+                    }
+                    _ => tree_cost_size(),
                 };
                 let flags_len = flags.as_ref().map_or(0, |flags| {
                     let flags_len = flags.len() as u32;
@@ -203,7 +207,7 @@ impl Element {
     /// Merk should be loaded by this moment
     pub fn get_with_absolute_refs<'db, K: AsRef<[u8]>, S: StorageContext<'db>>(
         merk: &Merk<S>,
-        path: &[&[u8]],
+        path: SubtreePath<'_>,
         key: K,
         allow_cache: bool,
     ) -> CostResult<Element, Error> {
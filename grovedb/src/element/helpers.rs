@@ -39,12 +39,15 @@ use merk::{
 };
 
 #[cfg(any(feature = "full", feature = "verify"))]
-use crate::{element::SUM_ITEM_COST_SIZE, Element, Error};
+use crate::{
+    element::{sum_item_cost_size, ElementFlags},
+    Element, Error, KeyOrdering,
+};
 #[cfg(feature = "full")]
 use crate::{
-    element::{SUM_TREE_COST_SIZE, TREE_COST_SIZE},
+    element::{sum_tree_cost_size, tree_cost_size},
     reference_path::{path_from_reference_path_type, ReferencePathType},
-    ElementFlags,
+    SubtreePath,
 };
 
 impl Element {
@@ -95,7 +98,20 @@ impl Element {
     #[cfg(any(feature = "full", feature = "verify"))]
     /// Check if the element is a tree
     pub fn is_tree(&self) -> bool {
-        matches!(self, Element::SumTree(..) | Element::Tree(..))
+        matches!(
+            self,
+            Element::SumTree(..) | Element::Tree(..) | Element::OrderedTree(..)
+        )
+    }
+
+    #[cfg(any(feature = "full", feature = "verify"))]
+    /// Gives the key ordering of a tree, trees which don't declare one are
+    /// ordered lexicographically
+    pub fn key_ordering(&self) -> KeyOrdering {
+        match self {
+            Element::OrderedTree(_, key_ordering, _) => *key_ordering,
+            _ => KeyOrdering::Lexicographic,
+        }
     }
 
     #[cfg(any(feature = "full", feature = "verify"))]
@@ -119,7 +135,7 @@ impl Element {
         }
     }
 
-    #[cfg(feature = "full")]
+    #[cfg(any(feature = "full", feature = "verify"))]
     /// Grab the optional flag stored in an element
     pub fn get_flags(&self) -> &Option<ElementFlags> {
         match self {
@@ -127,7 +143,8 @@ impl Element {
             | Element::Item(_, flags)
             | Element::Reference(_, _, flags)
             | Element::SumTree(.., flags)
-            | Element::SumItem(_, flags) => flags,
+            | Element::SumItem(_, flags)
+            | Element::OrderedTree(.., flags) => flags,
         }
     }
 
@@ -139,7 +156,8 @@ impl Element {
             | Element::Item(_, flags)
             | Element::Reference(_, _, flags)
             | Element::SumTree(.., flags)
-            | Element::SumItem(_, flags) => flags,
+            | Element::SumItem(_, flags)
+            | Element::OrderedTree(.., flags) => flags,
         }
     }
 
@@ -151,7 +169,8 @@ impl Element {
             | Element::Item(_, flags)
             | Element::Reference(_, _, flags)
             | Element::SumTree(.., flags)
-            | Element::SumItem(_, flags) => flags,
+            | Element::SumItem(_, flags)
+            | Element::OrderedTree(.., flags) => flags,
         }
     }
 
@@ -197,6 +216,14 @@ impl Element {
                     32 + 8
                 }
             }
+            Element::OrderedTree(_, key_ordering, element_flag) => {
+                let ordering_length = key_ordering.serialized_size();
+                if let Some(flag) = element_flag {
+                    flag.len() as u32 + 32 + ordering_length
+                } else {
+                    32 + ordering_length
+                }
+            }
         }
     }
 
@@ -210,7 +237,7 @@ impl Element {
     /// Convert the reference to an absolute reference
     pub(crate) fn convert_if_reference_to_absolute_reference(
         self,
-        path: &[&[u8]],
+        path: SubtreePath<'_>,
         key: Option<&[u8]>,
     ) -> Result<Element, Error> {
         // Convert any non absolute reference type to an absolute one
@@ -223,12 +250,8 @@ impl Element {
                 _ => {
                     // Element is a reference and is not absolute.
                     // build the stored path for this reference
-                    let current_path = <&[&[u8]]>::clone(&path).to_vec();
-                    let absolute_path = path_from_reference_path_type(
-                        reference_path_type.clone(),
-                        current_path,
-                        key,
-                    )?;
+                    let absolute_path =
+                        path_from_reference_path_type(reference_path_type.clone(), path, key)?;
                     // return an absolute reference that contains this info
                     Element::Reference(
                         ReferencePathType::AbsolutePathReference(absolute_path),
@@ -256,7 +279,20 @@ impl Element {
                     let flags_len = flags.len() as u32;
                     flags_len + flags_len.required_space() as u32
                 });
-                let value_len = TREE_COST_SIZE + flags_len;
+                let value_len = tree_cost_size() + flags_len;
+                let key_len = key.len() as u32;
+                KV::layered_value_byte_cost_size_for_key_and_value_lengths(
+                    key_len,
+                    value_len,
+                    is_sum_node,
+                )
+            }
+            Element::OrderedTree(_, key_ordering, flags) => {
+                let flags_len = flags.map_or(0, |flags| {
+                    let flags_len = flags.len() as u32;
+                    flags_len + flags_len.required_space() as u32
+                });
+                let value_len = tree_cost_size() + key_ordering.serialized_size() + flags_len;
                 let key_len = key.len() as u32;
                 KV::layered_value_byte_cost_size_for_key_and_value_lengths(
                     key_len,
@@ -269,7 +305,7 @@ impl Element {
                     let flags_len = flags.len() as u32;
                     flags_len + flags_len.required_space() as u32
                 });
-                let value_len = SUM_TREE_COST_SIZE + flags_len;
+                let value_len = sum_tree_cost_size() + flags_len;
                 let key_len = key.len() as u32;
                 KV::layered_value_byte_cost_size_for_key_and_value_lengths(
                     key_len,
@@ -282,7 +318,7 @@ impl Element {
                     let flags_len = flags.len() as u32;
                     flags_len + flags_len.required_space() as u32
                 });
-                let value_len = SUM_ITEM_COST_SIZE + flags_len;
+                let value_len = sum_item_cost_size() + flags_len;
                 let key_len = key.len() as u32;
                 KV::specialized_value_byte_cost_size_for_key_and_value_lengths(
                     key_len,
@@ -303,9 +339,12 @@ impl Element {
     /// Get tree cost for the element
     pub fn get_specialized_cost(&self) -> Result<u32, Error> {
         match self {
-            Element::Tree(..) => Ok(TREE_COST_SIZE),
-            Element::SumTree(..) => Ok(SUM_TREE_COST_SIZE),
-            Element::SumItem(..) => Ok(SUM_ITEM_COST_SIZE),
+            Element::Tree(..) => Ok(tree_cost_size()),
+            Element::OrderedTree(_, key_ordering, _) => {
+                Ok(tree_cost_size() + key_ordering.serialized_size())
+            }
+            Element::SumTree(..) => Ok(sum_tree_cost_size()),
+            Element::SumItem(..) => Ok(sum_item_cost_size()),
             _ => Err(Error::CorruptedCodeExecution(
                 "trying to get tree cost from non tree element",
             )),
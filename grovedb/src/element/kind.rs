@@ -0,0 +1,158 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Element kinds
+//! Every element variant has a numeric discriminant, equal to the tag byte
+//! starting its serialization. Discriminants are part of the public API and
+//! never change, new kinds get new values, so FFI layers, flags processors
+//! and wire encodings can name element types without decoding elements.
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use crate::{Element, Error};
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Kind of an element, with its stable discriminant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub enum ElementKind {
+    /// An item
+    Item = 0,
+    /// A reference
+    Reference = 1,
+    /// A tree
+    Tree = 2,
+    /// A sum item
+    SumItem = 3,
+    /// A sum tree
+    SumTree = 4,
+    /// An ordered tree
+    OrderedTree = 5,
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl ElementKind {
+    /// Every kind, in discriminant order
+    pub const ALL: [ElementKind; 6] = [
+        ElementKind::Item,
+        ElementKind::Reference,
+        ElementKind::Tree,
+        ElementKind::SumItem,
+        ElementKind::SumTree,
+        ElementKind::OrderedTree,
+    ];
+
+    /// Stable discriminant of the kind
+    pub const fn discriminant(self) -> u8 {
+        self as u8
+    }
+
+    /// Name of the kind, as used in messages
+    pub const fn name(self) -> &'static str {
+        match self {
+            ElementKind::Item => "item",
+            ElementKind::Reference => "reference",
+            ElementKind::Tree => "tree",
+            ElementKind::SumItem => "sum item",
+            ElementKind::SumTree => "sum tree",
+            ElementKind::OrderedTree => "ordered tree",
+        }
+    }
+
+    /// Returns true if elements of the kind hold a subtree
+    pub const fn is_tree(self) -> bool {
+        matches!(
+            self,
+            ElementKind::Tree | ElementKind::SumTree | ElementKind::OrderedTree
+        )
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl From<ElementKind> for u8 {
+    fn from(kind: ElementKind) -> Self {
+        kind.discriminant()
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl TryFrom<u8> for ElementKind {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        ElementKind::ALL
+            .get(value as usize)
+            .copied()
+            .ok_or(Error::InvalidInput("unknown element kind discriminant"))
+    }
+}
+
+impl Element {
+    #[cfg(any(feature = "full", feature = "verify"))]
+    /// Kind of the element
+    pub fn kind(&self) -> ElementKind {
+        match self {
+            Element::Item(..) => ElementKind::Item,
+            Element::Reference(..) => ElementKind::Reference,
+            Element::Tree(..) => ElementKind::Tree,
+            Element::SumItem(..) => ElementKind::SumItem,
+            Element::SumTree(..) => ElementKind::SumTree,
+            Element::OrderedTree(..) => ElementKind::OrderedTree,
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{key_ordering::KeyOrdering, reference_path::ReferencePathType};
+
+    #[test]
+    fn test_kind_discriminants_match_serialization() {
+        let elements = [
+            Element::new_item(b"value".to_vec()),
+            Element::new_reference(ReferencePathType::AbsolutePathReference(vec![
+                b"key".to_vec()
+            ])),
+            Element::empty_tree(),
+            Element::new_sum_item(5),
+            Element::empty_sum_tree(),
+            Element::empty_ordered_tree(KeyOrdering::ReverseLexicographic),
+        ];
+        for (element, kind) in elements.iter().zip(ElementKind::ALL) {
+            assert_eq!(element.kind(), kind);
+            let serialized = element.serialize().expect("should serialize element");
+            assert_eq!(serialized[0], u8::from(kind));
+            assert_eq!(ElementKind::try_from(serialized[0]).ok(), Some(kind));
+        }
+        assert!(matches!(
+            ElementKind::try_from(ElementKind::ALL.len() as u8),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+}
@@ -36,6 +36,8 @@ mod constructor;
 mod delete;
 #[cfg(feature = "full")]
 mod exists;
+#[cfg(any(feature = "full", feature = "verify"))]
+pub(crate) mod expiry;
 #[cfg(feature = "full")]
 mod get;
 #[cfg(any(feature = "full", feature = "verify"))]
@@ -43,23 +45,28 @@ pub(crate) mod helpers;
 #[cfg(feature = "full")]
 mod insert;
 #[cfg(any(feature = "full", feature = "verify"))]
+mod kind;
+#[cfg(any(feature = "full", feature = "verify"))]
 mod query;
 #[cfg(any(feature = "full", feature = "verify"))]
 mod serialize;
 #[cfg(feature = "full")]
 use core::fmt;
 
-#[cfg(any(feature = "full", feature = "verify"))]
-use merk::estimated_costs::SUM_VALUE_EXTRA_COST;
 #[cfg(feature = "full")]
 use merk::estimated_costs::{LAYER_COST_SIZE, SUM_LAYER_COST_SIZE};
 #[cfg(any(feature = "full", feature = "verify"))]
+use merk::{cost_constants::active_cost_constants, estimated_costs::SUM_VALUE_EXTRA_COST};
+#[cfg(any(feature = "full", feature = "verify"))]
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "full")]
 use visualize::visualize_to_vec;
 
 #[cfg(any(feature = "full", feature = "verify"))]
-use crate::reference_path::ReferencePathType;
+pub use kind::ElementKind;
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use crate::{key_ordering::KeyOrdering, reference_path::ReferencePathType};
 
 #[cfg(any(feature = "full", feature = "verify"))]
 /// Optional meta-data to be stored per element
@@ -83,6 +90,22 @@ pub const SUM_ITEM_COST_SIZE: u32 = SUM_VALUE_EXTRA_COST + 2; // 11
 /// The cost of a sum tree
 pub const SUM_TREE_COST_SIZE: u32 = SUM_LAYER_COST_SIZE; // 12
 
+#[cfg(feature = "full")]
+/// The cost of a tree with the active cost constants
+pub fn tree_cost_size() -> u32 {
+    active_cost_constants().layer_cost_size
+}
+#[cfg(any(feature = "full", feature = "verify"))]
+/// The cost of a sum item with the active cost constants
+pub fn sum_item_cost_size() -> u32 {
+    active_cost_constants().sum_value_extra_cost + 2
+}
+#[cfg(feature = "full")]
+/// The cost of a sum tree with the active cost constants
+pub fn sum_tree_cost_size() -> u32 {
+    active_cost_constants().sum_layer_cost_size()
+}
+
 #[cfg(any(feature = "full", feature = "verify"))]
 /// int 64 sum value
 pub type SumValue = i64;
@@ -90,7 +113,7 @@ pub type SumValue = i64;
 #[cfg(any(feature = "full", feature = "verify"))]
 /// Variants of GroveDB stored entities
 /// ONLY APPEND TO THIS LIST!!! Because
-/// of how serialization works.
+/// of how serialization works. New variants also get the next `ElementKind`.
 #[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum Element {
     /// An ordinary value
@@ -105,6 +128,9 @@ pub enum Element {
     /// Same as Element::Tree but underlying Merk sums value of it's summable
     /// nodes
     SumTree(Option<Vec<u8>>, SumValue, Option<ElementFlags>),
+    /// Same as Element::Tree but keys of the underlying Merk follow the key
+    /// ordering
+    OrderedTree(Option<Vec<u8>>, KeyOrdering, Option<ElementFlags>),
 }
 
 #[cfg(feature = "full")]
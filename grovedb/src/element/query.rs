@@ -53,7 +53,7 @@ use crate::{
         },
     },
     util::{merk_optional_tx, storage_context_optional_tx},
-    Error, PathQuery, TransactionArg,
+    Error, KeyOrdering, PathQuery, SubtreePath, TransactionArg,
 };
 #[cfg(any(feature = "full", feature = "verify"))]
 use crate::{Element, SizedQuery};
@@ -68,7 +68,7 @@ where
     pub transaction: TransactionArg<'db, 'ctx>,
     pub key: Option<&'a [u8]>,
     pub element: Element,
-    pub path: &'a [&'a [u8]],
+    pub path: SubtreePath<'a>,
     pub subquery_path: Option<Path>,
     pub subquery: Option<Query>,
     pub left_to_right: bool,
@@ -85,7 +85,7 @@ impl Element {
     /// Returns a vector of result elements based on given query
     pub fn get_query(
         storage: &RocksDbStorage,
-        merk_path: &[&[u8]],
+        merk_path: SubtreePath<'_>,
         query: &Query,
         result_type: QueryResultType,
         transaction: TransactionArg,
@@ -106,7 +106,7 @@ impl Element {
     /// Get values of result elements coming from given query
     pub fn get_query_values(
         storage: &RocksDbStorage,
-        merk_path: &[&[u8]],
+        merk_path: SubtreePath<'_>,
         query: &Query,
         transaction: TransactionArg,
     ) -> CostResult<Vec<Element>, Error> {
@@ -136,7 +136,7 @@ impl Element {
     /// based on given query
     pub fn get_query_apply_function(
         storage: &RocksDbStorage,
-        path: &[&[u8]],
+        path: SubtreePath<'_>,
         sized_query: &SizedQuery,
         allow_get_raw: bool,
         allow_cache: bool,
@@ -146,6 +146,13 @@ impl Element {
     ) -> CostResult<(QueryResultElements, u16), Error> {
         let mut cost = OperationCost::default();
 
+        let key_ordering = cost_return_on_error!(
+            &mut cost,
+            Self::subtree_key_ordering(storage, path, transaction)
+        );
+        let storage_sized_query = key_ordering.storage_sized_query(sized_query);
+        let sized_query = &storage_sized_query;
+
         let mut results = Vec::new();
 
         let mut limit = sized_query.limit;
@@ -208,6 +215,29 @@ impl Element {
         Ok((QueryResultElements::from_elements(results), skipped)).wrap_with_cost(cost)
     }
 
+    #[cfg(feature = "full")]
+    /// Gives the key ordering of the subtree at the path, the root tree and
+    /// subtrees which don't exist are ordered lexicographically
+    pub(crate) fn subtree_key_ordering(
+        storage: &RocksDbStorage,
+        path: SubtreePath<'_>,
+        transaction: TransactionArg,
+    ) -> CostResult<KeyOrdering, Error> {
+        let mut cost = OperationCost::default();
+        let (parent_path, key) = match path.derive_parent() {
+            Some(split) => split,
+            None => return Ok(KeyOrdering::default()).wrap_with_cost(cost),
+        };
+        let maybe_element = storage_context_optional_tx!(storage, parent_path, transaction, ctx, {
+            let ctx = ctx.unwrap_add_cost(&mut cost);
+            cost_return_on_error!(&mut cost, Element::get_optional_from_storage(&ctx, key))
+        });
+        Ok(maybe_element
+            .map(|element| element.key_ordering())
+            .unwrap_or_default())
+        .wrap_with_cost(cost)
+    }
+
     #[cfg(feature = "full")]
     /// Returns a vector of elements excluding trees, and the number of skipped
     /// elements
@@ -218,14 +248,9 @@ impl Element {
         result_type: QueryResultType,
         transaction: TransactionArg,
     ) -> CostResult<(QueryResultElements, u16), Error> {
-        let path_slices = path_query
-            .path
-            .iter()
-            .map(|x| x.as_slice())
-            .collect::<Vec<_>>();
         Element::get_query_apply_function(
             storage,
-            path_slices.as_slice(),
+            SubtreePath::from(&path_query.path),
             &path_query.query,
             false,
             allow_cache,
@@ -245,14 +270,9 @@ impl Element {
         result_type: QueryResultType,
         transaction: TransactionArg,
     ) -> CostResult<(QueryResultElements, u16), Error> {
-        let path_slices = path_query
-            .path
-            .iter()
-            .map(|x| x.as_slice())
-            .collect::<Vec<_>>();
         Element::get_query_apply_function(
             storage,
-            path_slices.as_slice(),
+            SubtreePath::from(&path_query.path),
             &path_query.query,
             true,
             allow_cache,
@@ -266,7 +286,7 @@ impl Element {
     /// Returns a vector of elements, and the number of skipped elements
     pub fn get_sized_query(
         storage: &RocksDbStorage,
-        path: &[&[u8]],
+        path: SubtreePath<'_>,
         sized_query: &SizedQuery,
         allow_cache: bool,
         result_type: QueryResultType,
@@ -306,32 +326,32 @@ impl Element {
             offset,
         } = args;
         if element.is_tree() {
-            let mut path_vec = path.to_vec();
             let key = cost_return_on_error_no_add!(
                 &cost,
                 key.ok_or(Error::MissingParameter(
                     "the key must be provided when using a subquery path",
                 ))
             );
-            path_vec.push(key);
+            let mut subtree_path = path.derive_owned_with_child(key);
 
             if let Some(subquery) = subquery {
                 if let Some(subquery_path) = &subquery_path {
-                    path_vec.extend(subquery_path.iter().map(|k| k.as_slice()));
+                    subtree_path.extend(subquery_path);
                 }
 
                 let inner_query = SizedQuery::new(subquery, *limit, *offset);
-                let path_vec_owned = path_vec.iter().map(|x| x.to_vec()).collect();
-                let inner_path_query = PathQuery::new(path_vec_owned, inner_query);
 
                 let (mut sub_elements, skipped) = cost_return_on_error!(
                     &mut cost,
-                    Element::get_path_query(
+                    Element::get_query_apply_function(
                         storage,
-                        &inner_path_query,
+                        subtree_path.as_path(),
+                        &inner_query,
+                        false,
                         allow_cache,
                         result_type,
-                        transaction
+                        transaction,
+                        Element::path_query_push,
                     )
                 );
 
@@ -347,13 +367,13 @@ impl Element {
                     if let Some((subquery_path_last_key, subquery_path_front_keys)) =
                         &subquery_path.split_last()
                     {
-                        path_vec.extend(subquery_path_front_keys.iter().map(|k| k.as_slice()));
+                        subtree_path.extend(subquery_path_front_keys.iter());
                         match result_type {
                             QueryElementResultType => {
                                 merk_optional_tx!(
                                     &mut cost,
                                     storage,
-                                    path_vec.iter().copied().peekable(),
+                                    subtree_path.as_path().iter().peekable(),
                                     transaction,
                                     subtree,
                                     {
@@ -362,7 +382,7 @@ impl Element {
                                                 &mut cost,
                                                 Element::get_with_absolute_refs(
                                                     &subtree,
-                                                    path_vec.as_slice(),
+                                                    subtree_path.as_path(),
                                                     subquery_path_last_key.as_slice(),
                                                     allow_cache,
                                                 )
@@ -375,7 +395,7 @@ impl Element {
                                 merk_optional_tx!(
                                     &mut cost,
                                     storage,
-                                    path_vec.iter().copied().peekable(),
+                                    subtree_path.as_path().iter().peekable(),
                                     transaction,
                                     subtree,
                                     {
@@ -386,7 +406,7 @@ impl Element {
                                                     &mut cost,
                                                     Element::get_with_absolute_refs(
                                                         &subtree,
-                                                        path_vec.as_slice(),
+                                                        subtree_path.as_path(),
                                                         subquery_path_last_key.as_slice(),
                                                         allow_cache,
                                                     )
@@ -400,19 +420,19 @@ impl Element {
                                 merk_optional_tx!(
                                     &mut cost,
                                     storage,
-                                    path_vec.iter().copied().peekable(),
+                                    subtree_path.as_path().iter().peekable(),
                                     transaction,
                                     subtree,
                                     {
                                         results.push(
                                             QueryResultElement::PathKeyElementTrioResultItem((
-                                                path_vec.iter().map(|p| p.to_vec()).collect(),
+                                                subtree_path.to_vec(),
                                                 subquery_path_last_key.to_vec(),
                                                 cost_return_on_error!(
                                                     &mut cost,
                                                     Element::get_with_absolute_refs(
                                                         &subtree,
-                                                        path_vec.as_slice(),
+                                                        subtree_path.as_path(),
                                                         subquery_path_last_key.as_slice(),
                                                         allow_cache,
                                                     )
@@ -530,7 +550,7 @@ impl Element {
         storage: &RocksDbStorage,
         item: &QueryItem,
         results: &mut Vec<QueryResultElement>,
-        path: &[&[u8]],
+        path: SubtreePath<'_>,
         sized_query: &SizedQuery,
         transaction: TransactionArg,
         limit: &mut Option<u16>,
@@ -548,7 +568,7 @@ impl Element {
                 let element_res = merk_optional_tx!(
                     &mut cost,
                     storage,
-                    path.iter().copied().peekable(),
+                    path.iter().peekable(),
                     transaction,
                     subtree,
                     { Element::get(&subtree, key, allow_cache).unwrap_add_cost(&mut cost) }
@@ -585,7 +605,7 @@ impl Element {
             }
         } else {
             // this is a query on a range
-            storage_context_optional_tx!(storage, path.iter().copied(), transaction, ctx, {
+            storage_context_optional_tx!(storage, path, transaction, ctx, {
                 let ctx = ctx.unwrap_add_cost(&mut cost);
                 let mut iter = ctx.raw_iter();
 
@@ -671,7 +691,7 @@ impl Element {
                 }
                 QueryResultType::QueryPathKeyElementTrioResultType => {
                     let key = key.ok_or(Error::CorruptedPath("basic push must have a key"))?;
-                    let path = path.iter().map(|a| a.to_vec()).collect();
+                    let path = path.to_vec();
                     results.push(QueryResultElement::PathKeyElementTrioResultItem((
                         path,
                         Vec::from(key),
@@ -710,7 +730,7 @@ mod tests {
             QueryResultType::{QueryKeyElementPairResultType, QueryPathKeyElementTrioResultType},
         },
         tests::{make_test_grovedb, TEST_LEAF},
-        SizedQuery,
+        SizedQuery, SubtreePath,
     };
 
     #[test]
@@ -760,7 +780,7 @@ mod tests {
         query.insert_key(b"a".to_vec());
 
         assert_eq!(
-            Element::get_query_values(&db.db, &[TEST_LEAF], &query, None)
+            Element::get_query_values(&db.db, SubtreePath::from(&[TEST_LEAF]), &query, None)
                 .unwrap()
                 .expect("expected successful get_query"),
             vec![
@@ -774,7 +794,7 @@ mod tests {
         query.insert_range(b"b".to_vec()..b"d".to_vec());
         query.insert_range(b"a".to_vec()..b"c".to_vec());
         assert_eq!(
-            Element::get_query_values(&db.db, &[TEST_LEAF], &query, None)
+            Element::get_query_values(&db.db, SubtreePath::from(&[TEST_LEAF]), &query, None)
                 .unwrap()
                 .expect("expected successful get_query"),
             vec![
@@ -789,7 +809,7 @@ mod tests {
         query.insert_range_inclusive(b"b".to_vec()..=b"d".to_vec());
         query.insert_range(b"b".to_vec()..b"c".to_vec());
         assert_eq!(
-            Element::get_query_values(&db.db, &[TEST_LEAF], &query, None)
+            Element::get_query_values(&db.db, SubtreePath::from(&[TEST_LEAF]), &query, None)
                 .unwrap()
                 .expect("expected successful get_query"),
             vec![
@@ -805,7 +825,7 @@ mod tests {
         query.insert_range(b"b".to_vec()..b"d".to_vec());
         query.insert_range(b"a".to_vec()..b"c".to_vec());
         assert_eq!(
-            Element::get_query_values(&db.db, &[TEST_LEAF], &query, None)
+            Element::get_query_values(&db.db, SubtreePath::from(&[TEST_LEAF]), &query, None)
                 .unwrap()
                 .expect("expected successful get_query"),
             vec![
@@ -864,7 +884,7 @@ mod tests {
         assert_eq!(
             Element::get_query(
                 &db.db,
-                &[TEST_LEAF],
+                SubtreePath::from(&[TEST_LEAF]),
                 &query,
                 QueryPathKeyElementTrioResultType,
                 None
@@ -921,7 +941,7 @@ mod tests {
         let ascending_query = SizedQuery::new(query.clone(), None, None);
         let (elements, skipped) = Element::get_sized_query(
             storage,
-            &[TEST_LEAF],
+            SubtreePath::from(&[TEST_LEAF]),
             &ascending_query,
             true,
             QueryKeyElementPairResultType,
@@ -955,7 +975,7 @@ mod tests {
         let backwards_query = SizedQuery::new(query.clone(), None, None);
         let (elements, skipped) = Element::get_sized_query(
             storage,
-            &[TEST_LEAF],
+            SubtreePath::from(&[TEST_LEAF]),
             &backwards_query,
             true,
             QueryKeyElementPairResultType,
@@ -1037,7 +1057,7 @@ mod tests {
         check_elements_no_skipped(
             Element::get_sized_query(
                 storage,
-                &[TEST_LEAF],
+                SubtreePath::from(&[TEST_LEAF]),
                 &ascending_query,
                 true,
                 QueryKeyElementPairResultType,
@@ -1054,7 +1074,7 @@ mod tests {
         check_elements_no_skipped(
             Element::get_sized_query(
                 storage,
-                &[TEST_LEAF],
+                SubtreePath::from(&[TEST_LEAF]),
                 &backwards_query,
                 true,
                 QueryKeyElementPairResultType,
@@ -1074,7 +1094,7 @@ mod tests {
         check_elements_no_skipped(
             Element::get_sized_query(
                 storage,
-                &[TEST_LEAF],
+                SubtreePath::from(&[TEST_LEAF]),
                 &backwards_query,
                 true,
                 QueryKeyElementPairResultType,
@@ -1136,7 +1156,7 @@ mod tests {
         let backwards_query = SizedQuery::new(query.clone(), None, None);
         let (elements, skipped) = Element::get_sized_query(
             &db.db,
-            &[TEST_LEAF],
+            SubtreePath::from(&[TEST_LEAF]),
             &backwards_query,
             true,
             QueryKeyElementPairResultType,
@@ -1162,7 +1182,7 @@ mod tests {
         let backwards_query = SizedQuery::new(query.clone(), None, None);
         let (elements, skipped) = Element::get_sized_query(
             &db.db,
-            &[TEST_LEAF],
+            SubtreePath::from(&[TEST_LEAF]),
             &backwards_query,
             true,
             QueryKeyElementPairResultType,
@@ -1183,7 +1203,7 @@ mod tests {
         let limit_query = SizedQuery::new(query.clone(), Some(1), None);
         let (elements, skipped) = Element::get_sized_query(
             &db.db,
-            &[TEST_LEAF],
+            SubtreePath::from(&[TEST_LEAF]),
             &limit_query,
             true,
             QueryKeyElementPairResultType,
@@ -1204,7 +1224,7 @@ mod tests {
         let limit_query = SizedQuery::new(query.clone(), Some(2), None);
         let (elements, skipped) = Element::get_sized_query(
             &db.db,
-            &[TEST_LEAF],
+            SubtreePath::from(&[TEST_LEAF]),
             &limit_query,
             true,
             QueryKeyElementPairResultType,
@@ -1224,7 +1244,7 @@ mod tests {
         let limit_offset_query = SizedQuery::new(query.clone(), Some(2), Some(1));
         let (elements, skipped) = Element::get_sized_query(
             &db.db,
-            &[TEST_LEAF],
+            SubtreePath::from(&[TEST_LEAF]),
             &limit_offset_query,
             true,
             QueryKeyElementPairResultType,
@@ -1249,7 +1269,7 @@ mod tests {
         let limit_offset_backwards_query = SizedQuery::new(query.clone(), Some(2), Some(1));
         let (elements, skipped) = Element::get_sized_query(
             &db.db,
-            &[TEST_LEAF],
+            SubtreePath::from(&[TEST_LEAF]),
             &limit_offset_backwards_query,
             true,
             QueryKeyElementPairResultType,
@@ -1273,7 +1293,7 @@ mod tests {
         let limit_full_query = SizedQuery::new(query.clone(), Some(5), Some(0));
         let (elements, skipped) = Element::get_sized_query(
             &db.db,
-            &[TEST_LEAF],
+            SubtreePath::from(&[TEST_LEAF]),
             &limit_full_query,
             true,
             QueryKeyElementPairResultType,
@@ -1298,7 +1318,7 @@ mod tests {
         let limit_offset_backwards_query = SizedQuery::new(query.clone(), Some(2), Some(1));
         let (elements, skipped) = Element::get_sized_query(
             &db.db,
-            &[TEST_LEAF],
+            SubtreePath::from(&[TEST_LEAF]),
             &limit_offset_backwards_query,
             true,
             QueryKeyElementPairResultType,
@@ -1323,7 +1343,7 @@ mod tests {
         let limit_backwards_query = SizedQuery::new(query.clone(), Some(2), Some(1));
         let (elements, skipped) = Element::get_sized_query(
             &db.db,
-            &[TEST_LEAF],
+            SubtreePath::from(&[TEST_LEAF]),
             &limit_backwards_query,
             true,
             QueryKeyElementPairResultType,
@@ -28,6 +28,9 @@
 
 //! Errors
 
+#[cfg(any(feature = "full", feature = "verify"))]
+use crate::reference_path::PartialReferenceChain;
+
 /// Errors
 #[cfg(any(feature = "full", feature = "verify"))]
 #[derive(Debug, thiserror::Error)]
@@ -36,9 +39,9 @@ pub enum Error {
     #[error("cyclic reference path")]
     /// Cyclic reference
     CyclicReference,
-    #[error("reference hops limit exceeded")]
-    /// Reference limit
-    ReferenceLimit,
+    #[error("reference hops limit exceeded after {} hops", .0.visited.len())]
+    /// Reference limit, with the part of the chain followed
+    ReferenceLimit(Box<PartialReferenceChain>),
     #[error("missing reference {0}")]
     /// Missing reference
     MissingReference(String),
@@ -128,6 +131,10 @@ pub enum Error {
     /// Delete up tree stop height more than initial path size
     DeleteUpTreeStopHeightMoreThanInitialPathSize(String),
 
+    #[error("batch already applied: {0:?}")]
+    /// Batch with this idempotency id was already applied
+    AlreadyApplied(Vec<u8>),
+
     #[error("deleting non empty tree error: {0}")]
     /// Deleting non empty tree
     DeletingNonEmptyTree(&'static str),
@@ -153,6 +160,30 @@ pub enum Error {
     /// Path not found in cache for estimated costs
     PathNotFoundInCacheForEstimatedCosts(String),
 
+    #[error("already open: {0}")]
+    /// The database path is already open in this process
+    AlreadyOpen(String),
+
+    #[error("read only subtree: {0}")]
+    /// The operation would change a subtree marked read only
+    ReadOnlySubtree(String),
+
+    #[error("subtree quota exceeded: {0}")]
+    /// The operation would grow a subtree past its quota
+    SubtreeQuotaExceeded(String),
+
+    #[error("pending migrations: {0}")]
+    /// The database has registered migrations not applied yet
+    PendingMigrations(String),
+
+    #[error("migration failed: {0}")]
+    /// A migration failed and its changes were rolled back
+    MigrationFailed(String),
+
+    #[error("migrations mismatch: {0}")]
+    /// The applied migrations aren't a prefix of the registered ones
+    MigrationsMismatch(String),
+
     // Support errors
     #[error("not supported: {0}")]
     /// Not supported
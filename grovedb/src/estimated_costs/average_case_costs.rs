@@ -48,7 +48,7 @@ use storage::{worst_case_costs::WorstKeyLength, Storage};
 
 use crate::{
     batch::{key_info::KeyInfo, KeyInfoPath},
-    element::{SUM_ITEM_COST_SIZE, SUM_TREE_COST_SIZE, TREE_COST_SIZE},
+    element::{sum_item_cost_size, sum_tree_cost_size, tree_cost_size},
     Element, ElementFlags, Error, GroveDb,
 };
 
@@ -97,9 +97,9 @@ impl GroveDb {
         .map(|f| f + f.required_space() as u32)
         .unwrap_or_default();
         let tree_cost_size = if estimated_layer_information.is_sum_tree {
-            SUM_TREE_COST_SIZE
+            sum_tree_cost_size()
         } else {
-            TREE_COST_SIZE
+            tree_cost_size()
         };
         let layer_extra_size = tree_cost_size + flags_size;
         add_average_case_merk_replace_layered(
@@ -132,9 +132,9 @@ impl GroveDb {
             flags_len + flags_len.required_space() as u32
         });
         let tree_cost_size = if is_sum_tree {
-            SUM_TREE_COST_SIZE
+            sum_tree_cost_size()
         } else {
-            TREE_COST_SIZE
+            tree_cost_size()
         };
         let value_len = tree_cost_size + flags_len;
         add_cost_case_merk_insert_layered(&mut cost, key_len, value_len, in_tree_using_sums);
@@ -165,9 +165,9 @@ impl GroveDb {
         .map(|f| f + f.required_space() as u32)
         .unwrap_or_default();
         let tree_cost_size = if is_sum_tree {
-            SUM_TREE_COST_SIZE
+            sum_tree_cost_size()
         } else {
-            TREE_COST_SIZE
+            tree_cost_size()
         };
         let layer_extra_size = tree_cost_size + flags_size;
         add_average_case_merk_delete_layered(&mut cost, key_len, layer_extra_size);
@@ -192,15 +192,19 @@ impl GroveDb {
         let mut cost = OperationCost::default();
         let key_len = key.max_length() as u32;
         match value {
-            Element::Tree(_, flags) | Element::SumTree(_, _, flags) => {
+            Element::Tree(_, flags)
+            | Element::SumTree(_, _, flags)
+            | Element::OrderedTree(_, _, flags) => {
                 let flags_len = flags.as_ref().map_or(0, |flags| {
                     let flags_len = flags.len() as u32;
                     flags_len + flags_len.required_space() as u32
                 });
-                let tree_cost_size = if value.is_sum_tree() {
-                    SUM_TREE_COST_SIZE
-                } else {
-                    TREE_COST_SIZE
+                let tree_cost_size = match value {
+                    Element::SumTree(..) => sum_tree_cost_size(),
+                    Element::OrderedTree(_, key_ordering, _) => {
+                        tree_cost_size() + key_ordering.serialized_size()
+                    }
+                    _ => tree_cost_size(),
                 };
                 let value_len = tree_cost_size + flags_len;
                 add_cost_case_merk_insert_layered(&mut cost, key_len, value_len, in_tree_using_sums)
@@ -232,15 +236,19 @@ impl GroveDb {
         let mut cost = OperationCost::default();
         let key_len = key.max_length() as u32;
         match value {
-            Element::Tree(_, flags) | Element::SumTree(_, _, flags) => {
+            Element::Tree(_, flags)
+            | Element::SumTree(_, _, flags)
+            | Element::OrderedTree(_, _, flags) => {
                 let flags_len = flags.as_ref().map_or(0, |flags| {
                     let flags_len = flags.len() as u32;
                     flags_len + flags_len.required_space() as u32
                 });
-                let tree_cost_size = if value.is_sum_tree() {
-                    SUM_TREE_COST_SIZE
-                } else {
-                    TREE_COST_SIZE
+                let tree_cost_size = match value {
+                    Element::SumTree(..) => sum_tree_cost_size(),
+                    Element::OrderedTree(_, key_ordering, _) => {
+                        tree_cost_size() + key_ordering.serialized_size()
+                    }
+                    _ => tree_cost_size(),
                 };
                 let value_len = tree_cost_size + flags_len;
                 add_cost_case_merk_replace_layered(
@@ -257,7 +265,7 @@ impl GroveDb {
                 });
                 // Items need to be always the same serialized size for this to work
                 let sum_item_cost_size = if value.is_sum_item() {
-                    SUM_ITEM_COST_SIZE
+                    sum_item_cost_size()
                 } else {
                     value.serialized_size() as u32
                 };
@@ -379,9 +387,9 @@ impl GroveDb {
         in_parent_tree_using_sums: bool,
     ) {
         let estimated_element_size = if is_sum_tree {
-            SUM_TREE_COST_SIZE + estimated_flags_size
+            sum_tree_cost_size() + estimated_flags_size
         } else {
-            TREE_COST_SIZE + estimated_flags_size
+            tree_cost_size() + estimated_flags_size
         };
         Self::add_average_case_has_raw_cost::<S>(
             cost,
@@ -419,9 +427,9 @@ impl GroveDb {
         in_parent_tree_using_sums: bool,
     ) {
         let estimated_element_size = if is_sum_tree {
-            SUM_TREE_COST_SIZE + estimated_flags_size
+            sum_tree_cost_size() + estimated_flags_size
         } else {
-            TREE_COST_SIZE + estimated_flags_size
+            tree_cost_size() + estimated_flags_size
         };
         cost.seek_count += 1;
         add_average_case_get_merk_node(
@@ -50,7 +50,7 @@ use storage::{worst_case_costs::WorstKeyLength, Storage};
 
 use crate::{
     batch::{key_info::KeyInfo, KeyInfoPath},
-    element::{SUM_ITEM_COST_SIZE, SUM_TREE_COST_SIZE, TREE_COST_SIZE},
+    element::{sum_item_cost_size, sum_tree_cost_size, tree_cost_size},
     Element, ElementFlags, Error, GroveDb,
 };
 
@@ -88,9 +88,9 @@ impl GroveDb {
         let mut cost = OperationCost::default();
         let key_len = key.max_length() as u32;
         let tree_cost = if is_sum_tree {
-            SUM_TREE_COST_SIZE
+            sum_tree_cost_size()
         } else {
-            TREE_COST_SIZE
+            tree_cost_size()
         };
         let layer_extra_size = tree_cost + WORST_CASE_FLAGS_LEN;
         add_worst_case_merk_replace_layered(
@@ -123,9 +123,9 @@ impl GroveDb {
             flags_len + flags_len.required_space() as u32
         });
         let tree_cost = if is_sum_tree {
-            SUM_TREE_COST_SIZE
+            sum_tree_cost_size()
         } else {
-            TREE_COST_SIZE
+            tree_cost_size()
         };
         let value_len = tree_cost + flags_len;
         add_cost_case_merk_insert_layered(&mut cost, key_len, value_len, is_in_parent_sum_tree);
@@ -147,9 +147,9 @@ impl GroveDb {
         let mut cost = OperationCost::default();
         let key_len = key.max_length() as u32;
         let tree_cost = if is_sum_tree {
-            SUM_TREE_COST_SIZE
+            sum_tree_cost_size()
         } else {
-            TREE_COST_SIZE
+            tree_cost_size()
         };
         let layer_extra_size = tree_cost + WORST_CASE_FLAGS_LEN;
         add_worst_case_merk_delete_layered(&mut cost, key_len, layer_extra_size);
@@ -174,15 +174,19 @@ impl GroveDb {
         let mut cost = OperationCost::default();
         let key_len = key.max_length() as u32;
         match value {
-            Element::Tree(_, flags) | Element::SumTree(_, _, flags) => {
+            Element::Tree(_, flags)
+            | Element::SumTree(_, _, flags)
+            | Element::OrderedTree(_, _, flags) => {
                 let flags_len = flags.as_ref().map_or(0, |flags| {
                     let flags_len = flags.len() as u32;
                     flags_len + flags_len.required_space() as u32
                 });
-                let tree_cost_size = if value.is_sum_tree() {
-                    SUM_TREE_COST_SIZE
-                } else {
-                    TREE_COST_SIZE
+                let tree_cost_size = match value {
+                    Element::SumTree(..) => sum_tree_cost_size(),
+                    Element::OrderedTree(_, key_ordering, _) => {
+                        tree_cost_size() + key_ordering.serialized_size()
+                    }
+                    _ => tree_cost_size(),
                 };
                 let value_len = tree_cost_size + flags_len;
                 add_cost_case_merk_insert_layered(
@@ -219,15 +223,19 @@ impl GroveDb {
         let mut cost = OperationCost::default();
         let key_len = key.max_length() as u32;
         match value {
-            Element::Tree(_, flags) | Element::SumTree(_, _, flags) => {
+            Element::Tree(_, flags)
+            | Element::SumTree(_, _, flags)
+            | Element::OrderedTree(_, _, flags) => {
                 let flags_len = flags.as_ref().map_or(0, |flags| {
                     let flags_len = flags.len() as u32;
                     flags_len + flags_len.required_space() as u32
                 });
-                let tree_cost_size = if value.is_sum_tree() {
-                    SUM_TREE_COST_SIZE
-                } else {
-                    TREE_COST_SIZE
+                let tree_cost_size = match value {
+                    Element::SumTree(..) => sum_tree_cost_size(),
+                    Element::OrderedTree(_, key_ordering, _) => {
+                        tree_cost_size() + key_ordering.serialized_size()
+                    }
+                    _ => tree_cost_size(),
                 };
                 let value_len = tree_cost_size + flags_len;
                 add_cost_case_merk_replace_layered(
@@ -242,7 +250,7 @@ impl GroveDb {
                     let flags_len = flags.len() as u32;
                     flags_len + flags_len.required_space() as u32
                 });
-                let value_len = SUM_ITEM_COST_SIZE + flags_len;
+                let value_len = sum_item_cost_size() + flags_len;
                 add_cost_case_merk_replace_same_size(
                     &mut cost,
                     key_len,
@@ -353,9 +361,9 @@ impl GroveDb {
     ) {
         cost.seek_count += 1;
         let tree_cost_size = if is_sum_tree {
-            SUM_TREE_COST_SIZE
+            sum_tree_cost_size()
         } else {
-            TREE_COST_SIZE
+            tree_cost_size()
         };
         add_worst_case_get_merk_node(
             cost,
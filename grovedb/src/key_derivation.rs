@@ -0,0 +1,174 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Key derivation
+//! Deterministic derivation of new element keys from seeds describing the
+//! logical operation, such as an owner id and a nonce. Every node applying the
+//! same logical operation derives the same keys, so helpers creating elements
+//! never need a source of randomness that could diverge between nodes.
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use costs::CostContext;
+#[cfg(feature = "full")]
+use costs::{CostsExt, OperationCost};
+#[cfg(any(feature = "full", feature = "verify"))]
+use integer_encoding::VarInt;
+#[cfg(any(feature = "full", feature = "verify"))]
+use merk::{tree::value_hash, CryptoHash};
+
+#[cfg(feature = "full")]
+use crate::{batch::GroveDbOp, Element};
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Derives a 32 bytes id from the seeds with the crate's hash function. Seeds
+/// are length prefixed, so `["ab", "c"]` and `["a", "bc"]` give different ids.
+pub fn generate_id(seeds: &[&[u8]]) -> CostContext<[u8; 32]> {
+    let mut bytes = seeds.len().encode_var_vec();
+    for seed in seeds {
+        bytes.extend_from_slice(&seed.len().encode_var_vec());
+        bytes.extend_from_slice(seed);
+    }
+    value_hash(&bytes)
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Sequence of keys derived for a single logical operation, the n-th key only
+/// depends on the seeds of the operation and on n
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyGenerator {
+    seed: CryptoHash,
+    counter: u64,
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl KeyGenerator {
+    /// Key generator of the operation described by the seeds
+    pub fn new(seeds: &[&[u8]]) -> CostContext<Self> {
+        generate_id(seeds).map(|seed| Self { seed, counter: 0 })
+    }
+
+    /// Number of keys generated so far
+    pub fn generated(&self) -> u64 {
+        self.counter
+    }
+
+    /// Derives the next key
+    pub fn next_key(&mut self) -> CostContext<[u8; 32]> {
+        let counter = self.counter.to_be_bytes();
+        self.counter += 1;
+        generate_id(&[&self.seed, &counter])
+    }
+}
+
+#[cfg(feature = "full")]
+impl GroveDbOp {
+    /// An insert op under a key derived from the seeds of the operation
+    pub fn insert_with_derived_key_op(
+        path: Vec<Vec<u8>>,
+        seeds: &[&[u8]],
+        element: Element,
+    ) -> CostContext<Self> {
+        generate_id(seeds).map(|key| Self::insert_op(path, key.to_vec(), element))
+    }
+
+    /// Insert ops of the elements under keys derived in sequence by the
+    /// generator
+    pub fn insert_with_generated_keys_ops(
+        path: Vec<Vec<u8>>,
+        generator: &mut KeyGenerator,
+        elements: Vec<Element>,
+    ) -> CostContext<Vec<Self>> {
+        let mut cost = OperationCost::default();
+        let ops: Vec<Self> = elements
+            .into_iter()
+            .map(|element| {
+                let key = generator.next_key().unwrap_add_cost(&mut cost);
+                Self::insert_op(path.clone(), key.to_vec(), element)
+            })
+            .collect();
+        ops.wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{make_test_grovedb, TEST_LEAF};
+
+    #[test]
+    fn test_generate_id_is_deterministic_and_unambiguous() {
+        let id = generate_id(&[b"owner", b"nonce"]);
+        assert!(id.cost.hash_node_calls > 0);
+        assert_eq!(id.value, generate_id(&[b"owner", b"nonce"]).unwrap());
+        assert_ne!(id.value, generate_id(&[b"owne", b"rnonce"]).unwrap());
+        assert_ne!(id.value, generate_id(&[b"ownernonce"]).unwrap());
+        assert_ne!(id.value, generate_id(&[b"nonce", b"owner"]).unwrap());
+    }
+
+    #[test]
+    fn test_generated_keys_match_across_nodes() {
+        let ops_of_node = || {
+            let mut generator = KeyGenerator::new(&[b"owner", b"batch_1"]).unwrap();
+            GroveDbOp::insert_with_generated_keys_ops(
+                vec![TEST_LEAF.to_vec()],
+                &mut generator,
+                vec![
+                    Element::new_item(b"first".to_vec()),
+                    Element::new_item(b"second".to_vec()),
+                ],
+            )
+            .unwrap()
+        };
+
+        let db = make_test_grovedb();
+        let other_db = make_test_grovedb();
+        db.apply_batch(ops_of_node(), None, None)
+            .unwrap()
+            .expect("should apply batch");
+        other_db
+            .apply_batch(ops_of_node(), None, None)
+            .unwrap()
+            .expect("should apply batch");
+        assert_eq!(
+            db.root_hash(None).unwrap().unwrap(),
+            other_db.root_hash(None).unwrap().unwrap()
+        );
+
+        let mut generator = KeyGenerator::new(&[b"owner", b"batch_1"]).unwrap();
+        let first_key = generator.next_key().unwrap();
+        assert_ne!(first_key, generator.next_key().unwrap());
+        assert_eq!(generator.generated(), 2);
+        assert_eq!(
+            db.get([TEST_LEAF], &first_key, None)
+                .unwrap()
+                .expect("should get element under derived key"),
+            Element::new_item(b"first".to_vec())
+        );
+    }
+}
@@ -0,0 +1,230 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Key ordering
+//! Subtrees keep their keys in bytes order in storage, a key ordering declared
+//! by a subtree is honored by translating queries into bytes order, so ranges,
+//! limits, iteration direction and proofs follow the declared ordering.
+
+#[cfg(feature = "full")]
+use std::collections::HashMap;
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use bincode::Options;
+#[cfg(feature = "full")]
+use costs::{
+    cost_return_on_error, cost_return_on_error_no_add, CostResult, CostsExt, OperationCost,
+};
+#[cfg(any(feature = "full", feature = "verify"))]
+use merk::proofs::{query::query_item::QueryItem, Query};
+#[cfg(any(feature = "full", feature = "verify"))]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "full")]
+use crate::{
+    batch::{GroveDbOp, Op},
+    Element, GroveDb, SubtreePath, TransactionArg,
+};
+#[cfg(any(feature = "full", feature = "verify"))]
+use crate::{Error, SizedQuery};
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Order of keys in a subtree
+#[derive(Debug, Hash, Eq, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+pub enum KeyOrdering {
+    /// Keys are ordered by their bytes
+    #[default]
+    Lexicographic,
+    /// Keys are ordered by their bytes from the biggest to the smallest, so
+    /// "latest first" layouts don't need to invert key bytes
+    ReverseLexicographic,
+    /// Keys are big endian unsigned integers of the given width and are ordered
+    /// numerically, keys of any other length are rejected
+    FixedWidthNumeric(u8),
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl KeyOrdering {
+    /// Returns true if the ordering differs from keys bytes order
+    pub fn is_reversed(&self) -> bool {
+        matches!(self, KeyOrdering::ReverseLexicographic)
+    }
+
+    #[cfg(feature = "full")]
+    /// Size of the ordering within a serialized tree element
+    pub fn serialized_size(&self) -> u32 {
+        match self {
+            KeyOrdering::FixedWidthNumeric(_) => 2,
+            _ => 1,
+        }
+    }
+
+    /// Serializes the ordering the same way it is serialized within a tree
+    /// element
+    pub fn serialize(&self) -> Result<Vec<u8>, Error> {
+        bincode::DefaultOptions::default()
+            .with_varint_encoding()
+            .reject_trailing_bytes()
+            .serialize(self)
+            .map_err(|_| Error::CorruptedData(String::from("unable to serialize key ordering")))
+    }
+
+    /// Deserializes a key ordering
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        bincode::DefaultOptions::default()
+            .with_varint_encoding()
+            .reject_trailing_bytes()
+            .deserialize(bytes)
+            .map_err(|_| Error::CorruptedData(String::from("unable to deserialize key ordering")))
+    }
+
+    /// Checks that the key can be stored in a subtree with this ordering
+    pub fn validate_key(&self, key: &[u8]) -> Result<(), Error> {
+        match self {
+            KeyOrdering::FixedWidthNumeric(width) if key.len() != *width as usize => Err(
+                Error::InvalidInput("key width doesn't match the subtree fixed width ordering"),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Translates a query item expressed in this ordering into the item
+    /// covering the same keys in bytes order
+    pub fn storage_query_item(&self, item: &QueryItem) -> QueryItem {
+        if !self.is_reversed() {
+            return item.clone();
+        }
+        match item {
+            QueryItem::Key(key) => QueryItem::Key(key.clone()),
+            QueryItem::Range(range) => {
+                QueryItem::RangeAfterToInclusive(range.end.clone()..=range.start.clone())
+            }
+            QueryItem::RangeInclusive(range) => {
+                QueryItem::RangeInclusive(range.end().clone()..=range.start().clone())
+            }
+            QueryItem::RangeFull(range) => QueryItem::RangeFull(*range),
+            QueryItem::RangeFrom(range) => QueryItem::RangeToInclusive(..=range.start.clone()),
+            QueryItem::RangeTo(range) => QueryItem::RangeAfter(range.end.clone()..),
+            QueryItem::RangeToInclusive(range) => QueryItem::RangeFrom(range.end.clone()..),
+            QueryItem::RangeAfter(range) => QueryItem::RangeTo(..range.start.clone()),
+            QueryItem::RangeAfterTo(range) => {
+                QueryItem::RangeAfterTo(range.end.clone()..range.start.clone())
+            }
+            QueryItem::RangeAfterToInclusive(range) => {
+                QueryItem::Range(range.end().clone()..range.start().clone())
+            }
+        }
+    }
+
+    /// Translates a query expressed in this ordering into bytes order.
+    /// Only the layer the query is applied to is translated, subqueries are
+    /// translated once applied to their subtrees.
+    pub fn storage_query(&self, query: &Query) -> Query {
+        if !self.is_reversed() {
+            return query.clone();
+        }
+        let mut storage_query = Query::new_with_direction(!query.left_to_right);
+        for item in query.items.iter() {
+            storage_query.insert_item(self.storage_query_item(item));
+        }
+        storage_query.default_subquery_branch = query.default_subquery_branch.clone();
+        storage_query.conditional_subquery_branches = query
+            .conditional_subquery_branches
+            .as_ref()
+            .map(|branches| {
+                branches
+                    .iter()
+                    .map(|(item, branch)| (self.storage_query_item(item), branch.clone()))
+                    .collect()
+            });
+        storage_query
+    }
+
+    /// Translates a sized query expressed in this ordering into bytes order
+    pub fn storage_sized_query(&self, sized_query: &SizedQuery) -> SizedQuery {
+        SizedQuery::new(
+            self.storage_query(&sized_query.query),
+            sized_query.limit,
+            sized_query.offset,
+        )
+    }
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Fails if one of the operations writes a key rejected by the key
+    /// ordering of its subtree, trees inserted by the batch are taken into
+    /// account
+    pub(crate) fn check_ops_key_ordering(
+        &self,
+        ops: &[GroveDbOp],
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+
+        let mut key_orderings: HashMap<Vec<Vec<u8>>, KeyOrdering> = ops
+            .iter()
+            .filter_map(|op| match &op.op {
+                Op::Insert { element } | Op::Replace { element } | Op::Patch { element, .. }
+                    if element.is_tree() =>
+                {
+                    let mut tree_path = op.path.to_path();
+                    tree_path.push(op.key.get_key_clone());
+                    Some((tree_path, element.key_ordering()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        for op in ops {
+            let key = match &op.op {
+                Op::Insert { .. } | Op::Replace { .. } | Op::Patch { .. } => op.key.as_slice(),
+                Op::Rekey { new_key } => new_key.as_slice(),
+                _ => continue,
+            };
+            let path = op.path.to_path();
+            let key_ordering = match key_orderings.get(&path) {
+                Some(key_ordering) => *key_ordering,
+                None => {
+                    let key_ordering = cost_return_on_error!(
+                        &mut cost,
+                        Element::subtree_key_ordering(
+                            &self.db,
+                            SubtreePath::from(path.as_slice()),
+                            transaction
+                        )
+                    );
+                    key_orderings.insert(path, key_ordering);
+                    key_ordering
+                }
+            };
+            cost_return_on_error_no_add!(&cost, key_ordering.validate_key(key));
+        }
+        Ok(()).wrap_with_cost(cost)
+    }
+}
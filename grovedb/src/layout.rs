@@ -0,0 +1,364 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Tree layouts
+//! A static hierarchy of subtrees and elements declared with the
+//! [`layout!`](crate::layout!) macro gets typed path constants and accessors,
+//! so paths and keys are not spelled out by hand across a codebase.
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use crate::{Element, ElementKind};
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Kind of an element declared in a layout
+pub type LayoutElementKind = ElementKind;
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Encoding of typed layout keys into key bytes
+pub trait LayoutKeyEncode {
+    /// Key bytes
+    fn encode_key(&self) -> Vec<u8>;
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl<const N: usize> LayoutKeyEncode for [u8; N] {
+    fn encode_key(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl LayoutKeyEncode for Vec<u8> {
+    fn encode_key(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl LayoutKeyEncode for &[u8] {
+    fn encode_key(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl LayoutKeyEncode for String {
+    fn encode_key(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl LayoutKeyEncode for &str {
+    fn encode_key(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+macro_rules! impl_layout_key_encode_for_unsigned {
+    ($($int:ty),*) => {
+        $(
+            impl LayoutKeyEncode for $int {
+                /// Big endian, so keys are ordered numerically
+                fn encode_key(&self) -> Vec<u8> {
+                    self.to_be_bytes().to_vec()
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl_layout_key_encode_for_unsigned!(u8, u16, u32, u64, u128);
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Location of an element declared in a layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutKey {
+    path: &'static [&'static [u8]],
+    key: Vec<u8>,
+    kind: ElementKind,
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl LayoutKey {
+    /// New layout key
+    pub fn new(path: &'static [&'static [u8]], key: Vec<u8>, kind: ElementKind) -> Self {
+        Self { path, key, kind }
+    }
+
+    /// Path of the subtree holding the element
+    pub fn path(&self) -> &'static [&'static [u8]] {
+        self.path
+    }
+
+    /// Owned path of the subtree holding the element
+    pub fn path_vec(&self) -> Vec<Vec<u8>> {
+        self.path.iter().map(|segment| segment.to_vec()).collect()
+    }
+
+    /// Key of the element
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Declared kind of the element
+    pub fn kind(&self) -> ElementKind {
+        self.kind
+    }
+
+    /// Returns true if the element is of the declared kind
+    pub fn matches(&self, element: &Element) -> bool {
+        element.kind() == self.kind
+    }
+}
+
+/// Declares a static hierarchy of subtrees and elements.
+///
+/// Every subtree gets a unit struct with `PATH`, `PARENT_PATH`, `KEY` and
+/// `KIND` constants, accessors of a subtree return its child subtrees and
+/// [`LayoutKey`]s of its elements. Elements are keyed either by a constant or
+/// by a typed argument encoded with [`LayoutKeyEncode`].
+///
+/// ```
+/// grovedb::layout! {
+///     /// Root of the layout
+///     pub Layout {
+///         /// Identities
+///         subtree identities: Identities = Tree(b"identities") {
+///             /// Balance of an identity
+///             element balance(id: [u8; 32]): SumItem;
+///             /// Keys of identities
+///             subtree keys: IdentityKeys = Tree(b"keys") {
+///                 element key(id: u32): Item;
+///             }
+///         }
+///         /// Total balance
+///         element total: SumItem = b"total";
+///     }
+/// }
+///
+/// let balance = Layout::identities().balance([1; 32]);
+/// assert_eq!(balance.path(), &[b"identities".as_slice()]);
+/// assert_eq!(balance.key(), &[1; 32]);
+/// assert_eq!(IdentityKeys::PATH, &[b"identities".as_slice(), b"keys"]);
+/// assert_eq!(Layout::identities().keys().key(7).key(), &[0, 0, 0, 7]);
+/// assert_eq!(Layout::total().key(), b"total");
+/// ```
+#[cfg(any(feature = "full", feature = "verify"))]
+#[macro_export]
+macro_rules! layout {
+    (
+        $(#[$meta:meta])*
+        $vis:vis $name:ident { $($body:tt)* }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+        $vis struct $name;
+
+        #[allow(dead_code)]
+        impl $name {
+            /// Path of the tree
+            pub const PATH: &'static [&'static [u8]] = &[];
+
+            /// Path of the tree
+            pub fn path(&self) -> &'static [&'static [u8]] {
+                Self::PATH
+            }
+        }
+
+        $crate::layout!(@children root $vis $name [] $($body)*);
+    };
+
+    (@children $receiver:ident $vis:vis $parent:ident [$($path:expr),*]) => {};
+
+    (@children $receiver:ident $vis:vis $parent:ident [$($path:expr),*]
+        $(#[$meta:meta])*
+        subtree $child:ident : $ty:ident = $kind:ident($key:expr) { $($body:tt)* }
+        $($rest:tt)*
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+        $vis struct $ty;
+
+        #[allow(dead_code)]
+        impl $ty {
+            /// Path of the tree
+            pub const PATH: &'static [&'static [u8]] = &[$($path,)* $key];
+            /// Path of the parent tree
+            pub const PARENT_PATH: &'static [&'static [u8]] = &[$($path),*];
+            /// Key of the tree in its parent
+            pub const KEY: &'static [u8] = $key;
+            /// Kind of the tree
+            pub const KIND: $crate::ElementKind =
+                $crate::ElementKind::$kind;
+
+            /// Path of the tree
+            pub fn path(&self) -> &'static [&'static [u8]] {
+                Self::PATH
+            }
+
+            /// Location of the tree element in its parent
+            pub fn as_key(&self) -> $crate::layout::LayoutKey {
+                $crate::layout::LayoutKey::new(Self::PARENT_PATH, Self::KEY.to_vec(), Self::KIND)
+            }
+        }
+
+        const _: () = assert!($ty::KIND.is_tree(), "subtrees must be of a tree kind");
+
+        $crate::layout!(@accessor $receiver $parent $(#[$meta])* fn $child() -> $ty { $ty });
+        $crate::layout!(@children node $vis $ty [$($path,)* $key] $($body)*);
+        $crate::layout!(@children $receiver $vis $parent [$($path),*] $($rest)*);
+    };
+
+    (@children $receiver:ident $vis:vis $parent:ident [$($path:expr),*]
+        $(#[$meta:meta])*
+        element $child:ident($arg:ident : $arg_ty:ty) : $kind:ident;
+        $($rest:tt)*
+    ) => {
+        $crate::layout!(@accessor $receiver $parent $(#[$meta])*
+            fn $child($arg: $arg_ty) -> $crate::layout::LayoutKey {
+                $crate::layout::LayoutKey::new(
+                    $parent::PATH,
+                    $crate::layout::LayoutKeyEncode::encode_key(&$arg),
+                    $crate::ElementKind::$kind,
+                )
+            }
+        );
+        $crate::layout!(@children $receiver $vis $parent [$($path),*] $($rest)*);
+    };
+
+    (@children $receiver:ident $vis:vis $parent:ident [$($path:expr),*]
+        $(#[$meta:meta])*
+        element $child:ident : $kind:ident = $key:expr;
+        $($rest:tt)*
+    ) => {
+        $crate::layout!(@accessor $receiver $parent $(#[$meta])*
+            fn $child() -> $crate::layout::LayoutKey {
+                $crate::layout::LayoutKey::new(
+                    $parent::PATH,
+                    <[u8]>::to_vec($key),
+                    $crate::ElementKind::$kind,
+                )
+            }
+        );
+        $crate::layout!(@children $receiver $vis $parent [$($path),*] $($rest)*);
+    };
+
+    (@accessor root $parent:ident $(#[$meta:meta])*
+        fn $child:ident($($arg:ident : $arg_ty:ty),*) -> $ret:ty $body:block
+    ) => {
+        #[allow(dead_code)]
+        impl $parent {
+            $(#[$meta])*
+            pub fn $child($($arg: $arg_ty),*) -> $ret $body
+        }
+    };
+
+    (@accessor node $parent:ident $(#[$meta:meta])*
+        fn $child:ident($($arg:ident : $arg_ty:ty),*) -> $ret:ty $body:block
+    ) => {
+        #[allow(dead_code)]
+        impl $parent {
+            $(#[$meta])*
+            pub fn $child(&self, $($arg: $arg_ty),*) -> $ret $body
+        }
+    };
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use crate::{
+        tests::{make_empty_grovedb, TEST_LEAF},
+        Element,
+    };
+
+    crate::layout! {
+        TestLayout {
+            subtree leaf: TestLeaf = Tree(b"test_leaf") {
+                element item(id: u64): Item;
+                subtree sums: Sums = SumTree(b"sums") {
+                    element sum(name: &str): SumItem;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_layout_paths_and_keys() {
+        assert_eq!(TestLeaf::PATH, &[TEST_LEAF]);
+        assert_eq!(Sums::PARENT_PATH, &[TEST_LEAF]);
+        assert_eq!(Sums::PATH, &[TEST_LEAF, b"sums"]);
+
+        let db = make_empty_grovedb();
+        for (tree, element) in [
+            (TestLayout::leaf().as_key(), Element::empty_tree()),
+            (
+                TestLayout::leaf().sums().as_key(),
+                Element::empty_sum_tree(),
+            ),
+        ] {
+            assert!(tree.matches(&element));
+            db.insert(tree.path().iter().copied(), tree.key(), element, None, None)
+                .unwrap()
+                .expect("should insert tree");
+        }
+
+        let item = TestLayout::leaf().item(5);
+        assert_eq!(item.key(), &5u64.to_be_bytes());
+        db.insert(
+            item.path().iter().copied(),
+            item.key(),
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+
+        let sum = TestLayout::leaf().sums().sum("alice");
+        db.insert(
+            sum.path().iter().copied(),
+            sum.key(),
+            Element::new_sum_item(3),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert sum item");
+
+        let element = db
+            .get(sum.path().iter().copied(), sum.key(), None)
+            .unwrap()
+            .expect("should get sum item");
+        assert!(sum.matches(&element));
+        assert!(!item.matches(&element));
+    }
+}
@@ -33,70 +33,140 @@
 #[cfg(feature = "full")]
 extern crate core;
 
+#[cfg(feature = "async")]
+pub mod asynch;
 #[cfg(feature = "full")]
 pub mod batch;
 #[cfg(any(feature = "full", feature = "verify"))]
+pub mod cold_storage;
+#[cfg(feature = "crash_testing")]
+pub mod crash_recovery;
+#[cfg(any(feature = "full", feature = "verify"))]
 pub mod element;
 #[cfg(any(feature = "full", feature = "verify"))]
 pub mod error;
 #[cfg(feature = "full")]
 mod estimated_costs;
 #[cfg(any(feature = "full", feature = "verify"))]
+pub mod key_derivation;
+#[cfg(any(feature = "full", feature = "verify"))]
+pub mod key_ordering;
+#[cfg(any(feature = "full", feature = "verify"))]
+pub mod layout;
+#[cfg(feature = "full")]
+pub mod migrations;
+#[cfg(feature = "full")]
+mod open_paths;
+#[cfg(any(feature = "full", feature = "verify"))]
 pub mod operations;
 #[cfg(any(feature = "full", feature = "verify"))]
-mod query;
+pub mod query;
 #[cfg(any(feature = "full", feature = "verify"))]
 pub mod query_result_type;
 #[cfg(any(feature = "full", feature = "verify"))]
 pub mod reference_path;
 #[cfg(feature = "full")]
 mod replication;
+#[cfg(any(feature = "full", feature = "verify"))]
+pub mod sharded_tree;
+#[cfg(any(feature = "full", feature = "verify"))]
+pub mod subtree_path;
 #[cfg(feature = "full")]
 #[cfg(test)]
 mod tests;
+#[cfg(any(feature = "full", feature = "verify"))]
+pub mod time_index;
 #[cfg(feature = "full")]
 mod util;
 #[cfg(feature = "full")]
 mod visualize;
 
 #[cfg(feature = "full")]
-use std::{collections::HashMap, option::Option::None, path::Path};
+use std::{collections::HashMap, option::Option::None, path::Path, sync::Arc};
 
 #[cfg(feature = "full")]
 use ::visualize::DebugByteVectors;
+#[cfg(any(feature = "full", feature = "verify"))]
+pub use cold_storage::verify_archived_subtree_query;
+#[cfg(feature = "full")]
+pub use cold_storage::ArchivedSubtree;
 #[cfg(feature = "full")]
 use costs::{
     cost_return_on_error, cost_return_on_error_no_add, CostResult, CostsExt, OperationCost,
 };
 #[cfg(any(feature = "full", feature = "verify"))]
+pub use element::expiry::{expiring_flags, split_expiring_flags, Epoch, EXPIRY_FLAGS_MARKER};
+#[cfg(any(feature = "full", feature = "verify"))]
 use element::helpers;
 #[cfg(any(feature = "full", feature = "verify"))]
 pub use element::Element;
 #[cfg(feature = "full")]
 pub use element::ElementFlags;
-#[cfg(feature = "full")]
-pub use merk::estimated_costs::{
-    average_case_costs::{
-        EstimatedLayerCount, EstimatedLayerInformation, EstimatedLayerSizes, EstimatedSumTrees,
-    },
-    worst_case_costs::WorstCaseLayerInformation,
-};
 #[cfg(any(feature = "full", feature = "verify"))]
-pub use merk::proofs::query::query_item::QueryItem;
+pub use element::ElementKind;
 #[cfg(any(feature = "full", feature = "verify"))]
-pub use merk::proofs::Query;
+pub use key_derivation::{generate_id, KeyGenerator};
+#[cfg(any(feature = "full", feature = "verify"))]
+pub use key_ordering::KeyOrdering;
+#[cfg(any(feature = "full", feature = "verify"))]
+pub use merk::cost_constants::{
+    active_cost_constants, cost_constants, with_cost_constants, CostConstants, CostEpoch,
+    COST_CONSTANTS, COST_EPOCH,
+};
+#[cfg(any(feature = "full", feature = "verify"))]
+pub use merk::estimated_costs::average_case_costs::{
+    EstimatedLayerCount, EstimatedLayerInformation, EstimatedLayerSizes, EstimatedSumTrees,
+};
+#[cfg(feature = "full")]
+pub use merk::estimated_costs::worst_case_costs::WorstCaseLayerInformation;
+#[cfg(feature = "full")]
+pub use merk::NODE_VERSION;
 #[cfg(feature = "full")]
 use merk::{
     self,
     tree::{combine_hash, value_hash},
     BatchEntry, CryptoHash, KVIterator, Merk,
 };
+#[cfg(feature = "full")]
+pub use migrations::{MigrationPolicy, Migrations};
+#[cfg(feature = "full")]
+pub use operations::get::{ReferenceResolutionCache, ReferenceStep};
+#[cfg(feature = "full")]
+pub use operations::invariants::{InvariantReport, InvariantScope, InvariantViolation};
+#[cfg(feature = "full")]
+pub use operations::kv_stats::{LengthHistogram, SubtreeKvStats};
+#[cfg(feature = "full")]
+pub use operations::memory::MemoryStats;
+#[cfg(any(feature = "full", feature = "verify"))]
+pub use operations::proof::context::VerificationContext;
+#[cfg(feature = "full")]
+pub use operations::proof::root_cache::RootProofCacheStats;
 #[cfg(any(feature = "full", feature = "verify"))]
-pub use query::{PathQuery, SizedQuery};
+pub use operations::proof::verify::ProofStats;
+#[cfg(feature = "full")]
+pub use operations::query_log::{QueryItemKind, QueryLogEntry, QueryShape};
+#[cfg(feature = "full")]
+pub use operations::quota::{SubtreeQuota, SubtreeUsage};
+#[cfg(feature = "full")]
+pub use operations::repair::{RepairedSubtree, RepropagationReport};
+#[cfg(feature = "full")]
+pub use operations::reservation::SubtreeReservation;
+#[cfg(feature = "full")]
+pub use operations::scan::ScanOptions;
+#[cfg(feature = "full")]
+pub use operations::state_manifest::StateManifest;
+#[cfg(feature = "full")]
+pub use query::{PaginationKey, PaginationToken};
+#[cfg(any(feature = "full", feature = "verify"))]
+pub use query::{PathQuery, Query, QueryItem, QueryVersion, SizedQuery};
 #[cfg(feature = "full")]
 pub use replication::{BufferedRestorer, Restorer, SiblingsChunkProducer, SubtreeChunkProducer};
+#[cfg(any(feature = "full", feature = "verify"))]
+pub use sharded_tree::ShardedTree;
 #[cfg(feature = "full")]
-pub use storage::rocksdb_storage::RocksDbStorage;
+pub use storage::rocksdb_storage::{
+    PhysicalChanges, PhysicalColumnFamily, PhysicalOperation, ReplicationSink, RocksDbStorage,
+};
 #[cfg(feature = "full")]
 pub use storage::{
     rocksdb_storage::{self},
@@ -110,21 +180,65 @@ use storage::{
     },
     StorageBatch,
 };
+#[cfg(any(feature = "full", feature = "verify"))]
+pub use subtree_path::{SubtreePath, SubtreePathBuilder};
+#[cfg(any(feature = "full", feature = "verify"))]
+pub use time_index::TimeIndex;
+#[cfg(feature = "full")]
+pub use time_index::TimeIndexEntry;
 
 #[cfg(any(feature = "full", feature = "verify"))]
 pub use crate::error::Error;
 #[cfg(feature = "full")]
 use crate::helpers::raw_decode;
 #[cfg(feature = "full")]
+use crate::open_paths::OpenPathGuard;
+#[cfg(feature = "full")]
+use crate::operations::{
+    deep_hash::DeepHashCache, expiry::ExpiryClock, memory::MemoryAccounting,
+    proof::root_cache::RootProofCache, propagation::PropagationState, query_log::QueryLog,
+    strict::StrictMode, subtree_meta::SubtreeMetaFlags,
+};
+#[cfg(feature = "full")]
 use crate::util::{root_merk_optional_tx, storage_context_optional_tx};
 
 #[cfg(feature = "full")]
 type Hash = [u8; 32];
 
-/// GroveDb
+/// GroveDb. Cloning it is cheap and gives another handle on the same
+/// storage, settings and caches, every handle starting its own transactions.
+#[derive(Clone)]
 pub struct GroveDb {
     #[cfg(feature = "full")]
-    db: RocksDbStorage,
+    db: Arc<RocksDbStorage>,
+    /// Deferred propagation state
+    #[cfg(feature = "full")]
+    propagation: Arc<PropagationState>,
+    /// Memory budget and accounting
+    #[cfg(feature = "full")]
+    memory: Arc<MemoryAccounting>,
+    /// Current epoch for element expiry
+    #[cfg(feature = "full")]
+    expiry: Arc<ExpiryClock>,
+    /// Cached root layer proofs
+    #[cfg(feature = "full")]
+    root_proofs: Arc<RootProofCache>,
+    /// Log of executed query shapes
+    #[cfg(feature = "full")]
+    query_log: Arc<QueryLog>,
+    /// Strict mode setting
+    #[cfg(feature = "full")]
+    strict: Arc<StrictMode>,
+    /// Deep hashes of subtrees by root hash
+    #[cfg(feature = "full")]
+    deep_hashes: Arc<DeepHashCache>,
+    /// Kinds of per subtree metadata in use
+    #[cfg(feature = "full")]
+    subtree_meta: Arc<SubtreeMetaFlags>,
+    /// Registration of the path as open in this process, released with the
+    /// last handle
+    #[cfg(feature = "full")]
+    open_path: Option<Arc<OpenPathGuard>>,
 }
 
 /// Transaction
@@ -136,10 +250,29 @@ pub type TransactionArg<'db, 'a> = Option<&'a Transaction<'db>>;
 
 #[cfg(feature = "full")]
 impl GroveDb {
-    /// Opens a given path
-    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+    /// Opens a given path without registering it as open, fails if the
+    /// internal metadata doesn't match its integrity hash
+    fn open_unregistered<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
         let db = RocksDbStorage::default_rocksdb_with_path(path)?;
-        Ok(GroveDb { db })
+        let grove_db = GroveDb {
+            db: Arc::new(db),
+            propagation: Arc::default(),
+            memory: Arc::default(),
+            expiry: Arc::default(),
+            root_proofs: Arc::default(),
+            query_log: Arc::default(),
+            strict: Arc::default(),
+            deep_hashes: Arc::default(),
+            subtree_meta: Arc::default(),
+            open_path: None,
+        };
+        grove_db.verify_metadata(None).unwrap()?;
+        grove_db.init_root_leaves_hash().unwrap()?;
+        {
+            let meta_storage = grove_db.db.get_storage_context(std::iter::empty()).unwrap();
+            grove_db.subtree_meta.load(&meta_storage).unwrap()?;
+        }
+        Ok(grove_db)
     }
 
     /// Opens the transactional Merk at the given path. Returns CostResult.
@@ -148,6 +281,21 @@ impl GroveDb {
         path: P,
         tx: &'db Transaction,
     ) -> CostResult<Merk<PrefixedRocksDbTransactionContext<'db>>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + Clone,
+    {
+        self.open_transactional_merk_and_key_ordering_at_path(path, tx)
+            .map_ok(|(merk, _)| merk)
+    }
+
+    /// Opens the transactional Merk at the given path along with the key
+    /// ordering of the subtree. Returns CostResult.
+    pub(crate) fn open_transactional_merk_and_key_ordering_at_path<'db, 'p, P>(
+        &'db self,
+        path: P,
+        tx: &'db Transaction,
+    ) -> CostResult<(Merk<PrefixedRocksDbTransactionContext<'db>>, KeyOrdering), Error>
     where
         P: IntoIterator<Item = &'p [u8]>,
         <P as IntoIterator>::IntoIter: DoubleEndedIterator + Clone,
@@ -176,8 +324,13 @@ impl GroveDb {
                     })
                 );
                 let is_sum_tree = element.is_sum_tree();
-                if let Element::Tree(root_key, _) | Element::SumTree(root_key, ..) = element {
+                let key_ordering = element.key_ordering();
+                if let Element::Tree(root_key, _)
+                | Element::SumTree(root_key, ..)
+                | Element::OrderedTree(root_key, ..) = element
+                {
                     Merk::open_layered_with_root_key(storage, root_key, is_sum_tree)
+                        .map_ok(|merk| (merk, key_ordering))
                         .map_err(|_| {
                             Error::CorruptedData(
                                 "cannot open a subtree with given root key".to_owned(),
@@ -192,6 +345,7 @@ impl GroveDb {
                 }
             }
             None => Merk::open_base(storage, false)
+                .map_ok(|merk| (merk, KeyOrdering::default()))
                 .map_err(|_| Error::CorruptedData("cannot open a the root subtree".to_owned()))
                 .add_cost(cost),
         }
@@ -202,6 +356,20 @@ impl GroveDb {
         &self,
         path: P,
     ) -> CostResult<Merk<PrefixedRocksDbStorageContext>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + Clone,
+    {
+        self.open_non_transactional_merk_and_key_ordering_at_path(path)
+            .map_ok(|(merk, _)| merk)
+    }
+
+    /// Opens the non-transactional Merk at the given path along with the key
+    /// ordering of the subtree. Returns CostResult.
+    pub(crate) fn open_non_transactional_merk_and_key_ordering_at_path<'p, P>(
+        &self,
+        path: P,
+    ) -> CostResult<(Merk<PrefixedRocksDbStorageContext<'_>>, KeyOrdering), Error>
     where
         P: IntoIterator<Item = &'p [u8]>,
         <P as IntoIterator>::IntoIter: DoubleEndedIterator + Clone,
@@ -230,8 +398,13 @@ impl GroveDb {
                     })
                 );
                 let is_sum_tree = element.is_sum_tree();
-                if let Element::Tree(root_key, _) | Element::SumTree(root_key, ..) = element {
+                let key_ordering = element.key_ordering();
+                if let Element::Tree(root_key, _)
+                | Element::SumTree(root_key, ..)
+                | Element::OrderedTree(root_key, ..) = element
+                {
                     Merk::open_layered_with_root_key(storage, root_key, is_sum_tree)
+                        .map_ok(|merk| (merk, key_ordering))
                         .map_err(|_| {
                             Error::CorruptedData(
                                 "cannot open a subtree with given root key".to_owned(),
@@ -246,6 +419,7 @@ impl GroveDb {
                 }
             }
             None => Merk::open_base(storage, false)
+                .map_ok(|merk| (merk, KeyOrdering::default()))
                 .map_err(|_| Error::CorruptedData("cannot open a the root subtree".to_owned()))
                 .add_cost(cost),
         }
@@ -271,11 +445,19 @@ impl GroveDb {
 
     /// Returns root hash of GroveDb.
     /// Will be `None` if GroveDb is empty.
+    /// Pending propagations of the transaction are accounted for, but not
+    /// written.
     pub fn root_hash(&self, transaction: TransactionArg) -> CostResult<Hash, Error> {
         let mut cost = OperationCost {
             ..Default::default()
         };
 
+        if let Some(tx) = transaction {
+            if let Some(root_hash) = cost_return_on_error!(&mut cost, self.pending_root_hash(tx)) {
+                return Ok(root_hash).wrap_with_cost(cost);
+            }
+        }
+
         root_merk_optional_tx!(&mut cost, self.db, transaction, subtree, {
             let root_hash = subtree.root_hash().unwrap_add_cost(&mut cost);
             Ok(root_hash).wrap_with_cost(cost)
@@ -470,6 +652,9 @@ impl GroveDb {
             if let Element::Tree(_, flag) = element {
                 let tree = Element::new_tree_with_flags(maybe_root_key, flag);
                 tree.insert_subtree(parent_tree, key.as_ref(), root_tree_hash, None)
+            } else if let Element::OrderedTree(_, key_ordering, flag) = element {
+                let tree = Element::new_ordered_tree_with_flags(maybe_root_key, key_ordering, flag);
+                tree.insert_subtree(parent_tree, key.as_ref(), root_tree_hash, None)
             } else if let Element::SumTree(.., flag) = element {
                 let tree = Element::new_sum_tree_with_flags_and_sum_value(
                     maybe_root_key,
@@ -516,6 +701,20 @@ impl GroveDb {
                     batch_operations,
                     merk_feature_type,
                 )
+            } else if let Element::OrderedTree(_, key_ordering, flag) = element {
+                let tree = Element::new_ordered_tree_with_flags(maybe_root_key, key_ordering, flag);
+                let merk_feature_type = cost_return_on_error!(
+                    &mut cost,
+                    tree.get_feature_type(parent_tree.is_sum_tree)
+                        .wrap_with_cost(OperationCost::default())
+                );
+                tree.insert_subtree_into_batch_operations(
+                    key,
+                    root_tree_hash,
+                    true,
+                    batch_operations,
+                    merk_feature_type,
+                )
             } else if let Element::SumTree(.., flag) = element {
                 let tree = Element::new_sum_tree_with_flags_and_sum_value(
                     maybe_root_key,
@@ -634,10 +833,19 @@ impl GroveDb {
         self.db.start_transaction()
     }
 
-    /// Commits previously started db transaction. For more details on the
+    /// Commits previously started db transaction. Pending propagations of
+    /// the transaction are applied first. For more details on the
     /// transaction usage, please check [`GroveDb::start_transaction`]
     pub fn commit_transaction(&self, transaction: Transaction) -> CostResult<(), Error> {
-        self.db.commit_transaction(transaction).map_err(Into::into)
+        let mut cost = OperationCost::default();
+        cost_return_on_error!(&mut cost, self.propagate_pending_changes(&transaction));
+        cost_return_on_error!(
+            &mut cost,
+            self.db.commit_transaction(transaction).map_err(Into::into)
+        );
+        self.root_proofs.clear();
+        self.enforce_memtable_budget_after_write();
+        Ok(()).wrap_with_cost(cost)
     }
 
     /// Rollbacks previously started db transaction to initial state.
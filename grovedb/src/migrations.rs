@@ -0,0 +1,404 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Migrations
+//! Embedders register ordered migrations, each changing the database within
+//! its own transaction. The ids of applied migrations are recorded in the
+//! metadata together with those changes, so a database can be opened either
+//! running the migrations still pending or refusing to open until they are.
+
+use std::{collections::BTreeSet, path::Path};
+
+use bincode::Options;
+use costs::{
+    cost_return_on_error, cost_return_on_error_no_add, CostResult, CostsExt, OperationCost,
+};
+
+use crate::{
+    operations::metadata::{get_internal_meta, put_internal_meta},
+    util::meta_storage_context_optional_tx,
+    Error, GroveDb, Transaction, TransactionArg,
+};
+
+/// Meta storage key under which the ids of applied migrations are kept
+pub(crate) const APPLIED_MIGRATIONS_KEY: &[u8] = b"applied_migrations";
+
+/// Changes of a migration, made within the transaction it is given
+type MigrationFn =
+    Box<dyn for<'db> Fn(&GroveDb, &Transaction<'db>) -> Result<(), Error> + Send + Sync>;
+
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::default()
+        .with_varint_encoding()
+        .reject_trailing_bytes()
+}
+
+/// A registered migration
+struct Migration {
+    id: u64,
+    name: &'static str,
+    apply: MigrationFn,
+}
+
+/// Ordered migrations registered by an embedder
+#[derive(Default)]
+pub struct Migrations {
+    migrations: Vec<Migration>,
+}
+
+impl Migrations {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a migration to run after the ones registered before it.
+    /// Ids must be strictly increasing in registration order, and the id of
+    /// a migration that was run somewhere must never be reused.
+    pub fn add<F>(mut self, id: u64, name: &'static str, apply: F) -> Self
+    where
+        F: for<'db> Fn(&GroveDb, &Transaction<'db>) -> Result<(), Error> + Send + Sync + 'static,
+    {
+        self.migrations.push(Migration {
+            id,
+            name,
+            apply: Box::new(apply),
+        });
+        self
+    }
+
+    /// Ids of the registered migrations in order
+    pub fn ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.migrations.iter().map(|migration| migration.id)
+    }
+
+    fn check_order(&self) -> Result<(), Error> {
+        if self
+            .migrations
+            .windows(2)
+            .all(|pair| pair[0].id < pair[1].id)
+        {
+            Ok(())
+        } else {
+            Err(Error::InvalidInput(
+                "migration ids must be strictly increasing",
+            ))
+        }
+    }
+}
+
+/// What opening a database does about migrations not applied yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationPolicy {
+    /// Run the pending migrations in order before returning the database
+    Run,
+    /// Fail with `Error::PendingMigrations` if any migration is pending
+    Refuse,
+}
+
+impl GroveDb {
+    /// Opens a given path exclusively like [`GroveDb::open`], then runs the
+    /// pending migrations or refuses to open if there are any, depending on
+    /// the policy
+    pub fn open_with_migrations<P: AsRef<Path>>(
+        path: P,
+        migrations: &Migrations,
+        policy: MigrationPolicy,
+    ) -> Result<Self, Error> {
+        let grove_db = Self::open(path)?;
+        match policy {
+            MigrationPolicy::Run => {
+                grove_db.run_migrations(migrations)?;
+            }
+            MigrationPolicy::Refuse => {
+                let pending = grove_db.pending_migrations(migrations)?;
+                if !pending.is_empty() {
+                    return Err(Error::PendingMigrations(format!("{:?}", pending)));
+                }
+            }
+        }
+        Ok(grove_db)
+    }
+
+    /// Ids of the registered migrations not applied to the database yet, in
+    /// the order they would run. Fails if a migration the registry doesn't
+    /// know was applied, or if a pending migration precedes an applied one.
+    pub fn pending_migrations(&self, migrations: &Migrations) -> Result<Vec<u64>, Error> {
+        migrations.check_order()?;
+        let applied = self.applied_migrations(None).unwrap()?;
+        let registered: BTreeSet<u64> = migrations.ids().collect();
+        let unregistered: Vec<u64> = applied.difference(&registered).copied().collect();
+        if !unregistered.is_empty() {
+            return Err(Error::MigrationsMismatch(format!(
+                "applied migrations {:?} aren't registered",
+                unregistered
+            )));
+        }
+        let pending: Vec<u64> = registered.difference(&applied).copied().collect();
+        if let (Some(first_pending), Some(last_applied)) = (pending.first(), applied.last()) {
+            if first_pending < last_applied {
+                return Err(Error::MigrationsMismatch(format!(
+                    "migration {} is pending but migration {} was applied",
+                    first_pending, last_applied
+                )));
+            }
+        }
+        Ok(pending)
+    }
+
+    /// Runs the pending migrations in order, each one in its own transaction
+    /// committed together with the record of its id. Stops at the first
+    /// failing migration, whose changes are rolled back, and returns the ids
+    /// of the migrations run otherwise.
+    pub fn run_migrations(&self, migrations: &Migrations) -> Result<Vec<u64>, Error> {
+        let pending = self.pending_migrations(migrations)?;
+        for migration in migrations
+            .migrations
+            .iter()
+            .filter(|migration| pending.contains(&migration.id))
+        {
+            let transaction = self.start_transaction();
+            (migration.apply)(self, &transaction).map_err(|e| {
+                Error::MigrationFailed(format!("{} {}: {}", migration.id, migration.name, e))
+            })?;
+            let mut applied = self.applied_migrations(Some(&transaction)).unwrap()?;
+            applied.insert(migration.id);
+            self.write_applied_migrations(&applied, Some(&transaction))
+                .unwrap()?;
+            self.commit_transaction(transaction).unwrap()?;
+        }
+        Ok(pending)
+    }
+
+    /// Ids of the migrations applied to the database
+    pub fn applied_migrations(
+        &self,
+        transaction: TransactionArg,
+    ) -> CostResult<BTreeSet<u64>, Error> {
+        let mut cost = OperationCost::default();
+        let maybe_bytes = meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            let meta_storage = meta_storage.unwrap_add_cost(&mut cost);
+            cost_return_on_error!(
+                &mut cost,
+                get_internal_meta(&meta_storage, APPLIED_MIGRATIONS_KEY)
+            )
+        });
+        match maybe_bytes {
+            Some(bytes) => bincode_options()
+                .deserialize(&bytes)
+                .map_err(|_| Error::CorruptedData("applied migrations are corrupted".to_owned())),
+            None => Ok(BTreeSet::new()),
+        }
+        .wrap_with_cost(cost)
+    }
+
+    fn write_applied_migrations(
+        &self,
+        applied: &BTreeSet<u64>,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+        let bytes = cost_return_on_error_no_add!(
+            &cost,
+            bincode_options().serialize(applied).map_err(|_| {
+                Error::CorruptedData("unable to serialize applied migrations".to_owned())
+            })
+        );
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            let meta_storage = meta_storage.unwrap_add_cost(&mut cost);
+            put_internal_meta(&meta_storage, APPLIED_MIGRATIONS_KEY, bytes)
+        })
+        .add_cost(cost)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::Element;
+
+    fn migrations(runs: Arc<AtomicUsize>) -> Migrations {
+        Migrations::new()
+            .add(1, "add documents tree", |db, transaction| {
+                db.insert(
+                    [],
+                    b"documents",
+                    Element::empty_tree(),
+                    None,
+                    Some(transaction),
+                )
+                .unwrap()
+            })
+            .add(2, "add first document", move |db, transaction| {
+                runs.fetch_add(1, Ordering::SeqCst);
+                db.insert(
+                    [b"documents".as_slice()],
+                    b"first",
+                    Element::new_item(b"doc".to_vec()),
+                    None,
+                    Some(transaction),
+                )
+                .unwrap()
+            })
+    }
+
+    #[test]
+    fn test_migrations_run_once_in_order() {
+        let tmp_dir = TempDir::new().unwrap();
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        assert!(matches!(
+            GroveDb::open_with_migrations(
+                tmp_dir.path(),
+                &migrations(runs.clone()),
+                MigrationPolicy::Refuse
+            ),
+            Err(Error::PendingMigrations(_))
+        ));
+
+        let db = GroveDb::open_with_migrations(
+            tmp_dir.path(),
+            &migrations(runs.clone()),
+            MigrationPolicy::Run,
+        )
+        .expect("should open running migrations");
+        assert_eq!(
+            db.get([b"documents".as_slice()], b"first", None)
+                .unwrap()
+                .expect("should get migrated item"),
+            Element::new_item(b"doc".to_vec())
+        );
+        assert_eq!(
+            db.applied_migrations(None).unwrap().expect("should read"),
+            BTreeSet::from([1, 2])
+        );
+        assert_eq!(
+            db.run_migrations(&migrations(runs.clone()))
+                .expect("should run nothing"),
+            Vec::<u64>::new()
+        );
+        drop(db);
+
+        GroveDb::open_with_migrations(
+            tmp_dir.path(),
+            &migrations(runs.clone()),
+            MigrationPolicy::Refuse,
+        )
+        .expect("should open with every migration applied");
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_failed_migration_is_rolled_back() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = GroveDb::open(tmp_dir.path()).expect("should open");
+        let failing = Migrations::new()
+            .add(1, "add tree", |db, transaction| {
+                db.insert([], b"tree", Element::empty_tree(), None, Some(transaction))
+                    .unwrap()
+            })
+            .add(2, "half done", |db, transaction| {
+                db.insert(
+                    [],
+                    b"partial",
+                    Element::empty_tree(),
+                    None,
+                    Some(transaction),
+                )
+                .unwrap()?;
+                Err(Error::InternalError("cannot finish"))
+            });
+
+        assert!(matches!(
+            db.run_migrations(&failing),
+            Err(Error::MigrationFailed(_))
+        ));
+        assert_eq!(
+            db.applied_migrations(None).unwrap().expect("should read"),
+            BTreeSet::from([1])
+        );
+        assert!(db.get([], b"tree", None).unwrap().is_ok());
+        assert!(db.get([], b"partial", None).unwrap().is_err());
+
+        let unordered =
+            Migrations::new()
+                .add(2, "second", |_, _| Ok(()))
+                .add(1, "first", |_, _| Ok(()));
+        assert!(matches!(
+            db.pending_migrations(&unordered),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_applied_migrations_must_match_the_registry() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = GroveDb::open(tmp_dir.path()).expect("should open");
+        db.run_migrations(&Migrations::new().add(1, "first", |_, _| Ok(())).add(
+            3,
+            "third",
+            |_, _| Ok(()),
+        ))
+        .expect("should run migrations");
+
+        let inserted_before_applied = Migrations::new()
+            .add(1, "first", |_, _| Ok(()))
+            .add(2, "second", |_, _| Ok(()))
+            .add(3, "third", |_, _| Ok(()));
+        assert!(matches!(
+            db.pending_migrations(&inserted_before_applied),
+            Err(Error::MigrationsMismatch(_))
+        ));
+
+        let missing_applied =
+            Migrations::new()
+                .add(1, "first", |_, _| Ok(()))
+                .add(4, "fourth", |_, _| Ok(()));
+        assert!(matches!(
+            db.run_migrations(&missing_applied),
+            Err(Error::MigrationsMismatch(_))
+        ));
+        drop(db);
+
+        assert!(matches!(
+            GroveDb::open_with_migrations(
+                tmp_dir.path(),
+                &inserted_before_applied,
+                MigrationPolicy::Refuse
+            ),
+            Err(Error::MigrationsMismatch(_))
+        ));
+    }
+}
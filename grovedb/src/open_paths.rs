@@ -0,0 +1,231 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Open paths
+//! In-process registry of the paths of open databases. A path can be opened
+//! either exclusively with [`GroveDb::open`] or as a handle shared by every
+//! caller of [`GroveDb::open_shared`], opening a path that is already open any
+//! other way fails with `Error::AlreadyOpen` instead of having two instances
+//! write to the same storage. The registry is only locked to claim or update
+//! an entry, not while the storage is being opened.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, MutexGuard, Weak},
+};
+
+use crate::{Error, GroveDb};
+
+/// How an open path is held
+enum OpenPath {
+    /// Opened with [`GroveDb::open`]
+    Exclusive,
+    /// Being opened with [`GroveDb::open_shared`]
+    OpeningShared,
+    /// Opened with [`GroveDb::open_shared`]
+    Shared(Weak<GroveDb>),
+}
+
+/// Canonical paths of the databases open in this process
+static OPEN_PATHS: Mutex<BTreeMap<PathBuf, OpenPath>> = Mutex::new(BTreeMap::new());
+
+/// Notified whenever a path stops being opened, either because its entry was
+/// finalized or removed
+static OPEN_PATHS_CHANGED: Condvar = Condvar::new();
+
+fn lock_open_paths() -> MutexGuard<'static, BTreeMap<PathBuf, OpenPath>> {
+    OPEN_PATHS.lock().expect("open paths lock is poisoned")
+}
+
+/// Registration of an open path, removed from the registry when dropped. It is
+/// taken before the storage is opened so a failed open releases the path, then
+/// shared by the clones of the handle. It is their last field, so the storage
+/// is closed by the time the last clone drops it.
+pub(crate) struct OpenPathGuard {
+    path: PathBuf,
+}
+
+impl Drop for OpenPathGuard {
+    fn drop(&mut self) {
+        if let Ok(mut open_paths) = OPEN_PATHS.lock() {
+            open_paths.remove(&self.path);
+        }
+        OPEN_PATHS_CHANGED.notify_all();
+    }
+}
+
+/// Resolves the path the database would be opened at, creating its directory
+/// if needed so it can be canonicalized
+fn canonical_path<P: AsRef<Path>>(path: P) -> Result<PathBuf, Error> {
+    std::fs::create_dir_all(&path)
+        .and_then(|_| path.as_ref().canonicalize())
+        .map_err(|e| Error::InvalidPath(format!("cannot resolve database path: {}", e)))
+}
+
+fn already_open(path: &Path) -> Error {
+    Error::AlreadyOpen(path.display().to_string())
+}
+
+impl GroveDb {
+    /// Opens a given path exclusively, fails with `Error::AlreadyOpen` if the
+    /// path is already open in this process
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = canonical_path(path)?;
+        let open_path = {
+            let mut open_paths = lock_open_paths();
+            if open_paths.contains_key(&path) {
+                return Err(already_open(&path));
+            }
+            open_paths.insert(path.clone(), OpenPath::Exclusive);
+            Arc::new(OpenPathGuard { path: path.clone() })
+        };
+        let mut grove_db = Self::open_unregistered(&path)?;
+        grove_db.open_path = Some(open_path);
+        Ok(grove_db)
+    }
+
+    /// Opens a given path as a handle shared with every other caller opening
+    /// the same path this way, fails with `Error::AlreadyOpen` if the path
+    /// was opened exclusively. Callers opening a path another caller is
+    /// already opening this way wait for that open to complete.
+    pub fn open_shared<P: AsRef<Path>>(path: P) -> Result<Arc<Self>, Error> {
+        let path = canonical_path(path)?;
+        let open_path = {
+            let mut open_paths = lock_open_paths();
+            loop {
+                match open_paths.get(&path) {
+                    Some(OpenPath::Shared(grove_db)) => {
+                        // a handle that can't be upgraded is being closed, or
+                        // only survives through clones of it
+                        return grove_db.upgrade().ok_or_else(|| already_open(&path));
+                    }
+                    Some(OpenPath::Exclusive) => return Err(already_open(&path)),
+                    Some(OpenPath::OpeningShared) => {
+                        open_paths = OPEN_PATHS_CHANGED
+                            .wait(open_paths)
+                            .expect("open paths lock is poisoned");
+                    }
+                    None => break,
+                }
+            }
+            open_paths.insert(path.clone(), OpenPath::OpeningShared);
+            Arc::new(OpenPathGuard { path: path.clone() })
+        };
+        let mut grove_db = Self::open_unregistered(&path)?;
+        grove_db.open_path = Some(open_path);
+        let grove_db = Arc::new(grove_db);
+        lock_open_paths().insert(path, OpenPath::Shared(Arc::downgrade(&grove_db)));
+        OPEN_PATHS_CHANGED.notify_all();
+        Ok(grove_db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::Element;
+
+    #[test]
+    fn test_open_same_path_twice() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = GroveDb::open(tmp_dir.path()).expect("should open");
+        assert!(matches!(
+            GroveDb::open(tmp_dir.path()),
+            Err(Error::AlreadyOpen(_))
+        ));
+        assert!(matches!(
+            GroveDb::open_shared(tmp_dir.path().join(".")),
+            Err(Error::AlreadyOpen(_))
+        ));
+        drop(db);
+
+        let shared = GroveDb::open_shared(tmp_dir.path()).expect("should open shared");
+        let other = GroveDb::open_shared(tmp_dir.path()).expect("should open shared");
+        assert!(Arc::ptr_eq(&shared, &other));
+        assert!(matches!(
+            GroveDb::open(tmp_dir.path()),
+            Err(Error::AlreadyOpen(_))
+        ));
+        drop(shared);
+        drop(other);
+
+        GroveDb::open(tmp_dir.path()).expect("should open once closed");
+    }
+
+    #[test]
+    fn test_concurrent_shared_opens() {
+        let tmp_dir = TempDir::new().unwrap();
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let path = tmp_dir.path().to_path_buf();
+                std::thread::spawn(move || GroveDb::open_shared(path).expect("should open shared"))
+            })
+            .collect();
+        let dbs: Vec<_> = handles
+            .into_iter()
+            .map(|handle| handle.join().expect("should join"))
+            .collect();
+        assert!(dbs.iter().all(|db| Arc::ptr_eq(db, &dbs[0])));
+        drop(dbs);
+
+        GroveDb::open(tmp_dir.path()).expect("should open once closed");
+    }
+
+    #[test]
+    fn test_cloned_handles() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = GroveDb::open(tmp_dir.path()).expect("should open");
+        let handle = db.clone();
+
+        let transaction = db.start_transaction();
+        let handle_transaction = handle.start_transaction();
+        db.insert([], b"leaf", Element::empty_tree(), None, Some(&transaction))
+            .unwrap()
+            .expect("should insert in transaction");
+        assert!(handle
+            .get_raw([], b"leaf", Some(&handle_transaction))
+            .unwrap()
+            .is_err());
+        db.commit_transaction(transaction)
+            .unwrap()
+            .expect("should commit");
+        assert!(handle.get_raw([], b"leaf", None).unwrap().is_ok());
+        drop(handle_transaction);
+
+        drop(db);
+        assert!(matches!(
+            GroveDb::open(tmp_dir.path()),
+            Err(Error::AlreadyOpen(_))
+        ));
+        drop(handle);
+        GroveDb::open(tmp_dir.path()).expect("should open once every handle is dropped");
+    }
+}
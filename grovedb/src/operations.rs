@@ -31,12 +31,48 @@
 #[cfg(feature = "full")]
 pub(crate) mod auxiliary;
 #[cfg(feature = "full")]
+pub(crate) mod clone_structure;
+#[cfg(feature = "full")]
+pub mod deep_hash;
+#[cfg(feature = "full")]
 pub mod delete;
 #[cfg(feature = "full")]
+pub(crate) mod dry_run;
+#[cfg(feature = "full")]
+pub(crate) mod expiry;
+#[cfg(feature = "full")]
 pub(crate) mod get;
 #[cfg(feature = "full")]
 pub mod insert;
 #[cfg(feature = "full")]
+pub mod invariants;
+#[cfg(feature = "full")]
 pub(crate) mod is_empty_tree;
+#[cfg(feature = "full")]
+pub mod kv_stats;
+#[cfg(feature = "full")]
+pub mod memory;
+#[cfg(feature = "full")]
+pub(crate) mod metadata;
 #[cfg(any(feature = "full", feature = "verify"))]
 pub mod proof;
+#[cfg(feature = "full")]
+pub(crate) mod propagation;
+#[cfg(feature = "full")]
+pub mod query_log;
+#[cfg(feature = "full")]
+pub mod quota;
+#[cfg(feature = "full")]
+pub mod read_only;
+#[cfg(feature = "full")]
+pub mod repair;
+#[cfg(feature = "full")]
+pub mod reservation;
+#[cfg(feature = "full")]
+pub mod scan;
+#[cfg(feature = "full")]
+pub mod state_manifest;
+#[cfg(feature = "full")]
+pub mod strict;
+#[cfg(feature = "full")]
+pub(crate) mod subtree_meta;
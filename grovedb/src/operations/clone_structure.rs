@@ -0,0 +1,310 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Subtree structure cloning
+//! Copies the tree of trees under a template subtree as empty subtrees with
+//! the same kinds and flags, leaving out every item and reference, so a new
+//! layout can be stamped out of a template in a single batch.
+
+#[cfg(feature = "full")]
+use costs::{cost_return_on_error, CostResult, CostsExt, OperationCost};
+#[cfg(feature = "full")]
+use storage::StorageContext;
+
+#[cfg(feature = "full")]
+use crate::{
+    batch::GroveDbOp, util::storage_context_optional_tx, Element, Error, GroveDb, TransactionArg,
+};
+
+#[cfg(feature = "full")]
+/// Empty subtree of the same kind and with the same flags as the tree element,
+/// `None` if the element isn't a tree
+fn empty_tree_like(element: &Element) -> Option<Element> {
+    match element {
+        Element::Tree(_, flags) => Some(Element::empty_tree_with_flags(flags.clone())),
+        Element::SumTree(_, _, flags) => Some(Element::empty_sum_tree_with_flags(flags.clone())),
+        Element::OrderedTree(_, key_ordering, flags) => Some(
+            Element::empty_ordered_tree_with_flags(*key_ordering, flags.clone()),
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Creates at `dst_path` an empty copy of the structure of the subtree at
+    /// `src_path`, see
+    /// [`clone_subtree_structure_operations`](GroveDb::clone_subtree_structure_operations)
+    pub fn clone_subtree_structure<'p, P, Q>(
+        &self,
+        src_path: P,
+        dst_path: Q,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        Q: IntoIterator<Item = &'p [u8]>,
+    {
+        let mut cost = OperationCost::default();
+
+        let ops = cost_return_on_error!(
+            &mut cost,
+            self.clone_subtree_structure_operations(src_path, dst_path, transaction)
+        );
+        self.apply_batch(ops, None, transaction).add_cost(cost)
+    }
+
+    /// Batch operations inserting at `dst_path` an empty subtree of the same
+    /// kind and with the same flags as the one at `src_path`, and under it the
+    /// same for every subtree nested in the source at any depth. Items and
+    /// references of the source are not copied. The parent of `dst_path` must
+    /// exist by the time the operations are applied, and `dst_path` can't be
+    /// inside the source.
+    pub fn clone_subtree_structure_operations<'p, P, Q>(
+        &self,
+        src_path: P,
+        dst_path: Q,
+        transaction: TransactionArg,
+    ) -> CostResult<Vec<GroveDbOp>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        Q: IntoIterator<Item = &'p [u8]>,
+    {
+        let mut cost = OperationCost::default();
+
+        let src_path: Vec<Vec<u8>> = src_path.into_iter().map(|x| x.to_vec()).collect();
+        let dst_path: Vec<Vec<u8>> = dst_path.into_iter().map(|x| x.to_vec()).collect();
+        let ((src_key, src_parent), (dst_key, dst_parent)) =
+            match (src_path.split_last(), dst_path.split_last()) {
+                (Some(src), Some(dst)) => (src, dst),
+                _ => {
+                    return Err(Error::InvalidInput(
+                        "the root tree structure can't be cloned",
+                    ))
+                    .wrap_with_cost(cost)
+                }
+            };
+        if dst_path.starts_with(&src_path) {
+            return Err(Error::InvalidInput(
+                "the structure of a subtree can't be cloned inside of it",
+            ))
+            .wrap_with_cost(cost);
+        }
+
+        let src_element = cost_return_on_error!(
+            &mut cost,
+            self.get_raw(
+                src_parent.iter().map(|x| x.as_slice()),
+                src_key,
+                transaction
+            )
+        );
+        let dst_element = match empty_tree_like(&src_element) {
+            Some(element) => element,
+            None => {
+                return Err(Error::WrongElementType(
+                    "the structure of an element that isn't a tree can't be cloned",
+                ))
+                .wrap_with_cost(cost)
+            }
+        };
+
+        let mut ops = vec![GroveDbOp::insert_op(
+            dst_parent.to_vec(),
+            dst_key.to_vec(),
+            dst_element,
+        )];
+        let mut queue = vec![(src_path.clone(), dst_path.clone())];
+        while let Some((src_subtree_path, dst_subtree_path)) = queue.pop() {
+            let path_iter = src_subtree_path.iter().map(|x| x.as_slice());
+            storage_context_optional_tx!(self.db, path_iter, transaction, storage, {
+                let storage = storage.unwrap_add_cost(&mut cost);
+                let mut raw_iter = Element::iterator(storage.raw_iter()).unwrap_add_cost(&mut cost);
+                while let Some((key, element)) =
+                    cost_return_on_error!(&mut cost, raw_iter.next_element())
+                {
+                    if let Some(empty_tree) = empty_tree_like(&element) {
+                        ops.push(GroveDbOp::insert_op(
+                            dst_subtree_path.clone(),
+                            key.clone(),
+                            empty_tree,
+                        ));
+                        let mut src_child_path = src_subtree_path.clone();
+                        src_child_path.push(key.clone());
+                        let mut dst_child_path = dst_subtree_path.clone();
+                        dst_child_path.push(key);
+                        queue.push((src_child_path, dst_child_path));
+                    }
+                }
+            })
+        }
+        Ok(ops).wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{make_test_grovedb, ANOTHER_TEST_LEAF, TEST_LEAF};
+
+    #[test]
+    fn test_clone_subtree_structure_without_items() {
+        let db = make_test_grovedb();
+        let flags = Some(b"index".to_vec());
+        db.insert(
+            [TEST_LEAF],
+            b"template",
+            Element::empty_tree_with_flags(flags.clone()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert template");
+        db.insert(
+            [TEST_LEAF, b"template"],
+            b"by_owner",
+            Element::empty_sum_tree(),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert index");
+        db.insert(
+            [TEST_LEAF, b"template", b"by_owner"],
+            b"owner_1",
+            Element::empty_tree_with_flags(flags.clone()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert nested index");
+        db.insert(
+            [TEST_LEAF, b"template", b"by_owner", b"owner_1"],
+            b"doc",
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+        db.insert(
+            [TEST_LEAF, b"template", b"by_owner"],
+            b"count",
+            Element::new_sum_item(3),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert sum item");
+
+        // the clone goes in a batch with the rest of the new document type
+        let mut ops = db
+            .clone_subtree_structure_operations(
+                [TEST_LEAF, b"template"],
+                [ANOTHER_TEST_LEAF, b"documents"],
+                None,
+            )
+            .unwrap()
+            .expect("should build clone operations");
+        assert_eq!(ops.len(), 3);
+        ops.push(GroveDbOp::insert_op(
+            vec![ANOTHER_TEST_LEAF.to_vec()],
+            b"name".to_vec(),
+            Element::new_item(b"documents".to_vec()),
+        ));
+        db.apply_batch(ops, None, None)
+            .unwrap()
+            .expect("should apply clone");
+
+        assert_eq!(
+            db.get([ANOTHER_TEST_LEAF], b"documents", None)
+                .unwrap()
+                .expect("should get clone"),
+            Element::empty_tree_with_flags(flags.clone())
+        );
+        assert_eq!(
+            db.get([ANOTHER_TEST_LEAF, b"documents"], b"by_owner", None)
+                .unwrap()
+                .expect("should get cloned index"),
+            Element::empty_sum_tree()
+        );
+        assert_eq!(
+            db.get(
+                [ANOTHER_TEST_LEAF, b"documents", b"by_owner"],
+                b"owner_1",
+                None
+            )
+            .unwrap()
+            .expect("should get cloned nested index"),
+            Element::empty_tree_with_flags(flags)
+        );
+        assert!(db
+            .is_empty_tree(
+                [ANOTHER_TEST_LEAF, b"documents", b"by_owner", b"owner_1"],
+                None
+            )
+            .unwrap()
+            .expect("should check cloned nested index"));
+        assert!(matches!(
+            db.get(
+                [ANOTHER_TEST_LEAF, b"documents", b"by_owner"],
+                b"count",
+                None
+            )
+            .unwrap(),
+            Err(Error::PathKeyNotFound(_))
+        ));
+
+        assert!(matches!(
+            db.clone_subtree_structure(
+                [TEST_LEAF, b"template"],
+                [TEST_LEAF, b"template", b"copy"],
+                None
+            )
+            .unwrap(),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            db.clone_subtree_structure(
+                [TEST_LEAF, b"template", b"by_owner", b"owner_1", b"doc"],
+                [TEST_LEAF, b"copy"],
+                None
+            )
+            .unwrap(),
+            Err(Error::WrongElementType(_))
+        ));
+        db.clone_subtree_structure([TEST_LEAF, b"template"], [TEST_LEAF, b"copy"], None)
+            .unwrap()
+            .expect("should clone next to the template");
+        assert!(db
+            .is_empty_tree([TEST_LEAF, b"copy", b"by_owner", b"owner_1"], None)
+            .unwrap()
+            .expect("should check cloned nested index"));
+    }
+}
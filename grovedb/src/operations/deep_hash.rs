@@ -0,0 +1,347 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Deep hashes
+//! Besides the merk root hash, which depends on the shape of the AVL tree and
+//! so on the order of insertions, a subtree can be summarized by a deep hash:
+//! an order independent hash of all of its keys and elements, descendant
+//! subtrees included. Two databases holding the same data have the same deep
+//! hashes, which makes them quick to compare across non consensus
+//! deployments. Deep hashes are computed on demand and, when tracking is on,
+//! kept per subtree root hash so that only changed subtrees are hashed again.
+
+#[cfg(feature = "full")]
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+#[cfg(feature = "full")]
+use costs::{
+    cost_return_on_error, cost_return_on_error_no_add, CostResult, CostsExt, OperationCost,
+};
+#[cfg(feature = "full")]
+use merk::{
+    tree::{combine_hash, kv_digest_to_kv_hash, kv_hash, value_hash},
+    CryptoHash,
+};
+
+#[cfg(feature = "full")]
+use crate::{operations::scan::ScanOptions, Element, Error, GroveDb, TransactionArg};
+
+#[cfg(feature = "full")]
+/// Maximum number of deep hashes kept while tracking
+pub const DEEP_HASH_CACHE_CAPACITY: usize = 4096;
+
+#[cfg(feature = "full")]
+#[derive(Default)]
+/// Deep hashes of subtrees of a GroveDb instance, by subtree root hash. The
+/// root hash commits to the whole content of a subtree, so an entry stays
+/// valid as long as the subtree has that root hash.
+pub(crate) struct DeepHashCache {
+    tracking: AtomicBool,
+    entries: Mutex<HashMap<CryptoHash, CryptoHash>>,
+}
+
+#[cfg(feature = "full")]
+impl DeepHashCache {
+    fn get(&self, root_hash: &CryptoHash) -> Option<CryptoHash> {
+        self.entries
+            .lock()
+            .expect("deep hash cache lock is poisoned")
+            .get(root_hash)
+            .copied()
+    }
+
+    fn insert(&self, root_hash: CryptoHash, deep_hash: CryptoHash) {
+        let mut entries = self
+            .entries
+            .lock()
+            .expect("deep hash cache lock is poisoned");
+        if entries.len() >= DEEP_HASH_CACHE_CAPACITY {
+            entries.clear();
+        }
+        entries.insert(root_hash, deep_hash);
+    }
+}
+
+#[cfg(feature = "full")]
+/// Order independent accumulation of hashes: their sum modulo 2^256
+#[derive(Default)]
+struct HashSum {
+    limbs: [u64; 4],
+    count: u64,
+}
+
+#[cfg(feature = "full")]
+impl HashSum {
+    fn add(&mut self, hash: &CryptoHash) {
+        let mut carry = false;
+        for (limb, chunk) in self.limbs.iter_mut().zip(hash.chunks_exact(8)) {
+            let value = u64::from_le_bytes(chunk.try_into().expect("chunks are 8 bytes"));
+            let (sum, overflow_one) = limb.overflowing_add(value);
+            let (sum, overflow_two) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = overflow_one || overflow_two;
+        }
+        self.count += 1;
+    }
+
+    fn finalize(&self) -> CostResult<CryptoHash, Error> {
+        let mut bytes = Vec::with_capacity(40);
+        for limb in self.limbs {
+            bytes.extend_from_slice(&limb.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.count.to_le_bytes());
+        value_hash(&bytes).map(Ok)
+    }
+}
+
+#[cfg(feature = "full")]
+/// The element without the root key of its subtree, which depends on the
+/// shape of the subtree rather than on its content
+pub(crate) fn without_root_key(element: Element) -> Element {
+    match element {
+        Element::Tree(_, flags) => Element::Tree(None, flags),
+        Element::SumTree(_, sum, flags) => Element::SumTree(None, sum, flags),
+        Element::OrderedTree(_, key_ordering, flags) => {
+            Element::OrderedTree(None, key_ordering, flags)
+        }
+        element => element,
+    }
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Keeps deep hashes of subtrees once computed, so that computing them
+    /// again only hashes the subtrees changed in the meantime. Turning it
+    /// off drops the kept hashes.
+    pub fn set_deep_hash_tracking(&self, tracking: bool) {
+        self.deep_hashes.tracking.store(tracking, Ordering::Relaxed);
+        if !tracking {
+            self.deep_hashes
+                .entries
+                .lock()
+                .expect("deep hash cache lock is poisoned")
+                .clear();
+        }
+    }
+
+    /// Returns true if deep hashes of subtrees are kept
+    pub fn is_deep_hash_tracking(&self) -> bool {
+        self.deep_hashes.tracking.load(Ordering::Relaxed)
+    }
+
+    /// Computes the deep hash of the subtree at `path`, an order independent
+    /// hash of all of its keys and elements, descendant subtrees included
+    pub fn deep_hash<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> CostResult<CryptoHash, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+    {
+        let path: Vec<Vec<u8>> = path.into_iter().map(|segment| segment.to_vec()).collect();
+        self.deep_hash_of_subtree(&path, transaction)
+    }
+
+    fn deep_hash_of_subtree(
+        &self,
+        path: &[Vec<u8>],
+        transaction: TransactionArg,
+    ) -> CostResult<CryptoHash, Error> {
+        let mut cost = OperationCost::default();
+
+        let path_refs: Vec<&[u8]> = path.iter().map(|segment| segment.as_slice()).collect();
+        let root_hash = if self.is_deep_hash_tracking() {
+            let root_hash = cost_return_on_error!(
+                &mut cost,
+                self.subtree_root_hash(path_refs.iter().copied(), transaction)
+            );
+            if let Some(deep_hash) = self.deep_hashes.get(&root_hash) {
+                return Ok(deep_hash).wrap_with_cost(cost);
+            }
+            Some(root_hash)
+        } else {
+            None
+        };
+
+        let mut sum = HashSum::default();
+        let mut subtrees = Vec::new();
+        let mut hash_cost = OperationCost::default();
+        let mut error = None;
+        cost_return_on_error!(
+            &mut cost,
+            self.scan_subtree(
+                path_refs.iter().copied(),
+                ScanOptions::default(),
+                transaction,
+                |key, element| {
+                    if element.is_tree() {
+                        subtrees.push((key.to_vec(), without_root_key(element)));
+                        return true;
+                    }
+                    match element.serialize() {
+                        Ok(bytes) => {
+                            sum.add(&kv_hash(key, &bytes).unwrap_add_cost(&mut hash_cost));
+                            true
+                        }
+                        Err(e) => {
+                            error = Some(e);
+                            false
+                        }
+                    }
+                }
+            )
+        );
+        cost += hash_cost;
+        if let Some(e) = error {
+            return Err(e).wrap_with_cost(cost);
+        }
+
+        for (key, element) in subtrees {
+            let mut child_path = path.to_vec();
+            child_path.push(key);
+            let child_deep_hash = cost_return_on_error!(
+                &mut cost,
+                self.deep_hash_of_subtree(&child_path, transaction)
+            );
+            let key = child_path.pop().expect("child path has the key pushed");
+            let bytes = cost_return_on_error_no_add!(&cost, element.serialize());
+            let element_hash = value_hash(&bytes).unwrap_add_cost(&mut cost);
+            let combined = combine_hash(&element_hash, &child_deep_hash).unwrap_add_cost(&mut cost);
+            sum.add(&kv_digest_to_kv_hash(&key, &combined).unwrap_add_cost(&mut cost));
+        }
+
+        let deep_hash = cost_return_on_error!(&mut cost, sum.finalize());
+        if let Some(root_hash) = root_hash {
+            self.deep_hashes.insert(root_hash, deep_hash);
+        }
+        Ok(deep_hash).wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{make_test_grovedb, ANOTHER_TEST_LEAF, TEST_LEAF};
+
+    fn insert_contents(db: &GroveDb, leaf: &[u8], keys: &[&[u8]]) {
+        for key in keys {
+            db.insert([leaf], key, Element::new_item(key.to_vec()), None, None)
+                .unwrap()
+                .expect("should insert item");
+        }
+        db.insert([leaf], b"tree", Element::empty_tree(), None, None)
+            .unwrap()
+            .expect("should insert subtree");
+        for key in keys {
+            db.insert(
+                [leaf, b"tree"],
+                key,
+                Element::new_item(key.to_vec()),
+                None,
+                None,
+            )
+            .unwrap()
+            .expect("should insert item");
+        }
+    }
+
+    #[test]
+    fn test_deep_hash_ignores_insertion_order() {
+        let db = make_test_grovedb();
+        insert_contents(&db, TEST_LEAF, &[b"a", b"b", b"c", b"d", b"e"]);
+        insert_contents(&db, ANOTHER_TEST_LEAF, &[b"e", b"d", b"c", b"b", b"a"]);
+
+        let hash = db
+            .deep_hash([TEST_LEAF], None)
+            .unwrap()
+            .expect("should compute deep hash");
+        let other_hash = db
+            .deep_hash([ANOTHER_TEST_LEAF], None)
+            .unwrap()
+            .expect("should compute deep hash");
+        assert_eq!(hash, other_hash);
+
+        db.insert(
+            [ANOTHER_TEST_LEAF, b"tree"],
+            b"f",
+            Element::new_item(b"f".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+        assert_ne!(
+            hash,
+            db.deep_hash([ANOTHER_TEST_LEAF], None)
+                .unwrap()
+                .expect("should compute deep hash")
+        );
+    }
+
+    #[test]
+    fn test_deep_hash_tracking() {
+        let db = make_test_grovedb();
+        insert_contents(&db, TEST_LEAF, &[b"a", b"b", b"c"]);
+        let untracked = db
+            .deep_hash([TEST_LEAF], None)
+            .unwrap()
+            .expect("should compute deep hash");
+
+        db.set_deep_hash_tracking(true);
+        let first = db.deep_hash([TEST_LEAF], None);
+        assert_eq!(first.value.expect("should compute deep hash"), untracked);
+        let second = db.deep_hash([TEST_LEAF], None);
+        assert_eq!(second.value.expect("should compute deep hash"), untracked);
+        assert!(second.cost.seek_count < first.cost.seek_count);
+
+        db.insert(
+            [TEST_LEAF, b"tree"],
+            b"d",
+            Element::new_item(b"d".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+        assert_ne!(
+            db.deep_hash([TEST_LEAF], None)
+                .unwrap()
+                .expect("should compute deep hash"),
+            untracked
+        );
+    }
+}
@@ -33,11 +33,11 @@ use costs::{
 };
 use intmap::IntMap;
 use merk::{
+    cost_constants::active_cost_constants,
     estimated_costs::{
         average_case_costs::EstimatedLayerInformation,
         worst_case_costs::add_average_case_cost_for_is_empty_tree_except,
     },
-    HASH_LENGTH_U32,
 };
 use storage::{worst_case_costs::WorstKeyLength, Storage};
 
@@ -182,7 +182,7 @@ impl GroveDb {
         add_average_case_cost_for_is_empty_tree_except(
             &mut cost,
             except_keys_count,
-            estimated_key_element_size.0 + HASH_LENGTH_U32,
+            estimated_key_element_size.0 + active_cost_constants().key_prefix_size,
         );
 
         Ok(GroveDbOp::delete_estimated_op(path.clone(), key.clone())).wrap_with_cost(cost)
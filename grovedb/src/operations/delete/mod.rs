@@ -33,6 +33,8 @@ mod average_case;
 #[cfg(feature = "full")]
 mod delete_up_tree;
 #[cfg(feature = "full")]
+mod root_leaf;
+#[cfg(feature = "full")]
 mod worst_case;
 
 #[cfg(feature = "full")]
@@ -49,6 +51,8 @@ pub use delete_up_tree::DeleteUpTreeOptions;
 #[cfg(feature = "full")]
 use merk::{Error as MerkError, Merk, MerkOptions};
 #[cfg(feature = "full")]
+pub use root_leaf::DeleteRootLeafOptions;
+#[cfg(feature = "full")]
 use storage::{
     rocksdb_storage::{
         PrefixedRocksDbBatchTransactionContext, PrefixedRocksDbStorageContext,
@@ -61,6 +65,7 @@ use crate::util::merk_optional_tx_path_not_empty;
 #[cfg(feature = "full")]
 use crate::{
     batch::{GroveDbOp, Op},
+    operations::{quota::SubtreeQuotas, reservation::SubtreeReservations},
     util::{storage_context_optional_tx, storage_context_with_parent_optional_tx},
     Element, ElementFlags, Error, GroveDb, Transaction, TransactionArg,
 };
@@ -274,7 +279,7 @@ impl GroveDb {
         if path_iter.len() == 0 {
             // Attempt to delete a root tree leaf
             Err(Error::InvalidPath(
-                "root tree leaves currently cannot be deleted".to_owned(),
+                "root tree leaves can only be deleted with delete_root_leaf".to_owned(),
             ))
             .wrap_with_cost(cost)
         } else {
@@ -291,7 +296,7 @@ impl GroveDb {
                         self.get_raw(path_iter.clone(), key.as_ref(), transaction)
                     );
                     match element {
-                        Element::Tree(..) => (true, false),
+                        Element::Tree(..) | Element::OrderedTree(..) => (true, false),
                         Element::SumTree(..) => (true, true),
                         _ => (false, false),
                     }
@@ -396,11 +401,126 @@ impl GroveDb {
         P: IntoIterator<Item = &'p [u8]>,
         <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
     {
-        if let Some(transaction) = transaction {
-            self.delete_internal_on_transaction(path, key, options, transaction, sectioned_removal)
-        } else {
-            self.delete_internal_without_transaction(path, key, options, sectioned_removal)
+        let mut cost = OperationCost::default();
+
+        let path_iter = path.into_iter();
+        cost_return_on_error!(
+            &mut cost,
+            self.check_writable(path_iter.clone(), key, transaction)
+        );
+        let quotas = cost_return_on_error!(
+            &mut cost,
+            self.check_delete_quotas(path_iter.clone(), key, transaction)
+        );
+        let reservations = cost_return_on_error!(
+            &mut cost,
+            self.check_delete_reservations(path_iter.clone(), key, transaction)
+        );
+        let updates_root_leaves = path_iter.len() == 0;
+        match (quotas, reservations, transaction) {
+            (None, None, Some(transaction)) if !updates_root_leaves => self
+                .delete_internal_on_transaction(
+                    path_iter,
+                    key,
+                    options,
+                    transaction,
+                    sectioned_removal,
+                ),
+            (None, None, None) if !updates_root_leaves => {
+                self.delete_internal_without_transaction(path_iter, key, options, sectioned_removal)
+            }
+            (quotas, reservations, Some(transaction)) => self.delete_internal_with_meta(
+                path_iter,
+                key,
+                options,
+                quotas.as_ref(),
+                reservations.as_ref(),
+                transaction,
+                sectioned_removal,
+            ),
+            (quotas, reservations, None) => {
+                // the usage and the root leaf registry hash have to be committed together
+                // with the deletion
+                let transaction = self.start_transaction();
+                let deleted = cost_return_on_error!(
+                    &mut cost,
+                    self.delete_internal_with_meta(
+                        path_iter,
+                        key,
+                        options,
+                        quotas.as_ref(),
+                        reservations.as_ref(),
+                        &transaction,
+                        sectioned_removal,
+                    )
+                );
+                self.commit_transaction(transaction).map_ok(|_| deleted)
+            }
         }
+        .add_cost(cost)
+    }
+
+    /// Deletes on the transaction and writes the quotas and reservations
+    /// left by the deletion, as well as the root leaf registry hash when a
+    /// root leaf is deleted
+    #[allow(clippy::too_many_arguments)]
+    fn delete_internal_with_meta<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        options: &DeleteOptions,
+        quotas: Option<&SubtreeQuotas>,
+        reservations: Option<&SubtreeReservations>,
+        transaction: &Transaction,
+        sectioned_removal: &mut impl FnMut(
+            &Vec<u8>,
+            u32,
+            u32,
+        ) -> Result<
+            (StorageRemovedBytes, StorageRemovedBytes),
+            MerkError,
+        >,
+    ) -> CostResult<bool, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+    {
+        let mut cost = OperationCost::default();
+        let path_iter = path.into_iter();
+        let updates_root_leaves = path_iter.len() == 0;
+        let deleted = cost_return_on_error!(
+            &mut cost,
+            self.delete_internal_on_transaction(
+                path_iter,
+                key,
+                options,
+                transaction,
+                sectioned_removal
+            )
+        );
+        if deleted {
+            if let Some(quotas) = quotas {
+                cost_return_on_error!(
+                    &mut cost,
+                    self.write_subtree_quotas(quotas, Some(transaction))
+                );
+            }
+            if let Some(reservations) = reservations {
+                cost_return_on_error!(
+                    &mut cost,
+                    self.write_subtree_reservations(reservations, Some(transaction))
+                );
+            }
+            if updates_root_leaves {
+                // bookkeeping of the database rather than of the operation, its
+                // cost isn't charged
+                cost_return_on_error_no_add!(
+                    &cost,
+                    self.update_root_leaves_hash(Some(transaction)).unwrap()
+                );
+            }
+        }
+        Ok(deleted).wrap_with_cost(cost)
     }
 
     fn delete_internal_on_transaction<'p, P>(
@@ -427,7 +547,12 @@ impl GroveDb {
         let path_iter = path.into_iter();
         let element = cost_return_on_error!(
             &mut cost,
-            self.get_raw(path_iter.clone(), key.as_ref(), Some(transaction))
+            self.get_raw_on_transaction_caching_optional(
+                path_iter.clone(),
+                key.as_ref(),
+                true,
+                transaction
+            )
         );
         let mut subtree_to_delete_from = cost_return_on_error!(
             &mut cost,
@@ -514,23 +639,35 @@ impl GroveDb {
                         sectioned_removal
                     )
                 );
-                let mut merk_cache: HashMap<
-                    Vec<Vec<u8>>,
-                    Merk<PrefixedRocksDbBatchTransactionContext>,
-                > = HashMap::default();
-                merk_cache.insert(
-                    path_iter.clone().map(|k| k.to_vec()).collect(),
-                    merk_to_delete_tree_from,
-                );
-                cost_return_on_error!(
-                    &mut cost,
-                    self.propagate_changes_with_batch_transaction(
-                        &storage_batch,
-                        merk_cache,
-                        path_iter,
-                        transaction
-                    )
-                );
+                if self.is_eager_propagation() {
+                    let mut merk_cache: HashMap<
+                        Vec<Vec<u8>>,
+                        Merk<PrefixedRocksDbBatchTransactionContext>,
+                    > = HashMap::default();
+                    merk_cache.insert(
+                        path_iter.clone().map(|k| k.to_vec()).collect(),
+                        merk_to_delete_tree_from,
+                    );
+                    cost_return_on_error!(
+                        &mut cost,
+                        self.propagate_changes_with_batch_transaction(
+                            &storage_batch,
+                            merk_cache,
+                            path_iter,
+                            transaction
+                        )
+                    );
+                } else {
+                    cost_return_on_error!(
+                        &mut cost,
+                        self.propagate_changes_deferred_with_transaction(
+                            &merk_to_delete_tree_from,
+                            path_iter.map(|k| k.to_vec()).collect(),
+                            transaction
+                        )
+                    );
+                    drop(merk_to_delete_tree_from);
+                }
                 cost_return_on_error_no_add!(
                     &cost,
                     self.db
@@ -551,15 +688,13 @@ impl GroveDb {
                         sectioned_removal
                     )
                 );
-                let mut merk_cache: HashMap<Vec<Vec<u8>>, Merk<PrefixedRocksDbTransactionContext>> =
-                    HashMap::default();
-                merk_cache.insert(
-                    path_iter.clone().map(|k| k.to_vec()).collect(),
-                    subtree_to_delete_from,
-                );
                 cost_return_on_error!(
                     &mut cost,
-                    self.propagate_changes_with_transaction(merk_cache, path_iter, transaction)
+                    self.propagate_deletion_with_transaction(
+                        subtree_to_delete_from,
+                        path_iter,
+                        transaction
+                    )
                 );
             }
         } else {
@@ -574,21 +709,45 @@ impl GroveDb {
                     sectioned_removal,
                 )
             );
-            let mut merk_cache: HashMap<Vec<Vec<u8>>, Merk<PrefixedRocksDbTransactionContext>> =
-                HashMap::default();
-            merk_cache.insert(
-                path_iter.clone().map(|k| k.to_vec()).collect(),
-                subtree_to_delete_from,
-            );
             cost_return_on_error!(
                 &mut cost,
-                self.propagate_changes_with_transaction(merk_cache, path_iter, transaction)
+                self.propagate_deletion_with_transaction(
+                    subtree_to_delete_from,
+                    path_iter,
+                    transaction
+                )
             );
         }
 
         Ok(true).wrap_with_cost(cost)
     }
 
+    /// Propagates the changes of the merk a deletion was made in, deferred
+    /// unless propagation is eager
+    fn propagate_deletion_with_transaction<'p, P>(
+        &self,
+        subtree: Merk<PrefixedRocksDbTransactionContext>,
+        path: P,
+        transaction: &Transaction,
+    ) -> CostResult<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+    {
+        let path_iter = path.into_iter();
+        if !self.is_eager_propagation() {
+            return self.propagate_changes_deferred_with_transaction(
+                &subtree,
+                path_iter.map(|k| k.to_vec()).collect(),
+                transaction,
+            );
+        }
+        let mut merk_cache: HashMap<Vec<Vec<u8>>, Merk<PrefixedRocksDbTransactionContext>> =
+            HashMap::default();
+        merk_cache.insert(path_iter.clone().map(|k| k.to_vec()).collect(), subtree);
+        self.propagate_changes_with_transaction(merk_cache, path_iter, transaction)
+    }
+
     fn delete_internal_without_transaction<'p, P>(
         &self,
         path: P,
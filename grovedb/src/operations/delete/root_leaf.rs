@@ -0,0 +1,156 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Delete root leaf
+//! Root leaves are the subtrees of the root tree. Deleting one is guarded: the
+//! subtree has to be empty unless the deletion is forced, in which case every
+//! subtree below it is cleared within the same storage batch. The root tree is
+//! updated like any other subtree, so the removal can be proved with an
+//! absence proof of the key in the root tree.
+
+#[cfg(feature = "full")]
+use costs::{cost_return_on_error, CostResult, CostsExt, OperationCost};
+
+#[cfg(feature = "full")]
+use crate::{operations::delete::DeleteOptions, Error, GroveDb, TransactionArg};
+
+#[cfg(feature = "full")]
+#[derive(Clone)]
+/// Delete root leaf options
+pub struct DeleteRootLeafOptions {
+    /// Delete the root leaf along with everything below it if it isn't empty
+    pub force: bool,
+    /// Base root storage is free
+    pub base_root_storage_is_free: bool,
+}
+
+#[cfg(feature = "full")]
+impl Default for DeleteRootLeafOptions {
+    fn default() -> Self {
+        DeleteRootLeafOptions {
+            force: false,
+            base_root_storage_is_free: true,
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Deletes a root leaf, fails with `Error::DeletingNonEmptyTree` if the
+    /// root leaf isn't empty and the deletion isn't forced
+    pub fn delete_root_leaf(
+        &self,
+        key: &[u8],
+        options: Option<DeleteRootLeafOptions>,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+        let options = options.unwrap_or_default();
+
+        let element = cost_return_on_error!(
+            &mut cost,
+            self.get_raw(std::iter::empty(), key, transaction)
+        );
+        if !element.is_tree() {
+            return Err(Error::InvalidInput("root leaves are trees")).wrap_with_cost(cost);
+        }
+
+        self.delete(
+            std::iter::empty(),
+            key,
+            Some(DeleteOptions {
+                allow_deleting_non_empty_trees: options.force,
+                deleting_non_empty_trees_returns_error: true,
+                base_root_storage_is_free: options.base_root_storage_is_free,
+                validate_tree_at_path_exists: false,
+            }),
+            transaction,
+        )
+        .add_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use crate::{
+        operations::delete::DeleteRootLeafOptions,
+        tests::{make_test_grovedb, TEST_LEAF},
+        Element, Error, GroveDb, PathQuery,
+    };
+
+    #[test]
+    fn test_delete_root_leaf_is_guarded_and_provable() {
+        let db = make_test_grovedb();
+        db.insert(
+            [TEST_LEAF],
+            b"key",
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+
+        assert!(matches!(
+            db.delete_root_leaf(TEST_LEAF, None, None).unwrap(),
+            Err(Error::DeletingNonEmptyTree(_))
+        ));
+        db.insert([], b"empty_leaf", Element::empty_tree(), None, None)
+            .unwrap()
+            .expect("should insert root leaf");
+        db.delete_root_leaf(b"empty_leaf", None, None)
+            .unwrap()
+            .expect("should delete empty root leaf");
+
+        let transaction = db.start_transaction();
+        db.delete_root_leaf(
+            TEST_LEAF,
+            Some(DeleteRootLeafOptions {
+                force: true,
+                ..Default::default()
+            }),
+            Some(&transaction),
+        )
+        .unwrap()
+        .expect("should force delete root leaf");
+        db.commit_transaction(transaction)
+            .unwrap()
+            .expect("should commit");
+
+        assert!(db.get([TEST_LEAF], b"key", None).unwrap().is_err());
+        assert!(db.get([], TEST_LEAF, None).unwrap().is_err());
+
+        let path_query = PathQuery::new_single_key(vec![], TEST_LEAF.to_vec());
+        let proof = db.prove_query(&path_query).unwrap().expect("should prove");
+        let (root_hash, result_set) =
+            GroveDb::verify_query(&proof, &path_query).expect("should verify");
+        assert_eq!(root_hash, db.root_hash(None).unwrap().unwrap());
+        assert!(result_set.is_empty());
+    }
+}
@@ -39,7 +39,7 @@ use storage::{worst_case_costs::WorstKeyLength, Storage};
 
 use crate::{
     batch::{key_info::KeyInfo, GroveDbOp, KeyInfoPath},
-    element::SUM_TREE_COST_SIZE,
+    element::sum_tree_cost_size,
     Error, GroveDb,
 };
 
@@ -94,7 +94,7 @@ impl GroveDb {
                             intermediate_tree_info.get(height as u64)
                         {
                             // the worst case is that we are only in sum trees
-                            let value_len = SUM_TREE_COST_SIZE + flags_size_at_level;
+                            let value_len = sum_tree_cost_size() + flags_size_at_level;
                             let max_tree_size =
                                 KV::layered_node_byte_cost_size_for_key_and_value_lengths(
                                     last_key.max_length() as u32,
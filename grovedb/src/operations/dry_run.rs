@@ -0,0 +1,143 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Dry runs
+//! Operations run as a dry run take the same code paths and report the same
+//! costs as real ones, but everything they write is rolled back afterwards.
+
+#[cfg(feature = "full")]
+use costs::{CostResult, CostsExt};
+
+#[cfg(feature = "full")]
+use crate::{Error, GroveDb, Transaction, TransactionArg};
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Runs `f` and rolls back everything it wrote, returning its result and
+    /// costs. Within a transaction the changes are rolled back to a savepoint,
+    /// otherwise `f` runs in a temporary transaction which is dropped. Reads
+    /// within `f` observe its writes, so queries return would-be results.
+    pub fn dry_run<T>(
+        &self,
+        transaction: TransactionArg,
+        f: impl FnOnce(&Transaction) -> CostResult<T, Error>,
+    ) -> CostResult<T, Error> {
+        match transaction {
+            Some(tx) => {
+                tx.set_savepoint();
+                f(tx).flat_map(|result| match tx.rollback_to_savepoint() {
+                    Ok(()) => result.wrap_with_cost(Default::default()),
+                    Err(e) => Err(e.into()).wrap_with_cost(Default::default()),
+                })
+            }
+            None => {
+                let tx = self.start_transaction();
+                f(&tx)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use crate::{
+        batch::{BatchApplyOptions, GroveDbOp},
+        tests::{make_test_grovedb, TEST_LEAF},
+        Element, PathQuery, Query,
+    };
+
+    fn ops() -> Vec<GroveDbOp> {
+        vec![
+            GroveDbOp::insert_op(
+                vec![TEST_LEAF.to_vec()],
+                b"key1".to_vec(),
+                Element::new_item(b"value1".to_vec()),
+            ),
+            GroveDbOp::insert_op(
+                vec![TEST_LEAF.to_vec()],
+                b"key2".to_vec(),
+                Element::new_item(b"value2".to_vec()),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_dry_run_batch_reports_costs_without_writing() {
+        let db = make_test_grovedb();
+        let root_hash = db.root_hash(None).unwrap().unwrap();
+        let dry_run_options = BatchApplyOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+
+        let dry_run_cost = db
+            .apply_batch(ops(), Some(dry_run_options.clone()), None)
+            .cost_as_result()
+            .expect("should dry run batch");
+        assert_eq!(db.root_hash(None).unwrap().unwrap(), root_hash);
+
+        let tx = db.start_transaction();
+        let dry_run_tx_cost = db
+            .apply_batch(ops(), Some(dry_run_options), Some(&tx))
+            .cost_as_result()
+            .expect("should dry run batch in transaction");
+        assert_eq!(db.root_hash(Some(&tx)).unwrap().unwrap(), root_hash);
+
+        let cost = db
+            .apply_batch(ops(), None, None)
+            .cost_as_result()
+            .expect("should apply batch");
+        assert_ne!(db.root_hash(None).unwrap().unwrap(), root_hash);
+        assert_eq!(dry_run_cost, cost);
+        assert_eq!(dry_run_tx_cost, cost);
+    }
+
+    #[test]
+    fn test_dry_run_returns_would_be_query_results() {
+        let db = make_test_grovedb();
+        let mut query = Query::new();
+        query.insert_all();
+        let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+
+        let (elements, _) = db
+            .dry_run(None, |tx| {
+                db.apply_batch(ops(), None, Some(tx))
+                    .flat_map_ok(|_| db.query_item_value(&path_query, true, Some(tx)))
+            })
+            .unwrap()
+            .expect("should dry run");
+        assert_eq!(elements.len(), 2);
+
+        let (elements, _) = db
+            .query_item_value(&path_query, true, None)
+            .unwrap()
+            .expect("should query");
+        assert!(elements.is_empty());
+    }
+}
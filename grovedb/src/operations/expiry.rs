@@ -0,0 +1,337 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Expiry
+//! The database can be given a current epoch, in which case elements whose
+//! expiry epoch is reached are left out of query results. A reference expires
+//! with the element it points to, as proofs carry the referenced element.
+//! Expired elements count towards neither the limit nor the offset of a query.
+//! They stay in storage, and thus in proofs, until they are purged.
+
+#[cfg(feature = "full")]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "full")]
+use costs::{cost_return_on_error, CostResult, CostsExt, OperationCost};
+#[cfg(feature = "full")]
+use storage::StorageContext;
+
+#[cfg(feature = "full")]
+use crate::{
+    batch::GroveDbOp,
+    element::expiry::Epoch,
+    query_result_type::{QueryResultElements, QueryResultType},
+    reference_path::ReferencePathType,
+    util::storage_context_optional_tx,
+    Element, Error, GroveDb, PathQuery, TransactionArg,
+};
+
+#[cfg(feature = "full")]
+#[derive(Default)]
+/// Current epoch of a GroveDb instance
+pub(crate) struct ExpiryClock {
+    /// Current epoch plus one, zero means no epoch is set
+    current_epoch: AtomicU64,
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Sets the epoch against which element expiry is checked, `None` stops
+    /// filtering expired elements out of query results
+    pub fn set_current_epoch(&self, current_epoch: Option<Epoch>) {
+        let stored = current_epoch
+            .map(|epoch| epoch.saturating_add(1))
+            .unwrap_or(0);
+        self.expiry.current_epoch.store(stored, Ordering::Relaxed);
+    }
+
+    /// Returns the epoch against which element expiry is checked
+    pub fn current_epoch(&self) -> Option<Epoch> {
+        match self.expiry.current_epoch.load(Ordering::Relaxed) {
+            0 => None,
+            stored => Some(stored - 1),
+        }
+    }
+
+    /// Queries the elements that didn't expire at the current epoch, the limit
+    /// and offset of the query are applied once expired elements are left out
+    pub(crate) fn query_raw_unexpired(
+        &self,
+        path_query: &PathQuery,
+        allow_cache: bool,
+        result_type: QueryResultType,
+        current_epoch: Epoch,
+        transaction: TransactionArg,
+    ) -> CostResult<(QueryResultElements, u16), Error> {
+        let mut cost = OperationCost::default();
+
+        let (elements, _) = cost_return_on_error!(
+            &mut cost,
+            Element::get_raw_path_query(
+                &self.db,
+                &path_query.without_limit_and_offset(),
+                allow_cache,
+                result_type,
+                transaction,
+            )
+        );
+        let mut unexpired = vec![];
+        for result in elements.into_iterator() {
+            let expired = cost_return_on_error!(
+                &mut cost,
+                self.is_expired_result(result.element(), allow_cache, current_epoch, transaction)
+            );
+            if !expired {
+                unexpired.push(result);
+            }
+        }
+
+        let offset = path_query.query.offset.unwrap_or(0) as usize;
+        let limit = path_query
+            .query
+            .limit
+            .map(|limit| limit as usize)
+            .unwrap_or(usize::MAX);
+        let skipped = offset.min(unexpired.len()) as u16;
+        let elements = unexpired.into_iter().skip(offset).take(limit).collect();
+        Ok((QueryResultElements::from_elements(elements), skipped)).wrap_with_cost(cost)
+    }
+
+    /// Whether a query result element expired, references being checked
+    /// against the element they point to
+    fn is_expired_result(
+        &self,
+        element: &Element,
+        allow_cache: bool,
+        current_epoch: Epoch,
+        transaction: TransactionArg,
+    ) -> CostResult<bool, Error> {
+        match element {
+            Element::Reference(ReferencePathType::AbsolutePathReference(path), ..) => self
+                .follow_reference(path.clone(), allow_cache, transaction)
+                .map(|result| match result {
+                    Ok(referenced) => Ok(referenced.is_expired(current_epoch)),
+                    // a dangling reference has nothing to expire with
+                    Err(Error::PathKeyNotFound(_))
+                    | Err(Error::PathNotFound(_))
+                    | Err(Error::PathParentLayerNotFound(_)) => Ok(false),
+                    Err(e) => Err(e),
+                }),
+            Element::Reference(..) => Err(Error::CorruptedCodeExecution(
+                "reference after query must have absolute paths",
+            ))
+            .wrap_with_cost(Default::default()),
+            _ => Ok(element.is_expired(current_epoch)).wrap_with_cost(Default::default()),
+        }
+    }
+
+    /// Proves the query for [`GroveDb::verify_query_at_epoch`]. As expired
+    /// elements count towards neither the limit nor the offset, the query is
+    /// proved without them and the verifier applies them.
+    pub fn prove_query_for_expiry(&self, path_query: &PathQuery) -> CostResult<Vec<u8>, Error> {
+        self.prove_query(&path_query.without_limit_and_offset())
+    }
+
+    /// Deletes the expired elements directly under the subtree at the given
+    /// path, returns the number of deleted elements
+    pub fn purge_expired<'p, P>(
+        &self,
+        path: P,
+        current_epoch: Epoch,
+        transaction: TransactionArg,
+    ) -> CostResult<usize, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let mut cost = OperationCost::default();
+        let path: Vec<Vec<u8>> = path.into_iter().map(|segment| segment.to_vec()).collect();
+
+        let expired_keys = storage_context_optional_tx!(
+            self.db,
+            path.iter().map(|segment| segment.as_slice()),
+            transaction,
+            storage,
+            {
+                let storage = storage.unwrap_add_cost(&mut cost);
+                let mut expired_keys = vec![];
+                let mut iter = Element::iterator(storage.raw_iter()).unwrap_add_cost(&mut cost);
+                while let Some((key, element)) =
+                    cost_return_on_error!(&mut cost, iter.next_element())
+                {
+                    if element.is_expired(current_epoch) {
+                        expired_keys.push(key);
+                    }
+                }
+                expired_keys
+            }
+        );
+        if expired_keys.is_empty() {
+            return Ok(0).wrap_with_cost(cost);
+        }
+
+        let purged = expired_keys.len();
+        let ops = expired_keys
+            .into_iter()
+            .map(|key| GroveDbOp::delete_op(path.clone(), key))
+            .collect();
+        cost_return_on_error!(&mut cost, self.apply_batch(ops, None, transaction));
+        Ok(purged).wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use crate::{
+        query_result_type::QueryResultType,
+        reference_path::ReferencePathType,
+        tests::{make_test_grovedb, ANOTHER_TEST_LEAF, TEST_LEAF},
+        Element, GroveDb, PathQuery, Query, SizedQuery,
+    };
+
+    #[test]
+    fn test_expired_elements_are_filtered_proved_and_purged() {
+        let db = make_test_grovedb();
+        for (key, expiry_epoch) in [(b"a", 5), (b"b", 10), (b"c", 20)] {
+            db.insert(
+                [TEST_LEAF],
+                key,
+                Element::new_item_with_expiry(key.to_vec(), expiry_epoch, None),
+                None,
+                None,
+            )
+            .unwrap()
+            .expect("should insert expiring item");
+        }
+
+        let mut query = Query::new();
+        query.insert_all();
+        let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+        let query_keys = |db: &GroveDb| -> Vec<Vec<u8>> {
+            db.query_raw(
+                &path_query,
+                true,
+                QueryResultType::QueryKeyElementPairResultType,
+                None,
+            )
+            .unwrap()
+            .expect("should query")
+            .0
+            .to_keys()
+        };
+
+        assert_eq!(query_keys(&db).len(), 3);
+        db.set_current_epoch(Some(10));
+        assert_eq!(db.current_epoch(), Some(10));
+        assert_eq!(query_keys(&db), vec![b"c".to_vec()]);
+
+        let proof = db
+            .prove_query_for_expiry(&path_query)
+            .unwrap()
+            .expect("should prove");
+        let (root_hash, result_set) =
+            GroveDb::verify_query_at_epoch(&proof, &path_query, 10).expect("should verify");
+        assert_eq!(root_hash, db.root_hash(None).unwrap().unwrap());
+        assert_eq!(result_set.len(), 1);
+        assert_eq!(result_set[0].1, b"c".to_vec());
+
+        let purged = db
+            .purge_expired([TEST_LEAF], 10, None)
+            .unwrap()
+            .expect("should purge");
+        assert_eq!(purged, 2);
+        db.set_current_epoch(None);
+        assert_eq!(query_keys(&db), vec![b"c".to_vec()]);
+    }
+
+    #[test]
+    fn test_expiring_reference_is_skipped_before_limit_and_offset() {
+        let db = make_test_grovedb();
+        db.insert(
+            [TEST_LEAF],
+            b"target",
+            Element::new_item_with_expiry(b"target".to_vec(), 5, None),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert expiring item");
+        db.insert(
+            [ANOTHER_TEST_LEAF],
+            b"a",
+            Element::new_reference(ReferencePathType::AbsolutePathReference(vec![
+                TEST_LEAF.to_vec(),
+                b"target".to_vec(),
+            ])),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert reference to expiring item");
+        for key in [b"b", b"c", b"d"] {
+            db.insert(
+                [ANOTHER_TEST_LEAF],
+                key,
+                Element::new_item(key.to_vec()),
+                None,
+                None,
+            )
+            .unwrap()
+            .expect("should insert item");
+        }
+        db.set_current_epoch(Some(10));
+
+        let mut query = Query::new();
+        query.insert_all();
+        let path_query = PathQuery::new(
+            vec![ANOTHER_TEST_LEAF.to_vec()],
+            SizedQuery::new(query, Some(1), Some(1)),
+        );
+        let (elements, skipped) = db
+            .query_raw(
+                &path_query,
+                true,
+                QueryResultType::QueryKeyElementPairResultType,
+                None,
+            )
+            .unwrap()
+            .expect("should query");
+        assert_eq!(elements.to_keys(), vec![b"c".to_vec()]);
+        assert_eq!(skipped, 1);
+
+        let proof = db
+            .prove_query_for_expiry(&path_query)
+            .unwrap()
+            .expect("should prove");
+        let (root_hash, result_set) =
+            GroveDb::verify_query_at_epoch(&proof, &path_query, 10).expect("should verify");
+        assert_eq!(root_hash, db.root_hash(None).unwrap().unwrap());
+        assert_eq!(result_set.len(), 1);
+        assert_eq!(result_set[0].1, b"c".to_vec());
+    }
+}
@@ -33,6 +33,10 @@ mod average_case;
 #[cfg(feature = "full")]
 mod query;
 #[cfg(feature = "full")]
+mod reference_cache;
+#[cfg(feature = "full")]
+mod resolve;
+#[cfg(feature = "full")]
 mod worst_case;
 
 #[cfg(feature = "full")]
@@ -49,17 +53,46 @@ use storage::{
     StorageContext,
 };
 
+#[cfg(feature = "full")]
+pub use self::reference_cache::ReferenceResolutionCache;
 #[cfg(feature = "full")]
 use crate::{
-    reference_path::{path_from_reference_path_type, path_from_reference_qualified_path_type},
+    reference_path::{
+        path_from_reference_path_type, path_from_reference_qualified_path_type,
+        PartialReferenceChain,
+    },
     util::storage_context_optional_tx,
-    Element, Error, GroveDb, Transaction, TransactionArg,
+    Element, Error, GroveDb, SubtreePath, Transaction, TransactionArg,
 };
 
 #[cfg(feature = "full")]
 /// Limit of possible indirections
 pub const MAX_REFERENCE_HOPS: usize = 10;
 
+#[cfg(feature = "full")]
+/// An element read while following a reference chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReferenceStep {
+    /// Qualified path of the element
+    pub path: Vec<Vec<u8>>,
+    /// The element
+    pub element: Element,
+    /// Qualified path the element points to if it is a reference
+    pub next_path: Option<Vec<Vec<u8>>>,
+}
+
+#[cfg(feature = "full")]
+/// Turns a failure to read the target of a reference into the matching
+/// corrupted reference error
+fn reference_target_error(error: Error) -> Error {
+    match error {
+        Error::PathParentLayerNotFound(p) => Error::CorruptedReferencePathParentLayerNotFound(p),
+        Error::PathKeyNotFound(p) => Error::CorruptedReferencePathKeyNotFound(p),
+        Error::PathNotFound(p) => Error::CorruptedReferencePathNotFound(p),
+        _ => error,
+    }
+}
+
 #[cfg(feature = "full")]
 impl GroveDb {
     /// Get an element from the backing store
@@ -114,60 +147,109 @@ impl GroveDb {
 
     /// Follow reference
     pub fn follow_reference(
+        &self,
+        path: Vec<Vec<u8>>,
+        allow_cache: bool,
+        transaction: TransactionArg,
+    ) -> CostResult<Element, Error> {
+        self.follow_reference_with_cache(
+            path,
+            allow_cache,
+            &mut ReferenceResolutionCache::default(),
+            transaction,
+        )
+    }
+
+    /// Follow reference reusing elements already read within the same
+    /// operation. Elements met on the way are added to the cache. When the
+    /// hop limit is exceeded the error holds the part of the chain followed.
+    pub fn follow_reference_with_cache(
         &self,
         mut path: Vec<Vec<u8>>,
         allow_cache: bool,
+        reference_cache: &mut ReferenceResolutionCache,
         transaction: TransactionArg,
     ) -> CostResult<Element, Error> {
         let mut cost = OperationCost::default();
 
         let mut hops_left = MAX_REFERENCE_HOPS;
-        let mut current_element;
         let mut visited = HashSet::new();
+        let mut chain = PartialReferenceChain::default();
 
         while hops_left > 0 {
             if visited.contains(&path) {
                 return Err(Error::CyclicReference).wrap_with_cost(cost);
             }
-            if let Some((key, path_slice)) = path.split_last() {
-                current_element = cost_return_on_error!(
-                    &mut cost,
-                    self.get_raw_caching_optional(
-                        path_slice.iter().map(|x| x.as_slice()),
-                        key,
-                        allow_cache,
-                        transaction
-                    )
-                    .map_err(|e| match e {
-                        Error::PathParentLayerNotFound(p) => {
-                            Error::CorruptedReferencePathParentLayerNotFound(p)
-                        }
-                        Error::PathKeyNotFound(p) => {
-                            Error::CorruptedReferencePathKeyNotFound(p)
-                        }
-                        Error::PathNotFound(p) => {
-                            Error::CorruptedReferencePathNotFound(p)
-                        }
-                        _ => e,
-                    })
-                )
+            let current_element = if let Some(element) = reference_cache.get(&path) {
+                element.clone()
             } else {
-                return Err(Error::CorruptedPath("empty path")).wrap_with_cost(cost);
-            }
+                let element = cost_return_on_error!(
+                    &mut cost,
+                    self.get_at_qualified_path(&path, allow_cache, transaction)
+                );
+                reference_cache.insert(path.clone(), element.clone());
+                element
+            };
             visited.insert(path.clone());
-            match current_element {
-                Element::Reference(reference_path, ..) => {
-                    path = cost_return_on_error!(
-                        &mut cost,
-                        path_from_reference_qualified_path_type(reference_path, &path)
-                            .wrap_with_cost(OperationCost::default())
-                    )
-                }
-                other => return Ok(other).wrap_with_cost(cost),
-            }
+            let next_path = match &current_element {
+                Element::Reference(reference_path, ..) => cost_return_on_error_no_add!(
+                    &cost,
+                    path_from_reference_qualified_path_type(reference_path.clone(), &path)
+                ),
+                _ => return Ok(current_element).wrap_with_cost(cost),
+            };
+            chain.visited.push(std::mem::replace(&mut path, next_path));
+            chain.last_element = Some(current_element);
             hops_left -= 1;
         }
-        Err(Error::ReferenceLimit).wrap_with_cost(cost)
+        chain.next_path = Some(path);
+        Err(Error::ReferenceLimit(Box::new(chain))).wrap_with_cost(cost)
+    }
+
+    /// Reads the element at the qualified path, the first step of following
+    /// a reference chain from there. Repeating it on the next path of every
+    /// step walks the chain one hop at a time, without any hop limit.
+    pub fn resolve_reference_step(
+        &self,
+        path: Vec<Vec<u8>>,
+        transaction: TransactionArg,
+    ) -> CostResult<ReferenceStep, Error> {
+        let mut cost = OperationCost::default();
+
+        let element = cost_return_on_error!(
+            &mut cost,
+            self.get_at_qualified_path(&path, true, transaction)
+        );
+        let next_path = match &element {
+            Element::Reference(reference_path, ..) => Some(cost_return_on_error_no_add!(
+                &cost,
+                path_from_reference_qualified_path_type(reference_path.clone(), &path)
+            )),
+            _ => None,
+        };
+        Ok(ReferenceStep {
+            path,
+            element,
+            next_path,
+        })
+        .wrap_with_cost(cost)
+    }
+
+    /// Reads the element at a qualified path pointed to by a reference
+    fn get_at_qualified_path(
+        &self,
+        path: &[Vec<u8>],
+        allow_cache: bool,
+        transaction: TransactionArg,
+    ) -> CostResult<Element, Error> {
+        match SubtreePath::from(path).derive_parent() {
+            Some((parent_path, key)) => self
+                .get_raw_caching_optional(parent_path, key, allow_cache, transaction)
+                .map_err(reference_target_error),
+            None => {
+                Err(Error::CorruptedPath("empty path")).wrap_with_cost(OperationCost::default())
+            }
+        }
     }
 
     /// Get tree item without following references
@@ -197,7 +279,19 @@ impl GroveDb {
         <P as IntoIterator>::IntoIter: ExactSizeIterator + DoubleEndedIterator + Clone,
     {
         if let Some(transaction) = transaction {
-            self.get_raw_on_transaction_caching_optional(path, key, allow_cache, transaction)
+            let path_iter = path.into_iter();
+            self.propagate_pending_changes_below(
+                path_iter.clone().chain(std::iter::once(key)),
+                transaction,
+            )
+            .flat_map_ok(|_| {
+                self.get_raw_on_transaction_caching_optional(
+                    path_iter,
+                    key,
+                    allow_cache,
+                    transaction,
+                )
+            })
         } else {
             self.get_raw_without_transaction_caching_optional(path, key, allow_cache)
         }
@@ -230,12 +324,19 @@ impl GroveDb {
         <P as IntoIterator>::IntoIter: ExactSizeIterator + DoubleEndedIterator + Clone,
     {
         if let Some(transaction) = transaction {
-            self.get_raw_optional_on_transaction_caching_optional(
-                path,
-                key,
-                allow_cache,
+            let path_iter = path.into_iter();
+            self.propagate_pending_changes_below(
+                path_iter.clone().chain(std::iter::once(key)),
                 transaction,
             )
+            .flat_map_ok(|_| {
+                self.get_raw_optional_on_transaction_caching_optional(
+                    path_iter,
+                    key,
+                    allow_cache,
+                    transaction,
+                )
+            })
         } else {
             self.get_raw_optional_without_transaction_caching_optional(path, key, allow_cache)
         }
@@ -425,7 +526,9 @@ impl GroveDb {
         }
         .unwrap_add_cost(&mut cost);
         match element {
-            Ok(Element::Tree(..)) | Ok(Element::SumTree(..)) => Ok(()).wrap_with_cost(cost),
+            Ok(Element::Tree(..)) | Ok(Element::SumTree(..)) | Ok(Element::OrderedTree(..)) => {
+                Ok(()).wrap_with_cost(cost)
+            }
             Ok(_) | Err(Error::PathKeyNotFound(_)) => Err(error).wrap_with_cost(cost),
             Err(e) => Err(e).wrap_with_cost(cost),
         }
@@ -39,9 +39,10 @@ use integer_encoding::VarInt;
 use crate::query_result_type::PathKeyOptionalElementTrio;
 #[cfg(feature = "full")]
 use crate::{
+    operations::get::ReferenceResolutionCache,
     query_result_type::{QueryResultElement, QueryResultElements, QueryResultType},
     reference_path::ReferencePathType,
-    Element, Error, GroveDb, PathQuery, TransactionArg,
+    Element, Error, GroveDb, PathQuery, QueryVersion, TransactionArg,
 };
 
 #[cfg(feature = "full")]
@@ -64,6 +65,7 @@ impl GroveDb {
                 transaction
             )
         );
+        let mut reference_cache = ReferenceResolutionCache::default();
         let results_wrapped = elements
             .into_iterator()
             .map(|result_item| match result_item {
@@ -75,7 +77,12 @@ impl GroveDb {
                             // external costs accumulator instead of
                             // returning costs from `map` call.
                             let maybe_item = self
-                                .follow_reference(absolute_path, allow_cache, transaction)
+                                .follow_reference_with_cache(
+                                    absolute_path,
+                                    allow_cache,
+                                    &mut reference_cache,
+                                    transaction,
+                                )
                                 .unwrap_add_cost(&mut cost)?;
 
                             match maybe_item {
@@ -142,6 +149,7 @@ where {
         &self,
         element: Element,
         allow_cache: bool,
+        reference_cache: &mut ReferenceResolutionCache,
         cost: &mut OperationCost,
         transaction: TransactionArg,
     ) -> Result<Element, Error> {
@@ -155,7 +163,12 @@ where {
                         // external costs accumulator instead of
                         // returning costs from `map` call.
                         let maybe_item = self
-                            .follow_reference(absolute_path, allow_cache, transaction)
+                            .follow_reference_with_cache(
+                                absolute_path,
+                                allow_cache,
+                                reference_cache,
+                                transaction,
+                            )
                             .unwrap_add_cost(cost)?;
 
                         if maybe_item.is_item() {
@@ -170,9 +183,9 @@ where {
                 }
             }
             Element::Item(..) | Element::SumItem(..) => Ok(element),
-            Element::Tree(..) | Element::SumTree(..) => Err(Error::InvalidQuery(
-                "path_queries can only refer to items and references",
-            )),
+            Element::Tree(..) | Element::SumTree(..) | Element::OrderedTree(..) => Err(
+                Error::InvalidQuery("path_queries can only refer to items and references"),
+            ),
         }
     }
 
@@ -191,11 +204,18 @@ where {
             self.query_raw(path_query, allow_cache, result_type, transaction)
         );
 
+        let mut reference_cache = ReferenceResolutionCache::default();
         let results_wrapped = elements
             .into_iterator()
             .map(|result_item| {
                 result_item.map_element(|element| {
-                    self.follow_element(element, allow_cache, &mut cost, transaction)
+                    self.follow_element(
+                        element,
+                        allow_cache,
+                        &mut reference_cache,
+                        &mut cost,
+                        transaction,
+                    )
                 })
             })
             .collect::<Result<Vec<QueryResultElement>, Error>>();
@@ -204,6 +224,24 @@ where {
         Ok((QueryResultElements { elements: results }, skipped)).wrap_with_cost(cost)
     }
 
+    /// Same as [`GroveDb::query`] under the given version of query semantics
+    pub fn query_with_version(
+        &self,
+        path_query: &PathQuery,
+        allow_cache: bool,
+        result_type: QueryResultType,
+        version: QueryVersion,
+        transaction: TransactionArg,
+    ) -> CostResult<(QueryResultElements, u16), Error> {
+        cost_return_on_error_default!(version.validate(path_query));
+        self.query(
+            &version.traversal_query(path_query),
+            allow_cache,
+            result_type,
+            transaction,
+        )
+    }
+
     /// Queries the backing store and returns element items by their value,
     /// Sum Items are encoded as var vec
     pub fn query_item_value(
@@ -224,6 +262,7 @@ where {
             )
         );
 
+        let mut reference_cache = ReferenceResolutionCache::default();
         let results_wrapped = elements
             .into_iterator()
             .map(|result_item| match result_item {
@@ -238,7 +277,12 @@ where {
                                     // external costs accumulator instead of
                                     // returning costs from `map` call.
                                     let maybe_item = self
-                                        .follow_reference(absolute_path, allow_cache, transaction)
+                                        .follow_reference_with_cache(
+                                            absolute_path,
+                                            allow_cache,
+                                            &mut reference_cache,
+                                            transaction,
+                                        )
                                         .unwrap_add_cost(&mut cost)?;
 
                                     match maybe_item {
@@ -256,9 +300,11 @@ where {
                         }
                         Element::Item(item, _) => Ok(item),
                         Element::SumItem(item, _) => Ok(item.encode_var_vec()),
-                        Element::Tree(..) | Element::SumTree(..) => Err(Error::InvalidQuery(
-                            "path_queries can only refer to items and references",
-                        )),
+                        Element::Tree(..) | Element::SumTree(..) | Element::OrderedTree(..) => {
+                            Err(Error::InvalidQuery(
+                                "path_queries can only refer to items and references",
+                            ))
+                        }
                     }
                 }
                 _ => Err(Error::CorruptedCodeExecution(
@@ -290,6 +336,7 @@ where {
             )
         );
 
+        let mut reference_cache = ReferenceResolutionCache::default();
         let results_wrapped = elements
             .into_iterator()
             .map(|result_item| match result_item {
@@ -304,7 +351,12 @@ where {
                                     // external costs accumulator instead of
                                     // returning costs from `map` call.
                                     let maybe_item = self
-                                        .follow_reference(absolute_path, allow_cache, transaction)
+                                        .follow_reference_with_cache(
+                                            absolute_path,
+                                            allow_cache,
+                                            &mut reference_cache,
+                                            transaction,
+                                        )
                                         .unwrap_add_cost(&mut cost)?;
 
                                     if let Element::SumItem(item, _) = maybe_item {
@@ -321,12 +373,13 @@ where {
                             }
                         }
                         Element::SumItem(item, _) => Ok(item),
-                        Element::Tree(..) | Element::SumTree(..) | Element::Item(..) => {
-                            Err(Error::InvalidQuery(
-                                "path_queries over sum items can only refer to sum items and \
+                        Element::Tree(..)
+                        | Element::SumTree(..)
+                        | Element::OrderedTree(..)
+                        | Element::Item(..) => Err(Error::InvalidQuery(
+                            "path_queries over sum items can only refer to sum items and \
                                  references",
-                            ))
-                        }
+                        )),
                     }
                 }
                 _ => Err(Error::CorruptedCodeExecution(
@@ -347,7 +400,38 @@ where {
         result_type: QueryResultType,
         transaction: TransactionArg,
     ) -> CostResult<(QueryResultElements, u16), Error> {
-        Element::get_raw_path_query(&self.db, path_query, allow_cache, result_type, transaction)
+        let mut cost = OperationCost::default();
+        if let Some(tx) = transaction {
+            cost_return_on_error!(
+                &mut cost,
+                self.propagate_pending_changes_below(
+                    path_query.path.iter().map(|k| k.as_slice()),
+                    tx
+                )
+            );
+        }
+        let mut query_result = match self.current_epoch() {
+            Some(current_epoch) => self.query_raw_unexpired(
+                path_query,
+                allow_cache,
+                result_type,
+                current_epoch,
+                transaction,
+            ),
+            None => Element::get_raw_path_query(
+                &self.db,
+                path_query,
+                allow_cache,
+                result_type,
+                transaction,
+            ),
+        };
+        if let Ok((elements, _)) = &query_result.value {
+            self.query_log
+                .record(path_query, elements.len(), &query_result.cost);
+        }
+        query_result.cost += cost;
+        query_result
     }
 
     /// Splits the result set of a path query by query path.
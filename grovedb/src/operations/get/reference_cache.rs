@@ -0,0 +1,69 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Reference resolution cache
+
+use std::collections::HashMap;
+
+use crate::Element;
+
+/// Elements read while following references, keyed by their qualified path
+/// (subtree path with the key appended).
+/// Meant to live for a single operation only, so that references resolving
+/// through the same intermediate paths don't hit the storage again.
+#[derive(Debug, Default)]
+pub struct ReferenceResolutionCache {
+    elements: HashMap<Vec<Vec<u8>>, Element>,
+}
+
+impl ReferenceResolutionCache {
+    /// Creates an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of cached elements
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// Returns true if nothing was cached yet
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Gets a cached element by its qualified path
+    pub(crate) fn get(&self, qualified_path: &[Vec<u8>]) -> Option<&Element> {
+        self.elements.get(qualified_path)
+    }
+
+    /// Caches an element read from the qualified path
+    pub(crate) fn insert(&mut self, qualified_path: Vec<Vec<u8>>, element: Element) {
+        self.elements.insert(qualified_path, element);
+    }
+}
@@ -0,0 +1,235 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Batched reference resolution
+
+#[cfg(feature = "full")]
+use std::collections::{BTreeMap, BTreeSet};
+
+#[cfg(feature = "full")]
+use costs::{
+    cost_return_on_error, cost_return_on_error_no_add, CostResult, CostsExt, OperationCost,
+};
+#[cfg(feature = "full")]
+use merk::Merk;
+#[cfg(feature = "full")]
+use storage::{
+    rocksdb_storage::{PrefixedRocksDbStorageContext, PrefixedRocksDbTransactionContext},
+    StorageContext,
+};
+
+#[cfg(feature = "full")]
+use super::{reference_target_error, ReferenceResolutionCache, MAX_REFERENCE_HOPS};
+#[cfg(feature = "full")]
+use crate::{
+    reference_path::{path_from_reference_qualified_path_type, PartialReferenceChain},
+    Element, Error, GroveDb, TransactionArg,
+};
+
+#[cfg(feature = "full")]
+/// Reference chain followed for one of the requested keys
+struct PendingChain {
+    /// Qualified path to read next
+    path: Vec<Vec<u8>>,
+    /// Qualified paths read after the requested key, in the order they were
+    /// read
+    visited: Vec<Vec<Vec<u8>>>,
+    /// Last reference read
+    last_element: Option<Element>,
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Gets the elements under many keys of one subtree, following
+    /// references the same way `get` does. Chains are walked side by side
+    /// one hop at a time: each hop opens every target subtree once for all
+    /// keys pointing into it, and targets shared by several chains are read
+    /// only once. Resolved elements are returned in the order of the keys.
+    pub fn resolve_references<'p, 'k, P, K>(
+        &self,
+        path: P,
+        keys: K,
+        transaction: TransactionArg,
+    ) -> CostResult<Vec<Element>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        K: IntoIterator<Item = &'k [u8]>,
+    {
+        let mut cost = OperationCost::default();
+
+        let path: Vec<Vec<u8>> = path.into_iter().map(|segment| segment.to_vec()).collect();
+        let mut chains: Vec<PendingChain> = keys
+            .into_iter()
+            .map(|key| {
+                let mut qualified_path = path.clone();
+                qualified_path.push(key.to_vec());
+                PendingChain {
+                    path: qualified_path,
+                    visited: Vec::new(),
+                    last_element: None,
+                }
+            })
+            .collect();
+        let mut resolved: Vec<Option<Element>> = vec![None; chains.len()];
+        let mut reference_cache = ReferenceResolutionCache::new();
+
+        // The first round reads the requested keys, every following one reads
+        // a hop of each chain still standing on a reference
+        for round in 0..=MAX_REFERENCE_HOPS {
+            if resolved.iter().all(Option::is_some) {
+                break;
+            }
+            let mut targets: BTreeMap<&[Vec<u8>], BTreeSet<&[u8]>> = BTreeMap::new();
+            for (chain, _) in chains.iter().zip(&resolved).filter(|(_, r)| r.is_none()) {
+                if round > 0 && chain.visited.contains(&chain.path) {
+                    return Err(Error::CyclicReference).wrap_with_cost(cost);
+                }
+                if reference_cache.get(&chain.path).is_some() {
+                    continue;
+                }
+                match chain.path.split_last() {
+                    Some((key, parent_path)) => {
+                        targets.entry(parent_path).or_default().insert(key);
+                    }
+                    None => return Err(Error::CorruptedPath("empty path")).wrap_with_cost(cost),
+                }
+            }
+
+            for (parent_path, keys) in targets {
+                let read = self
+                    .get_raw_many(parent_path, &keys, transaction)
+                    .map_err(|e| {
+                        if round > 0 {
+                            reference_target_error(e)
+                        } else {
+                            e
+                        }
+                    });
+                let elements = cost_return_on_error!(&mut cost, read);
+                for (key, element) in keys.into_iter().zip(elements) {
+                    let mut qualified_path = parent_path.to_vec();
+                    qualified_path.push(key.to_vec());
+                    reference_cache.insert(qualified_path, element);
+                }
+            }
+
+            for (chain, result) in chains.iter_mut().zip(resolved.iter_mut()) {
+                if result.is_some() {
+                    continue;
+                }
+                let element = reference_cache
+                    .get(&chain.path)
+                    .expect("every pending path was read this round")
+                    .clone();
+                let next_path = match &element {
+                    Element::Reference(reference_path, ..) => cost_return_on_error_no_add!(
+                        &cost,
+                        path_from_reference_qualified_path_type(
+                            reference_path.clone(),
+                            &chain.path
+                        )
+                    ),
+                    _ => {
+                        *result = Some(element);
+                        continue;
+                    }
+                };
+                let read_path = std::mem::replace(&mut chain.path, next_path);
+                if round > 0 {
+                    chain.visited.push(read_path);
+                    chain.last_element = Some(element);
+                }
+            }
+        }
+
+        let mut elements = Vec::with_capacity(resolved.len());
+        for (chain, result) in chains.into_iter().zip(resolved) {
+            match result {
+                Some(element) => elements.push(element),
+                None => {
+                    return Err(Error::ReferenceLimit(Box::new(PartialReferenceChain {
+                        visited: chain.visited,
+                        last_element: chain.last_element,
+                        next_path: Some(chain.path),
+                    })))
+                    .wrap_with_cost(cost)
+                }
+            }
+        }
+        Ok(elements).wrap_with_cost(cost)
+    }
+
+    /// Gets many elements of one subtree without following references,
+    /// opening the subtree only once
+    fn get_raw_many(
+        &self,
+        path: &[Vec<u8>],
+        keys: &BTreeSet<&[u8]>,
+        transaction: TransactionArg,
+    ) -> CostResult<Vec<Element>, Error> {
+        let mut cost = OperationCost::default();
+
+        let path_iter = path.iter().map(|segment| segment.as_slice());
+        let missing_parent = |e: Error| match e {
+            Error::InvalidParentLayerPath(s) => Error::PathParentLayerNotFound(s),
+            _ => e,
+        };
+        if let Some(transaction) = transaction {
+            let merk: Merk<PrefixedRocksDbTransactionContext> = cost_return_on_error!(
+                &mut cost,
+                self.open_transactional_merk_at_path(path_iter, transaction)
+                    .map_err(missing_parent)
+            );
+            Self::get_many_from_merk(&merk, keys).add_cost(cost)
+        } else {
+            let merk: Merk<PrefixedRocksDbStorageContext> = cost_return_on_error!(
+                &mut cost,
+                self.open_non_transactional_merk_at_path(path_iter)
+                    .map_err(missing_parent)
+            );
+            Self::get_many_from_merk(&merk, keys).add_cost(cost)
+        }
+    }
+
+    /// Gets the elements under the keys of an opened subtree
+    fn get_many_from_merk<'db, S: StorageContext<'db>>(
+        merk: &Merk<S>,
+        keys: &BTreeSet<&[u8]>,
+    ) -> CostResult<Vec<Element>, Error> {
+        let mut cost = OperationCost::default();
+
+        let mut elements = Vec::with_capacity(keys.len());
+        for key in keys {
+            elements.push(cost_return_on_error!(
+                &mut cost,
+                Element::get(merk, *key, true)
+            ));
+        }
+        Ok(elements).wrap_with_cost(cost)
+    }
+}
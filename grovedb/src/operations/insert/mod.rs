@@ -56,6 +56,9 @@ pub struct InsertOptions {
     pub validate_insertion_does_not_override_tree: bool,
     /// Base root storage is free
     pub base_root_storage_is_free: bool,
+    /// Explicitly allow overwriting an existing element when the instance is
+    /// in strict mode
+    pub overwrite: bool,
 }
 
 #[cfg(feature = "full")]
@@ -65,6 +68,7 @@ impl Default for InsertOptions {
             validate_insertion_does_not_override: false,
             validate_insertion_does_not_override_tree: true,
             base_root_storage_is_free: true,
+            overwrite: false,
         }
     }
 }
@@ -97,11 +101,68 @@ impl GroveDb {
         P: IntoIterator<Item = &'p [u8]>,
         <P as IntoIterator>::IntoIter: ExactSizeIterator + DoubleEndedIterator + Clone,
     {
-        if let Some(transaction) = transaction {
-            self.insert_on_transaction(path, key, element, options.unwrap_or_default(), transaction)
-        } else {
-            self.insert_without_transaction(path, key, element, options.unwrap_or_default())
+        let mut cost = OperationCost::default();
+
+        let path_iter = path.into_iter();
+        cost_return_on_error!(
+            &mut cost,
+            self.check_writable(path_iter.clone(), key, transaction)
+        );
+        let options = self.apply_strict_mode(options.unwrap_or_default());
+        let quotas = cost_return_on_error!(
+            &mut cost,
+            self.check_insert_quotas(path_iter.clone(), key, &element, transaction)
+        );
+        // the root leaf registry hash is bookkeeping of the database rather than
+        // of the operation, so its cost isn't charged
+        let updates_root_leaves = path_iter.len() == 0;
+        match (quotas, transaction) {
+            (None, None) if !updates_root_leaves => {
+                self.insert_without_transaction(path_iter, key, element, options)
+            }
+            (quotas, Some(transaction)) => {
+                cost_return_on_error!(
+                    &mut cost,
+                    self.insert_on_transaction(path_iter, key, element, options, transaction)
+                );
+                if let Some(quotas) = quotas {
+                    cost_return_on_error!(
+                        &mut cost,
+                        self.write_subtree_quotas(&quotas, Some(transaction))
+                    );
+                }
+                if updates_root_leaves {
+                    cost_return_on_error_no_add!(
+                        &cost,
+                        self.update_root_leaves_hash(Some(transaction)).unwrap()
+                    );
+                }
+                Ok(()).wrap_with_cost(OperationCost::default())
+            }
+            (quotas, None) => {
+                // the usage and the root leaf registry hash have to be committed together
+                // with the element
+                let transaction = self.start_transaction();
+                cost_return_on_error!(
+                    &mut cost,
+                    self.insert_on_transaction(path_iter, key, element, options, &transaction)
+                );
+                if let Some(quotas) = quotas {
+                    cost_return_on_error!(
+                        &mut cost,
+                        self.write_subtree_quotas(&quotas, Some(&transaction))
+                    );
+                }
+                if updates_root_leaves {
+                    cost_return_on_error_no_add!(
+                        &cost,
+                        self.update_root_leaves_hash(Some(&transaction)).unwrap()
+                    );
+                }
+                self.commit_transaction(transaction)
+            }
         }
+        .add_cost(cost)
     }
 
     fn insert_on_transaction<'db, 'p, P>(
@@ -127,6 +188,15 @@ impl GroveDb {
             &mut cost,
             self.add_element_on_transaction(path_iter.clone(), key, element, options, transaction)
         );
+        if !self.is_eager_propagation() {
+            return self
+                .propagate_changes_deferred_with_transaction(
+                    &merk,
+                    path_iter.map(|k| k.to_vec()).collect(),
+                    transaction,
+                )
+                .add_cost(cost);
+        }
         merk_cache.insert(path_iter.clone().map(|k| k.to_vec()).collect(), merk);
         cost_return_on_error!(
             &mut cost,
@@ -187,10 +257,12 @@ impl GroveDb {
     {
         let mut cost = OperationCost::default();
         let path_iter = path.into_iter();
-        let mut subtree_to_insert_into = cost_return_on_error!(
+        let (mut subtree_to_insert_into, key_ordering) = cost_return_on_error!(
             &mut cost,
-            self.open_transactional_merk_at_path(path_iter.clone(), transaction)
+            self.open_transactional_merk_and_key_ordering_at_path(path_iter.clone(), transaction)
         );
+        // keys must fit the ordering declared by the subtree they are inserted in
+        cost_return_on_error_no_add!(&cost, key_ordering.validate_key(key));
         // if we don't allow a tree override then we should check
 
         if options.checks_for_override() {
@@ -272,7 +344,9 @@ impl GroveDb {
                     )
                 );
             }
-            Element::Tree(ref value, _) | Element::SumTree(ref value, ..) => {
+            Element::Tree(ref value, _)
+            | Element::SumTree(ref value, ..)
+            | Element::OrderedTree(ref value, ..) => {
                 if value.is_some() {
                     return Err(Error::InvalidCodeExecution(
                         "a tree should be empty at the moment of insertion when not using batches",
@@ -323,10 +397,12 @@ impl GroveDb {
     {
         let mut cost = OperationCost::default();
         let path_iter = path.into_iter();
-        let mut subtree_to_insert_into: Merk<PrefixedRocksDbStorageContext> = cost_return_on_error!(
+        let (mut subtree_to_insert_into, key_ordering) = cost_return_on_error!(
             &mut cost,
-            self.open_non_transactional_merk_at_path(path_iter.clone())
+            self.open_non_transactional_merk_and_key_ordering_at_path(path_iter.clone())
         );
+        // keys must fit the ordering declared by the subtree they are inserted in
+        cost_return_on_error_no_add!(&cost, key_ordering.validate_key(key));
 
         if options.checks_for_override() {
             let maybe_element_bytes = cost_return_on_error!(
@@ -408,7 +484,9 @@ impl GroveDb {
                     )
                 );
             }
-            Element::Tree(ref value, _) | Element::SumTree(ref value, ..) => {
+            Element::Tree(ref value, _)
+            | Element::SumTree(ref value, ..)
+            | Element::OrderedTree(ref value, ..) => {
                 if value.is_some() {
                     return Err(Error::InvalidCodeExecution(
                         "a tree should be empty at the moment of insertion when not using batches",
@@ -492,7 +570,7 @@ impl GroveDb {
         if !needs_insert {
             Ok((false, None)).wrap_with_cost(cost)
         } else {
-            self.insert(path_iter, key, element, None, transaction)
+            self.replace(path_iter, key, element, None, transaction)
                 .map_ok(|_| (true, previous_element))
                 .add_cost(cost)
         }
@@ -1771,6 +1849,7 @@ mod tests {
                     validate_insertion_does_not_override: false,
                     validate_insertion_does_not_override_tree: false,
                     base_root_storage_is_free: true,
+                    overwrite: false,
                 }),
                 Some(&tx),
             )
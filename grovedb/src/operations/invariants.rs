@@ -0,0 +1,467 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Invariant assertions
+//! Runtime checks of the consistency between subtrees and the tree elements
+//! pointing at them, cheap enough for embedders to run at intervals. Checks
+//! only read the database and report every violation they find instead of
+//! failing on the first one. Tree elements of transactions with pending
+//! deferred propagations are stale, so those have to be propagated first.
+
+#[cfg(feature = "full")]
+use costs::{
+    cost_return_on_error, cost_return_on_error_no_add, CostResult, CostsExt, OperationCost,
+};
+#[cfg(feature = "full")]
+use merk::{
+    tree::{combine_hash, value_hash},
+    CryptoHash, KVIterator, Merk,
+};
+#[cfg(feature = "full")]
+use storage::{RawIterator, StorageContext};
+
+#[cfg(feature = "full")]
+use crate::{element::helpers::raw_decode, Element, Error, GroveDb, Query, TransactionArg};
+
+#[cfg(feature = "full")]
+/// Part of the database checked by [`GroveDb::assert_invariants`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvariantScope {
+    /// Every subtree
+    All,
+    /// The subtree at the path, including its tree element, and every
+    /// subtree nested in it
+    Subtree(Vec<Vec<u8>>),
+}
+
+#[cfg(feature = "full")]
+/// Broken invariant found by [`GroveDb::assert_invariants`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvariantViolation {
+    /// The value hash of the tree element doesn't commit to the root hash of
+    /// its subtree
+    HashMismatch {
+        /// Path of the subtree
+        path: Vec<Vec<u8>>,
+        /// Value hash stored for the tree element
+        stored_value_hash: CryptoHash,
+        /// Value hash combining the tree element and the subtree root hash
+        expected_value_hash: CryptoHash,
+    },
+    /// The root key kept by the tree element isn't the root node found under
+    /// the prefix of its subtree
+    RootKeyMismatch {
+        /// Path of the subtree
+        path: Vec<Vec<u8>>,
+        /// Root key kept by the tree element
+        element_root_key: Option<Vec<u8>>,
+        /// Key of the root node loaded from the subtree prefix
+        root_key: Option<Vec<u8>>,
+    },
+    /// The prefix of a subtree without a root node holds data
+    OrphanedPrefixData {
+        /// Path of the subtree
+        path: Vec<Vec<u8>>,
+    },
+    /// The sum kept by a sum tree element doesn't match its subtree
+    SumMismatch {
+        /// Path of the subtree
+        path: Vec<Vec<u8>>,
+        /// Sum kept by the sum tree element
+        element_sum: i64,
+        /// Sum aggregated by the root node of the subtree
+        aggregate_sum: i64,
+        /// Sum of the sum items and sum trees of the subtree
+        children_sum: i64,
+    },
+}
+
+#[cfg(feature = "full")]
+/// Report of [`GroveDb::assert_invariants`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InvariantReport {
+    /// Number of subtrees checked
+    pub subtrees_checked: usize,
+    /// Broken invariants, parents before their subtrees
+    pub violations: Vec<InvariantViolation>,
+}
+
+#[cfg(feature = "full")]
+impl InvariantReport {
+    /// Returns true if no invariant is broken
+    pub fn is_consistent(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+#[cfg(feature = "full")]
+/// Subtree waiting to be checked, with the sum its sum tree element keeps
+struct PendingSubtree {
+    path: Vec<Vec<u8>>,
+    element_sum: Option<(i64, i64)>,
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Checks that tree elements commit to the root hash of their subtree,
+    /// that the root keys they keep are the root nodes found under the
+    /// subtree prefixes, which hold no data for empty subtrees, and that sum
+    /// trees keep the sum of their subtree, for every subtree in scope. Fails
+    /// if the transaction has pending propagations, see
+    /// [`GroveDb::propagate_pending_changes`].
+    pub fn assert_invariants(
+        &self,
+        scope: InvariantScope,
+        transaction: TransactionArg,
+    ) -> CostResult<InvariantReport, Error> {
+        let mut cost = OperationCost::default();
+
+        let path = match scope {
+            InvariantScope::All => vec![],
+            InvariantScope::Subtree(path) => path,
+        };
+        if let Some(tx) = transaction {
+            if self.has_pending_propagations(tx) {
+                return Err(Error::NotSupported(
+                    "invariants can't be asserted while propagations are pending",
+                ))
+                .wrap_with_cost(cost);
+            }
+            Self::check_invariants(path, |path| {
+                self.open_transactional_merk_at_path(path.iter().map(|k| k.as_slice()), tx)
+            })
+            .add_cost(cost)
+        } else {
+            Self::check_invariants(path, |path| {
+                self.open_non_transactional_merk_at_path(path.iter().map(|k| k.as_slice()))
+            })
+        }
+    }
+
+    fn check_invariants<'db, S, F>(
+        path: Vec<Vec<u8>>,
+        open_merk: F,
+    ) -> CostResult<InvariantReport, Error>
+    where
+        S: StorageContext<'db>,
+        F: Fn(&[Vec<u8>]) -> CostResult<Merk<S>, Error>,
+    {
+        let mut cost = OperationCost::default();
+        let mut report = InvariantReport::default();
+
+        let mut queue = Vec::new();
+        // the tree element of the scope is checked from its parent
+        if let Some((key, parent_path)) = path.split_last() {
+            let parent = cost_return_on_error!(&mut cost, open_merk(parent_path));
+            let element = cost_return_on_error!(&mut cost, Element::get(&parent, key, true));
+            if !element.is_tree() {
+                return Err(Error::InvalidPath(
+                    "invariants can only be asserted on subtrees".to_owned(),
+                ))
+                .wrap_with_cost(cost);
+            }
+            let subtree = cost_return_on_error!(&mut cost, open_merk(&path));
+            let element_sum = cost_return_on_error!(
+                &mut cost,
+                Self::check_tree_element(&parent, &path, &element, &subtree, &mut report)
+            );
+            queue.push(PendingSubtree { path, element_sum });
+        } else {
+            queue.push(PendingSubtree {
+                path,
+                element_sum: None,
+            });
+        }
+
+        let mut all_query = Query::new();
+        all_query.insert_all();
+        while let Some(PendingSubtree { path, element_sum }) = queue.pop() {
+            report.subtrees_checked += 1;
+            let merk = cost_return_on_error!(&mut cost, open_merk(&path));
+            let mut children_sum: i64 = 0;
+            let mut element_iterator =
+                KVIterator::new(merk.storage.raw_iter(), &all_query).unwrap_add_cost(&mut cost);
+            while let Some((key, element_value)) =
+                element_iterator.next_kv().unwrap_add_cost(&mut cost)
+            {
+                let element = cost_return_on_error_no_add!(&cost, raw_decode(&element_value));
+                children_sum = children_sum.saturating_add(element.sum_value_or_default());
+                if element.is_tree() {
+                    let mut child_path = path.clone();
+                    child_path.push(key);
+                    let child = cost_return_on_error!(&mut cost, open_merk(&child_path));
+                    let child_element_sum = cost_return_on_error!(
+                        &mut cost,
+                        Self::check_tree_element(&merk, &child_path, &element, &child, &mut report)
+                    );
+                    queue.push(PendingSubtree {
+                        path: child_path,
+                        element_sum: child_element_sum,
+                    });
+                }
+            }
+            if let Some((element_sum, aggregate_sum)) = element_sum {
+                if element_sum != aggregate_sum || element_sum != children_sum {
+                    report.violations.push(InvariantViolation::SumMismatch {
+                        path,
+                        element_sum,
+                        aggregate_sum,
+                        children_sum,
+                    });
+                }
+            }
+        }
+
+        Ok(report).wrap_with_cost(cost)
+    }
+
+    /// Checks the tree element of the subtree at `path` against the subtree,
+    /// returns the sum kept by the element and the one aggregated by the
+    /// subtree if it is a sum tree, for them to be checked against the sum of
+    /// its children
+    fn check_tree_element<'db, S: StorageContext<'db>>(
+        parent: &Merk<S>,
+        path: &[Vec<u8>],
+        element: &Element,
+        subtree: &Merk<S>,
+        report: &mut InvariantReport,
+    ) -> CostResult<Option<(i64, i64)>, Error> {
+        let mut cost = OperationCost::default();
+
+        let key = path.last().expect("subtree paths have a key");
+        let (element_bytes, stored_value_hash) = cost_return_on_error!(
+            &mut cost,
+            parent
+                .get_value_and_value_hash(key, true)
+                .map_err(Error::MerkError)
+                .map(|result| result.and_then(|maybe_value| {
+                    maybe_value
+                        .ok_or_else(|| Error::CorruptedData("tree element is missing".to_owned()))
+                }))
+        );
+        let (root_hash, root_key, aggregate_sum) = cost_return_on_error!(
+            &mut cost,
+            subtree.root_hash_key_and_sum().map_err(Error::MerkError)
+        );
+
+        let element_value_hash = value_hash(&element_bytes).unwrap_add_cost(&mut cost);
+        let expected_value_hash =
+            combine_hash(&element_value_hash, &root_hash).unwrap_add_cost(&mut cost);
+        if expected_value_hash != stored_value_hash {
+            report.violations.push(InvariantViolation::HashMismatch {
+                path: path.to_vec(),
+                stored_value_hash,
+                expected_value_hash,
+            });
+        }
+
+        let (element_root_key, element_sum) = match element {
+            Element::Tree(root_key, _) | Element::OrderedTree(root_key, ..) => (root_key, None),
+            Element::SumTree(root_key, sum, _) => {
+                (root_key, Some((*sum, aggregate_sum.unwrap_or_default())))
+            }
+            _ => {
+                return Err(Error::CorruptedCodeExecution(
+                    "only tree elements have subtrees",
+                ))
+                .wrap_with_cost(cost)
+            }
+        };
+        if element_root_key != &root_key {
+            report.violations.push(InvariantViolation::RootKeyMismatch {
+                path: path.to_vec(),
+                element_root_key: element_root_key.clone(),
+                root_key: root_key.clone(),
+            });
+        }
+        if root_key.is_none() {
+            let mut raw_iter = subtree.storage.raw_iter();
+            raw_iter.seek_to_first().unwrap_add_cost(&mut cost);
+            if raw_iter.valid().unwrap_add_cost(&mut cost) {
+                report
+                    .violations
+                    .push(InvariantViolation::OrphanedPrefixData {
+                        path: path.to_vec(),
+                    });
+            }
+        }
+
+        Ok(element_sum).wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{make_test_grovedb, TEST_LEAF};
+
+    #[test]
+    fn test_assert_invariants_reports_stale_elements() {
+        let db = make_test_grovedb();
+        db.insert([TEST_LEAF], b"inner", Element::empty_tree(), None, None)
+            .unwrap()
+            .expect("should insert tree");
+        db.insert([TEST_LEAF], b"sums", Element::empty_sum_tree(), None, None)
+            .unwrap()
+            .expect("should insert sum tree");
+        db.insert(
+            [TEST_LEAF, b"sums"],
+            b"a",
+            Element::new_sum_item(5),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert sum item");
+        db.insert(
+            [TEST_LEAF, b"inner"],
+            b"key",
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+
+        let report = db
+            .assert_invariants(InvariantScope::All, None)
+            .unwrap()
+            .expect("should assert invariants");
+        assert!(report.is_consistent());
+        assert_eq!(report.subtrees_checked, 5);
+
+        // change both subtrees bypassing propagation
+        let tx = db.start_transaction();
+        let mut merk = db
+            .open_transactional_merk_at_path([TEST_LEAF, b"inner"], &tx)
+            .unwrap()
+            .expect("should open merk");
+        Element::new_item(b"changed".to_vec())
+            .insert(&mut merk, b"key", None)
+            .unwrap()
+            .expect("should insert item");
+        let mut merk = db
+            .open_transactional_merk_at_path([TEST_LEAF, b"sums"], &tx)
+            .unwrap()
+            .expect("should open merk");
+        Element::new_sum_item(7)
+            .insert(&mut merk, b"b", None)
+            .unwrap()
+            .expect("should insert sum item");
+        drop(merk);
+
+        let report = db
+            .assert_invariants(
+                InvariantScope::Subtree(vec![TEST_LEAF.to_vec(), b"sums".to_vec()]),
+                Some(&tx),
+            )
+            .unwrap()
+            .expect("should assert invariants");
+        assert_eq!(report.subtrees_checked, 1);
+        assert_eq!(report.violations.len(), 2);
+        assert!(matches!(
+            report.violations[0],
+            InvariantViolation::HashMismatch { .. }
+        ));
+        assert_eq!(
+            report.violations[1],
+            InvariantViolation::SumMismatch {
+                path: vec![TEST_LEAF.to_vec(), b"sums".to_vec()],
+                element_sum: 5,
+                aggregate_sum: 12,
+                children_sum: 12,
+            }
+        );
+
+        let report = db
+            .assert_invariants(InvariantScope::All, Some(&tx))
+            .unwrap()
+            .expect("should assert invariants");
+        assert_eq!(
+            report
+                .violations
+                .iter()
+                .filter(|violation| matches!(violation, InvariantViolation::HashMismatch { .. }))
+                .count(),
+            2
+        );
+
+        assert!(matches!(
+            db.assert_invariants(
+                InvariantScope::Subtree(vec![
+                    TEST_LEAF.to_vec(),
+                    b"inner".to_vec(),
+                    b"key".to_vec()
+                ]),
+                Some(&tx),
+            )
+            .unwrap(),
+            Err(Error::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_assert_invariants_refuses_pending_propagations() {
+        let db = make_test_grovedb();
+        db.set_eager_propagation(false);
+        let tx = db.start_transaction();
+        db.insert(
+            [TEST_LEAF],
+            b"inner",
+            Element::empty_tree(),
+            None,
+            Some(&tx),
+        )
+        .unwrap()
+        .expect("should insert tree");
+        db.insert(
+            [TEST_LEAF, b"inner"],
+            b"key",
+            Element::new_item(b"value".to_vec()),
+            None,
+            Some(&tx),
+        )
+        .unwrap()
+        .expect("should insert item");
+
+        assert!(matches!(
+            db.assert_invariants(InvariantScope::All, Some(&tx))
+                .unwrap(),
+            Err(Error::NotSupported(_))
+        ));
+        db.propagate_pending_changes(&tx)
+            .unwrap()
+            .expect("should propagate pending changes");
+        assert!(db
+            .assert_invariants(InvariantScope::All, Some(&tx))
+            .unwrap()
+            .expect("should assert invariants")
+            .is_consistent());
+    }
+}
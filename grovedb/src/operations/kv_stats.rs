@@ -0,0 +1,223 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Key/value statistics
+//! Histograms of the key and value lengths of subtrees, computed on demand by
+//! scanning them. They point at outliers blowing up proof sizes and allow
+//! checking the key and value sizes assumed by worst case cost estimation
+//! against the actual data.
+
+#[cfg(feature = "full")]
+use costs::{cost_return_on_error, CostResult, CostsExt, OperationCost};
+
+#[cfg(feature = "full")]
+use crate::{operations::scan::ScanOptions, Error, GroveDb, TransactionArg};
+
+#[cfg(feature = "full")]
+/// Number of buckets of a length histogram
+pub const LENGTH_BUCKETS: usize = 18;
+
+#[cfg(feature = "full")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Histogram of lengths in power of two buckets: the first bucket counts
+/// zero lengths, bucket `i` counts lengths from `2^(i-1)` up to `2^i`
+/// excluded and the last one every length from `2^(LENGTH_BUCKETS-2)` on
+pub struct LengthHistogram {
+    /// Count of lengths per bucket
+    pub buckets: [u64; LENGTH_BUCKETS],
+    /// Number of recorded lengths
+    pub count: u64,
+    /// Sum of recorded lengths
+    pub total: u64,
+    /// Largest recorded length
+    pub max: usize,
+}
+
+#[cfg(feature = "full")]
+impl LengthHistogram {
+    /// Records a length
+    pub fn record(&mut self, length: usize) {
+        self.buckets[Self::bucket(length)] += 1;
+        self.count += 1;
+        self.total += length as u64;
+        self.max = self.max.max(length);
+    }
+
+    /// Bucket counting the length
+    pub fn bucket(length: usize) -> usize {
+        let bits = (usize::BITS - length.leading_zeros()) as usize;
+        bits.min(LENGTH_BUCKETS - 1)
+    }
+
+    /// Lengths counted by a bucket, the upper bound is excluded and missing
+    /// for the last bucket
+    pub fn bucket_range(bucket: usize) -> (usize, Option<usize>) {
+        match bucket {
+            0 => (0, Some(1)),
+            b if b >= LENGTH_BUCKETS - 1 => (1 << (LENGTH_BUCKETS - 2), None),
+            b => (1 << (b - 1), Some(1 << b)),
+        }
+    }
+
+    /// Average of recorded lengths
+    pub fn mean(&self) -> Option<f64> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.total as f64 / self.count as f64)
+        }
+    }
+
+    /// Number of recorded lengths in the buckets above the one of `length`,
+    /// it is exact when `length + 1` is a power of two
+    pub fn count_above(&self, length: usize) -> u64 {
+        self.buckets[Self::bucket(length) + 1..].iter().sum()
+    }
+}
+
+#[cfg(feature = "full")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Key and value length statistics of a subtree
+pub struct SubtreeKvStats {
+    /// Path of the subtree
+    pub path: Vec<Vec<u8>>,
+    /// Histogram of key lengths
+    pub key_lengths: LengthHistogram,
+    /// Histogram of serialized element lengths
+    pub value_lengths: LengthHistogram,
+    /// Key of the largest serialized element
+    pub largest_value_key: Option<Vec<u8>>,
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Computes key and value length statistics of the subtree at `path`
+    pub fn subtree_kv_stats<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> CostResult<SubtreeKvStats, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+    {
+        let path_iter = path.into_iter();
+        let mut stats = SubtreeKvStats {
+            path: path_iter.clone().map(|segment| segment.to_vec()).collect(),
+            ..Default::default()
+        };
+        self.scan_subtree(
+            path_iter,
+            ScanOptions::default(),
+            transaction,
+            |key, element| {
+                let value_length = element.serialized_size();
+                if value_length > stats.value_lengths.max || stats.largest_value_key.is_none() {
+                    stats.largest_value_key = Some(key.to_vec());
+                }
+                stats.key_lengths.record(key.len());
+                stats.value_lengths.record(value_length);
+                true
+            },
+        )
+        .map_ok(|_| stats)
+    }
+
+    /// Computes key and value length statistics of every subtree
+    pub fn kv_stats(&self, transaction: TransactionArg) -> CostResult<Vec<SubtreeKvStats>, Error> {
+        let mut cost = OperationCost::default();
+
+        let subtrees = cost_return_on_error!(&mut cost, self.find_subtrees([], transaction));
+        let mut stats = Vec::with_capacity(subtrees.len());
+        for path in subtrees {
+            stats.push(cost_return_on_error!(
+                &mut cost,
+                self.subtree_kv_stats(path.iter().map(|segment| segment.as_slice()), transaction)
+            ));
+        }
+
+        Ok(stats).wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::{make_test_grovedb, TEST_LEAF},
+        Element,
+    };
+
+    #[test]
+    fn test_length_histogram_buckets() {
+        assert_eq!(LengthHistogram::bucket(0), 0);
+        assert_eq!(LengthHistogram::bucket(1), 1);
+        assert_eq!(LengthHistogram::bucket(3), 2);
+        assert_eq!(LengthHistogram::bucket(4), 3);
+        assert_eq!(LengthHistogram::bucket(usize::MAX), LENGTH_BUCKETS - 1);
+        for length in [0, 1, 5, 64, 1000, 1 << 20] {
+            let (low, high) = LengthHistogram::bucket_range(LengthHistogram::bucket(length));
+            assert!(low <= length);
+            assert!(high.map(|high| length < high).unwrap_or(true));
+        }
+    }
+
+    #[test]
+    fn test_subtree_kv_stats() {
+        let db = make_test_grovedb();
+        for (key, value_length) in [
+            (b"a".to_vec(), 10),
+            (b"bbbb".to_vec(), 20),
+            (b"c".to_vec(), 5000),
+        ] {
+            db.insert(
+                [TEST_LEAF],
+                &key,
+                Element::new_item(vec![0; value_length]),
+                None,
+                None,
+            )
+            .unwrap()
+            .expect("should insert item");
+        }
+
+        let stats = db
+            .subtree_kv_stats([TEST_LEAF], None)
+            .unwrap()
+            .expect("should compute stats");
+        assert_eq!(stats.key_lengths.count, 3);
+        assert_eq!(stats.key_lengths.max, 4);
+        assert_eq!(stats.value_lengths.count_above(1023), 1);
+        assert_eq!(stats.largest_value_key, Some(b"c".to_vec()));
+
+        let all_stats = db.kv_stats(None).unwrap().expect("should compute stats");
+        assert_eq!(all_stats.len(), 3);
+        assert!(all_stats.contains(&stats));
+    }
+}
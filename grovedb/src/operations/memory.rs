@@ -0,0 +1,280 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Memtable budget
+//! Approximate accounting of the memory held by a GroveDb instance: subtree
+//! handles opened while applying batches, storage batches not yet committed
+//! and the storage memtables. Only the memtables are bounded, they are flushed
+//! to disk once they exceed the configured budget. The other figures are
+//! reported but not evicted, and the storage block cache isn't accounted.
+
+#[cfg(feature = "full")]
+use std::{
+    mem::size_of,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+#[cfg(feature = "full")]
+use merk::Merk;
+#[cfg(feature = "full")]
+use storage::{rocksdb_storage::PrefixedRocksDbStorageContext, Storage};
+
+#[cfg(feature = "full")]
+use crate::{Error, GroveDb};
+
+#[cfg(feature = "full")]
+/// Approximate memory of an open subtree handle along with its loaded root
+pub const OPEN_SUBTREE_ESTIMATED_BYTES: usize =
+    size_of::<Merk<PrefixedRocksDbStorageContext>>() + 512;
+
+#[cfg(feature = "full")]
+/// Stored memtable budget meaning no budget is set
+const NO_MEMTABLE_BUDGET: usize = usize::MAX;
+
+#[cfg(feature = "full")]
+/// Memtable budget settings and memory accounting of a GroveDb instance
+pub(crate) struct MemoryAccounting {
+    /// Memtable budget in bytes, `NO_MEMTABLE_BUDGET` if unlimited
+    memtable_budget: AtomicUsize,
+    /// Subtree handles opened by batches being applied
+    open_subtrees: AtomicUsize,
+    /// Bytes held by storage batches not yet committed
+    in_flight_batch_bytes: AtomicUsize,
+    /// Number of memtable flushes triggered by the budget
+    budget_flushes: AtomicU64,
+    /// Number of times the budget couldn't be enforced after a write
+    budget_failures: AtomicU64,
+}
+
+#[cfg(feature = "full")]
+impl Default for MemoryAccounting {
+    fn default() -> Self {
+        MemoryAccounting {
+            memtable_budget: AtomicUsize::new(NO_MEMTABLE_BUDGET),
+            open_subtrees: AtomicUsize::default(),
+            in_flight_batch_bytes: AtomicUsize::default(),
+            budget_flushes: AtomicU64::default(),
+            budget_failures: AtomicU64::default(),
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+/// Snapshot of the approximate memory used by a GroveDb instance
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Configured memtable budget in bytes
+    pub memtable_budget: Option<usize>,
+    /// Subtree handles opened by batches being applied
+    pub open_subtrees: usize,
+    /// Approximate bytes of the open subtree handles
+    pub open_subtrees_bytes: usize,
+    /// Bytes held by storage batches not yet committed
+    pub in_flight_batch_bytes: usize,
+    /// Bytes held by the storage memtables
+    pub memtables_bytes: usize,
+    /// Number of memtable flushes triggered by the budget
+    pub budget_flushes: u64,
+    /// Number of times the budget couldn't be enforced after a write, the
+    /// write itself having succeeded
+    pub budget_failures: u64,
+}
+
+#[cfg(feature = "full")]
+impl MemoryStats {
+    /// Total of the accounted memory
+    pub fn total_bytes(&self) -> usize {
+        self.open_subtrees_bytes + self.in_flight_batch_bytes + self.memtables_bytes
+    }
+
+    /// Returns true if a memtable budget is set and the memtables exceed it
+    pub fn is_over_memtable_budget(&self) -> bool {
+        self.memtable_budget
+            .map(|budget| self.memtables_bytes > budget)
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(feature = "full")]
+/// Keeps memory accounted for as long as it lives
+pub(crate) struct MemoryReservation<'a> {
+    accounting: &'a MemoryAccounting,
+    open_subtrees: usize,
+    in_flight_batch_bytes: usize,
+}
+
+#[cfg(feature = "full")]
+impl<'a> MemoryReservation<'a> {
+    /// Accounts for an opened subtree handle
+    pub(crate) fn add_open_subtree(&mut self) {
+        self.accounting
+            .open_subtrees
+            .fetch_add(1, Ordering::Relaxed);
+        self.open_subtrees += 1;
+    }
+
+    /// Replaces the accounted in flight batch size
+    pub(crate) fn set_in_flight_batch_bytes(&mut self, bytes: usize) {
+        self.accounting
+            .in_flight_batch_bytes
+            .fetch_sub(self.in_flight_batch_bytes, Ordering::Relaxed);
+        self.accounting
+            .in_flight_batch_bytes
+            .fetch_add(bytes, Ordering::Relaxed);
+        self.in_flight_batch_bytes = bytes;
+    }
+}
+
+#[cfg(feature = "full")]
+impl<'a> Drop for MemoryReservation<'a> {
+    fn drop(&mut self) {
+        self.accounting
+            .open_subtrees
+            .fetch_sub(self.open_subtrees, Ordering::Relaxed);
+        self.accounting
+            .in_flight_batch_bytes
+            .fetch_sub(self.in_flight_batch_bytes, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Sets the memtable budget in bytes, `None` removes the budget
+    pub fn set_memtable_budget(&self, budget: Option<usize>) {
+        self.memory
+            .memtable_budget
+            .store(budget.unwrap_or(NO_MEMTABLE_BUDGET), Ordering::Relaxed);
+    }
+
+    /// Returns the memtable budget in bytes
+    pub fn memtable_budget(&self) -> Option<usize> {
+        match self.memory.memtable_budget.load(Ordering::Relaxed) {
+            NO_MEMTABLE_BUDGET => None,
+            budget => Some(budget),
+        }
+    }
+
+    /// Returns the approximate memory used by this instance
+    pub fn memory_stats(&self) -> Result<MemoryStats, Error> {
+        let open_subtrees = self.memory.open_subtrees.load(Ordering::Relaxed);
+        Ok(MemoryStats {
+            memtable_budget: self.memtable_budget(),
+            open_subtrees,
+            open_subtrees_bytes: open_subtrees * OPEN_SUBTREE_ESTIMATED_BYTES,
+            in_flight_batch_bytes: self.memory.in_flight_batch_bytes.load(Ordering::Relaxed),
+            memtables_bytes: self.db.memtables_size()? as usize,
+            budget_flushes: self.memory.budget_flushes.load(Ordering::Relaxed),
+            budget_failures: self.memory.budget_failures.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Starts accounting memory of an operation, released once dropped
+    pub(crate) fn reserve_memory(&self) -> MemoryReservation<'_> {
+        MemoryReservation {
+            accounting: &self.memory,
+            open_subtrees: 0,
+            in_flight_batch_bytes: 0,
+        }
+    }
+
+    /// Flushes the storage memtables if they exceed the budget and waits for
+    /// the flush to complete, returns true if a flush happened
+    pub fn enforce_memtable_budget(&self) -> Result<bool, Error> {
+        self.flush_memtables_over_budget(true)
+    }
+
+    /// Enforces the memtable budget once a write succeeded. The flush is only
+    /// scheduled so the write doesn't wait for it. Failing to schedule it
+    /// doesn't undo the write, so it is only counted in the memory stats.
+    pub(crate) fn enforce_memtable_budget_after_write(&self) {
+        if self.flush_memtables_over_budget(false).is_err() {
+            self.memory.budget_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Flushes the storage memtables if they exceed the budget, waiting for
+    /// the flush only if asked to
+    fn flush_memtables_over_budget(&self, wait: bool) -> Result<bool, Error> {
+        if self.memtable_budget().is_none() || !self.memory_stats()?.is_over_memtable_budget() {
+            return Ok(false);
+        }
+        if wait {
+            self.db.flush()?;
+        } else {
+            self.db.schedule_flush()?;
+        }
+        self.memory.budget_flushes.fetch_add(1, Ordering::Relaxed);
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use crate::{
+        batch::GroveDbOp,
+        tests::{make_test_grovedb, TEST_LEAF},
+        Element,
+    };
+
+    #[test]
+    fn test_memtable_budget_flushes_memtables() {
+        let db = make_test_grovedb();
+        assert_eq!(db.memtable_budget(), None);
+        assert!(!db.enforce_memtable_budget().expect("should check budget"));
+
+        let ops = (0..100u32)
+            .map(|i| {
+                GroveDbOp::insert_op(
+                    vec![TEST_LEAF.to_vec()],
+                    i.to_be_bytes().to_vec(),
+                    Element::new_item(vec![7; 100]),
+                )
+            })
+            .collect();
+        db.apply_batch(ops, None, None)
+            .unwrap()
+            .expect("should apply batch");
+        let stats_before_flush = db.memory_stats().expect("should get stats");
+        assert!(stats_before_flush.memtables_bytes > 0);
+        assert_eq!(stats_before_flush.open_subtrees, 0);
+        assert_eq!(stats_before_flush.in_flight_batch_bytes, 0);
+
+        db.set_memtable_budget(Some(0));
+        assert_eq!(db.memtable_budget(), Some(0));
+        assert!(db.enforce_memtable_budget().expect("should flush"));
+        let stats = db.memory_stats().expect("should get stats");
+        assert_eq!(stats.memtable_budget, Some(0));
+        assert_eq!(stats.budget_flushes, 1);
+        assert_eq!(stats.budget_failures, 0);
+        assert!(stats.memtables_bytes < stats_before_flush.memtables_bytes);
+
+        db.set_memtable_budget(None);
+        assert_eq!(db.memtable_budget(), None);
+    }
+}
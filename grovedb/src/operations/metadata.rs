@@ -0,0 +1,498 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Metadata integrity
+//! Internal metadata kept in the meta storage is stored together with an
+//! integrity hash of its key and value. Every internal metadata key is listed
+//! in a single registry, the hashes of all of them are checked when the
+//! database is opened, so a silent corruption is reported right away instead
+//! of surfacing much later as unrelated path errors. The root leaf registry,
+//! the root leaf keys with the storage prefixes of their subtrees, is hashed
+//! the same way and checked against the root tree.
+
+#[cfg(feature = "full")]
+use std::collections::BTreeSet;
+
+#[cfg(feature = "full")]
+use costs::{cost_return_on_error, CostContext, CostResult, CostsExt, OperationCost};
+#[cfg(feature = "full")]
+use integer_encoding::VarInt;
+#[cfg(feature = "full")]
+use merk::{tree::value_hash, CryptoHash, HASH_LENGTH};
+#[cfg(feature = "full")]
+use storage::{rocksdb_storage::RocksDbStorage, Storage, StorageBatch, StorageContext};
+
+#[cfg(feature = "full")]
+use crate::{
+    batch::{
+        bulk_import::BULK_IMPORT_CHECKPOINTS_KEY, idempotency::APPLIED_BATCH_IDS_KEY, GroveDbOp, Op,
+    },
+    migrations::APPLIED_MIGRATIONS_KEY,
+    operations::{
+        quota::SUBTREE_QUOTAS_KEY,
+        read_only::READ_ONLY_SUBTREES_KEY,
+        reservation::SUBTREE_RESERVATIONS_KEY,
+        subtree_meta::{is_subtree_meta_key, verify_subtree_meta, SUBTREE_META_KINDS},
+    },
+    util::{
+        merk_optional_tx_path_not_empty, meta_storage_context_optional_tx, root_merk_optional_tx,
+        storage_context_optional_tx,
+    },
+    Element, Error, GroveDb, TransactionArg,
+};
+
+#[cfg(feature = "full")]
+/// Meta storage key of the hash of the root leaf registry
+pub(crate) const ROOT_LEAVES_HASH_KEY: &[u8] = b"root_leaves_hash";
+
+#[cfg(feature = "full")]
+/// Meta storage keys of all internal metadata, values under these keys are
+/// sealed with their integrity hash, as are the per subtree entries of the
+/// kinds listed in `SUBTREE_META_KINDS`. A feature persisting internal
+/// metadata registers its key here.
+pub(crate) const INTERNAL_METADATA_KEYS: [&[u8]; 7] = [
+    ROOT_LEAVES_HASH_KEY,
+    READ_ONLY_SUBTREES_KEY,
+    SUBTREE_RESERVATIONS_KEY,
+    SUBTREE_QUOTAS_KEY,
+    APPLIED_BATCH_IDS_KEY,
+    APPLIED_MIGRATIONS_KEY,
+    BULK_IMPORT_CHECKPOINTS_KEY,
+];
+
+#[cfg(feature = "full")]
+/// Hashes an internal metadata value together with its key, so a value
+/// copied under another key doesn't verify either
+fn internal_meta_hash(key: &[u8], value: &[u8]) -> CostContext<CryptoHash> {
+    let mut bytes = key.len().encode_var_vec();
+    bytes.extend_from_slice(key);
+    bytes.extend_from_slice(value);
+    value_hash(&bytes)
+}
+
+#[cfg(feature = "full")]
+/// Appends the integrity hash to an internal metadata value, the result is
+/// what gets put into the meta storage under `key`
+pub(crate) fn seal_internal_meta(key: &[u8], mut value: Vec<u8>) -> CostContext<Vec<u8>> {
+    debug_assert!(
+        INTERNAL_METADATA_KEYS.contains(&key) || is_subtree_meta_key(key),
+        "internal metadata key must be registered"
+    );
+    internal_meta_hash(key, &value).map(|hash| {
+        value.extend_from_slice(&hash);
+        value
+    })
+}
+
+#[cfg(feature = "full")]
+/// Checks an internal metadata value read from the meta storage against its
+/// integrity hash and strips the hash off
+pub(crate) fn unseal_internal_meta(
+    key: &[u8],
+    maybe_sealed: Option<Vec<u8>>,
+) -> CostResult<Option<Vec<u8>>, Error> {
+    let mut value = match maybe_sealed {
+        Some(value) => value,
+        None => return Ok(None).wrap_with_cost(Default::default()),
+    };
+    if value.len() < HASH_LENGTH {
+        return Err(Error::CorruptedData(format!(
+            "internal metadata {} is missing its integrity hash",
+            hex::encode(key)
+        )))
+        .wrap_with_cost(Default::default());
+    }
+    let stored_hash = value.split_off(value.len() - HASH_LENGTH);
+    internal_meta_hash(key, &value).map(|hash| {
+        if hash.as_slice() == stored_hash.as_slice() {
+            Ok(Some(value))
+        } else {
+            Err(Error::CorruptedData(format!(
+                "internal metadata {} doesn't match its integrity hash",
+                hex::encode(key)
+            )))
+        }
+    })
+}
+
+#[cfg(feature = "full")]
+/// Reads an internal metadata value and checks its integrity hash
+pub(crate) fn get_internal_meta<'db, S: StorageContext<'db>>(
+    meta_storage: &S,
+    key: &[u8],
+) -> CostResult<Option<Vec<u8>>, Error> {
+    meta_storage
+        .get_meta(key)
+        .map_err(Error::StorageError)
+        .flat_map_ok(|maybe_sealed| unseal_internal_meta(key, maybe_sealed))
+}
+
+#[cfg(feature = "full")]
+/// Puts an internal metadata value sealed with its integrity hash
+pub(crate) fn put_internal_meta<'db, S: StorageContext<'db>>(
+    meta_storage: &S,
+    key: &[u8],
+    value: Vec<u8>,
+) -> CostResult<(), Error> {
+    let mut cost = OperationCost::default();
+    let sealed = seal_internal_meta(key, value).unwrap_add_cost(&mut cost);
+    meta_storage
+        .put_meta(key, &sealed, None)
+        .map_err(Error::StorageError)
+        .add_cost(cost)
+}
+
+#[cfg(feature = "full")]
+/// Hashes the root leaf registry, every root leaf key in order together with
+/// the storage prefix of its subtree
+fn root_leaves_hash<'k>(keys: impl ExactSizeIterator<Item = &'k [u8]>) -> CostContext<CryptoHash> {
+    let mut cost = OperationCost::default();
+    let mut bytes = keys.len().encode_var_vec();
+    for key in keys {
+        bytes.extend(key.len().encode_var_vec());
+        bytes.extend_from_slice(key);
+        bytes.extend(RocksDbStorage::build_prefix(std::iter::once(key)).unwrap_add_cost(&mut cost));
+    }
+    value_hash(&bytes).add_cost(cost)
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Lists the keys of the root leaves in order
+    fn root_leaf_keys(&self, transaction: TransactionArg) -> CostResult<Vec<Vec<u8>>, Error> {
+        let mut cost = OperationCost::default();
+        let root_leaf_keys = storage_context_optional_tx!(
+            self.db,
+            std::iter::empty::<&[u8]>(),
+            transaction,
+            storage,
+            {
+                let storage = storage.unwrap_add_cost(&mut cost);
+                let mut root_leaf_keys = vec![];
+                let mut iter = Element::iterator(storage.raw_iter()).unwrap_add_cost(&mut cost);
+                while let Some((key, element)) =
+                    cost_return_on_error!(&mut cost, iter.next_element())
+                {
+                    if element.is_tree() {
+                        root_leaf_keys.push(key);
+                    }
+                }
+                root_leaf_keys
+            }
+        );
+        Ok(root_leaf_keys).wrap_with_cost(cost)
+    }
+
+    /// Persists the hash of the root leaf registry as it is in the root tree,
+    /// called after root leaves are inserted or deleted
+    pub(crate) fn update_root_leaves_hash(
+        &self,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+        let root_leaf_keys = cost_return_on_error!(&mut cost, self.root_leaf_keys(transaction));
+        let hash =
+            root_leaves_hash(root_leaf_keys.iter().map(Vec::as_slice)).unwrap_add_cost(&mut cost);
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            let meta_storage = meta_storage.unwrap_add_cost(&mut cost);
+            put_internal_meta(&meta_storage, ROOT_LEAVES_HASH_KEY, hash.to_vec())
+        })
+        .add_cost(cost)
+    }
+
+    /// Puts the hash of the root leaf registry as it will be once the root
+    /// level ops of a batch are applied into the storage batch, nothing is
+    /// put if no op of the batch is on the root tree
+    pub(crate) fn record_root_leaves_hash<'a>(
+        &self,
+        ops: impl IntoIterator<Item = &'a GroveDbOp>,
+        storage_batch: &StorageBatch,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+        let root_ops: Vec<&GroveDbOp> = ops.into_iter().filter(|op| op.path.is_empty()).collect();
+        if root_ops.is_empty() {
+            return Ok(()).wrap_with_cost(cost);
+        }
+        let mut root_leaf_keys: BTreeSet<Vec<u8>> =
+            cost_return_on_error!(&mut cost, self.root_leaf_keys(transaction))
+                .into_iter()
+                .collect();
+        // deletions are applied before insertions of the same key
+        for op in &root_ops {
+            if matches!(op.op, Op::Delete | Op::DeleteTree | Op::DeleteSumTree) {
+                root_leaf_keys.remove(op.key.as_slice());
+            }
+        }
+        for op in root_ops {
+            match &op.op {
+                Op::Insert { element } | Op::Replace { element } | Op::Patch { element, .. } => {
+                    if element.is_tree() {
+                        root_leaf_keys.insert(op.key.get_key_clone());
+                    } else {
+                        root_leaf_keys.remove(op.key.as_slice());
+                    }
+                }
+                Op::InsertTreeWithRootHash { .. } => {
+                    root_leaf_keys.insert(op.key.get_key_clone());
+                }
+                _ => {}
+            }
+        }
+        let hash =
+            root_leaves_hash(root_leaf_keys.iter().map(Vec::as_slice)).unwrap_add_cost(&mut cost);
+        if let Some(tx) = transaction {
+            let meta_storage = self
+                .db
+                .get_batch_transactional_storage_context(std::iter::empty(), storage_batch, tx)
+                .unwrap_add_cost(&mut cost);
+            put_internal_meta(&meta_storage, ROOT_LEAVES_HASH_KEY, hash.to_vec())
+        } else {
+            let meta_storage = self
+                .db
+                .get_batch_storage_context(std::iter::empty(), storage_batch)
+                .unwrap_add_cost(&mut cost);
+            put_internal_meta(&meta_storage, ROOT_LEAVES_HASH_KEY, hash.to_vec())
+        }
+        .add_cost(cost)
+    }
+
+    /// Persists the hash of the root leaf registry of a database that predates
+    /// it, the registry is taken as it is
+    pub(crate) fn init_root_leaves_hash(&self) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+        let meta_storage = self
+            .db
+            .get_storage_context(std::iter::empty())
+            .unwrap_add_cost(&mut cost);
+        let stored_hash = cost_return_on_error!(
+            &mut cost,
+            get_internal_meta(&meta_storage, ROOT_LEAVES_HASH_KEY)
+        );
+        if stored_hash.is_some() {
+            return Ok(()).wrap_with_cost(cost);
+        }
+        self.update_root_leaves_hash(None).add_cost(cost)
+    }
+
+    /// Verifies every registered internal metadata value against its
+    /// integrity hash, the root leaf registry against its hash and checks that
+    /// every root leaf subtree can be opened
+    pub fn verify_metadata(&self, transaction: TransactionArg) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+
+        let stored_root_leaves_hash =
+            meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+                let meta_storage = meta_storage.unwrap_add_cost(&mut cost);
+                for key in INTERNAL_METADATA_KEYS {
+                    cost_return_on_error!(&mut cost, get_internal_meta(&meta_storage, key));
+                }
+                for kind_key in SUBTREE_META_KINDS {
+                    cost_return_on_error!(&mut cost, verify_subtree_meta(&meta_storage, kind_key));
+                }
+                cost_return_on_error!(
+                    &mut cost,
+                    get_internal_meta(&meta_storage, ROOT_LEAVES_HASH_KEY)
+                )
+            });
+
+        // the root leaf registry must match its hash and its subtrees must open
+        root_merk_optional_tx!(&mut cost, self.db, transaction, root_tree, {
+            drop(root_tree);
+        });
+        let root_leaf_keys = cost_return_on_error!(&mut cost, self.root_leaf_keys(transaction));
+        let hash =
+            root_leaves_hash(root_leaf_keys.iter().map(Vec::as_slice)).unwrap_add_cost(&mut cost);
+        if stored_root_leaves_hash.is_some_and(|stored_hash| stored_hash != hash) {
+            return Err(Error::CorruptedData(
+                "root leaf registry doesn't match its hash".to_owned(),
+            ))
+            .wrap_with_cost(cost);
+        }
+        for key in root_leaf_keys {
+            let path = std::iter::once(key.as_slice());
+            merk_optional_tx_path_not_empty!(&mut cost, self.db, path, transaction, subtree, {
+                drop(subtree);
+            });
+        }
+
+        Ok(()).wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use storage::{Storage, StorageContext};
+    use tempfile::TempDir;
+
+    use super::ROOT_LEAVES_HASH_KEY;
+    use crate::{
+        batch::GroveDbOp,
+        operations::read_only::READ_ONLY_SUBTREES_KEY,
+        tests::{make_test_grovedb, ANOTHER_TEST_LEAF, TEST_LEAF},
+        Element, Error, GroveDb,
+    };
+
+    #[test]
+    fn test_verify_metadata_detects_corruption() {
+        let db = make_test_grovedb();
+        db.verify_metadata(None)
+            .unwrap()
+            .expect("fresh database metadata should verify");
+
+        let transaction = db.start_transaction();
+        db.set_readonly([TEST_LEAF], true, Some(&transaction))
+            .unwrap()
+            .expect("should freeze subtree");
+        db.verify_metadata(Some(&transaction))
+            .unwrap()
+            .expect("pending metadata should verify");
+
+        let meta_storage = db
+            .db
+            .get_transactional_storage_context(std::iter::empty(), &transaction)
+            .unwrap();
+        meta_storage
+            .put_meta(READ_ONLY_SUBTREES_KEY, b"garbage", None)
+            .unwrap()
+            .expect("should corrupt metadata");
+        assert!(matches!(
+            db.verify_metadata(Some(&transaction)).unwrap(),
+            Err(Error::CorruptedData(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_metadata_detects_persisted_corruption() {
+        let tmp_dir = TempDir::new().unwrap();
+        {
+            let db = GroveDb::open(tmp_dir.path()).expect("should open");
+            db.insert([], TEST_LEAF, Element::empty_tree(), None, None)
+                .unwrap()
+                .expect("should insert tree");
+            db.set_readonly([TEST_LEAF], true, None)
+                .unwrap()
+                .expect("should freeze subtree");
+            db.verify_metadata(None)
+                .unwrap()
+                .expect("persisted metadata should verify");
+
+            let meta_storage = db.db.get_storage_context(std::iter::empty()).unwrap();
+            let mut sealed = meta_storage
+                .get_meta(READ_ONLY_SUBTREES_KEY)
+                .unwrap()
+                .expect("should read metadata")
+                .expect("metadata should be persisted");
+            sealed[0] ^= 1;
+            meta_storage
+                .put_meta(READ_ONLY_SUBTREES_KEY, &sealed, None)
+                .unwrap()
+                .expect("should corrupt metadata");
+            assert!(matches!(
+                db.verify_metadata(None).unwrap(),
+                Err(Error::CorruptedData(_))
+            ));
+        }
+        assert!(matches!(
+            GroveDb::open(tmp_dir.path()),
+            Err(Error::CorruptedData(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_metadata_detects_root_leaf_registry_mismatch() {
+        let tmp_dir = TempDir::new().unwrap();
+        {
+            let db = GroveDb::open(tmp_dir.path()).expect("should open");
+            db.insert([], TEST_LEAF, Element::empty_tree(), None, None)
+                .unwrap()
+                .expect("should insert root leaf");
+            db.apply_batch(
+                vec![GroveDbOp::insert_op(
+                    vec![],
+                    ANOTHER_TEST_LEAF.to_vec(),
+                    Element::empty_tree(),
+                )],
+                None,
+                None,
+            )
+            .unwrap()
+            .expect("should insert root leaf with a batch");
+            db.delete([], TEST_LEAF, None, None)
+                .unwrap()
+                .expect("should delete root leaf");
+            db.verify_metadata(None)
+                .unwrap()
+                .expect("root leaf registry should match its hash");
+
+            // a root leaf written around the registry is a mismatch
+            let root_storage = db.db.get_storage_context(std::iter::empty()).unwrap();
+            let root_leaf = root_storage
+                .get(ANOTHER_TEST_LEAF)
+                .unwrap()
+                .expect("should read root leaf")
+                .expect("root leaf should be persisted");
+            root_storage
+                .put(TEST_LEAF, &root_leaf, None, None)
+                .unwrap()
+                .expect("should write root leaf");
+            assert!(matches!(
+                db.verify_metadata(None).unwrap(),
+                Err(Error::CorruptedData(_))
+            ));
+        }
+        assert!(matches!(
+            GroveDb::open(tmp_dir.path()),
+            Err(Error::CorruptedData(_))
+        ));
+    }
+
+    #[test]
+    fn test_root_leaf_registry_hash_is_written_for_older_databases() {
+        let tmp_dir = TempDir::new().unwrap();
+        {
+            let db = GroveDb::open(tmp_dir.path()).expect("should open");
+            db.insert([], TEST_LEAF, Element::empty_tree(), None, None)
+                .unwrap()
+                .expect("should insert root leaf");
+            let meta_storage = db.db.get_storage_context(std::iter::empty()).unwrap();
+            meta_storage
+                .delete_meta(ROOT_LEAVES_HASH_KEY, None)
+                .unwrap()
+                .expect("should remove the registry hash");
+        }
+        let db = GroveDb::open(tmp_dir.path()).expect("should open without a registry hash");
+        let meta_storage = db.db.get_storage_context(std::iter::empty()).unwrap();
+        assert!(meta_storage
+            .get_meta(ROOT_LEAVES_HASH_KEY)
+            .unwrap()
+            .expect("should read metadata")
+            .is_some());
+    }
+}
@@ -28,8 +28,16 @@
 
 //! Proof operations
 
+#[cfg(any(feature = "full", feature = "verify"))]
+pub mod context;
+#[cfg(any(feature = "full", feature = "verify"))]
+pub mod diff;
 #[cfg(feature = "full")]
 mod generate;
+#[cfg(feature = "full")]
+pub mod root_cache;
+#[cfg(any(feature = "full", feature = "verify"))]
+pub mod structured;
 #[cfg(any(feature = "full", feature = "verify"))]
 pub mod util;
 #[cfg(any(feature = "full", feature = "verify"))]
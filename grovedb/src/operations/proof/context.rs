@@ -0,0 +1,156 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Verification context
+//! Settings proofs are verified with. Every merk hash computed while
+//! verifying goes through the hash backend of the context, so verifiers can
+//! plug a hardware accelerated or platform provided BLAKE3 implementation,
+//! which matters for mobile verifiers checking large proofs.
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use std::sync::Arc;
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use merk::{with_hash_backend, Blake3Backend, HashBackend};
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use crate::{
+    operations::proof::util::ProvedPathKeyValues, query_result_type::PathKeyOptionalElementTrio,
+    Error, GroveDb, PathQuery,
+};
+
+#[cfg(any(feature = "full", feature = "verify"))]
+#[derive(Clone)]
+/// Context proofs are verified in
+pub struct VerificationContext {
+    hash_backend: Arc<dyn HashBackend>,
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl Default for VerificationContext {
+    fn default() -> Self {
+        Self::new(Arc::new(Blake3Backend))
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl VerificationContext {
+    /// Create a context computing hashes with the given backend
+    pub fn new(hash_backend: Arc<dyn HashBackend>) -> Self {
+        VerificationContext { hash_backend }
+    }
+
+    /// Runs a verification with the hash backend of the context, any of the
+    /// `GroveDb::verify_*` functions can be run this way
+    pub fn run<T>(&self, verify: impl FnOnce() -> T) -> T {
+        with_hash_backend(self.hash_backend.clone(), verify)
+    }
+
+    /// Same as [`GroveDb::verify_query`] within this context
+    pub fn verify_query(
+        &self,
+        proof: &[u8],
+        query: &PathQuery,
+    ) -> Result<([u8; 32], Vec<PathKeyOptionalElementTrio>), Error> {
+        self.run(|| GroveDb::verify_query(proof, query))
+    }
+
+    /// Same as [`GroveDb::verify_query_raw`] within this context
+    pub fn verify_query_raw(
+        &self,
+        proof: &[u8],
+        query: &PathQuery,
+    ) -> Result<([u8; 32], ProvedPathKeyValues), Error> {
+        self.run(|| GroveDb::verify_query_raw(proof, query))
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use merk::CryptoHash;
+
+    use super::*;
+    use crate::{
+        tests::{make_deep_tree, DEEP_LEAF},
+        Query,
+    };
+
+    #[derive(Default)]
+    struct CountingBackend {
+        hashes: AtomicUsize,
+    }
+
+    impl HashBackend for CountingBackend {
+        fn hash(&self, parts: &[&[u8]]) -> CryptoHash {
+            self.hashes.fetch_add(1, Ordering::Relaxed);
+            Blake3Backend.hash(parts)
+        }
+    }
+
+    struct ZeroBackend;
+
+    impl HashBackend for ZeroBackend {
+        fn hash(&self, _parts: &[&[u8]]) -> CryptoHash {
+            [0; 32]
+        }
+    }
+
+    #[test]
+    fn test_verification_context_hash_backend() {
+        let db = make_deep_tree();
+        let mut query = Query::new();
+        query.insert_all();
+        let path_query =
+            PathQuery::new_unsized(vec![DEEP_LEAF.to_vec(), b"deep_node_1".to_vec()], query);
+        let proof = db.prove_query(&path_query).unwrap().expect("should prove");
+        let (expected_root_hash, expected_results) =
+            GroveDb::verify_query(&proof, &path_query).expect("should verify");
+
+        let backend = Arc::new(CountingBackend::default());
+        let context = VerificationContext::new(backend.clone());
+        let (root_hash, results) = context
+            .verify_query(&proof, &path_query)
+            .expect("should verify");
+        assert_eq!(root_hash, expected_root_hash);
+        assert_eq!(results, expected_results);
+        assert!(backend.hashes.load(Ordering::Relaxed) > 0);
+
+        let hashes = backend.hashes.load(Ordering::Relaxed);
+        GroveDb::verify_query(&proof, &path_query).expect("should verify");
+        assert_eq!(backend.hashes.load(Ordering::Relaxed), hashes);
+
+        let zero_context = VerificationContext::new(Arc::new(ZeroBackend));
+        assert!(zero_context
+            .verify_query(&proof, &path_query)
+            .map(|(root_hash, _)| root_hash != expected_root_hash)
+            .unwrap_or(true));
+    }
+}
@@ -0,0 +1,353 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Differential proofs
+//! A client that verified the proof of a query against a previous state can
+//! verify the answer of a later state from a differential proof, which only
+//! carries the layers of the new proof that aren't found in the previous one.
+//! The verifier rebuilds the full proof of the new state from both and
+//! verifies it as usual, so a differential proof is exactly as sound as a full
+//! proof, it only saves the bytes of the unchanged subtrees.
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use std::collections::{BTreeMap, HashMap};
+
+#[cfg(feature = "full")]
+use costs::{cost_return_on_error, CostResult, CostsExt, OperationCost};
+#[cfg(any(feature = "full", feature = "verify"))]
+use integer_encoding::VarInt;
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use crate::{
+    operations::proof::structured::{ProofLayer, StructuredProof},
+    query_result_type::{Key, Path, PathKeyElementTrio},
+    Error, GroveDb, PathQuery,
+};
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Layer of a differential proof
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffProofLayer {
+    /// Same as the layer at the given index in the previous proof
+    Unchanged(usize),
+    /// Layer not found in the previous proof
+    Changed(ProofLayer),
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Layers of a proof expressed against the proof of a previous state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffProof {
+    /// Whether the proofs are verbose
+    pub is_verbose: bool,
+    /// Layers of the new proof, in proof order
+    pub layers: Vec<DiffProofLayer>,
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Differences between the result sets of a query in two states
+#[derive(Clone, Default, PartialEq)]
+#[cfg_attr(feature = "full", derive(Debug))]
+pub struct QueryResultDiff {
+    /// Elements only found in the new state
+    pub added: Vec<PathKeyElementTrio>,
+    /// Paths and keys of elements only found in the previous state
+    pub removed: Vec<(Path, Key)>,
+    /// Elements found in both states with a different value, as of the new
+    /// state
+    pub changed: Vec<PathKeyElementTrio>,
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+fn encode_layer(layer: &ProofLayer, is_verbose: bool) -> Result<Vec<u8>, Error> {
+    StructuredProof {
+        is_verbose,
+        layers: vec![layer.clone()],
+    }
+    .encode()
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl DiffProof {
+    /// Expresses the current proof against the previous one
+    pub fn new(previous: &StructuredProof, current: &StructuredProof) -> Result<Self, Error> {
+        if previous.is_verbose != current.is_verbose {
+            return Err(Error::InvalidInput(
+                "proofs of a differential proof must be equally verbose",
+            ));
+        }
+        let mut previous_layers = HashMap::new();
+        for (index, layer) in previous.layers.iter().enumerate() {
+            previous_layers
+                .entry(encode_layer(layer, previous.is_verbose)?)
+                .or_insert(index);
+        }
+        let layers = current
+            .layers
+            .iter()
+            .map(|layer| {
+                Ok(
+                    match previous_layers.get(&encode_layer(layer, current.is_verbose)?) {
+                        Some(index) => DiffProofLayer::Unchanged(*index),
+                        None => DiffProofLayer::Changed(layer.clone()),
+                    },
+                )
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(Self {
+            is_verbose: current.is_verbose,
+            layers,
+        })
+    }
+
+    /// Rebuilds the current proof from the previous one
+    pub fn apply(&self, previous: &StructuredProof) -> Result<StructuredProof, Error> {
+        let layers = self
+            .layers
+            .iter()
+            .map(|layer| match layer {
+                DiffProofLayer::Unchanged(index) => {
+                    previous
+                        .layers
+                        .get(*index)
+                        .cloned()
+                        .ok_or(Error::InvalidProof(
+                            "differential proof refers to a missing layer",
+                        ))
+                }
+                DiffProofLayer::Changed(layer) => Ok(layer.clone()),
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(StructuredProof {
+            is_verbose: self.is_verbose,
+            layers,
+        })
+    }
+
+    /// Encodes the differential proof
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = vec![self.is_verbose as u8];
+        bytes.extend(self.layers.len().encode_var_vec());
+        for layer in &self.layers {
+            match layer {
+                DiffProofLayer::Unchanged(index) => {
+                    bytes.push(0);
+                    bytes.extend(index.encode_var_vec());
+                }
+                DiffProofLayer::Changed(layer) => {
+                    let layer_bytes = encode_layer(layer, self.is_verbose)?;
+                    bytes.push(1);
+                    bytes.extend(layer_bytes.len().encode_var_vec());
+                    bytes.extend(layer_bytes);
+                }
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Decodes a differential proof
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        let invalid = || Error::InvalidProof("invalid differential proof");
+        let (is_verbose, mut bytes) = match bytes.split_first() {
+            Some((0, rest)) => (false, rest),
+            Some((1, rest)) => (true, rest),
+            _ => return Err(invalid()),
+        };
+        let (count, read) = usize::decode_var(bytes).ok_or_else(invalid)?;
+        bytes = &bytes[read..];
+        let mut layers = Vec::with_capacity(count.min(bytes.len()));
+        for _ in 0..count {
+            let (tag, rest) = bytes.split_first().ok_or_else(invalid)?;
+            let (value, read) = usize::decode_var(rest).ok_or_else(invalid)?;
+            bytes = &rest[read..];
+            match tag {
+                0 => layers.push(DiffProofLayer::Unchanged(value)),
+                1 => {
+                    if bytes.len() < value {
+                        return Err(invalid());
+                    }
+                    let mut decoded = StructuredProof::decode(&bytes[..value], is_verbose)?;
+                    if decoded.layers.len() != 1 {
+                        return Err(invalid());
+                    }
+                    layers.push(DiffProofLayer::Changed(decoded.layers.remove(0)));
+                    bytes = &bytes[value..];
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        if !bytes.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self { is_verbose, layers })
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl GroveDb {
+    /// Verifies a differential proof against the previous proof of the same
+    /// query, returns the root hash of the new state and the differences of
+    /// the result sets
+    pub fn verify_query_diff(
+        previous_proof: &[u8],
+        diff_proof: &[u8],
+        query: &PathQuery,
+    ) -> Result<([u8; 32], QueryResultDiff), Error> {
+        let diff_proof = DiffProof::decode(diff_proof)?;
+        if diff_proof.is_verbose {
+            return Err(Error::NotSupported(
+                "differential proofs are only supported for non verbose proofs",
+            ));
+        }
+        let previous = StructuredProof::decode(previous_proof, false)?;
+        let current_proof = diff_proof.apply(&previous)?.encode()?;
+
+        let (_, previous_results) = GroveDb::verify_query(previous_proof, query)?;
+        let (root_hash, current_results) = GroveDb::verify_query(&current_proof, query)?;
+
+        let mut previous_elements: BTreeMap<(Path, Key), _> = previous_results
+            .into_iter()
+            .filter_map(|(path, key, element)| element.map(|element| ((path, key), element)))
+            .collect();
+        let mut diff = QueryResultDiff::default();
+        for (path, key, element) in current_results {
+            let element = match element {
+                Some(element) => element,
+                None => continue,
+            };
+            match previous_elements.remove(&(path.clone(), key.clone())) {
+                None => diff.added.push((path, key, element)),
+                Some(previous_element) if previous_element != element => {
+                    diff.changed.push((path, key, element))
+                }
+                Some(_) => {}
+            }
+        }
+        diff.removed = previous_elements.into_keys().collect();
+        Ok((root_hash, diff))
+    }
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Proves a query against the current state as a differential proof over
+    /// the given proof of the same query against a previous state
+    pub fn prove_query_diff(
+        &self,
+        previous_proof: &[u8],
+        query: &PathQuery,
+    ) -> CostResult<Vec<u8>, Error> {
+        let mut cost = OperationCost::default();
+        let current = cost_return_on_error!(&mut cost, self.prove_query_structured(query));
+        StructuredProof::decode(previous_proof, false)
+            .and_then(|previous| DiffProof::new(&previous, &current))
+            .and_then(|diff_proof| diff_proof.encode())
+            .wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::{make_deep_tree, DEEP_LEAF},
+        Element, Query,
+    };
+
+    #[test]
+    fn test_diff_proof_reports_result_set_differences() {
+        let db = make_deep_tree();
+        let mut subquery = Query::new();
+        subquery.insert_all();
+        let mut query = Query::new();
+        query.insert_all();
+        query.set_subquery(subquery);
+        let path_query =
+            PathQuery::new_unsized(vec![DEEP_LEAF.to_vec(), b"deep_node_1".to_vec()], query);
+        let previous_proof = db.prove_query(&path_query).unwrap().expect("should prove");
+
+        let deeper_2 = [DEEP_LEAF, b"deep_node_1", b"deeper_2"];
+        db.insert(
+            deeper_2,
+            b"key0",
+            Element::new_item(b"value0".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert");
+        db.insert(
+            deeper_2,
+            b"key4",
+            Element::new_item(b"changed".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert");
+        db.delete(deeper_2, b"key6", None, None)
+            .unwrap()
+            .expect("should delete");
+
+        let diff_proof = db
+            .prove_query_diff(&previous_proof, &path_query)
+            .unwrap()
+            .expect("should prove diff");
+        let current_proof = db.prove_query(&path_query).unwrap().expect("should prove");
+        assert!(diff_proof.len() < current_proof.len());
+        assert!(DiffProof::decode(&diff_proof)
+            .expect("should decode")
+            .layers
+            .iter()
+            .any(|layer| matches!(layer, DiffProofLayer::Unchanged(_))));
+
+        let (root_hash, diff) =
+            GroveDb::verify_query_diff(&previous_proof, &diff_proof, &path_query)
+                .expect("should verify diff");
+        assert_eq!(root_hash, db.root_hash(None).unwrap().unwrap());
+        let deeper_2_path: Vec<Vec<u8>> = deeper_2.iter().map(|k| k.to_vec()).collect();
+        assert_eq!(
+            diff.added,
+            vec![(
+                deeper_2_path.clone(),
+                b"key0".to_vec(),
+                Element::new_item(b"value0".to_vec())
+            )]
+        );
+        assert_eq!(
+            diff.changed,
+            vec![(
+                deeper_2_path.clone(),
+                b"key4".to_vec(),
+                Element::new_item(b"changed".to_vec())
+            )]
+        );
+        assert_eq!(diff.removed, vec![(deeper_2_path, b"key6".to_vec())]);
+    }
+}
@@ -51,11 +51,12 @@ use crate::element::helpers::raw_decode;
 use crate::operations::proof::util::{write_slice_of_slice_to_slice, write_slice_to_vec};
 #[cfg(feature = "full")]
 use crate::{
-    operations::proof::util::{
-        reduce_limit_and_offset_by, write_to_vec, ProofTokenType, EMPTY_TREE_HASH,
+    operations::{
+        get::ReferenceResolutionCache,
+        proof::util::{reduce_limit_and_offset_by, write_to_vec, ProofTokenType, EMPTY_TREE_HASH},
     },
     reference_path::path_from_reference_path_type,
-    Element, Error, GroveDb, PathQuery, Query,
+    Element, Error, GroveDb, KeyOrdering, PathQuery, Query, QueryVersion, SubtreePath,
 };
 
 #[cfg(feature = "full")]
@@ -85,12 +86,31 @@ impl GroveDb {
 
     /// Generate a minimalistic proof for a given path query
     /// doesn't allow for subset verification
+    ///
+    /// Proof generation is deterministic: for the same state and the same
+    /// path query the produced bytes are identical on every platform, as
+    /// subtrees and keys are always visited in query order and no hash map
+    /// iteration or pointer dependent ordering is involved. Consensus code
+    /// relies on this to compare proof hashes.
     pub fn prove_query(&self, query: &PathQuery) -> CostResult<Vec<u8>, Error> {
         self.prove_internal(query, false)
     }
 
+    /// Same as [`GroveDb::prove_query`] under the given version of query
+    /// semantics, the query is proved as traversed under the version
+    pub fn prove_query_with_version(
+        &self,
+        query: &PathQuery,
+        version: QueryVersion,
+    ) -> CostResult<Vec<u8>, Error> {
+        cost_return_on_error_default!(version.validate(query));
+        self.prove_query(&version.traversal_query(query))
+    }
+
     /// Generate a verbose proof for a given path query
     /// allows for subset verification
+    ///
+    /// Same as [`GroveDb::prove_query`], the produced bytes are deterministic.
     pub fn prove_verbose(&self, query: &PathQuery) -> CostResult<Vec<u8>, Error> {
         self.prove_internal(query, true)
     }
@@ -120,6 +140,9 @@ impl GroveDb {
 
         let path_slices = query.path.iter().map(|x| x.as_slice()).collect::<Vec<_>>();
 
+        // References met across the whole proof are resolved only once
+        let mut reference_cache = ReferenceResolutionCache::default();
+
         let subtree_exists = self
             .check_subtree_exists_path_not_found(path_slices.clone(), None)
             .unwrap_add_cost(&mut cost);
@@ -137,7 +160,8 @@ impl GroveDb {
                     self.generate_and_store_absent_path_proof(
                         &path_slices,
                         &mut proof_result,
-                        is_verbose
+                        is_verbose,
+                        &mut reference_cache
                     )
                 );
                 // return the absence proof no need to continue proof generation
@@ -154,6 +178,23 @@ impl GroveDb {
             );
         }
 
+        // subtrees that don't follow bytes order declare their ordering, so the
+        // verifier can translate the query the same way
+        let key_ordering = cost_return_on_error!(
+            &mut cost,
+            Element::subtree_key_ordering(
+                &self.db,
+                SubtreePath::from(path_slices.as_slice()),
+                None
+            )
+        );
+        if key_ordering != KeyOrdering::default() {
+            cost_return_on_error!(
+                &mut cost,
+                Self::generate_and_store_key_ordering_proof(key_ordering, &mut proof_result)
+            );
+        }
+
         cost_return_on_error!(
             &mut cost,
             self.prove_subqueries(
@@ -163,12 +204,18 @@ impl GroveDb {
                 &mut limit,
                 &mut offset,
                 true,
-                is_verbose
+                is_verbose,
+                &mut reference_cache
             )
         );
         cost_return_on_error!(
             &mut cost,
-            self.prove_path(&mut proof_result, path_slices, is_verbose)
+            self.prove_path(
+                &mut proof_result,
+                path_slices,
+                is_verbose,
+                &mut reference_cache
+            )
         );
 
         Ok(proof_result).wrap_with_cost(cost)
@@ -185,11 +232,22 @@ impl GroveDb {
         current_offset: &mut Option<u16>,
         is_first_call: bool,
         is_verbose: bool,
+        reference_cache: &mut ReferenceResolutionCache,
     ) -> CostResult<(), Error> {
         let mut cost = OperationCost::default();
 
         let mut to_add_to_result_set: u16 = 0;
 
+        let key_ordering = cost_return_on_error!(
+            &mut cost,
+            Element::subtree_key_ordering(&self.db, SubtreePath::from(path.as_slice()), None)
+        );
+        let storage_query = PathQuery::new(
+            query.path.clone(),
+            key_ordering.storage_sized_query(&query.query),
+        );
+        let query = &storage_query;
+
         let subtree = cost_return_on_error!(&mut cost, self.open_subtree(path.iter().copied()));
         if subtree.root_hash().unwrap_add_cost(&mut cost) == EMPTY_TREE_HASH {
             cost_return_on_error_no_add!(
@@ -212,7 +270,8 @@ impl GroveDb {
                         ProofTokenType::SizedMerk,
                         proofs,
                         is_verbose,
-                        path.iter().last().unwrap_or(&(&[][..]))
+                        path.iter().last().unwrap_or(&(&[][..])),
+                        reference_cache
                     )
                 );
             }
@@ -229,7 +288,9 @@ impl GroveDb {
 
             let element = cost_return_on_error_no_add!(&cost, raw_decode(&value_bytes));
             match element {
-                Element::Tree(root_key, _) | Element::SumTree(root_key, ..) => {
+                Element::Tree(root_key, _)
+                | Element::SumTree(root_key, ..)
+                | Element::OrderedTree(root_key, ..) => {
                     let (mut subquery_path, subquery_value) =
                         Element::subquery_paths_and_value_for_sized_query(&query.query, &key);
 
@@ -257,7 +318,8 @@ impl GroveDb {
                                 ProofTokenType::Merk,
                                 proofs,
                                 is_verbose,
-                                path.iter().last().unwrap_or(&Default::default())
+                                path.iter().last().unwrap_or(&Default::default()),
+                                reference_cache
                             )
                         );
                     }
@@ -288,7 +350,8 @@ impl GroveDb {
                                         ProofTokenType::Merk,
                                         proofs,
                                         is_verbose,
-                                        new_path.iter().last().unwrap_or(&Default::default())
+                                        new_path.iter().last().unwrap_or(&Default::default()),
+                                        reference_cache
                                     )
                                 );
 
@@ -336,7 +399,8 @@ impl GroveDb {
                                     ProofTokenType::Merk,
                                     proofs,
                                     is_verbose,
-                                    new_path.iter().last().unwrap_or(&Default::default())
+                                    new_path.iter().last().unwrap_or(&Default::default()),
+                                    reference_cache
                                 )
                             );
 
@@ -388,6 +452,7 @@ impl GroveDb {
                             current_offset,
                             false,
                             is_verbose,
+                            reference_cache,
                         )
                     );
 
@@ -414,7 +479,8 @@ impl GroveDb {
                     ProofTokenType::SizedMerk,
                     proofs,
                     is_verbose,
-                    path.iter().last().unwrap_or(&Default::default())
+                    path.iter().last().unwrap_or(&Default::default()),
+                    reference_cache
                 )
             );
 
@@ -435,6 +501,7 @@ impl GroveDb {
         proof_result: &mut Vec<u8>,
         path_slices: Vec<&[u8]>,
         is_verbose: bool,
+        reference_cache: &mut ReferenceResolutionCache,
     ) -> CostResult<(), Error> {
         let mut cost = OperationCost::default();
 
@@ -443,22 +510,49 @@ impl GroveDb {
         while let Some((key, path_slice)) = split_path {
             let subtree =
                 cost_return_on_error!(&mut cost, self.open_subtree(path_slice.iter().copied()));
+
+            // the root layer proof only depends on the root tree state, so it is cached
+            let maybe_root_hash = if path_slice.is_empty() {
+                let root_hash = subtree.root_hash().unwrap_add_cost(&mut cost);
+                if let Some((cached_proof, cached_cost)) =
+                    self.root_proofs.get(key, root_hash, is_verbose)
+                {
+                    // charged as if generated, so costs don't depend on the cache
+                    cost += cached_cost;
+                    proof_result.extend(cached_proof);
+                    break;
+                }
+                Some(root_hash)
+            } else {
+                None
+            };
+            let layer_proof_start = proof_result.len();
+
             let mut query = Query::new();
             query.insert_key(key.to_vec());
 
-            cost_return_on_error!(
-                &mut cost,
-                self.generate_and_store_merk_proof(
-                    path_slice.iter().copied(),
-                    &subtree,
-                    &query,
-                    (None, None),
-                    ProofTokenType::Merk,
-                    proof_result,
-                    is_verbose,
-                    path_slice.iter().last().unwrap_or(&Default::default())
-                )
+            let layer_proof = self.generate_and_store_merk_proof(
+                path_slice.iter().copied(),
+                &subtree,
+                &query,
+                (None, None),
+                ProofTokenType::Merk,
+                proof_result,
+                is_verbose,
+                path_slice.iter().last().unwrap_or(&Default::default()),
+                reference_cache,
             );
+            let layer_cost = layer_proof.cost.clone();
+            cost_return_on_error!(&mut cost, layer_proof);
+            if let Some(root_hash) = maybe_root_hash {
+                self.root_proofs.insert(
+                    key,
+                    root_hash,
+                    is_verbose,
+                    proof_result[layer_proof_start..].to_vec(),
+                    layer_cost,
+                );
+            }
             split_path = path_slice.split_last();
         }
         Ok(()).wrap_with_cost(cost)
@@ -476,6 +570,7 @@ impl GroveDb {
         proofs: &mut Vec<u8>,
         is_verbose: bool,
         key: &[u8],
+        reference_cache: &mut ReferenceResolutionCache,
     ) -> CostResult<(Option<u16>, Option<u16>), Error>
     where
         S: StorageContext<'a>,
@@ -497,7 +592,10 @@ impl GroveDb {
             .unwrap()
             .expect("should generate proof");
 
-        cost_return_on_error!(&mut cost, self.post_process_proof(path, &mut proof_result));
+        cost_return_on_error!(
+            &mut cost,
+            self.post_process_proof(path, &mut proof_result, reference_cache)
+        );
 
         let mut proof_bytes = Vec::with_capacity(128);
         encode_into(proof_result.proof.iter(), &mut proof_bytes);
@@ -532,11 +630,31 @@ impl GroveDb {
         Ok(()).wrap_with_cost(cost)
     }
 
+    /// Serializes the key ordering of the queried subtree and adds it to the
+    /// proof vector
+    fn generate_and_store_key_ordering_proof(
+        key_ordering: KeyOrdering,
+        proofs: &mut Vec<u8>,
+    ) -> CostResult<(), Error> {
+        let cost = OperationCost::default();
+
+        cost_return_on_error_no_add!(
+            &cost,
+            write_to_vec(proofs, &[ProofTokenType::KeyOrdering.into()])
+        );
+
+        let key_ordering_bytes = cost_return_on_error_no_add!(&cost, key_ordering.serialize());
+        cost_return_on_error_no_add!(&cost, write_slice_to_vec(proofs, &key_ordering_bytes));
+
+        Ok(()).wrap_with_cost(cost)
+    }
+
     fn generate_and_store_absent_path_proof(
         &self,
         path_slices: &[&[u8]],
         proof_result: &mut Vec<u8>,
         is_verbose: bool,
+        reference_cache: &mut ReferenceResolutionCache,
     ) -> CostResult<(), Error> {
         let mut cost = OperationCost::default();
 
@@ -575,7 +693,8 @@ impl GroveDb {
                     ProofTokenType::Merk,
                     proof_result,
                     is_verbose,
-                    current_path.iter().last().unwrap_or(&(&[][..]))
+                    current_path.iter().last().unwrap_or(&(&[][..])),
+                    reference_cache
                 )
             );
 
@@ -599,6 +718,7 @@ impl GroveDb {
         &self,
         path: P,
         proof_result: &mut ProofWithoutEncodingResult,
+        reference_cache: &mut ReferenceResolutionCache,
     ) -> CostResult<(), Error>
     where
         P: IntoIterator<Item = &'p [u8]>,
@@ -628,7 +748,12 @@ impl GroveDb {
 
                                 let referenced_elem = cost_return_on_error!(
                                     &mut cost,
-                                    self.follow_reference(absolute_path, true, None)
+                                    self.follow_reference_with_cache(
+                                        absolute_path,
+                                        true,
+                                        reference_cache,
+                                        None
+                                    )
                                 );
 
                                 let serialized_referenced_elem = referenced_elem.serialize();
@@ -675,10 +800,58 @@ mod tests {
 
     use crate::{
         operations::proof::util::{ProofReader, ProofTokenType},
-        tests::{make_deep_tree, TEST_LEAF},
-        GroveDb,
+        tests::{make_deep_tree, ANOTHER_TEST_LEAF, TEST_LEAF},
+        GroveDb, PathQuery, ReferenceResolutionCache,
     };
 
+    /// Path query with a subquery spanning several subtrees of the deep tree
+    fn deep_tree_path_query() -> PathQuery {
+        let mut query = Query::new();
+        query.insert_all();
+        let mut subquery = Query::new();
+        subquery.insert_range_from(b"k2".to_vec()..);
+        query.set_subquery(subquery);
+        PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query)
+    }
+
+    #[test]
+    fn test_proofs_are_deterministic_across_instances() {
+        let path_query = deep_tree_path_query();
+
+        let db = make_deep_tree();
+        let other_db = make_deep_tree();
+        let proof = db.prove_query(&path_query).unwrap().unwrap();
+        assert_eq!(proof, db.prove_query(&path_query).unwrap().unwrap());
+        assert_eq!(proof, other_db.prove_query(&path_query).unwrap().unwrap());
+
+        let verbose_proof = db.prove_verbose(&path_query).unwrap().unwrap();
+        assert_eq!(
+            verbose_proof,
+            other_db.prove_verbose(&path_query).unwrap().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_proof_bytes_regression_fixture() {
+        // Proof bytes must never change for the same state and query, whatever the
+        // platform is, as consensus code compares proof hashes. If this fixture
+        // breaks the proof format was changed.
+        let path_query = deep_tree_path_query();
+        let db = make_deep_tree();
+        let proof = db.prove_query(&path_query).unwrap().unwrap();
+        assert_eq!(hex::encode(proof), DEEP_TREE_PROOF_FIXTURE);
+
+        let mut query = Query::new();
+        query.insert_key(b"innertree2".to_vec());
+        let path_query = PathQuery::new_unsized(vec![ANOTHER_TEST_LEAF.to_vec()], query);
+        let proof = db.prove_query(&path_query).unwrap().unwrap();
+        assert_eq!(hex::encode(proof), ANOTHER_TEST_LEAF_PROOF_FIXTURE);
+    }
+
+    const DEEP_TREE_PROOF_FIXTURE: &str = "016c0409696e6e65727472656500080201046b657932004910536da659a3dbdbcf68c4a6630e72de4ba20cfc60b08b3dd45b4225a599b6040a696e6e6572747265653400080201046b6579340067213c10f0209bbaa2c53f1aa2ec83f7b1a00f837252c856705fb0f8ab1ae39411023503046b6579310009000676616c7565310003046b6579320009000676616c756532001003046b6579330009000676616c7565330011022303046b6579340009000676616c7565340003046b6579350009000676616c7565350011017e01a13c11da39188746311987858d630be4a76a24ebb54d4c3d4aea7fd6bb43526d0409746573745f6c656166000d020109696e6e65727472656500d860a71bf7078d3d9fb8cb2a16e7c6009b0f1bee77ef9385c6760fcb055b194a1001b55f830550604719833d54ce2bf139aff4bb699fa4111b9741633554318792c511";
+
+    const ANOTHER_TEST_LEAF_PROOF_FIXTURE: &str = "0258040a696e6e6572747265653200080201046b657933001147833acf6bb278961c6392567a8b0206167002c5f781039965e418f0f19671013a090155ea7d14038c7062d94930798f885a19d6ebff8a87489a1debf66560471101800101a13c11da39188746311987858d630be4a76a24ebb54d4c3d4aea7fd6bb43526d023afd5dc3d12153ff4d95dee1158abba95ff1105515146843f070c3cc96d0bedb10040a746573745f6c65616632000e02010a696e6e65727472656532009512320e7cbf18939613671249aabbfcd4157d56e8833754c2fb7f2d63aa66ed11";
+
     #[test]
     fn test_path_info_encoding_and_decoding() {
         let path = vec![b"a".as_slice(), b"b".as_slice(), b"c".as_slice()];
@@ -717,6 +890,7 @@ mod tests {
             &mut proof,
             true,
             b"innertree",
+            &mut ReferenceResolutionCache::default(),
         )
         .unwrap()
         .unwrap();
@@ -749,6 +923,7 @@ mod tests {
             &mut proof,
             true,
             path.iter().last().unwrap_or(&(&[][..])),
+            &mut ReferenceResolutionCache::default(),
         )
         .unwrap()
         .unwrap();
@@ -793,6 +968,7 @@ mod tests {
             &mut proofs,
             true,
             path.iter().last().unwrap_or(&(&[][..])),
+            &mut ReferenceResolutionCache::default(),
         )
         .unwrap()
         .unwrap();
@@ -813,6 +989,7 @@ mod tests {
             &mut proofs,
             true,
             path.iter().last().unwrap_or(&(&[][..])),
+            &mut ReferenceResolutionCache::default(),
         )
         .unwrap()
         .unwrap();
@@ -833,6 +1010,7 @@ mod tests {
             &mut proofs,
             true,
             path.iter().last().unwrap_or(&(&[][..])),
+            &mut ReferenceResolutionCache::default(),
         )
         .unwrap()
         .unwrap();
@@ -0,0 +1,212 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Root proof cache
+//! Every path proof ends with the proof of a root leaf in the root tree, which
+//! stays the same for every request as long as the root tree doesn't change.
+//! These root layer proofs are cached per leaf key and root hash, the cache is
+//! cleared on commit and whenever it grows beyond its capacity. A cached proof
+//! is charged the cost of generating it, so costs don't depend on the cache.
+
+#[cfg(feature = "full")]
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+#[cfg(feature = "full")]
+use costs::OperationCost;
+#[cfg(feature = "full")]
+use merk::CryptoHash;
+
+#[cfg(feature = "full")]
+use crate::GroveDb;
+
+#[cfg(feature = "full")]
+/// Maximum number of cached root layer proofs
+pub const ROOT_PROOF_CACHE_CAPACITY: usize = 1024;
+
+#[cfg(feature = "full")]
+/// Leaf key, root hash and whether the proof is verbose
+type RootProofCacheKey = (Vec<u8>, CryptoHash, bool);
+
+#[cfg(feature = "full")]
+#[derive(Default)]
+/// Cached root layer proofs of a GroveDb instance
+pub(crate) struct RootProofCache {
+    entries: Mutex<HashMap<RootProofCacheKey, (Vec<u8>, OperationCost)>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[cfg(feature = "full")]
+/// Snapshot of the root proof cache usage
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RootProofCacheStats {
+    /// Number of cached root layer proofs
+    pub entries: usize,
+    /// Number of root layer proofs served from the cache
+    pub hits: u64,
+    /// Number of root layer proofs that had to be generated
+    pub misses: u64,
+}
+
+#[cfg(feature = "full")]
+impl RootProofCache {
+    /// Returns the cached encoded root layer proof of a leaf and the cost of
+    /// generating it
+    pub(crate) fn get(
+        &self,
+        leaf_key: &[u8],
+        root_hash: CryptoHash,
+        is_verbose: bool,
+    ) -> Option<(Vec<u8>, OperationCost)> {
+        let entries = self
+            .entries
+            .lock()
+            .expect("root proof cache lock is poisoned");
+        let cached = entries
+            .get(&(leaf_key.to_vec(), root_hash, is_verbose))
+            .cloned();
+        if cached.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        cached
+    }
+
+    /// Caches the encoded root layer proof of a leaf with the cost of
+    /// generating it
+    pub(crate) fn insert(
+        &self,
+        leaf_key: &[u8],
+        root_hash: CryptoHash,
+        is_verbose: bool,
+        proof: Vec<u8>,
+        cost: OperationCost,
+    ) {
+        let mut entries = self
+            .entries
+            .lock()
+            .expect("root proof cache lock is poisoned");
+        if entries.len() >= ROOT_PROOF_CACHE_CAPACITY {
+            entries.clear();
+        }
+        entries.insert((leaf_key.to_vec(), root_hash, is_verbose), (proof, cost));
+    }
+
+    /// Drops every cached proof
+    pub(crate) fn clear(&self) {
+        self.entries
+            .lock()
+            .expect("root proof cache lock is poisoned")
+            .clear();
+    }
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Returns the usage of the root proof cache
+    pub fn root_proof_cache_stats(&self) -> RootProofCacheStats {
+        RootProofCacheStats {
+            entries: self
+                .root_proofs
+                .entries
+                .lock()
+                .expect("root proof cache lock is poisoned")
+                .len(),
+            hits: self.root_proofs.hits.load(Ordering::Relaxed),
+            misses: self.root_proofs.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use crate::{
+        tests::{make_test_grovedb, TEST_LEAF},
+        Element, GroveDb, PathQuery, Query,
+    };
+
+    #[test]
+    fn test_root_layer_proofs_are_cached_per_root_hash() {
+        let db = make_test_grovedb();
+        db.insert(
+            [TEST_LEAF],
+            b"key",
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+
+        let mut query = Query::new();
+        query.insert_key(b"key".to_vec());
+        let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+
+        let proof = db.prove_query(&path_query);
+        let stats = db.root_proof_cache_stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 0, 1));
+
+        let cached_proof = db.prove_query(&path_query);
+        assert_eq!(db.root_proof_cache_stats().hits, 1);
+        assert_eq!(cached_proof.cost, proof.cost);
+        assert_eq!(
+            cached_proof.value.expect("should prove"),
+            proof.value.expect("should prove")
+        );
+
+        db.insert(
+            [TEST_LEAF],
+            b"other",
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+        let proof = db.prove_query(&path_query).unwrap().expect("should prove");
+        assert_eq!(db.root_proof_cache_stats().misses, 2);
+        let (root_hash, result_set) =
+            GroveDb::verify_query(&proof, &path_query).expect("should verify");
+        assert_eq!(root_hash, db.root_hash(None).unwrap().unwrap());
+        assert_eq!(result_set.len(), 1);
+
+        let transaction = db.start_transaction();
+        db.commit_transaction(transaction)
+            .unwrap()
+            .expect("should commit");
+        assert_eq!(db.root_proof_cache_stats().entries, 0);
+    }
+}
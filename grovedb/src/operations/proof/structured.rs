@@ -0,0 +1,234 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Structured proofs
+//! A proof split into its layers with the merk operations decoded, so clients
+//! can re-encode, compress or merge proofs without parsing the binary format
+//! themselves. Encoding a structured proof gives back the original bytes.
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use std::collections::LinkedList;
+
+#[cfg(feature = "full")]
+use costs::{cost_return_on_error, CostResult, CostsExt, OperationCost};
+#[cfg(any(feature = "full", feature = "verify"))]
+use merk::proofs::{encode_into, Decoder, Op};
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use crate::{
+    operations::proof::util::{
+        write_slice_of_slice_to_slice, write_slice_to_vec, write_to_vec, ProofReader,
+        ProofTokenType,
+    },
+    Error, KeyOrdering,
+};
+#[cfg(feature = "full")]
+use crate::{GroveDb, PathQuery};
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Layer of a proof
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofLayer {
+    /// Merk proof of a subtree
+    Merk {
+        /// Whether the merk proof was limited by the query limit or offset
+        sized: bool,
+        /// Key of the subtree, only set in verbose proofs
+        key: Option<Vec<u8>>,
+        /// Merk proof operations
+        ops: LinkedList<Op>,
+    },
+    /// The subtree is empty
+    EmptyTree,
+    /// The queried path doesn't exist
+    AbsentPath,
+    /// Path of the queried subtree, only in verbose proofs
+    PathInfo(Vec<Vec<u8>>),
+    /// Key ordering of the queried subtree
+    KeyOrdering(KeyOrdering),
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Proof split into its layers, in proof order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructuredProof {
+    /// Whether the proof is verbose
+    pub is_verbose: bool,
+    /// Layers of the proof
+    pub layers: Vec<ProofLayer>,
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl StructuredProof {
+    /// Splits an encoded proof into its layers
+    pub fn decode(proof: &[u8], is_verbose: bool) -> Result<Self, Error> {
+        let mut reader = ProofReader::new_with_verbose_status(proof, is_verbose);
+        let mut layers = vec![];
+        while let Some(proof_token_type) = reader.peek_proof_token_type() {
+            let layer = match proof_token_type {
+                ProofTokenType::PathInfo => ProofLayer::PathInfo(reader.read_path_info()?),
+                ProofTokenType::KeyOrdering => ProofLayer::KeyOrdering(reader.read_key_ordering()?),
+                _ => match reader.read_proof()? {
+                    (ProofTokenType::EmptyTree, ..) => ProofLayer::EmptyTree,
+                    (ProofTokenType::AbsentPath, ..) => ProofLayer::AbsentPath,
+                    (proof_token_type, merk_proof, key) => ProofLayer::Merk {
+                        sized: proof_token_type == ProofTokenType::SizedMerk,
+                        key,
+                        ops: Decoder::new(&merk_proof)
+                            .collect::<Result<_, _>>()
+                            .map_err(|_| Error::InvalidProof("invalid merk proof operations"))?,
+                    },
+                },
+            };
+            layers.push(layer);
+        }
+        Ok(Self { is_verbose, layers })
+    }
+
+    /// Encodes the layers back into a proof
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        let mut proof = vec![];
+        for layer in &self.layers {
+            match layer {
+                ProofLayer::Merk { sized, key, ops } => {
+                    let proof_token_type = if *sized {
+                        ProofTokenType::SizedMerk
+                    } else {
+                        ProofTokenType::Merk
+                    };
+                    write_to_vec(&mut proof, &[proof_token_type.into()])?;
+                    if self.is_verbose {
+                        let key = key.as_ref().ok_or(Error::InvalidProof(
+                            "key must exist for verbose merk proofs",
+                        ))?;
+                        write_slice_to_vec(&mut proof, key)?;
+                    }
+                    let mut merk_proof = vec![];
+                    encode_into(ops.iter(), &mut merk_proof);
+                    write_slice_to_vec(&mut proof, &merk_proof)?;
+                }
+                ProofLayer::EmptyTree => {
+                    write_to_vec(&mut proof, &[ProofTokenType::EmptyTree.into()])?
+                }
+                ProofLayer::AbsentPath => {
+                    write_to_vec(&mut proof, &[ProofTokenType::AbsentPath.into()])?
+                }
+                ProofLayer::PathInfo(path) => {
+                    write_to_vec(&mut proof, &[ProofTokenType::PathInfo.into()])?;
+                    let path_slices: Vec<&[u8]> = path.iter().map(|k| k.as_slice()).collect();
+                    write_slice_of_slice_to_slice(&mut proof, &path_slices)?;
+                }
+                ProofLayer::KeyOrdering(key_ordering) => {
+                    write_to_vec(&mut proof, &[ProofTokenType::KeyOrdering.into()])?;
+                    write_slice_to_vec(&mut proof, &key_ordering.serialize()?)?;
+                }
+            }
+        }
+        Ok(proof)
+    }
+
+    /// Merk proof operations of the root tree, which is the last layer of
+    /// proofs of existing paths
+    pub fn root_layer(&self) -> Option<&LinkedList<Op>> {
+        match self.layers.last() {
+            Some(ProofLayer::Merk { ops, .. }) => Some(ops),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Same as [`GroveDb::prove_query`] but returns the proof split into its
+    /// layers
+    pub fn prove_query_structured(&self, query: &PathQuery) -> CostResult<StructuredProof, Error> {
+        let mut cost = OperationCost::default();
+        let proof = cost_return_on_error!(&mut cost, self.prove_query(query));
+        StructuredProof::decode(&proof, false).wrap_with_cost(cost)
+    }
+
+    /// Same as [`GroveDb::prove_verbose`] but returns the proof split into
+    /// its layers
+    pub fn prove_verbose_structured(
+        &self,
+        query: &PathQuery,
+    ) -> CostResult<StructuredProof, Error> {
+        let mut cost = OperationCost::default();
+        let proof = cost_return_on_error!(&mut cost, self.prove_verbose(query));
+        StructuredProof::decode(&proof, true).wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::{make_deep_tree, DEEP_LEAF},
+        Query,
+    };
+
+    #[test]
+    fn test_structured_proof_round_trips() {
+        let db = make_deep_tree();
+        let mut subquery = Query::new();
+        subquery.insert_all();
+        let mut query = Query::new();
+        query.insert_all();
+        query.set_subquery(subquery);
+        let path_query = PathQuery::new_unsized(vec![DEEP_LEAF.to_vec()], query);
+
+        for is_verbose in [false, true] {
+            let proof = if is_verbose {
+                db.prove_verbose(&path_query)
+            } else {
+                db.prove_query(&path_query)
+            }
+            .unwrap()
+            .expect("should prove");
+            let structured_proof = if is_verbose {
+                db.prove_verbose_structured(&path_query)
+            } else {
+                db.prove_query_structured(&path_query)
+            }
+            .unwrap()
+            .expect("should prove structured");
+
+            assert_eq!(structured_proof.encode().expect("should encode"), proof);
+            assert_eq!(
+                StructuredProof::decode(&proof, is_verbose).expect("should decode"),
+                structured_proof
+            );
+            assert_eq!(
+                structured_proof.is_verbose,
+                matches!(structured_proof.layers[0], ProofLayer::PathInfo(_))
+            );
+            assert!(structured_proof.root_layer().is_some());
+        }
+    }
+}
@@ -28,7 +28,7 @@
 
 #[cfg(any(feature = "full", feature = "verify"))]
 use std::io::Read;
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 use std::io::Write;
 
 #[cfg(any(feature = "full", feature = "verify"))]
@@ -40,7 +40,7 @@ use merk::{
 
 use crate::operations::proof::verify::ProvedKeyValues;
 #[cfg(any(feature = "full", feature = "verify"))]
-use crate::Error;
+use crate::{Error, KeyOrdering};
 
 #[cfg(any(feature = "full", feature = "verify"))]
 pub const EMPTY_TREE_HASH: [u8; 32] = [0; 32];
@@ -57,6 +57,7 @@ pub enum ProofTokenType {
     EmptyTree,
     AbsentPath,
     PathInfo,
+    KeyOrdering,
     Invalid,
 }
 
@@ -69,6 +70,7 @@ impl From<ProofTokenType> for u8 {
             ProofTokenType::EmptyTree => 0x04,
             ProofTokenType::AbsentPath => 0x05,
             ProofTokenType::PathInfo => 0x06,
+            ProofTokenType::KeyOrdering => 0x07,
             ProofTokenType::Invalid => 0x10,
         }
     }
@@ -83,6 +85,7 @@ impl From<u8> for ProofTokenType {
             0x04 => ProofTokenType::EmptyTree,
             0x05 => ProofTokenType::AbsentPath,
             0x06 => ProofTokenType::PathInfo,
+            0x07 => ProofTokenType::KeyOrdering,
             _ => ProofTokenType::Invalid,
         }
     }
@@ -248,6 +251,12 @@ impl<'a> ProofReader<'a> {
         Ok((proof_token_type, proof, key))
     }
 
+    /// Returns the type of the next proof token without reading it, None once
+    /// the whole proof was read
+    pub fn peek_proof_token_type(&self) -> Option<ProofTokenType> {
+        self.proof_data.first().map(|data_type| (*data_type).into())
+    }
+
     /// Reads path information from the proof vector
     pub fn read_path_info(&mut self) -> Result<Vec<Vec<u8>>, Error> {
         let mut data_type = [0; 1];
@@ -269,9 +278,25 @@ impl<'a> ProofReader<'a> {
 
         Ok(path)
     }
+
+    /// Reads the key ordering of the queried subtree if the proof declares
+    /// one, subtrees without a declared ordering are ordered lexicographically
+    pub fn read_key_ordering(&mut self) -> Result<KeyOrdering, Error> {
+        if self.proof_data.first() != Some(&ProofTokenType::KeyOrdering.into()) {
+            return Ok(KeyOrdering::default());
+        }
+        self.proof_data = &self.proof_data[1..];
+
+        let key_ordering_len = self.read_length_data()?;
+        let mut key_ordering_bytes = vec![0; key_ordering_len];
+        self.read_into_slice(&mut key_ordering_bytes)?;
+
+        KeyOrdering::deserialize(&key_ordering_bytes)
+            .map_err(|_| Error::InvalidProof("invalid key ordering"))
+    }
 }
 
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 /// Write to vec
 // TODO: this can error out handle the error
 pub fn write_to_vec<W: Write>(dest: &mut W, value: &[u8]) -> Result<(), Error> {
@@ -279,7 +304,7 @@ pub fn write_to_vec<W: Write>(dest: &mut W, value: &[u8]) -> Result<(), Error> {
         .map_err(|_e| Error::InternalError("failed to write to vector"))
 }
 
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 /// Write a slice to the vector, first write the length of the slice
 pub fn write_slice_to_vec<W: Write>(dest: &mut W, value: &[u8]) -> Result<(), Error> {
     write_to_vec(dest, value.len().encode_var_vec().as_slice())?;
@@ -287,7 +312,7 @@ pub fn write_slice_to_vec<W: Write>(dest: &mut W, value: &[u8]) -> Result<(), Er
     Ok(())
 }
 
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 /// Write a slice of a slice to a flat vector:w
 pub fn write_slice_of_slice_to_slice<W: Write>(dest: &mut W, value: &[&[u8]]) -> Result<(), Error> {
     // write the number of slices we are about to write
@@ -32,7 +32,7 @@ use std::{borrow::Cow, collections::BTreeMap};
 
 use merk::proofs::query::PathKey;
 #[cfg(any(feature = "full", feature = "verify"))]
-pub use merk::proofs::query::{Path, ProvedKeyValue};
+pub use merk::proofs::query::{Path, ProofNodeCounts, ProvedKeyValue};
 #[cfg(any(feature = "full", feature = "verify"))]
 use merk::{
     proofs::Query,
@@ -52,12 +52,22 @@ use crate::{
     operations::proof::util::{
         ProofReader, ProofTokenType, ProofTokenType::AbsentPath, EMPTY_TREE_HASH,
     },
-    Element, Error, GroveDb, PathQuery,
+    Element, Epoch, Error, GroveDb, KeyOrdering, PathQuery, QueryVersion,
 };
 
 #[cfg(any(feature = "full", feature = "verify"))]
 pub type ProvedKeyValues = Vec<ProvedKeyValue>;
 
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Size of a verified proof, summed over the merk proofs of every layer
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ProofStats {
+    /// Nodes of each type pushed by the merk proofs
+    pub node_counts: ProofNodeCounts,
+    /// Size of the merk proofs in bytes
+    pub proof_bytes: usize,
+}
+
 #[cfg(any(feature = "full", feature = "verify"))]
 type EncounteredAbsence = bool;
 
@@ -68,12 +78,67 @@ impl GroveDb {
         proof: &[u8],
         query: &PathQuery,
     ) -> Result<([u8; 32], Vec<PathKeyOptionalElementTrio>), Error> {
-        let (root_hash, proved_path_key_values) = Self::verify_query_raw(proof, query)?;
+        Self::verify_query_with_stats(proof, query).map(
+            |(root_hash, path_key_optional_elements, _)| (root_hash, path_key_optional_elements),
+        )
+    }
+
+    /// Same as [`GroveDb::verify_query`], also returning the size of the
+    /// proof
+    pub fn verify_query_with_stats(
+        proof: &[u8],
+        query: &PathQuery,
+    ) -> Result<([u8; 32], Vec<PathKeyOptionalElementTrio>, ProofStats), Error> {
+        let (root_hash, proved_path_key_values, stats) =
+            Self::verify_query_raw_with_stats(proof, query)?;
         let path_key_optional_elements = proved_path_key_values
             .into_iter()
             .map(|pkv| pkv.try_into())
             .collect::<Result<Vec<PathKeyOptionalElementTrio>, Error>>()?;
-        Ok((root_hash, path_key_optional_elements))
+        Ok((root_hash, path_key_optional_elements, stats))
+    }
+
+    /// Verify proof return deserialized elements, leaving out the elements
+    /// expired at the given epoch. Proved references carry the referenced
+    /// element, so its expiry is the one checked. Expired elements count
+    /// towards neither the limit nor the offset of the query, so the proof
+    /// has to come from [`GroveDb::prove_query_for_expiry`].
+    pub fn verify_query_at_epoch(
+        proof: &[u8],
+        query: &PathQuery,
+        current_epoch: Epoch,
+    ) -> Result<([u8; 32], Vec<PathKeyOptionalElementTrio>), Error> {
+        let (root_hash, path_key_optional_elements) =
+            Self::verify_query(proof, &query.without_limit_and_offset())?;
+        let offset = query.query.offset.unwrap_or(0) as usize;
+        let limit = query
+            .query
+            .limit
+            .map(|limit| limit as usize)
+            .unwrap_or(usize::MAX);
+        let unexpired = path_key_optional_elements
+            .into_iter()
+            .filter(|(_, _, maybe_element)| {
+                !maybe_element
+                    .as_ref()
+                    .map(|element| element.is_expired(current_epoch))
+                    .unwrap_or(false)
+            })
+            .skip(offset)
+            .take(limit)
+            .collect();
+        Ok((root_hash, unexpired))
+    }
+
+    /// Same as [`GroveDb::verify_query`] under the given version of query
+    /// semantics
+    pub fn verify_query_with_version(
+        proof: &[u8],
+        query: &PathQuery,
+        version: QueryVersion,
+    ) -> Result<([u8; 32], Vec<PathKeyOptionalElementTrio>), Error> {
+        version.validate(query)?;
+        Self::verify_query(proof, &version.traversal_query(query))
     }
 
     /// Verify proof for query returns serialized elements
@@ -81,10 +146,20 @@ impl GroveDb {
         proof: &[u8],
         query: &PathQuery,
     ) -> Result<([u8; 32], ProvedPathKeyValues), Error> {
+        Self::verify_query_raw_with_stats(proof, query)
+            .map(|(hash, proved_path_key_values, _)| (hash, proved_path_key_values))
+    }
+
+    /// Same as [`GroveDb::verify_query_raw`], also returning the size of the
+    /// proof
+    pub fn verify_query_raw_with_stats(
+        proof: &[u8],
+        query: &PathQuery,
+    ) -> Result<([u8; 32], ProvedPathKeyValues, ProofStats), Error> {
         let mut verifier = ProofVerifier::new(query);
         let hash = verifier.execute_proof(proof, query, false)?;
 
-        Ok((hash, verifier.result_set))
+        Ok((hash, verifier.result_set, verifier.stats))
     }
 
     /// Verify proof for query many
@@ -105,12 +180,24 @@ impl GroveDb {
         proof: &[u8],
         query: &PathQuery,
     ) -> Result<([u8; 32], Vec<PathKeyOptionalElementTrio>), Error> {
-        let (root_hash, proved_path_key_values) = Self::verify_subset_query_raw(proof, query)?;
+        Self::verify_subset_query_with_stats(proof, query).map(
+            |(root_hash, path_key_optional_elements, _)| (root_hash, path_key_optional_elements),
+        )
+    }
+
+    /// Same as [`GroveDb::verify_subset_query`], also returning the size of
+    /// the proof
+    pub fn verify_subset_query_with_stats(
+        proof: &[u8],
+        query: &PathQuery,
+    ) -> Result<([u8; 32], Vec<PathKeyOptionalElementTrio>, ProofStats), Error> {
+        let (root_hash, proved_path_key_values, stats) =
+            Self::verify_subset_query_raw_with_stats(proof, query)?;
         let path_key_optional_elements = proved_path_key_values
             .into_iter()
             .map(|pkv| pkv.try_into())
             .collect::<Result<Vec<PathKeyOptionalElementTrio>, Error>>()?;
-        Ok((root_hash, path_key_optional_elements))
+        Ok((root_hash, path_key_optional_elements, stats))
     }
 
     /// Verify verbose proof with a subquery path query return serialized
@@ -119,9 +206,19 @@ impl GroveDb {
         proof: &[u8],
         query: &PathQuery,
     ) -> Result<([u8; 32], ProvedPathKeyValues), Error> {
+        Self::verify_subset_query_raw_with_stats(proof, query)
+            .map(|(hash, proved_path_key_values, _)| (hash, proved_path_key_values))
+    }
+
+    /// Same as [`GroveDb::verify_subset_query_raw`], also returning the size
+    /// of the proof
+    pub fn verify_subset_query_raw_with_stats(
+        proof: &[u8],
+        query: &PathQuery,
+    ) -> Result<([u8; 32], ProvedPathKeyValues, ProofStats), Error> {
         let mut verifier = ProofVerifier::new(query);
         let hash = verifier.execute_proof(proof, query, true)?;
-        Ok((hash, verifier.result_set))
+        Ok((hash, verifier.result_set, verifier.stats))
     }
 
     /// Verify non subset query return the absence proof
@@ -226,6 +323,8 @@ struct ProofVerifier {
     limit: Option<u16>,
     offset: Option<u16>,
     result_set: ProvedPathKeyValues,
+    /// Size of the merk proofs executed so far
+    stats: ProofStats,
 }
 
 #[cfg(any(feature = "full", feature = "verify"))]
@@ -236,6 +335,7 @@ impl ProofVerifier {
             limit: query.query.limit,
             offset: query.query.offset,
             result_set: vec![],
+            stats: ProofStats::default(),
         }
     }
 
@@ -295,6 +395,16 @@ impl ProofVerifier {
             }
         }
 
+        // the queried subtree might declare an ordering other than bytes order,
+        // in which case the query is translated the same way it was on generation
+        let key_ordering = proof_reader.read_key_ordering()?;
+        if key_ordering != KeyOrdering::default() {
+            query = Cow::Owned(PathQuery::new(
+                query.path.clone(),
+                key_ordering.storage_sized_query(&query.query),
+            ));
+        }
+
         let (proof_token_type, proof, _) = proof_reader.read_proof()?;
 
         let root_hash = if proof_token_type == AbsentPath {
@@ -311,8 +421,8 @@ impl ProofVerifier {
 
             // validate the path elements are connected
             self.verify_path_to_root(
-                query.as_ref(),
                 query.path.iter().map(|a| a.as_ref()).collect(),
+                key_ordering,
                 &mut proof_reader,
                 &mut last_subtree_root_hash,
             )?
@@ -371,7 +481,8 @@ impl ProofVerifier {
                     let child_element = Element::deserialize(value_bytes.as_slice())?;
                     match child_element {
                         Element::Tree(expected_root_key, _)
-                        | Element::SumTree(expected_root_key, ..) => {
+                        | Element::SumTree(expected_root_key, ..)
+                        | Element::OrderedTree(expected_root_key, ..) => {
                             let mut expected_combined_child_hash = value_hash;
                             let mut current_value_bytes = value_bytes;
 
@@ -480,8 +591,14 @@ impl ProofVerifier {
                                 }
                             }
 
-                            let new_path_query =
-                                PathQuery::new_unsized(vec![], subquery_value.unwrap());
+                            // subqueries are expressed in the ordering of the subtree they
+                            // apply to
+                            let child_key_ordering =
+                                Element::deserialize(&current_value_bytes)?.key_ordering();
+                            let new_path_query = PathQuery::new_unsized(
+                                vec![],
+                                child_key_ordering.storage_query(&subquery_value.unwrap()),
+                            );
 
                             let (child_proof_token_type, child_proof) = proof_reader
                                 .read_next_proof(new_path.last().unwrap_or(&Default::default()))?;
@@ -557,7 +674,7 @@ impl ProofVerifier {
         let subquery_path_element = Element::deserialize(elem_value)
             .map_err(|_| Error::CorruptedData("failed to deserialize element".to_string()))?;
         match subquery_path_element {
-            Element::Tree(..) | Element::SumTree(..) => {
+            Element::Tree(..) | Element::SumTree(..) | Element::OrderedTree(..) => {
                 *expected_child_hash = subquery_path_result_set[0].proof;
                 *current_value_bytes = subquery_path_result_set[0].value.to_owned();
             }
@@ -733,7 +850,9 @@ impl ProofVerifier {
 
             let elem = Element::deserialize(last_result_set[0].value.as_slice())?;
             let child_hash = match elem {
-                Element::Tree(..) | Element::SumTree(..) => Ok(Some(last_result_set[0].proof)),
+                Element::Tree(..) | Element::SumTree(..) | Element::OrderedTree(..) => {
+                    Ok(Some(last_result_set[0].proof))
+                }
                 _ => Err(Error::InvalidProof(
                     "intermediate proofs should be for trees",
                 )),
@@ -756,11 +875,13 @@ impl ProofVerifier {
     /// query
     fn verify_path_to_root(
         &mut self,
-        query: &PathQuery,
         path_slices: Vec<&[u8]>,
+        key_ordering: KeyOrdering,
         proof_reader: &mut ProofReader,
         expected_root_hash: &mut [u8; 32],
     ) -> Result<[u8; 32], Error> {
+        // the ordering declared by the proof must be the one of the queried subtree
+        let mut expected_key_ordering = Some(key_ordering);
         let mut split_path = path_slices.split_last();
         while let Some((key, path_slice)) = split_path {
             // for every subtree, there should be a corresponding proof for the parent
@@ -774,11 +895,12 @@ impl ProofVerifier {
             let mut parent_query = Query::new();
             parent_query.insert_key(key.to_vec());
 
+            // path proofs are generated left to right whatever the query direction
             let proof_result = self.execute_merk_proof(
                 ProofTokenType::Merk,
                 &parent_merk_proof,
                 &parent_query,
-                query.query.query.left_to_right,
+                parent_query.left_to_right,
                 // TODO: don't pass empty vec
                 Vec::new(),
             )?;
@@ -792,12 +914,22 @@ impl ProofVerifier {
 
             let elem = Element::deserialize(result_set[0].value.as_slice())?;
             let child_hash = match elem {
-                Element::Tree(..) | Element::SumTree(..) => Ok(result_set[0].proof),
+                Element::Tree(..) | Element::SumTree(..) | Element::OrderedTree(..) => {
+                    Ok(result_set[0].proof)
+                }
                 _ => Err(Error::InvalidProof(
                     "intermediate proofs should be for trees",
                 )),
             }?;
 
+            if let Some(key_ordering) = expected_key_ordering.take() {
+                if elem.key_ordering() != key_ordering {
+                    return Err(Error::InvalidProof(
+                        "key ordering doesn't match the queried subtree",
+                    ));
+                }
+            }
+
             let combined_root_hash = combine_hash(
                 value_hash_fn(&result_set[0].value).value(),
                 expected_root_hash,
@@ -815,6 +947,14 @@ impl ProofVerifier {
             split_path = path_slice.split_last();
         }
 
+        // the root tree is always ordered lexicographically
+        if matches!(expected_key_ordering, Some(key_ordering) if key_ordering != KeyOrdering::default())
+        {
+            return Err(Error::InvalidProof(
+                "key ordering doesn't match the queried subtree",
+            ));
+        }
+
         Ok(*expected_root_hash)
     }
 
@@ -844,6 +984,9 @@ impl ProofVerifier {
                 Error::InvalidProof("invalid proof verification parameters")
             })?;
 
+        self.stats.node_counts += result.node_counts;
+        self.stats.proof_bytes += result.proof_bytes;
+
         // convert the result set to proved_path_key_values
         let proved_path_key_values =
             ProvedPathKeyValue::from_proved_key_values(path, result.result_set);
@@ -0,0 +1,557 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+//! Deferred propagation
+//! Inside of a transaction the root hash of a changed subtree is carried up
+//! to its ancestors only once, when the transaction is committed, instead of
+//! after every single operation. Elements of ancestors are kept accurate,
+//! only their hashes are left behind until then.
+
+#[cfg(feature = "full")]
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+#[cfg(feature = "full")]
+use costs::{cost_return_on_error, CostResult, CostsExt, OperationCost};
+#[cfg(feature = "full")]
+use merk::Merk;
+#[cfg(feature = "full")]
+use storage::{
+    rocksdb_storage::PrefixedRocksDbBatchTransactionContext, Storage, StorageBatch, StorageContext,
+};
+
+#[cfg(feature = "full")]
+use crate::{Element, Error, GroveDb, Hash, Transaction};
+
+#[cfg(feature = "full")]
+/// Subtree paths whose root hash is not yet reflected in their parent
+pub(crate) type PendingPropagations = BTreeSet<Vec<Vec<u8>>>;
+
+#[cfg(feature = "full")]
+#[derive(Default)]
+/// Propagation settings of a GroveDb instance
+pub(crate) struct PropagationState {
+    /// Forces propagation to ancestors after every operation
+    eager: AtomicBool,
+}
+
+#[cfg(feature = "full")]
+/// Returns true if the error means that a subtree with pending propagation
+/// was deleted or replaced by an item later on in the transaction
+fn is_vanished_subtree_error(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::InvalidPath(_) | Error::CorruptedPath(_) | Error::PathKeyNotFound(_)
+    )
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Forces every operation inside of a transaction to propagate changes to
+    /// the ancestors right away instead of on commit. Useful for debugging,
+    /// as root hashes stored inside of a transaction are always up to date
+    /// then.
+    pub fn set_eager_propagation(&self, eager: bool) {
+        self.propagation.eager.store(eager, Ordering::Relaxed);
+    }
+
+    /// Returns true if propagation is forced to be eager
+    pub fn is_eager_propagation(&self) -> bool {
+        self.propagation.eager.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if the transaction has subtrees with pending propagation
+    pub fn has_pending_propagations(&self, transaction: &Transaction) -> bool {
+        transaction.has_pending_subtrees(|_| true)
+    }
+
+    /// Propagates the changes of `child_tree` at `path` only as far as needed
+    /// to keep ancestors' elements accurate, that is while the root key or
+    /// the sum of a subtree differ from what its parent element holds. The
+    /// first subtree whose parent element is still accurate is marked as
+    /// pending, its root hash will be propagated on commit.
+    pub(crate) fn propagate_changes_deferred_with_transaction<'db, S: StorageContext<'db>>(
+        &self,
+        child_tree: &Merk<S>,
+        mut path: Vec<Vec<u8>>,
+        transaction: &Transaction,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+
+        let (mut root_hash, mut root_key, mut sum) = cost_return_on_error!(
+            &mut cost,
+            child_tree.root_hash_key_and_sum().map_err(Error::MerkError)
+        );
+        while let Some(key) = path.pop() {
+            let mut parent_tree = cost_return_on_error!(
+                &mut cost,
+                self.open_transactional_merk_at_path(
+                    path.iter().map(|k| k.as_slice()),
+                    transaction
+                )
+            );
+            let element = cost_return_on_error!(
+                &mut cost,
+                Self::get_element_from_subtree(&parent_tree, &key)
+            );
+            let parent_element_is_accurate = match element {
+                Element::Tree(stored_root_key, _) | Element::OrderedTree(stored_root_key, ..) => {
+                    stored_root_key == root_key
+                }
+                Element::SumTree(stored_root_key, stored_sum, _) => {
+                    stored_root_key == root_key && Some(stored_sum) == sum
+                }
+                _ => {
+                    return Err(Error::InvalidPath(
+                        "can only propagate on tree items".to_owned(),
+                    ))
+                    .wrap_with_cost(cost)
+                }
+            };
+            let mut child_path = path.clone();
+            child_path.push(key);
+            if parent_element_is_accurate {
+                transaction.add_pending_subtrees([child_path]);
+                break;
+            }
+            cost_return_on_error!(
+                &mut cost,
+                Self::update_tree_item_preserve_flag(
+                    &mut parent_tree,
+                    child_path
+                        .last()
+                        .expect("child path is not empty")
+                        .as_slice(),
+                    root_key,
+                    root_hash,
+                    sum
+                )
+            );
+            transaction.remove_pending_subtree(&child_path);
+            (root_hash, root_key, sum) = cost_return_on_error!(
+                &mut cost,
+                parent_tree
+                    .root_hash_key_and_sum()
+                    .map_err(Error::MerkError)
+            );
+        }
+
+        Ok(()).wrap_with_cost(cost)
+    }
+
+    /// Carries the root hashes of `pending` subtrees up to the root within
+    /// `storage_batch`. Deepest subtrees are handled first so that every
+    /// ancestor is updated only once, subtrees deleted in the meantime are
+    /// skipped. Returns the root tree if it was updated.
+    fn apply_pending_propagations<'db>(
+        &'db self,
+        mut pending: PendingPropagations,
+        storage_batch: &'db StorageBatch,
+        transaction: &'db Transaction,
+    ) -> CostResult<Option<Merk<PrefixedRocksDbBatchTransactionContext<'db>>>, Error> {
+        let mut cost = OperationCost::default();
+        let mut merks: HashMap<Vec<Vec<u8>>, Merk<PrefixedRocksDbBatchTransactionContext>> =
+            HashMap::new();
+
+        while let Some(mut path) = pending
+            .iter()
+            .max_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)))
+            .cloned()
+        {
+            pending.remove(&path);
+            let mut open_merk =
+                |path: &[Vec<u8>], cost: &mut OperationCost| match merks.remove(path) {
+                    Some(merk) => Ok(Some(merk)),
+                    None => match self
+                        .open_batch_transactional_merk_at_path(
+                            storage_batch,
+                            path.iter().map(|k| k.as_slice()),
+                            transaction,
+                            false,
+                        )
+                        .unwrap_add_cost(cost)
+                    {
+                        Ok(merk) => Ok(Some(merk)),
+                        Err(e) if is_vanished_subtree_error(&e) => Ok(None),
+                        Err(e) => Err(e),
+                    },
+                };
+            let child_tree = match open_merk(&path, &mut cost) {
+                Ok(Some(merk)) => merk,
+                Ok(None) => continue,
+                Err(e) => return Err(e).wrap_with_cost(cost),
+            };
+            let key = match path.pop() {
+                Some(key) => key,
+                None => {
+                    merks.insert(path, child_tree);
+                    continue;
+                }
+            };
+            let mut parent_tree = match open_merk(&path, &mut cost) {
+                Ok(Some(merk)) => merk,
+                Ok(None) => continue,
+                Err(e) => return Err(e).wrap_with_cost(cost),
+            };
+            let (root_hash, root_key, sum) = cost_return_on_error!(
+                &mut cost,
+                child_tree.root_hash_key_and_sum().map_err(Error::MerkError)
+            );
+            match Self::update_tree_item_preserve_flag(
+                &mut parent_tree,
+                key.as_slice(),
+                root_key,
+                root_hash,
+                sum,
+            )
+            .unwrap_add_cost(&mut cost)
+            {
+                Ok(()) => {
+                    if !path.is_empty() {
+                        pending.insert(path.clone());
+                    }
+                }
+                Err(e) if is_vanished_subtree_error(&e) => {}
+                Err(e) => return Err(e).wrap_with_cost(cost),
+            }
+            merks.insert(path, parent_tree);
+        }
+
+        Ok(merks.remove(&Vec::new())).wrap_with_cost(cost)
+    }
+
+    /// Propagates the root hashes of pending subtrees satisfying `filter` up
+    /// to the root
+    fn propagate_pending_changes_matching(
+        &self,
+        transaction: &Transaction,
+        filter: impl Fn(&[Vec<u8>]) -> bool,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+
+        let pending = transaction.take_pending_subtrees(filter);
+        if pending.is_empty() {
+            return Ok(()).wrap_with_cost(cost);
+        }
+        let storage_batch = StorageBatch::new();
+        let result = self
+            .apply_pending_propagations(pending.clone(), &storage_batch, transaction)
+            .unwrap_add_cost(&mut cost)
+            .map(drop);
+        let result = result.and_then(|_| {
+            self.db
+                .commit_multi_context_batch(storage_batch, Some(transaction))
+                .unwrap_add_cost(&mut cost)
+                .map_err(Into::into)
+        });
+        if result.is_err() {
+            transaction.add_pending_subtrees(pending);
+        }
+        result.wrap_with_cost(cost)
+    }
+
+    /// Propagates root hashes of all subtrees with pending propagation in the
+    /// transaction up to the root
+    pub fn propagate_pending_changes(&self, transaction: &Transaction) -> CostResult<(), Error> {
+        self.propagate_pending_changes_matching(transaction, |_| true)
+    }
+
+    /// Propagates pending changes below `path`, so that reads of elements
+    /// there see up to date hashes
+    pub(crate) fn propagate_pending_changes_below<'p>(
+        &self,
+        path: impl Iterator<Item = &'p [u8]>,
+        transaction: &Transaction,
+    ) -> CostResult<(), Error> {
+        if !self.has_pending_propagations(transaction) {
+            return Ok(()).wrap_with_cost(OperationCost::default());
+        }
+        let path: Vec<&[u8]> = path.collect();
+        self.propagate_pending_changes_matching(transaction, |pending_path| {
+            pending_path.len() >= path.len()
+                && pending_path
+                    .iter()
+                    .zip(&path)
+                    .all(|(pending_key, key)| pending_key.as_slice() == *key)
+        })
+    }
+
+    /// Returns the root hash the transaction will have once its pending
+    /// propagations are applied, without writing anything, or `None` if
+    /// there are no pending propagations
+    pub(crate) fn pending_root_hash(
+        &self,
+        transaction: &Transaction,
+    ) -> CostResult<Option<Hash>, Error> {
+        let mut cost = OperationCost::default();
+
+        if !self.has_pending_propagations(transaction) {
+            return Ok(None).wrap_with_cost(cost);
+        }
+        let storage_batch = StorageBatch::new();
+        let root_tree = cost_return_on_error!(
+            &mut cost,
+            self.apply_pending_propagations(
+                transaction.pending_subtrees(),
+                &storage_batch,
+                transaction
+            )
+        );
+        // the storage batch is dropped, nothing gets written
+        Ok(root_tree.map(|root_tree| root_tree.root_hash().unwrap_add_cost(&mut cost)))
+            .wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        batch::GroveDbOp,
+        tests::{make_test_grovedb, TempGroveDb, ANOTHER_TEST_LEAF, TEST_LEAF},
+        Element, Transaction,
+    };
+
+    /// Makes an eager and a deferring database holding a sum tree at
+    /// `[TEST_LEAF, innertree, deeper]`
+    fn make_eager_and_deferred_grovedb() -> (TempGroveDb, TempGroveDb) {
+        let eager_db = make_test_grovedb();
+        let deferred_db = make_test_grovedb();
+        deferred_db.set_eager_propagation(false);
+
+        for db in [&eager_db, &deferred_db] {
+            db.insert([TEST_LEAF], b"innertree", Element::empty_tree(), None, None)
+                .unwrap()
+                .expect("successful subtree insert");
+            db.insert(
+                [TEST_LEAF, b"innertree"],
+                b"deeper",
+                Element::empty_sum_tree(),
+                None,
+                None,
+            )
+            .unwrap()
+            .expect("successful sum tree insert");
+        }
+        (eager_db, deferred_db)
+    }
+
+    fn insert_sum_items(db: &TempGroveDb, range: std::ops::Range<u8>, transaction: &Transaction) {
+        for i in range {
+            db.insert(
+                [TEST_LEAF, b"innertree", b"deeper"],
+                &[i],
+                Element::new_sum_item(i as i64),
+                None,
+                Some(transaction),
+            )
+            .unwrap()
+            .expect("successful insert");
+        }
+    }
+
+    #[test]
+    fn test_deferred_propagation_matches_eager_propagation() {
+        let (eager_db, deferred_db) = make_eager_and_deferred_grovedb();
+
+        let eager_tx = eager_db.start_transaction();
+        let deferred_tx = deferred_db.start_transaction();
+        insert_sum_items(&eager_db, 0..50, &eager_tx);
+        insert_sum_items(&deferred_db, 0..50, &deferred_tx);
+
+        assert!(!eager_db.has_pending_propagations(&eager_tx));
+        assert!(deferred_db.has_pending_propagations(&deferred_tx));
+        // elements of ancestors are always accurate
+        assert_eq!(
+            eager_db
+                .get([TEST_LEAF, b"innertree"], b"deeper", Some(&eager_tx))
+                .unwrap()
+                .expect("expected sum tree"),
+            deferred_db
+                .get([TEST_LEAF, b"innertree"], b"deeper", Some(&deferred_tx))
+                .unwrap()
+                .expect("expected sum tree"),
+        );
+
+        eager_db
+            .commit_transaction(eager_tx)
+            .unwrap()
+            .expect("expected to commit");
+        deferred_db
+            .commit_transaction(deferred_tx)
+            .unwrap()
+            .expect("expected to commit");
+
+        assert_eq!(
+            eager_db.root_hash(None).unwrap().unwrap(),
+            deferred_db.root_hash(None).unwrap().unwrap()
+        );
+        assert!(deferred_db.verify_grovedb().is_empty());
+    }
+
+    #[test]
+    fn test_eager_propagation_flag_overrides_deferral() {
+        let db = make_test_grovedb();
+        db.set_eager_propagation(true);
+
+        let tx = db.start_transaction();
+        db.insert(
+            [TEST_LEAF],
+            b"key",
+            Element::new_item(b"value".to_vec()),
+            None,
+            Some(&tx),
+        )
+        .unwrap()
+        .expect("successful insert");
+
+        assert!(!db.has_pending_propagations(&tx));
+    }
+
+    #[test]
+    fn test_root_hash_accounts_for_pending_propagations_without_writing() {
+        let (eager_db, deferred_db) = make_eager_and_deferred_grovedb();
+
+        let eager_tx = eager_db.start_transaction();
+        let deferred_tx = deferred_db.start_transaction();
+        insert_sum_items(&eager_db, 0..10, &eager_tx);
+        insert_sum_items(&deferred_db, 0..10, &deferred_tx);
+
+        assert_eq!(
+            eager_db.root_hash(Some(&eager_tx)).unwrap().unwrap(),
+            deferred_db.root_hash(Some(&deferred_tx)).unwrap().unwrap()
+        );
+        assert!(deferred_db.has_pending_propagations(&deferred_tx));
+    }
+
+    #[test]
+    fn test_reads_of_ancestors_propagate_pending_changes() {
+        let (_, db) = make_eager_and_deferred_grovedb();
+
+        let tx = db.start_transaction();
+        insert_sum_items(&db, 0..10, &tx);
+        assert!(db.has_pending_propagations(&tx));
+
+        db.get([], ANOTHER_TEST_LEAF, Some(&tx))
+            .unwrap()
+            .expect("expected tree");
+        assert!(db.has_pending_propagations(&tx));
+
+        db.get([], TEST_LEAF, Some(&tx))
+            .unwrap()
+            .expect("expected tree");
+        assert!(!db.has_pending_propagations(&tx));
+    }
+
+    #[test]
+    fn test_deletes_and_batches_defer_propagation() {
+        let (eager_db, deferred_db) = make_eager_and_deferred_grovedb();
+
+        let eager_tx = eager_db.start_transaction();
+        let deferred_tx = deferred_db.start_transaction();
+        insert_sum_items(&eager_db, 0..10, &eager_tx);
+        insert_sum_items(&deferred_db, 0..10, &deferred_tx);
+        deferred_db
+            .propagate_pending_changes(&deferred_tx)
+            .unwrap()
+            .expect("expected to propagate");
+
+        for (db, tx) in [(&eager_db, &eager_tx), (&deferred_db, &deferred_tx)] {
+            db.delete([TEST_LEAF, b"innertree", b"deeper"], &[3], None, Some(tx))
+                .unwrap()
+                .expect("successful delete");
+        }
+        assert!(deferred_db.has_pending_propagations(&deferred_tx));
+        deferred_db
+            .propagate_pending_changes(&deferred_tx)
+            .unwrap()
+            .expect("expected to propagate");
+
+        let ops = (10u8..20)
+            .map(|i| {
+                GroveDbOp::insert_op(
+                    vec![
+                        TEST_LEAF.to_vec(),
+                        b"innertree".to_vec(),
+                        b"deeper".to_vec(),
+                    ],
+                    vec![i],
+                    Element::new_sum_item(i as i64),
+                )
+            })
+            .collect::<Vec<_>>();
+        for (db, tx) in [(&eager_db, &eager_tx), (&deferred_db, &deferred_tx)] {
+            db.apply_batch(ops.clone(), None, Some(tx))
+                .unwrap()
+                .expect("successful batch");
+        }
+        assert!(deferred_db.has_pending_propagations(&deferred_tx));
+
+        eager_db
+            .commit_transaction(eager_tx)
+            .unwrap()
+            .expect("expected to commit");
+        deferred_db
+            .commit_transaction(deferred_tx)
+            .unwrap()
+            .expect("expected to commit");
+        assert_eq!(
+            eager_db.root_hash(None).unwrap().unwrap(),
+            deferred_db.root_hash(None).unwrap().unwrap()
+        );
+        assert!(deferred_db.verify_grovedb().is_empty());
+    }
+
+    #[test]
+    fn test_deleted_subtrees_with_pending_propagations_are_skipped() {
+        let (_, db) = make_eager_and_deferred_grovedb();
+
+        let tx = db.start_transaction();
+        insert_sum_items(&db, 0..10, &tx);
+        assert!(db.has_pending_propagations(&tx));
+        db.delete(
+            [TEST_LEAF],
+            b"innertree",
+            Some(crate::operations::delete::DeleteOptions {
+                allow_deleting_non_empty_trees: true,
+                ..Default::default()
+            }),
+            Some(&tx),
+        )
+        .unwrap()
+        .expect("successful delete");
+
+        db.commit_transaction(tx)
+            .unwrap()
+            .expect("expected to commit");
+        assert!(db.verify_grovedb().is_empty());
+    }
+}
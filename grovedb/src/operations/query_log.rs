@@ -0,0 +1,353 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Query log
+//! Opt-in collector of the shapes of executed path queries along with their
+//! costs. A shape keeps the structure of a query, path depth, item counts and
+//! kinds, limits and subquery nesting, but none of its keys, so the log can be
+//! handed to operators to find which query patterns dominate the load.
+
+#[cfg(feature = "full")]
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Mutex,
+};
+
+#[cfg(feature = "full")]
+use costs::OperationCost;
+#[cfg(feature = "full")]
+use merk::proofs::{query::SubqueryBranch, Query};
+
+#[cfg(feature = "full")]
+use crate::{GroveDb, PathQuery, QueryItem};
+
+#[cfg(feature = "full")]
+/// Maximum number of distinct shapes kept, executions of further shapes are
+/// not logged
+pub const QUERY_LOG_CAPACITY: usize = 1024;
+
+#[cfg(feature = "full")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Kind of a query item
+pub enum QueryItemKind {
+    /// Single key
+    Key,
+    /// Range
+    Range,
+    /// Range inclusive
+    RangeInclusive,
+    /// Range full
+    RangeFull,
+    /// Range from
+    RangeFrom,
+    /// Range to
+    RangeTo,
+    /// Range to inclusive
+    RangeToInclusive,
+    /// Range after
+    RangeAfter,
+    /// Range after to
+    RangeAfterTo,
+    /// Range after to inclusive
+    RangeAfterToInclusive,
+}
+
+#[cfg(feature = "full")]
+impl From<&QueryItem> for QueryItemKind {
+    fn from(item: &QueryItem) -> Self {
+        match item {
+            QueryItem::Key(_) => QueryItemKind::Key,
+            QueryItem::Range(_) => QueryItemKind::Range,
+            QueryItem::RangeInclusive(_) => QueryItemKind::RangeInclusive,
+            QueryItem::RangeFull(_) => QueryItemKind::RangeFull,
+            QueryItem::RangeFrom(_) => QueryItemKind::RangeFrom,
+            QueryItem::RangeTo(_) => QueryItemKind::RangeTo,
+            QueryItem::RangeToInclusive(_) => QueryItemKind::RangeToInclusive,
+            QueryItem::RangeAfter(_) => QueryItemKind::RangeAfter,
+            QueryItem::RangeAfterTo(_) => QueryItemKind::RangeAfterTo,
+            QueryItem::RangeAfterToInclusive(_) => QueryItemKind::RangeAfterToInclusive,
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Structure of a path query without its keys
+pub struct QueryShape {
+    /// Length of the path of the queried subtree
+    pub path_depth: usize,
+    /// Number of items of the top level query
+    pub item_count: usize,
+    /// Kinds of the items of the top level query
+    pub item_kinds: BTreeSet<QueryItemKind>,
+    /// Limit
+    pub limit: Option<u16>,
+    /// Offset
+    pub offset: Option<u16>,
+    /// Whether the query goes left to right
+    pub left_to_right: bool,
+    /// Number of subtree levels the query descends below the queried subtree
+    pub subquery_depth: usize,
+    /// Number of conditional subquery branches across all levels
+    pub conditional_subquery_branches: usize,
+}
+
+#[cfg(feature = "full")]
+impl QueryShape {
+    /// Shape of a path query
+    pub fn from_path_query(path_query: &PathQuery) -> Self {
+        let query = &path_query.query.query;
+        QueryShape {
+            path_depth: path_query.path.len(),
+            item_count: query.items.len(),
+            item_kinds: query.items.iter().map(QueryItemKind::from).collect(),
+            limit: path_query.query.limit,
+            offset: path_query.query.offset,
+            left_to_right: query.left_to_right,
+            subquery_depth: subquery_depth(query),
+            conditional_subquery_branches: conditional_subquery_branches(query),
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+fn query_branches(query: &Query) -> impl Iterator<Item = &SubqueryBranch> {
+    std::iter::once(&query.default_subquery_branch).chain(
+        query
+            .conditional_subquery_branches
+            .iter()
+            .flat_map(|branches| branches.values()),
+    )
+}
+
+#[cfg(feature = "full")]
+fn subquery_depth(query: &Query) -> usize {
+    query_branches(query)
+        .map(|branch| {
+            let path_depth = branch.subquery_path.as_ref().map_or(0, |path| path.len());
+            match &branch.subquery {
+                Some(subquery) => path_depth + 1 + subquery_depth(subquery),
+                None => path_depth,
+            }
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(feature = "full")]
+fn conditional_subquery_branches(query: &Query) -> usize {
+    let own = query
+        .conditional_subquery_branches
+        .as_ref()
+        .map_or(0, |branches| branches.len());
+    own + query_branches(query)
+        .filter_map(|branch| branch.subquery.as_deref())
+        .map(conditional_subquery_branches)
+        .sum::<usize>()
+}
+
+#[cfg(feature = "full")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Executions of a query shape
+pub struct QueryLogEntry {
+    /// Shape of the executed queries
+    pub shape: QueryShape,
+    /// Number of executions
+    pub executions: u64,
+    /// Number of results returned across all executions
+    pub results: u64,
+    /// Cost accumulated across all executions
+    pub cost: OperationCost,
+}
+
+#[cfg(feature = "full")]
+#[derive(Default)]
+/// Query log of a GroveDb instance, `None` while disabled
+pub(crate) struct QueryLog {
+    entries: Mutex<Option<HashMap<QueryShape, QueryLogEntry>>>,
+}
+
+#[cfg(feature = "full")]
+impl QueryLog {
+    /// Records an execution of a path query if the log is enabled
+    pub(crate) fn record(&self, path_query: &PathQuery, results: usize, cost: &OperationCost) {
+        let mut entries = self.entries.lock().expect("query log lock is poisoned");
+        let entries = match entries.as_mut() {
+            Some(entries) => entries,
+            None => return,
+        };
+        let shape = QueryShape::from_path_query(path_query);
+        if !entries.contains_key(&shape) && entries.len() >= QUERY_LOG_CAPACITY {
+            return;
+        }
+        let entry = entries
+            .entry(shape.clone())
+            .or_insert_with(|| QueryLogEntry {
+                shape,
+                executions: 0,
+                results: 0,
+                cost: OperationCost::default(),
+            });
+        entry.executions += 1;
+        entry.results += results as u64;
+        entry.cost += cost.clone();
+    }
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Starts or stops logging query shapes, stopping drops the logged entries
+    pub fn set_query_log_enabled(&self, enabled: bool) {
+        let mut entries = self
+            .query_log
+            .entries
+            .lock()
+            .expect("query log lock is poisoned");
+        match (enabled, entries.is_some()) {
+            (true, false) => *entries = Some(HashMap::new()),
+            (false, _) => *entries = None,
+            (true, true) => {}
+        }
+    }
+
+    /// Returns the logged query shapes, the ones that loaded the most bytes
+    /// from storage first
+    pub fn query_log(&self) -> Vec<QueryLogEntry> {
+        let mut log: Vec<QueryLogEntry> = self
+            .query_log
+            .entries
+            .lock()
+            .expect("query log lock is poisoned")
+            .as_ref()
+            .map(|entries| entries.values().cloned().collect())
+            .unwrap_or_default();
+        log.sort_by(|a, b| {
+            (b.cost.storage_loaded_bytes, b.cost.seek_count)
+                .cmp(&(a.cost.storage_loaded_bytes, a.cost.seek_count))
+        });
+        log
+    }
+
+    /// Drops the logged entries, keeping the log enabled if it was
+    pub fn clear_query_log(&self) {
+        if let Some(entries) = self
+            .query_log
+            .entries
+            .lock()
+            .expect("query log lock is poisoned")
+            .as_mut()
+        {
+            entries.clear();
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        query_result_type::QueryResultType,
+        tests::{make_deep_tree, TEST_LEAF},
+        SizedQuery,
+    };
+
+    #[test]
+    fn test_query_log_records_shapes() {
+        let db = make_deep_tree();
+        let mut query = Query::new();
+        query.insert_key(b"key1".to_vec());
+        let path_query =
+            PathQuery::new_unsized(vec![TEST_LEAF.to_vec(), b"innertree".to_vec()], query);
+
+        db.query_raw(
+            &path_query,
+            true,
+            QueryResultType::QueryElementResultType,
+            None,
+        )
+        .unwrap()
+        .expect("should query");
+        assert!(db.query_log().is_empty());
+
+        db.set_query_log_enabled(true);
+        let mut subquery = Query::new();
+        subquery.insert_all();
+        let mut deep_query = Query::new();
+        deep_query.insert_range_from(b"a".to_vec()..);
+        deep_query.set_subquery(subquery);
+        let deep_path_query = PathQuery::new(
+            vec![TEST_LEAF.to_vec()],
+            SizedQuery::new(deep_query, Some(5), None),
+        );
+        for path_query in [&path_query, &path_query, &deep_path_query] {
+            db.query_raw(
+                path_query,
+                true,
+                QueryResultType::QueryElementResultType,
+                None,
+            )
+            .unwrap()
+            .expect("should query");
+        }
+
+        let log = db.query_log();
+        assert_eq!(log.len(), 2);
+        let key_entry = log
+            .iter()
+            .find(|entry| entry.shape == QueryShape::from_path_query(&path_query))
+            .expect("should log key query");
+        assert_eq!(key_entry.executions, 2);
+        assert_eq!(key_entry.results, 2);
+        assert_eq!(key_entry.shape.path_depth, 2);
+        assert_eq!(
+            key_entry.shape.item_kinds,
+            BTreeSet::from([QueryItemKind::Key])
+        );
+        let range_entry = log
+            .iter()
+            .find(|entry| entry.shape.item_kinds.contains(&QueryItemKind::RangeFrom))
+            .expect("should log range query");
+        assert_eq!(range_entry.shape.subquery_depth, 1);
+        assert_eq!(range_entry.shape.limit, Some(5));
+        assert!(range_entry.cost.seek_count > 0);
+
+        db.clear_query_log();
+        assert!(db.query_log().is_empty());
+        db.set_query_log_enabled(false);
+        db.query_raw(
+            &path_query,
+            true,
+            QueryResultType::QueryElementResultType,
+            None,
+        )
+        .unwrap()
+        .expect("should query");
+        assert!(db.query_log().is_empty());
+    }
+}
@@ -0,0 +1,799 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Subtree quotas
+//! A subtree can be given a maximum number of elements and a maximum number
+//! of bytes, counting the elements of its descendants too. Inserts, deletes
+//! and batches keep the usage of every quota up to date, within the
+//! transaction they run on, and fail with `Error::SubtreeQuotaExceeded` when
+//! they would grow a subtree past its quota. The quota and usage of every
+//! subtree are kept in the meta storage under a key of their own, only the
+//! quotas of the subtrees holding changed elements are read and written.
+
+#[cfg(feature = "full")]
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::atomic::Ordering,
+};
+
+#[cfg(feature = "full")]
+use costs::{cost_return_on_error, CostResult, CostsExt, OperationCost};
+#[cfg(feature = "full")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "full")]
+use storage::{Storage, StorageBatch, StorageContext};
+
+#[cfg(feature = "full")]
+use crate::{
+    batch::{GroveDbOp, Op},
+    operations::{
+        deep_hash::without_root_key,
+        scan::ScanOptions,
+        subtree_meta::{
+            delete_subtree_meta, get_subtree_meta, get_subtree_meta_paths, put_subtree_meta,
+            put_subtree_meta_paths, SubtreeMetaPath,
+        },
+    },
+    util::meta_storage_context_optional_tx,
+    Element, Error, GroveDb, TransactionArg,
+};
+
+#[cfg(feature = "full")]
+/// Meta storage key under which the paths of the subtrees having a quota are
+/// kept, the key of the quota of a subtree starts with it
+pub(crate) const SUBTREE_QUOTAS_KEY: &[u8] = b"subtree_quotas";
+
+#[cfg(feature = "full")]
+/// Limits on the content of a subtree and its descendants, `None` meaning
+/// unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubtreeQuota {
+    /// Maximum number of elements
+    pub max_elements: Option<u64>,
+    /// Maximum number of bytes taken by keys and serialized elements
+    pub max_bytes: Option<u64>,
+}
+
+#[cfg(feature = "full")]
+/// Content of a subtree and its descendants
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubtreeUsage {
+    /// Number of elements
+    pub elements: u64,
+    /// Number of bytes taken by keys and serialized elements
+    pub bytes: u64,
+}
+
+#[cfg(feature = "full")]
+impl SubtreeUsage {
+    fn apply(&mut self, delta: UsageDelta) {
+        self.elements = add_signed(self.elements, delta.elements);
+        self.bytes = add_signed(self.bytes, delta.bytes);
+    }
+}
+
+#[cfg(feature = "full")]
+impl SubtreeQuota {
+    /// Whether going from `before` to `after` grows the usage past the quota
+    fn is_exceeded(&self, before: &SubtreeUsage, after: &SubtreeUsage) -> bool {
+        let exceeds = |max: Option<u64>, before: u64, after: u64| matches!(max, Some(max) if after > max && after > before);
+        exceeds(self.max_elements, before.elements, after.elements)
+            || exceeds(self.max_bytes, before.bytes, after.bytes)
+    }
+}
+
+#[cfg(feature = "full")]
+#[derive(Debug, Default)]
+/// Quotas touched by an operation, to be written with it
+pub(crate) struct SubtreeQuotas {
+    /// Quotas of the subtrees holding changed elements, with their usage
+    /// after the operation
+    pub(crate) entries: BTreeMap<SubtreeMetaPath, (SubtreeQuota, SubtreeUsage)>,
+    /// Quotas of deleted subtrees, going with them
+    removed: BTreeSet<SubtreeMetaPath>,
+    /// Paths of the quotas left, when any was removed
+    paths: Option<BTreeSet<SubtreeMetaPath>>,
+}
+
+#[cfg(feature = "full")]
+#[derive(Clone, Copy)]
+/// Change of the element at a key counted against the quotas
+enum QuotaChange<'a> {
+    /// The element is inserted or replaces the previous one
+    Insert(&'a Element),
+    /// The element is deleted, with its subtree if it is a tree
+    Delete,
+    /// The element moves unchanged to the new key of the same subtree
+    Rekey(&'a [u8]),
+}
+
+#[cfg(feature = "full")]
+#[derive(Debug, Clone, Copy, Default)]
+struct UsageDelta {
+    elements: i64,
+    bytes: i64,
+}
+
+#[cfg(feature = "full")]
+fn add_signed(value: u64, delta: i64) -> u64 {
+    if delta >= 0 {
+        value.saturating_add(delta as u64)
+    } else {
+        value.saturating_sub(delta.unsigned_abs())
+    }
+}
+
+#[cfg(feature = "full")]
+/// Bytes counted for an element, the root key of a subtree is left out as it
+/// changes with the content of the subtree rather than with the element
+fn entry_size(key: &[u8], element: &Element) -> u64 {
+    let size = match element {
+        Element::Tree(..) | Element::SumTree(..) | Element::OrderedTree(..) => {
+            without_root_key(element.clone()).serialized_size()
+        }
+        _ => element.serialized_size(),
+    };
+    (key.len() + size) as u64
+}
+
+#[cfg(feature = "full")]
+/// Whether the subtree at `path` is the quota subtree or one of its
+/// descendants
+fn is_within(quota_path: &[Vec<u8>], path: &[&[u8]]) -> bool {
+    quota_path.len() <= path.len() && quota_path.iter().zip(path).all(|(a, b)| a == b)
+}
+
+#[cfg(feature = "full")]
+/// Whether the quota subtree is the subtree at `path` or one of its
+/// descendants
+pub(crate) fn is_below(path: &[&[u8]], quota_path: &[Vec<u8>]) -> bool {
+    path.len() <= quota_path.len() && path.iter().zip(quota_path).all(|(a, b)| *a == b.as_slice())
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Sets the quota of the subtree at the path, or removes it with `None`.
+    /// The current usage of the subtree is counted when the quota is set, a
+    /// subtree already over its quota can only shrink.
+    pub fn set_subtree_quota<'p, P>(
+        &self,
+        path: P,
+        quota: Option<SubtreeQuota>,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+    {
+        let mut cost = OperationCost::default();
+
+        let path_iter = path.into_iter();
+        let path: Vec<Vec<u8>> = path_iter.clone().map(|segment| segment.to_vec()).collect();
+        let mut quotas = SubtreeQuotas::default();
+        let mut paths = cost_return_on_error!(&mut cost, self.subtree_quota_paths(transaction));
+        match quota {
+            Some(quota) => {
+                let usage =
+                    cost_return_on_error!(&mut cost, self.subtree_usage(path_iter, transaction));
+                self.subtree_meta.quotas.store(true, Ordering::Relaxed);
+                if paths.insert(path.clone()) {
+                    quotas.paths = Some(paths);
+                }
+                quotas.entries.insert(path, (quota, usage));
+            }
+            None => {
+                if paths.remove(&path) {
+                    quotas.paths = Some(paths);
+                }
+                quotas.removed.insert(path);
+            }
+        }
+        self.write_subtree_quotas(&quotas, transaction)
+            .add_cost(cost)
+    }
+
+    /// Returns the quota of the subtree at the path with its tracked usage
+    pub fn subtree_quota<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> CostResult<Option<(SubtreeQuota, SubtreeUsage)>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let path: Vec<Vec<u8>> = path.into_iter().map(|segment| segment.to_vec()).collect();
+        self.subtree_quota_entry(&path, transaction)
+    }
+
+    /// Counts the elements and bytes of the subtree at the path and its
+    /// descendants
+    pub fn subtree_usage<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> CostResult<SubtreeUsage, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+    {
+        let mut cost = OperationCost::default();
+
+        let path_iter = path.into_iter();
+        cost_return_on_error!(
+            &mut cost,
+            self.check_subtree_exists_path_not_found(path_iter.clone(), transaction)
+        );
+        let subtrees = cost_return_on_error!(&mut cost, self.find_subtrees(path_iter, transaction));
+        let mut usage = SubtreeUsage::default();
+        for subtree in subtrees {
+            cost_return_on_error!(
+                &mut cost,
+                self.scan_subtree(
+                    subtree.iter().map(|segment| segment.as_slice()),
+                    ScanOptions::default(),
+                    transaction,
+                    |key, element| {
+                        usage.elements += 1;
+                        usage.bytes += entry_size(key, &element);
+                        true
+                    },
+                )
+            );
+        }
+        Ok(usage).wrap_with_cost(cost)
+    }
+
+    /// Fails if inserting `element` under `path` would exceed a quota,
+    /// otherwise returns the quotas with their usage after the insertion if
+    /// it touches any
+    pub(crate) fn check_insert_quotas<'p, P>(
+        &self,
+        path: P,
+        key: &[u8],
+        element: &Element,
+        transaction: TransactionArg,
+    ) -> CostResult<Option<SubtreeQuotas>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let path: Vec<&[u8]> = path.into_iter().collect();
+        self.updated_quotas([(path, key, QuotaChange::Insert(element))], transaction)
+    }
+
+    /// Returns the quotas with their usage after deleting `key` under `path`
+    /// if the deletion touches any
+    pub(crate) fn check_delete_quotas<'p, P>(
+        &self,
+        path: P,
+        key: &[u8],
+        transaction: TransactionArg,
+    ) -> CostResult<Option<SubtreeQuotas>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let path: Vec<&[u8]> = path.into_iter().collect();
+        self.updated_quotas([(path, key, QuotaChange::Delete)], transaction)
+    }
+
+    /// Fails if the operations would exceed a quota, otherwise returns the
+    /// quotas with their usage after the operations if they touch any
+    pub(crate) fn check_ops_quotas(
+        &self,
+        ops: &[GroveDbOp],
+        transaction: TransactionArg,
+    ) -> CostResult<Option<SubtreeQuotas>, Error> {
+        let changes = ops.iter().filter_map(|op| {
+            let change = match &op.op {
+                Op::Insert { element } | Op::Replace { element } | Op::Patch { element, .. } => {
+                    QuotaChange::Insert(element)
+                }
+                Op::Delete | Op::DeleteTree | Op::DeleteSumTree => QuotaChange::Delete,
+                Op::Rekey { new_key } => QuotaChange::Rekey(new_key),
+                Op::ReplaceTreeRootKey { .. } | Op::InsertTreeWithRootHash { .. } => return None,
+            };
+            Some((op.path.to_path_refs(), op.key.as_slice(), change))
+        });
+        self.updated_quotas(changes, transaction)
+    }
+
+    /// Applies the changes to the usage of the quotas of the subtrees holding
+    /// changed elements and fails if a quota grew past its limits. Nothing is
+    /// read when no quota was ever set.
+    fn updated_quotas<'a, I>(
+        &self,
+        changes: I,
+        transaction: TransactionArg,
+    ) -> CostResult<Option<SubtreeQuotas>, Error>
+    where
+        I: IntoIterator<Item = (Vec<&'a [u8]>, &'a [u8], QuotaChange<'a>)>,
+    {
+        let mut cost = OperationCost::default();
+        if !self.subtree_meta.quotas.load(Ordering::Relaxed) {
+            return Ok(None).wrap_with_cost(cost);
+        }
+
+        // quotas of the subtrees read so far, `None` for subtrees without one
+        let mut loaded: BTreeMap<SubtreeMetaPath, Option<(SubtreeQuota, SubtreeUsage)>> =
+            BTreeMap::new();
+        let mut before: BTreeMap<SubtreeMetaPath, SubtreeUsage> = BTreeMap::new();
+        let mut quotas = SubtreeQuotas::default();
+        for (path, key, change) in changes {
+            if let QuotaChange::Delete = change {
+                // quotas of a deleted subtree and its descendants go with it
+                let mut subtree_path = path.clone();
+                subtree_path.push(key);
+                if quotas.paths.is_none() {
+                    quotas.paths = Some(cost_return_on_error!(
+                        &mut cost,
+                        self.subtree_quota_paths(transaction)
+                    ));
+                }
+                if let Some(paths) = quotas.paths.as_mut() {
+                    let removed: Vec<SubtreeMetaPath> = paths
+                        .iter()
+                        .filter(|quota_path| is_below(&subtree_path, quota_path))
+                        .cloned()
+                        .collect();
+                    for quota_path in removed {
+                        paths.remove(&quota_path);
+                        loaded.remove(&quota_path);
+                        quotas.removed.insert(quota_path);
+                    }
+                }
+            }
+            for depth in 0..=path.len() {
+                let quota_path: SubtreeMetaPath = path[..depth]
+                    .iter()
+                    .map(|segment| segment.to_vec())
+                    .collect();
+                if loaded.contains_key(&quota_path) || quotas.removed.contains(&quota_path) {
+                    continue;
+                }
+                let entry = cost_return_on_error!(
+                    &mut cost,
+                    self.subtree_quota_entry(&quota_path, transaction)
+                );
+                if let Some((_, usage)) = entry {
+                    before.insert(quota_path.clone(), usage);
+                }
+                loaded.insert(quota_path, entry);
+            }
+            if !loaded
+                .iter()
+                .any(|(quota_path, entry)| entry.is_some() && is_within(quota_path, &path))
+            {
+                continue;
+            }
+            let mut delta = UsageDelta::default();
+            match change {
+                QuotaChange::Rekey(new_key) => {
+                    // the moved element is unchanged, only its key length counts
+                    delta.bytes = new_key.len() as i64 - key.len() as i64;
+                }
+                QuotaChange::Insert(_) | QuotaChange::Delete => {
+                    // the path of elements inserted in subtrees created by the
+                    // same batch doesn't exist yet
+                    let old_element = match self
+                        .get_raw_optional(path.iter().copied(), key, transaction)
+                        .unwrap_add_cost(&mut cost)
+                    {
+                        Ok(old_element) => old_element,
+                        Err(Error::PathNotFound(_))
+                        | Err(Error::PathParentLayerNotFound(_))
+                        | Err(Error::InvalidParentLayerPath(_)) => None,
+                        Err(e) => return Err(e).wrap_with_cost(cost),
+                    };
+                    if let Some(old_element) = &old_element {
+                        delta.elements -= 1;
+                        delta.bytes -= entry_size(key, old_element) as i64;
+                        if old_element.is_tree() && matches!(change, QuotaChange::Delete) {
+                            let mut subtree_path = path.clone();
+                            subtree_path.push(key);
+                            let usage = cost_return_on_error!(
+                                &mut cost,
+                                self.subtree_usage(subtree_path, transaction)
+                            );
+                            delta.elements -= usage.elements as i64;
+                            delta.bytes -= usage.bytes as i64;
+                        }
+                    }
+                    if let QuotaChange::Insert(element) = change {
+                        delta.elements += 1;
+                        delta.bytes += entry_size(key, element) as i64;
+                    }
+                }
+            }
+            loaded
+                .iter_mut()
+                .filter(|(quota_path, _)| is_within(quota_path, &path))
+                .filter_map(|(_, entry)| entry.as_mut())
+                .for_each(|(_, usage)| usage.apply(delta));
+        }
+        quotas.entries = loaded
+            .into_iter()
+            .filter_map(|(quota_path, entry)| entry.map(|entry| (quota_path, entry)))
+            .collect();
+        for (quota_path, (quota, usage)) in quotas.entries.iter() {
+            let usage_before = before.get(quota_path).unwrap_or(usage);
+            if quota.is_exceeded(usage_before, usage) {
+                return Err(Error::SubtreeQuotaExceeded(format!(
+                    "subtree at path {:?} would hold {} elements and {} bytes",
+                    quota_path.iter().map(hex::encode).collect::<Vec<String>>(),
+                    usage.elements,
+                    usage.bytes
+                )))
+                .wrap_with_cost(cost);
+            }
+        }
+        if quotas.removed.is_empty() {
+            quotas.paths = None;
+            if quotas.entries.is_empty() {
+                return Ok(None).wrap_with_cost(cost);
+            }
+        }
+        Ok(Some(quotas)).wrap_with_cost(cost)
+    }
+
+    /// Writes the quotas touched by an operation
+    pub(crate) fn write_subtree_quotas(
+        &self,
+        quotas: &SubtreeQuotas,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            let meta_storage = meta_storage.unwrap_add_cost(&mut cost);
+            put_subtree_quotas(&meta_storage, quotas)
+        })
+        .add_cost(cost)
+    }
+
+    /// Adds writing the quotas touched by a batch to the storage batch, so
+    /// that they are committed with the rest of the batch
+    pub(crate) fn record_subtree_quotas(
+        &self,
+        quotas: &SubtreeQuotas,
+        storage_batch: &StorageBatch,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+        if let Some(tx) = transaction {
+            let meta_storage = self
+                .db
+                .get_batch_transactional_storage_context(std::iter::empty(), storage_batch, tx)
+                .unwrap_add_cost(&mut cost);
+            put_subtree_quotas(&meta_storage, quotas)
+        } else {
+            let meta_storage = self
+                .db
+                .get_batch_storage_context(std::iter::empty(), storage_batch)
+                .unwrap_add_cost(&mut cost);
+            put_subtree_quotas(&meta_storage, quotas)
+        }
+        .add_cost(cost)
+    }
+
+    /// Reads the quota of the subtree at the path, without touching the meta
+    /// storage when no quota was ever set
+    fn subtree_quota_entry(
+        &self,
+        path: &[Vec<u8>],
+        transaction: TransactionArg,
+    ) -> CostResult<Option<(SubtreeQuota, SubtreeUsage)>, Error> {
+        let mut cost = OperationCost::default();
+        if !self.subtree_meta.quotas.load(Ordering::Relaxed) {
+            return Ok(None).wrap_with_cost(cost);
+        }
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            let meta_storage = meta_storage.unwrap_add_cost(&mut cost);
+            get_subtree_meta(&meta_storage, SUBTREE_QUOTAS_KEY, path)
+        })
+        .add_cost(cost)
+    }
+
+    /// Reads the paths of the subtrees having a quota
+    fn subtree_quota_paths(
+        &self,
+        transaction: TransactionArg,
+    ) -> CostResult<BTreeSet<SubtreeMetaPath>, Error> {
+        let mut cost = OperationCost::default();
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            let meta_storage = meta_storage.unwrap_add_cost(&mut cost);
+            get_subtree_meta_paths(&meta_storage, SUBTREE_QUOTAS_KEY)
+        })
+        .add_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+/// Puts the quotas touched by an operation, deletes the removed ones and
+/// updates the paths of the quotas if they changed
+fn put_subtree_quotas<'db, S: StorageContext<'db>>(
+    meta_storage: &S,
+    quotas: &SubtreeQuotas,
+) -> CostResult<(), Error> {
+    let mut cost = OperationCost::default();
+    for (path, entry) in quotas.entries.iter() {
+        cost_return_on_error!(
+            &mut cost,
+            put_subtree_meta(meta_storage, SUBTREE_QUOTAS_KEY, path, entry)
+        );
+    }
+    for path in quotas.removed.iter() {
+        cost_return_on_error!(
+            &mut cost,
+            delete_subtree_meta(meta_storage, SUBTREE_QUOTAS_KEY, path)
+        );
+    }
+    if let Some(paths) = quotas.paths.as_ref() {
+        cost_return_on_error!(
+            &mut cost,
+            put_subtree_meta_paths(meta_storage, SUBTREE_QUOTAS_KEY, paths)
+        );
+    }
+    Ok(()).wrap_with_cost(cost)
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{make_test_grovedb, TEST_LEAF};
+
+    #[test]
+    fn test_subtree_quota_limits_elements_and_bytes() {
+        let db = make_test_grovedb();
+        db.insert([TEST_LEAF], b"contract", Element::empty_tree(), None, None)
+            .unwrap()
+            .expect("should insert subtree");
+        db.insert(
+            [TEST_LEAF, b"contract"],
+            b"doc_0",
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+        db.set_subtree_quota(
+            [TEST_LEAF, b"contract"],
+            Some(SubtreeQuota {
+                max_elements: Some(3),
+                max_bytes: None,
+            }),
+            None,
+        )
+        .unwrap()
+        .expect("should set quota");
+
+        db.insert(
+            [TEST_LEAF, b"contract"],
+            b"nested",
+            Element::empty_tree(),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert nested subtree");
+        db.insert(
+            [TEST_LEAF, b"contract", b"nested"],
+            b"doc_1",
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert nested item");
+        let (_, usage) = db
+            .subtree_quota([TEST_LEAF, b"contract"], None)
+            .unwrap()
+            .expect("should get quota")
+            .expect("quota should be set");
+        assert_eq!(usage.elements, 3);
+        assert_eq!(
+            usage,
+            db.subtree_usage([TEST_LEAF, b"contract"], None)
+                .unwrap()
+                .expect("should count usage")
+        );
+
+        assert!(matches!(
+            db.insert(
+                [TEST_LEAF, b"contract", b"nested"],
+                b"doc_2",
+                Element::new_item(b"value".to_vec()),
+                None,
+                None,
+            )
+            .unwrap(),
+            Err(Error::SubtreeQuotaExceeded(_))
+        ));
+        assert!(matches!(
+            db.apply_batch(
+                vec![GroveDbOp::insert_op(
+                    vec![TEST_LEAF.to_vec(), b"contract".to_vec()],
+                    b"doc_2".to_vec(),
+                    Element::new_item(b"value".to_vec()),
+                )],
+                None,
+                None
+            )
+            .unwrap(),
+            Err(Error::SubtreeQuotaExceeded(_))
+        ));
+
+        // replacing an element by a smaller one keeps the element count
+        db.insert(
+            [TEST_LEAF, b"contract"],
+            b"doc_0",
+            Element::new_item(b"v".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should replace item");
+
+        // changes made in a transaction are only counted within it
+        let transaction = db.start_transaction();
+        db.delete([TEST_LEAF, b"contract"], b"doc_0", None, Some(&transaction))
+            .unwrap()
+            .expect("should delete item");
+        let (_, usage) = db
+            .subtree_quota([TEST_LEAF, b"contract"], Some(&transaction))
+            .unwrap()
+            .expect("should get quota")
+            .expect("quota should be set");
+        assert_eq!(usage.elements, 2);
+        let (_, usage) = db
+            .subtree_quota([TEST_LEAF, b"contract"], None)
+            .unwrap()
+            .expect("should get quota")
+            .expect("quota should be set");
+        assert_eq!(usage.elements, 3);
+        db.apply_batch(
+            vec![GroveDbOp::insert_op(
+                vec![TEST_LEAF.to_vec(), b"contract".to_vec()],
+                b"doc_2".to_vec(),
+                Element::new_item(b"value".to_vec()),
+            )],
+            None,
+            Some(&transaction),
+        )
+        .unwrap()
+        .expect("batch within quota should apply");
+        db.commit_transaction(transaction)
+            .unwrap()
+            .expect("should commit transaction");
+        assert_eq!(
+            db.subtree_quota([TEST_LEAF, b"contract"], None)
+                .unwrap()
+                .expect("should get quota")
+                .map(|(_, usage)| usage),
+            Some(
+                db.subtree_usage([TEST_LEAF, b"contract"], None)
+                    .unwrap()
+                    .expect("should count usage")
+            )
+        );
+
+        let usage = db
+            .subtree_usage([TEST_LEAF, b"contract"], None)
+            .unwrap()
+            .expect("should count usage");
+        db.set_subtree_quota(
+            [TEST_LEAF, b"contract"],
+            Some(SubtreeQuota {
+                max_elements: None,
+                max_bytes: Some(usage.bytes),
+            }),
+            None,
+        )
+        .unwrap()
+        .expect("should set quota");
+        assert!(matches!(
+            db.insert(
+                [TEST_LEAF, b"contract", b"nested"],
+                b"doc_3",
+                Element::new_item(Vec::new()),
+                None,
+                None,
+            )
+            .unwrap(),
+            Err(Error::SubtreeQuotaExceeded(_))
+        ));
+
+        // deleting the subtree drops its quota
+        db.delete(
+            [TEST_LEAF],
+            b"contract",
+            Some(crate::operations::delete::DeleteOptions {
+                allow_deleting_non_empty_trees: true,
+                ..Default::default()
+            }),
+            None,
+        )
+        .unwrap()
+        .expect("should delete subtree");
+        assert_eq!(
+            db.subtree_quota([TEST_LEAF, b"contract"], None)
+                .unwrap()
+                .expect("should get quota"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_subtree_quota_counts_rekey_key_length() {
+        let db = make_test_grovedb();
+        db.insert(
+            [TEST_LEAF],
+            b"doc",
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+        db.set_subtree_quota(
+            [TEST_LEAF],
+            Some(SubtreeQuota {
+                max_elements: None,
+                max_bytes: Some(64),
+            }),
+            None,
+        )
+        .unwrap()
+        .expect("should set quota");
+        let (_, usage) = db
+            .subtree_quota([TEST_LEAF], None)
+            .unwrap()
+            .expect("should get quota")
+            .expect("quota should be set");
+
+        let rekey = GroveDbOp::rekey_op(
+            vec![TEST_LEAF.to_vec()],
+            b"doc".to_vec(),
+            b"renamed_doc".to_vec(),
+        );
+        let quotas = db
+            .check_ops_quotas(&[rekey], None)
+            .unwrap()
+            .expect("rekey should fit the quota")
+            .expect("quota should be set");
+        let (_, rekeyed_usage) = quotas.entries[&vec![TEST_LEAF.to_vec()]];
+        assert_eq!(rekeyed_usage.elements, usage.elements);
+        assert_eq!(rekeyed_usage.bytes, usage.bytes + 8);
+
+        let long_rekey =
+            GroveDbOp::rekey_op(vec![TEST_LEAF.to_vec()], b"doc".to_vec(), vec![0; 64]);
+        assert!(matches!(
+            db.check_ops_quotas(&[long_rekey], None).unwrap(),
+            Err(Error::SubtreeQuotaExceeded(_))
+        ));
+    }
+}
@@ -0,0 +1,330 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Read only subtrees
+//! Subtrees can be frozen, for instance once they hold finalized historical
+//! data like closed epochs. Inserts, deletes and batches changing a frozen
+//! subtree, one of its descendants or replacing one of its ancestors fail with
+//! `Error::ReadOnlySubtree` until it is unfrozen. Frozen paths are kept in the
+//! meta storage, writes pay for reading them once any subtree was frozen.
+
+#[cfg(feature = "full")]
+use std::{collections::BTreeSet, sync::atomic::Ordering};
+
+#[cfg(feature = "full")]
+use bincode::Options;
+#[cfg(feature = "full")]
+use costs::{
+    cost_return_on_error, cost_return_on_error_no_add, CostResult, CostsExt, OperationCost,
+};
+#[cfg(feature = "full")]
+use storage::StorageContext;
+
+#[cfg(feature = "full")]
+use crate::{
+    batch::GroveDbOp,
+    operations::metadata::{get_internal_meta, put_internal_meta},
+    util::meta_storage_context_optional_tx,
+    Error, GroveDb, TransactionArg,
+};
+
+#[cfg(feature = "full")]
+/// Meta storage key under which the paths of read only subtrees are kept
+pub(crate) const READ_ONLY_SUBTREES_KEY: &[u8] = b"read_only_subtrees";
+
+#[cfg(feature = "full")]
+type ReadOnlySubtrees = BTreeSet<Vec<Vec<u8>>>;
+
+#[cfg(feature = "full")]
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::default()
+        .with_varint_encoding()
+        .reject_trailing_bytes()
+}
+
+#[cfg(feature = "full")]
+/// Whether writing `key` under `path` changes the frozen subtree: the write is
+/// inside it, or it replaces the subtree or one of its ancestors
+fn touches(frozen: &[Vec<u8>], path: &[&[u8]], key: &[u8]) -> bool {
+    if frozen.len() <= path.len() {
+        frozen.iter().zip(path).all(|(a, b)| a == b)
+    } else {
+        path.iter().zip(frozen).all(|(a, b)| *a == b.as_slice()) && frozen[path.len()] == key
+    }
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Marks the subtree at the path read only, or writable again
+    pub fn set_readonly<'p, P>(
+        &self,
+        path: P,
+        read_only: bool,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+    {
+        let mut cost = OperationCost::default();
+
+        let path_iter = path.into_iter();
+        let path: Vec<Vec<u8>> = path_iter.clone().map(|segment| segment.to_vec()).collect();
+        let mut read_only_subtrees =
+            cost_return_on_error!(&mut cost, self.read_only_subtree_set(transaction));
+        if read_only {
+            cost_return_on_error!(
+                &mut cost,
+                self.check_subtree_exists_invalid_path(path_iter, transaction)
+            );
+            self.subtree_meta.read_only.store(true, Ordering::Relaxed);
+            read_only_subtrees.insert(path);
+        } else {
+            read_only_subtrees.remove(&path);
+        }
+
+        let bytes = cost_return_on_error_no_add!(
+            &cost,
+            bincode_options()
+                .serialize(&read_only_subtrees)
+                .map_err(|_| Error::CorruptedData(
+                    "unable to serialize read only subtrees".to_owned()
+                ))
+        );
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            let meta_storage = meta_storage.unwrap_add_cost(&mut cost);
+            if read_only_subtrees.is_empty() {
+                meta_storage
+                    .delete_meta(READ_ONLY_SUBTREES_KEY, None)
+                    .map_err(Error::StorageError)
+            } else {
+                put_internal_meta(&meta_storage, READ_ONLY_SUBTREES_KEY, bytes)
+            }
+        })
+        .add_cost(cost)
+    }
+
+    /// Returns true if the subtree at the path or one of its ancestors is
+    /// read only
+    pub fn is_readonly<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> CostResult<bool, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let path: Vec<&[u8]> = path.into_iter().collect();
+        self.read_only_subtree_set(transaction)
+            .map_ok(|read_only_subtrees| {
+                read_only_subtrees.iter().any(|frozen| {
+                    frozen.len() <= path.len() && frozen.iter().zip(&path).all(|(a, b)| a == b)
+                })
+            })
+    }
+
+    /// Returns the paths of the subtrees marked read only
+    pub fn read_only_subtrees(
+        &self,
+        transaction: TransactionArg,
+    ) -> CostResult<Vec<Vec<Vec<u8>>>, Error> {
+        self.read_only_subtree_set(transaction)
+            .map_ok(|read_only_subtrees| read_only_subtrees.into_iter().collect())
+    }
+
+    /// Fails if writing `key` under `path` would change a read only subtree
+    pub(crate) fn check_writable<'p, P>(
+        &self,
+        path: P,
+        key: &[u8],
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let mut cost = OperationCost::default();
+        if !self.subtree_meta.read_only.load(Ordering::Relaxed) {
+            return Ok(()).wrap_with_cost(cost);
+        }
+        let read_only_subtrees =
+            cost_return_on_error!(&mut cost, self.read_only_subtree_set(transaction));
+        let path: Vec<&[u8]> = path.into_iter().collect();
+        check_writable(&read_only_subtrees, &path, key).wrap_with_cost(cost)
+    }
+
+    /// Fails if one of the operations would change a read only subtree
+    pub(crate) fn check_ops_writable(
+        &self,
+        ops: &[GroveDbOp],
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+        if !self.subtree_meta.read_only.load(Ordering::Relaxed) {
+            return Ok(()).wrap_with_cost(cost);
+        }
+        let read_only_subtrees =
+            cost_return_on_error!(&mut cost, self.read_only_subtree_set(transaction));
+        ops.iter()
+            .try_for_each(|op| {
+                check_writable(
+                    &read_only_subtrees,
+                    &op.path.to_path_refs(),
+                    op.key.as_slice(),
+                )
+            })
+            .wrap_with_cost(cost)
+    }
+
+    fn read_only_subtree_set(
+        &self,
+        transaction: TransactionArg,
+    ) -> CostResult<ReadOnlySubtrees, Error> {
+        let mut cost = OperationCost::default();
+        let maybe_bytes = meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            let meta_storage = meta_storage.unwrap_add_cost(&mut cost);
+            cost_return_on_error!(
+                &mut cost,
+                get_internal_meta(&meta_storage, READ_ONLY_SUBTREES_KEY)
+            )
+        });
+        match maybe_bytes {
+            Some(bytes) => bincode_options()
+                .deserialize(&bytes)
+                .map_err(|_| Error::CorruptedData("read only subtrees are corrupted".to_owned())),
+            None => Ok(ReadOnlySubtrees::new()),
+        }
+        .wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+fn check_writable(
+    read_only_subtrees: &ReadOnlySubtrees,
+    path: &[&[u8]],
+    key: &[u8],
+) -> Result<(), Error> {
+    match read_only_subtrees
+        .iter()
+        .find(|frozen| touches(frozen, path, key))
+    {
+        Some(frozen) => Err(Error::ReadOnlySubtree(format!(
+            "subtree at path {:?} is read only",
+            frozen.iter().map(hex::encode).collect::<Vec<String>>()
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        batch::GroveDbOp,
+        tests::{make_test_grovedb, ANOTHER_TEST_LEAF, TEST_LEAF},
+        Element,
+    };
+
+    #[test]
+    fn test_read_only_subtree_rejects_writes() {
+        let db = make_test_grovedb();
+        db.insert([TEST_LEAF], b"epoch_0", Element::empty_tree(), None, None)
+            .unwrap()
+            .expect("should insert subtree");
+        db.insert(
+            [TEST_LEAF, b"epoch_0"],
+            b"item",
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+        db.set_readonly([TEST_LEAF, b"epoch_0"], true, None)
+            .unwrap()
+            .expect("should freeze subtree");
+        assert!(db
+            .is_readonly([TEST_LEAF, b"epoch_0", b"nested"], None)
+            .unwrap()
+            .expect("should check subtree"));
+
+        assert!(matches!(
+            db.insert(
+                [TEST_LEAF, b"epoch_0"],
+                b"other",
+                Element::new_item(b"value".to_vec()),
+                None,
+                None,
+            )
+            .unwrap(),
+            Err(Error::ReadOnlySubtree(_))
+        ));
+        assert!(matches!(
+            db.delete([TEST_LEAF, b"epoch_0"], b"item", None, None)
+                .unwrap(),
+            Err(Error::ReadOnlySubtree(_))
+        ));
+        assert!(matches!(
+            db.delete([], TEST_LEAF, None, None).unwrap(),
+            Err(Error::ReadOnlySubtree(_))
+        ));
+        assert!(matches!(
+            db.apply_batch(
+                vec![GroveDbOp::delete_op(
+                    vec![TEST_LEAF.to_vec(), b"epoch_0".to_vec()],
+                    b"item".to_vec()
+                )],
+                None,
+                None
+            )
+            .unwrap(),
+            Err(Error::ReadOnlySubtree(_))
+        ));
+
+        // siblings stay writable
+        db.insert([TEST_LEAF], b"epoch_1", Element::empty_tree(), None, None)
+            .unwrap()
+            .expect("should insert sibling subtree");
+        db.insert(
+            [ANOTHER_TEST_LEAF],
+            b"item",
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+
+        db.set_readonly([TEST_LEAF, b"epoch_0"], false, None)
+            .unwrap()
+            .expect("should unfreeze subtree");
+        db.delete([TEST_LEAF, b"epoch_0"], b"item", None, None)
+            .unwrap()
+            .expect("should delete item");
+    }
+}
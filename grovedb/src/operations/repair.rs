@@ -0,0 +1,229 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Repair
+//! Heals databases whose tree elements don't commit to the current root hash
+//! of their subtree, as left behind by historical propagation bugs.
+
+#[cfg(feature = "full")]
+use costs::{
+    cost_return_on_error, cost_return_on_error_no_add, CostResult, CostsExt, OperationCost,
+};
+#[cfg(feature = "full")]
+use merk::{
+    tree::{combine_hash, value_hash},
+    CryptoHash,
+};
+
+#[cfg(feature = "full")]
+use crate::{Element, Error, GroveDb, Transaction};
+
+#[cfg(feature = "full")]
+/// Subtree whose tree element was rewritten by a repropagation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairedSubtree {
+    /// Path of the subtree
+    pub path: Vec<Vec<u8>>,
+    /// Value hash of the tree element before the repair
+    pub stale_value_hash: CryptoHash,
+    /// Value hash of the tree element committing to the subtree root hash
+    pub value_hash: CryptoHash,
+}
+
+#[cfg(feature = "full")]
+/// Report of a repropagation
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepropagationReport {
+    /// Number of tree elements checked on the way to the root
+    pub checked: usize,
+    /// Subtrees whose tree elements were stale, deepest first
+    pub repaired: Vec<RepairedSubtree>,
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Recomputes the root hash of the subtree at `path` and rewrites every
+    /// stale tree element on the way up to the root. Changes deferred within
+    /// the transaction are propagated first, so only historical damage ends
+    /// up in the report.
+    pub fn repropagate<'p, P>(
+        &self,
+        path: P,
+        transaction: &Transaction,
+    ) -> CostResult<RepropagationReport, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let mut cost = OperationCost::default();
+        let mut report = RepropagationReport::default();
+
+        cost_return_on_error!(&mut cost, self.propagate_pending_changes(transaction));
+
+        let mut path: Vec<Vec<u8>> = path.into_iter().map(|segment| segment.to_vec()).collect();
+        let mut child_tree = cost_return_on_error!(
+            &mut cost,
+            self.open_transactional_merk_at_path(path.iter().map(|k| k.as_slice()), transaction)
+        );
+
+        while let Some(key) = path.pop() {
+            let mut parent_tree = cost_return_on_error!(
+                &mut cost,
+                self.open_transactional_merk_at_path(
+                    path.iter().map(|k| k.as_slice()),
+                    transaction
+                )
+            );
+            let (root_hash, root_key, sum) = cost_return_on_error!(
+                &mut cost,
+                child_tree.root_hash_key_and_sum().map_err(Error::MerkError)
+            );
+            let (element_bytes, stored_value_hash) = cost_return_on_error!(
+                &mut cost,
+                parent_tree
+                    .get_value_and_value_hash(&key, true)
+                    .map_err(Error::MerkError)
+                    .map(|result| result.and_then(|maybe_value| {
+                        maybe_value.ok_or_else(|| {
+                            Error::CorruptedData("tree element is missing".to_owned())
+                        })
+                    }))
+            );
+            let element = cost_return_on_error_no_add!(&cost, Element::deserialize(&element_bytes));
+            let element_is_accurate = match &element {
+                Element::Tree(stored_root_key, _) | Element::OrderedTree(stored_root_key, ..) => {
+                    stored_root_key == &root_key
+                }
+                Element::SumTree(stored_root_key, stored_sum, _) => {
+                    stored_root_key == &root_key && Some(*stored_sum) == sum
+                }
+                _ => {
+                    return Err(Error::InvalidPath(
+                        "can only repropagate on tree items".to_owned(),
+                    ))
+                    .wrap_with_cost(cost)
+                }
+            };
+            let element_value_hash = value_hash(&element_bytes).unwrap_add_cost(&mut cost);
+            let value_hash =
+                combine_hash(&element_value_hash, &root_hash).unwrap_add_cost(&mut cost);
+            report.checked += 1;
+
+            if !element_is_accurate || value_hash != stored_value_hash {
+                cost_return_on_error!(
+                    &mut cost,
+                    Self::update_tree_item_preserve_flag(
+                        &mut parent_tree,
+                        key.as_slice(),
+                        root_key,
+                        root_hash,
+                        sum
+                    )
+                );
+                let value_hash = cost_return_on_error!(
+                    &mut cost,
+                    parent_tree
+                        .get_value_hash(&key, true)
+                        .map_err(Error::MerkError)
+                )
+                .unwrap_or(value_hash);
+                let mut repaired_path = path.clone();
+                repaired_path.push(key);
+                report.repaired.push(RepairedSubtree {
+                    path: repaired_path,
+                    stale_value_hash: stored_value_hash,
+                    value_hash,
+                });
+            }
+            child_tree = parent_tree;
+        }
+
+        Ok(report).wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use crate::{
+        tests::{make_test_grovedb, TEST_LEAF},
+        Element,
+    };
+
+    #[test]
+    fn test_repropagate_heals_stale_tree_elements() {
+        let db = make_test_grovedb();
+        db.insert([TEST_LEAF], b"inner", Element::empty_tree(), None, None)
+            .unwrap()
+            .expect("should insert tree");
+        db.insert(
+            [TEST_LEAF, b"inner"],
+            b"key",
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+
+        // overwrite the item bypassing propagation
+        let tx = db.start_transaction();
+        let mut merk = db
+            .open_transactional_merk_at_path([TEST_LEAF, b"inner"], &tx)
+            .unwrap()
+            .expect("should open merk");
+        Element::new_item(b"changed".to_vec())
+            .insert(&mut merk, b"key", None)
+            .unwrap()
+            .expect("should insert item");
+        drop(merk);
+        db.commit_transaction(tx).unwrap().unwrap();
+        assert_eq!(db.verify_grovedb().len(), 1);
+
+        let tx = db.start_transaction();
+        let report = db
+            .repropagate([TEST_LEAF, b"inner"], &tx)
+            .unwrap()
+            .expect("should repropagate");
+        assert_eq!(report.checked, 2);
+        assert_eq!(report.repaired.len(), 2);
+        assert_eq!(
+            report.repaired[0].path,
+            vec![TEST_LEAF.to_vec(), b"inner".to_vec()]
+        );
+        db.commit_transaction(tx).unwrap().unwrap();
+        assert!(db.verify_grovedb().is_empty());
+
+        let tx = db.start_transaction();
+        let report = db
+            .repropagate([TEST_LEAF, b"inner"], &tx)
+            .unwrap()
+            .expect("should repropagate");
+        assert_eq!(report.checked, 2);
+        assert!(report.repaired.is_empty());
+    }
+}
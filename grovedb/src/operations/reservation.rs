@@ -0,0 +1,520 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Subtree reservations
+//! A subtree can be reserved ahead of the writes it is expected to receive
+//! with the number of elements and the size of values it will hold. The
+//! reservation of every subtree is kept in the meta storage under a key of its
+//! own, deletes only look them up once a reservation was set. They are turned
+//! into the layer
+//! information taken by the worst and average case cost calculators, so fees
+//! can be estimated from the expected shape rather than the current one.
+//! Subtrees share the column families of the storage, so a reservation doesn't
+//! change how the subtree is laid out on disk.
+
+#[cfg(feature = "full")]
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::atomic::Ordering,
+};
+
+#[cfg(feature = "full")]
+use costs::{cost_return_on_error, CostResult, CostsExt, OperationCost};
+#[cfg(feature = "full")]
+use merk::estimated_costs::{
+    average_case_costs::{
+        AverageKeySize, EstimatedLayerCount, EstimatedLayerInformation, EstimatedLayerSizes,
+    },
+    worst_case_costs::WorstCaseLayerInformation,
+};
+#[cfg(feature = "full")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "full")]
+use storage::{Storage, StorageBatch, StorageContext};
+
+#[cfg(feature = "full")]
+use crate::{
+    batch::{GroveDbOp, KeyInfoPath, Op},
+    operations::{
+        quota::is_below,
+        subtree_meta::{
+            delete_subtree_meta, get_subtree_meta, get_subtree_meta_paths, put_subtree_meta,
+            put_subtree_meta_paths, SubtreeMetaPath,
+        },
+    },
+    util::meta_storage_context_optional_tx,
+    Element, Error, GroveDb, TransactionArg,
+};
+
+#[cfg(feature = "full")]
+/// Meta storage key under which the paths of the reserved subtrees are kept,
+/// the key of the reservation of a subtree starts with it
+pub(crate) const SUBTREE_RESERVATIONS_KEY: &[u8] = b"subtree_reservations";
+
+#[cfg(feature = "full")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// Expected shape of a reserved subtree
+pub struct SubtreeReservation {
+    /// Number of elements the subtree is expected to hold
+    pub expected_elements: u32,
+    /// Size of the values the subtree is expected to hold
+    pub expected_value_size: u32,
+}
+
+#[cfg(feature = "full")]
+impl SubtreeReservation {
+    /// Worst case layer information of the reserved subtree
+    pub fn worst_case_layer_information(&self) -> WorstCaseLayerInformation {
+        WorstCaseLayerInformation::MaxElementsNumber(self.expected_elements)
+    }
+
+    /// Average case layer information of the reserved subtree, expected to
+    /// hold items
+    pub fn estimated_layer_information(
+        &self,
+        is_sum_tree: bool,
+        average_key_size: AverageKeySize,
+    ) -> EstimatedLayerInformation {
+        EstimatedLayerInformation {
+            is_sum_tree,
+            estimated_layer_count: EstimatedLayerCount::ApproximateElements(self.expected_elements),
+            estimated_layer_sizes: EstimatedLayerSizes::AllItems(
+                average_key_size,
+                self.expected_value_size,
+                None,
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+#[derive(Debug, Default)]
+/// Reservations changed by an operation, to be written with it
+pub(crate) struct SubtreeReservations {
+    /// Reservations set or replaced
+    entries: Vec<(SubtreeMetaPath, SubtreeReservation)>,
+    /// Reservations of deleted subtrees, going with them
+    removed: BTreeSet<SubtreeMetaPath>,
+    /// Paths of the reserved subtrees, when they changed
+    paths: Option<BTreeSet<SubtreeMetaPath>>,
+}
+
+#[cfg(feature = "full")]
+/// Puts the changed reservations, deletes the removed ones and updates the
+/// paths of the reserved subtrees if they changed
+fn put_reservations<'db, S: StorageContext<'db>>(
+    meta_storage: &S,
+    reservations: &SubtreeReservations,
+) -> CostResult<(), Error> {
+    let mut cost = OperationCost::default();
+    for (path, reservation) in reservations.entries.iter() {
+        cost_return_on_error!(
+            &mut cost,
+            put_subtree_meta(meta_storage, SUBTREE_RESERVATIONS_KEY, path, reservation)
+        );
+    }
+    for path in reservations.removed.iter() {
+        cost_return_on_error!(
+            &mut cost,
+            delete_subtree_meta(meta_storage, SUBTREE_RESERVATIONS_KEY, path)
+        );
+    }
+    if let Some(paths) = reservations.paths.as_ref() {
+        cost_return_on_error!(
+            &mut cost,
+            put_subtree_meta_paths(meta_storage, SUBTREE_RESERVATIONS_KEY, paths)
+        );
+    }
+    Ok(()).wrap_with_cost(cost)
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Reserves the subtree at the path for the expected number of elements
+    /// and value size, creating it as an empty tree if it doesn't exist yet.
+    /// Reserving a subtree again replaces its previous reservation.
+    pub fn reserve_subtree<'p, P>(
+        &self,
+        path: P,
+        expected_elements: u32,
+        expected_value_size: u32,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let mut cost = OperationCost::default();
+        let path: Vec<&[u8]> = path.into_iter().collect();
+
+        if let Some((key, parent_path)) = path.split_last() {
+            let created = cost_return_on_error!(
+                &mut cost,
+                self.insert_if_not_exists(
+                    parent_path.iter().copied(),
+                    key,
+                    Element::empty_tree(),
+                    transaction
+                )
+            );
+            if !created {
+                let element = cost_return_on_error!(
+                    &mut cost,
+                    self.get_raw(parent_path.iter().copied(), key, transaction)
+                );
+                if !element.is_tree() {
+                    return Err(Error::InvalidPath(
+                        "only subtrees can be reserved".to_owned(),
+                    ))
+                    .wrap_with_cost(cost);
+                }
+            }
+        }
+
+        let path: SubtreeMetaPath = path.iter().map(|segment| segment.to_vec()).collect();
+        let mut paths =
+            cost_return_on_error!(&mut cost, self.subtree_reservation_paths(transaction));
+        self.subtree_meta
+            .reservations
+            .store(true, Ordering::Relaxed);
+        let reservations = SubtreeReservations {
+            paths: paths.insert(path.clone()).then_some(paths),
+            entries: vec![(
+                path,
+                SubtreeReservation {
+                    expected_elements,
+                    expected_value_size,
+                },
+            )],
+            ..Default::default()
+        };
+        self.write_subtree_reservations(&reservations, transaction)
+            .add_cost(cost)
+    }
+
+    /// Returns the reservation of the subtree at the path
+    pub fn subtree_reservation<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> CostResult<Option<SubtreeReservation>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let mut cost = OperationCost::default();
+        if !self.subtree_meta.reservations.load(Ordering::Relaxed) {
+            return Ok(None).wrap_with_cost(cost);
+        }
+        let path: SubtreeMetaPath = path.into_iter().map(|segment| segment.to_vec()).collect();
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            let meta_storage = meta_storage.unwrap_add_cost(&mut cost);
+            get_subtree_meta(&meta_storage, SUBTREE_RESERVATIONS_KEY, &path)
+        })
+        .add_cost(cost)
+    }
+
+    /// Returns the worst case layer information of every reserved subtree, to
+    /// be used with
+    /// [`EstimatedCostsType::WorstCaseCostsType`](crate::batch::estimated_costs::EstimatedCostsType)
+    pub fn reserved_worst_case_layer_information(
+        &self,
+        transaction: TransactionArg,
+    ) -> CostResult<HashMap<KeyInfoPath, WorstCaseLayerInformation>, Error> {
+        let mut cost = OperationCost::default();
+        let paths = cost_return_on_error!(&mut cost, self.subtree_reservation_paths(transaction));
+        let mut layer_information = HashMap::new();
+        for path in paths {
+            let reservation: Option<SubtreeReservation> = cost_return_on_error!(
+                &mut cost,
+                self.subtree_reservation(
+                    path.iter().map(|segment| segment.as_slice()),
+                    transaction
+                )
+            );
+            if let Some(reservation) = reservation {
+                layer_information.insert(
+                    KeyInfoPath::from_known_owned_path(path),
+                    reservation.worst_case_layer_information(),
+                );
+            }
+        }
+        Ok(layer_information).wrap_with_cost(cost)
+    }
+
+    /// Returns the reservations left after deleting `key` under `path` if the
+    /// deletion removes any reservation
+    pub(crate) fn check_delete_reservations<'p, P>(
+        &self,
+        path: P,
+        key: &[u8],
+        transaction: TransactionArg,
+    ) -> CostResult<Option<SubtreeReservations>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let mut subtree_path: Vec<&[u8]> = path.into_iter().collect();
+        subtree_path.push(key);
+        self.remaining_reservations([subtree_path], transaction)
+    }
+
+    /// Returns the reservations left after the operations if they delete any
+    /// reserved subtree
+    pub(crate) fn check_ops_reservations(
+        &self,
+        ops: &[GroveDbOp],
+        transaction: TransactionArg,
+    ) -> CostResult<Option<SubtreeReservations>, Error> {
+        let deleted = ops
+            .iter()
+            .filter(|op| matches!(op.op, Op::Delete | Op::DeleteTree | Op::DeleteSumTree))
+            .map(|op| {
+                let mut subtree_path = op.path.to_path_refs();
+                subtree_path.push(op.key.as_slice());
+                subtree_path
+            });
+        self.remaining_reservations(deleted, transaction)
+    }
+
+    /// Reservations of deleted subtrees and their descendants go with them.
+    /// Nothing is read when no reservation was ever set.
+    fn remaining_reservations<'a, I>(
+        &self,
+        deleted: I,
+        transaction: TransactionArg,
+    ) -> CostResult<Option<SubtreeReservations>, Error>
+    where
+        I: IntoIterator<Item = Vec<&'a [u8]>>,
+    {
+        let mut cost = OperationCost::default();
+        let mut deleted = deleted.into_iter().peekable();
+        if !self.subtree_meta.reservations.load(Ordering::Relaxed) || deleted.peek().is_none() {
+            return Ok(None).wrap_with_cost(cost);
+        }
+        let mut paths =
+            cost_return_on_error!(&mut cost, self.subtree_reservation_paths(transaction));
+        let mut removed = BTreeSet::new();
+        for subtree_path in deleted {
+            removed.extend(
+                paths
+                    .iter()
+                    .filter(|reserved_path| is_below(&subtree_path, reserved_path))
+                    .cloned(),
+            );
+        }
+        if removed.is_empty() {
+            return Ok(None).wrap_with_cost(cost);
+        }
+        paths.retain(|reserved_path| !removed.contains(reserved_path));
+        Ok(Some(SubtreeReservations {
+            removed,
+            paths: Some(paths),
+            ..Default::default()
+        }))
+        .wrap_with_cost(cost)
+    }
+
+    /// Writes the reservations changed by an operation
+    pub(crate) fn write_subtree_reservations(
+        &self,
+        reservations: &SubtreeReservations,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            let meta_storage = meta_storage.unwrap_add_cost(&mut cost);
+            put_reservations(&meta_storage, reservations)
+        })
+        .add_cost(cost)
+    }
+
+    /// Adds writing the reservations changed by a batch to the storage batch,
+    /// so that they are committed with the rest of the batch
+    pub(crate) fn record_subtree_reservations(
+        &self,
+        reservations: &SubtreeReservations,
+        storage_batch: &StorageBatch,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+        if let Some(tx) = transaction {
+            let meta_storage = self
+                .db
+                .get_batch_transactional_storage_context(std::iter::empty(), storage_batch, tx)
+                .unwrap_add_cost(&mut cost);
+            put_reservations(&meta_storage, reservations)
+        } else {
+            let meta_storage = self
+                .db
+                .get_batch_storage_context(std::iter::empty(), storage_batch)
+                .unwrap_add_cost(&mut cost);
+            put_reservations(&meta_storage, reservations)
+        }
+        .add_cost(cost)
+    }
+
+    /// Reads the paths of the reserved subtrees
+    fn subtree_reservation_paths(
+        &self,
+        transaction: TransactionArg,
+    ) -> CostResult<BTreeSet<SubtreeMetaPath>, Error> {
+        let mut cost = OperationCost::default();
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            let meta_storage = meta_storage.unwrap_add_cost(&mut cost);
+            get_subtree_meta_paths(&meta_storage, SUBTREE_RESERVATIONS_KEY)
+        })
+        .add_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        operations::delete::DeleteOptions,
+        tests::{make_test_grovedb, TEST_LEAF},
+    };
+
+    #[test]
+    fn test_reserve_subtree_records_sizing_hints() {
+        let db = make_test_grovedb();
+        db.reserve_subtree([TEST_LEAF, b"reserved"], 10_000, 64, None)
+            .unwrap()
+            .expect("should reserve subtree");
+
+        assert!(db
+            .is_empty_tree([TEST_LEAF, b"reserved"], None)
+            .unwrap()
+            .expect("reserved subtree should exist"));
+        assert_eq!(
+            db.subtree_reservation([TEST_LEAF, b"reserved"], None)
+                .unwrap()
+                .expect("should get reservation"),
+            Some(SubtreeReservation {
+                expected_elements: 10_000,
+                expected_value_size: 64,
+            })
+        );
+        assert_eq!(
+            db.subtree_reservation([TEST_LEAF], None)
+                .unwrap()
+                .expect("should get reservation"),
+            None
+        );
+
+        let layer_information = db
+            .reserved_worst_case_layer_information(None)
+            .unwrap()
+            .expect("should get layer information");
+        assert_eq!(
+            layer_information.get(&KeyInfoPath::from_known_path([TEST_LEAF, b"reserved"])),
+            Some(&WorstCaseLayerInformation::MaxElementsNumber(10_000))
+        );
+
+        db.insert(
+            [TEST_LEAF],
+            b"item",
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+        assert!(matches!(
+            db.reserve_subtree([TEST_LEAF, b"item"], 1, 1, None)
+                .unwrap(),
+            Err(Error::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_deleting_subtrees_clears_their_reservations() {
+        let db = make_test_grovedb();
+        for path in [
+            vec![TEST_LEAF, b"reserved".as_slice()],
+            vec![TEST_LEAF, b"reserved", b"inner"],
+            vec![TEST_LEAF, b"batched"],
+            vec![TEST_LEAF, b"kept"],
+        ] {
+            db.reserve_subtree(path, 100, 32, None)
+                .unwrap()
+                .expect("should reserve subtree");
+        }
+
+        db.delete(
+            [TEST_LEAF],
+            b"reserved",
+            Some(DeleteOptions {
+                allow_deleting_non_empty_trees: true,
+                deleting_non_empty_trees_returns_error: false,
+                ..Default::default()
+            }),
+            None,
+        )
+        .unwrap()
+        .expect("should delete reserved subtree");
+        for path in [
+            vec![TEST_LEAF, b"reserved".as_slice()],
+            vec![TEST_LEAF, b"reserved", b"inner"],
+        ] {
+            assert_eq!(
+                db.subtree_reservation(path, None)
+                    .unwrap()
+                    .expect("should get reservation"),
+                None
+            );
+        }
+
+        db.apply_batch(
+            vec![GroveDbOp::delete_tree_op(
+                vec![TEST_LEAF.to_vec()],
+                b"batched".to_vec(),
+                false,
+            )],
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should delete reserved subtree in a batch");
+        assert_eq!(
+            db.subtree_reservation([TEST_LEAF, b"batched"], None)
+                .unwrap()
+                .expect("should get reservation"),
+            None
+        );
+
+        assert_eq!(
+            db.subtree_reservation([TEST_LEAF, b"kept"], None)
+                .unwrap()
+                .expect("should get reservation"),
+            Some(SubtreeReservation {
+                expected_elements: 100,
+                expected_value_size: 32,
+            })
+        );
+    }
+}
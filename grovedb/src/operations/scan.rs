@@ -0,0 +1,185 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Scans
+//! Iteration over every element of a subtree for analytics workloads. Scans
+//! read ahead sequentially and bypass the block cache, so going through a big
+//! subtree doesn't evict the blocks hot for consensus reads.
+
+#[cfg(feature = "full")]
+use costs::{
+    cost_return_on_error, cost_return_on_error_no_add, CostResult, CostsExt, OperationCost,
+};
+#[cfg(feature = "full")]
+use merk::KVIterator;
+#[cfg(feature = "full")]
+use storage::{RawIterator, Storage};
+
+#[cfg(feature = "full")]
+use crate::{element::helpers::raw_decode, Element, Error, GroveDb, Query, TransactionArg};
+
+#[cfg(feature = "full")]
+/// Default number of bytes read ahead by subtree scans
+pub const DEFAULT_SCAN_READAHEAD_SIZE: usize = 2 * 1024 * 1024;
+
+#[cfg(feature = "full")]
+/// Subtree scan options
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Number of bytes read ahead from disk
+    pub readahead_size: usize,
+    /// Scan from the smallest key to the largest
+    pub left_to_right: bool,
+}
+
+#[cfg(feature = "full")]
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            readahead_size: DEFAULT_SCAN_READAHEAD_SIZE,
+            left_to_right: true,
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Scans the elements of the subtree at `path` in key order, calling
+    /// `visit` with every key and element until it returns false
+    pub fn scan_subtree<'p, P, F>(
+        &self,
+        path: P,
+        options: ScanOptions,
+        transaction: TransactionArg,
+        mut visit: F,
+    ) -> CostResult<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+        F: FnMut(&[u8], Element) -> bool,
+    {
+        let mut cost = OperationCost::default();
+
+        let path_iter = path.into_iter();
+        cost_return_on_error!(
+            &mut cost,
+            self.check_subtree_exists_path_not_found(path_iter.clone(), transaction)
+        );
+
+        let mut query = Query::new_with_direction(options.left_to_right);
+        query.insert_all();
+        if let Some(tx) = transaction {
+            let storage = self
+                .db
+                .get_transactional_storage_context(path_iter, tx)
+                .unwrap_add_cost(&mut cost);
+            scan(
+                storage.scan_iter(options.readahead_size),
+                &query,
+                &mut visit,
+            )
+            .add_cost(cost)
+        } else {
+            let storage = self
+                .db
+                .get_storage_context(path_iter)
+                .unwrap_add_cost(&mut cost);
+            scan(
+                storage.scan_iter(options.readahead_size),
+                &query,
+                &mut visit,
+            )
+            .add_cost(cost)
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+fn scan<I, F>(raw_iter: I, query: &Query, visit: &mut F) -> CostResult<(), Error>
+where
+    I: RawIterator,
+    F: FnMut(&[u8], Element) -> bool,
+{
+    let mut cost = OperationCost::default();
+
+    let mut kv_iterator = KVIterator::new(raw_iter, query).unwrap_add_cost(&mut cost);
+    while let Some((key, value_bytes)) = kv_iterator.next_kv().unwrap_add_cost(&mut cost) {
+        let element = cost_return_on_error_no_add!(&cost, raw_decode(&value_bytes));
+        if !visit(&key, element) {
+            break;
+        }
+    }
+
+    Ok(()).wrap_with_cost(cost)
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{make_test_grovedb, TEST_LEAF};
+
+    #[test]
+    fn test_scan_subtree() {
+        let db = make_test_grovedb();
+        for i in 0u8..10 {
+            db.insert([TEST_LEAF], &[i], Element::new_item(vec![i]), None, None)
+                .unwrap()
+                .expect("should insert item");
+        }
+
+        let mut keys = vec![];
+        db.scan_subtree(
+            [TEST_LEAF],
+            ScanOptions {
+                left_to_right: false,
+                ..Default::default()
+            },
+            None,
+            |key, element| {
+                assert_eq!(element, Element::new_item(key.to_vec()));
+                keys.push(key[0]);
+                keys.len() < 4
+            },
+        )
+        .unwrap()
+        .expect("should scan subtree");
+        assert_eq!(keys, vec![9, 8, 7, 6]);
+
+        assert!(matches!(
+            db.scan_subtree(
+                [b"missing".as_ref()],
+                ScanOptions::default(),
+                None,
+                |_, _| true
+            )
+            .unwrap(),
+            Err(Error::PathNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,288 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! State manifests
+//! A state manifest lists the root hash, the keys of the root tree and every
+//! subtree path with its root hash, taken at a consistent state and
+//! serialized deterministically. It anchors external audits and chunked sync
+//! manifests: a single digest commits to the layout of the whole database.
+
+#[cfg(feature = "full")]
+use bincode::Options;
+#[cfg(feature = "full")]
+use costs::{cost_return_on_error, CostResult, CostsExt, OperationCost};
+#[cfg(feature = "full")]
+use merk::{tree::value_hash, CryptoHash};
+#[cfg(feature = "full")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "full")]
+use crate::{util::merk_optional_tx, Element, Error, GroveDb, TransactionArg};
+
+#[cfg(feature = "full")]
+/// Number of times the manifest is taken again when the database changed
+/// while it was being taken
+pub const STATE_MANIFEST_ATTEMPTS: usize = 8;
+
+#[cfg(feature = "full")]
+/// Root hash, root tree keys and subtree root hashes of a database
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateManifest {
+    /// Root hash of the database
+    pub root_hash: CryptoHash,
+    /// Keys of the root tree in key order
+    pub leaf_keys: Vec<Vec<u8>>,
+    /// Path and root hash of every subtree, the root tree included, in path
+    /// order
+    pub subtrees: Vec<(Vec<Vec<u8>>, CryptoHash)>,
+}
+
+#[cfg(feature = "full")]
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::default()
+        .with_varint_encoding()
+        .reject_trailing_bytes()
+}
+
+#[cfg(feature = "full")]
+impl StateManifest {
+    /// Deterministic serialization of the manifest
+    pub fn serialize(&self) -> Result<Vec<u8>, Error> {
+        bincode_options()
+            .serialize(self)
+            .map_err(|_| Error::CorruptedData("unable to serialize state manifest".to_owned()))
+    }
+
+    /// Deserializes a manifest
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        bincode_options()
+            .deserialize(bytes)
+            .map_err(|_| Error::CorruptedData("unable to deserialize state manifest".to_owned()))
+    }
+
+    /// Hash of the serialized manifest
+    pub fn digest(&self) -> CostResult<CryptoHash, Error> {
+        match self.serialize() {
+            Ok(bytes) => value_hash(&bytes).map(Ok),
+            Err(e) => Err(e).wrap_with_cost(OperationCost::default()),
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Returns the root hash of the subtree at the path
+    pub fn subtree_root_hash<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> CostResult<CryptoHash, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+    {
+        let mut cost = OperationCost::default();
+
+        let mut path_iter = path.into_iter().peekable();
+        cost_return_on_error!(
+            &mut cost,
+            self.check_subtree_exists_path_not_found(path_iter.clone(), transaction)
+        );
+        merk_optional_tx!(&mut cost, self.db, path_iter, transaction, subtree, {
+            Ok(subtree.root_hash().unwrap_add_cost(&mut cost)).wrap_with_cost(cost)
+        })
+    }
+
+    /// Takes the state manifest of the database. Outside of a transaction,
+    /// writes landing while it is taken are detected by the root hash
+    /// changing, in which case it is taken again. Within a transaction,
+    /// pending deferred propagations are propagated first.
+    pub fn state_manifest(&self, transaction: TransactionArg) -> CostResult<StateManifest, Error> {
+        let mut cost = OperationCost::default();
+
+        if let Some(tx) = transaction {
+            cost_return_on_error!(&mut cost, self.propagate_pending_changes(tx));
+        }
+
+        for _ in 0..STATE_MANIFEST_ATTEMPTS {
+            let manifest = cost_return_on_error!(&mut cost, self.take_state_manifest(transaction));
+            let root_hash = cost_return_on_error!(&mut cost, self.root_hash(transaction));
+            if root_hash == manifest.root_hash {
+                return Ok(manifest).wrap_with_cost(cost);
+            }
+        }
+        Err(Error::InternalError(
+            "database kept changing while taking the state manifest",
+        ))
+        .wrap_with_cost(cost)
+    }
+
+    fn take_state_manifest(&self, transaction: TransactionArg) -> CostResult<StateManifest, Error> {
+        let mut cost = OperationCost::default();
+
+        let root_hash = cost_return_on_error!(&mut cost, self.root_hash(transaction));
+        let mut paths = cost_return_on_error!(&mut cost, self.find_subtrees([], transaction));
+        paths.sort();
+
+        let mut subtrees = Vec::with_capacity(paths.len());
+        for path in paths {
+            let subtree_root_hash = cost_return_on_error!(
+                &mut cost,
+                self.subtree_root_hash(path.iter().map(|segment| segment.as_slice()), transaction)
+            );
+            subtrees.push((path, subtree_root_hash));
+        }
+        let leaf_keys = subtrees
+            .iter()
+            .filter(|(path, _)| path.len() == 1)
+            .map(|(path, _)| path[0].clone())
+            .collect();
+
+        Ok(StateManifest {
+            root_hash,
+            leaf_keys,
+            subtrees,
+        })
+        .wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{make_test_grovedb, ANOTHER_TEST_LEAF, TEST_LEAF};
+
+    #[test]
+    fn test_state_manifest() {
+        let db = make_test_grovedb();
+        db.insert([TEST_LEAF], b"nested", Element::empty_tree(), None, None)
+            .unwrap()
+            .expect("should insert subtree");
+        db.insert(
+            [TEST_LEAF, b"nested"],
+            b"key",
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+
+        let manifest = db
+            .state_manifest(None)
+            .unwrap()
+            .expect("should take state manifest");
+        assert_eq!(manifest.root_hash, db.root_hash(None).unwrap().unwrap());
+        assert_eq!(
+            manifest.leaf_keys,
+            vec![TEST_LEAF.to_vec(), ANOTHER_TEST_LEAF.to_vec()]
+        );
+        let paths: Vec<Vec<Vec<u8>>> = manifest
+            .subtrees
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                vec![],
+                vec![TEST_LEAF.to_vec()],
+                vec![TEST_LEAF.to_vec(), b"nested".to_vec()],
+                vec![ANOTHER_TEST_LEAF.to_vec()],
+            ]
+        );
+        assert_eq!(manifest.subtrees[0].1, manifest.root_hash);
+        assert_eq!(
+            manifest.subtrees[2].1,
+            db.subtree_root_hash([TEST_LEAF, b"nested"], None)
+                .unwrap()
+                .expect("should get subtree root hash")
+        );
+
+        let bytes = manifest.serialize().expect("should serialize manifest");
+        assert_eq!(
+            StateManifest::deserialize(&bytes).expect("should deserialize manifest"),
+            manifest
+        );
+        let digest = manifest.digest().unwrap().expect("should digest manifest");
+
+        db.insert(
+            [ANOTHER_TEST_LEAF],
+            b"key",
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+        let changed = db
+            .state_manifest(None)
+            .unwrap()
+            .expect("should take state manifest");
+        assert_ne!(changed.digest().unwrap().unwrap(), digest);
+    }
+
+    #[test]
+    fn test_state_manifest_propagates_pending_changes() {
+        let db = make_test_grovedb();
+        db.set_eager_propagation(false);
+        let tx = db.start_transaction();
+        db.insert(
+            [TEST_LEAF],
+            b"nested",
+            Element::empty_tree(),
+            None,
+            Some(&tx),
+        )
+        .unwrap()
+        .expect("should insert subtree");
+        db.insert(
+            [TEST_LEAF, b"nested"],
+            b"key",
+            Element::new_item(b"value".to_vec()),
+            None,
+            Some(&tx),
+        )
+        .unwrap()
+        .expect("should insert item");
+
+        let manifest = db
+            .state_manifest(Some(&tx))
+            .unwrap()
+            .expect("should take state manifest");
+        assert!(!db.has_pending_propagations(&tx));
+        db.commit_transaction(tx).unwrap().expect("should commit");
+        assert_eq!(
+            manifest,
+            db.state_manifest(None)
+                .unwrap()
+                .expect("should take state manifest")
+        );
+    }
+}
@@ -0,0 +1,261 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Strict mode
+//! In strict mode inserting over an existing element fails with
+//! `Error::OverrideNotAllowed` unless the caller states the intent to
+//! overwrite, either with `replace`, the `overwrite` insert option or replace
+//! operations in batches. This catches accidental clobbering of data in
+//! application code.
+
+#[cfg(feature = "full")]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(feature = "full")]
+use costs::{CostResult, CostsExt, OperationCost};
+
+#[cfg(feature = "full")]
+use crate::{
+    batch::{GroveDbOp, Op},
+    operations::insert::InsertOptions,
+    Element, Error, GroveDb, TransactionArg,
+};
+
+#[cfg(feature = "full")]
+#[derive(Default)]
+/// Strict mode setting of a GroveDb instance
+pub(crate) struct StrictMode {
+    enabled: AtomicBool,
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Turns strict mode on or off, in strict mode inserts may only overwrite
+    /// existing elements when asked to explicitly
+    pub fn set_strict_mode(&self, strict: bool) {
+        self.strict.enabled.store(strict, Ordering::Relaxed);
+    }
+
+    /// Returns true if strict mode is on
+    pub fn is_strict_mode(&self) -> bool {
+        self.strict.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Inserts the element, overwriting the existing one if there is any,
+    /// also in strict mode
+    pub fn replace<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        element: Element,
+        options: Option<InsertOptions>,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: ExactSizeIterator + DoubleEndedIterator + Clone,
+    {
+        let options = InsertOptions {
+            overwrite: true,
+            ..options.unwrap_or_default()
+        };
+        self.insert(path, key, element, Some(options), transaction)
+    }
+
+    /// Makes inserts without overwrite intent fail on existing elements when
+    /// in strict mode
+    pub(crate) fn apply_strict_mode(&self, options: InsertOptions) -> InsertOptions {
+        if self.is_strict_mode() && !options.overwrite {
+            InsertOptions {
+                validate_insertion_does_not_override: true,
+                ..options
+            }
+        } else {
+            options
+        }
+    }
+
+    /// Fails in strict mode if one of the insert operations would overwrite
+    /// an existing element, replace operations are allowed to
+    pub(crate) fn check_ops_overwrite_intent(
+        &self,
+        ops: &[GroveDbOp],
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+
+        if !self.is_strict_mode() {
+            return Ok(()).wrap_with_cost(cost);
+        }
+        for op in ops.iter().filter(|op| matches!(op.op, Op::Insert { .. })) {
+            // subtrees created by the batch itself don't exist yet
+            match self
+                .get_raw_optional(op.path.to_path_refs(), op.key.as_slice(), transaction)
+                .unwrap_add_cost(&mut cost)
+            {
+                Ok(Some(_)) => {
+                    return Err(Error::OverrideNotAllowed(
+                        "insertion not allowed to override in strict mode",
+                    ))
+                    .wrap_with_cost(cost)
+                }
+                Ok(None)
+                | Err(Error::PathNotFound(_))
+                | Err(Error::PathParentLayerNotFound(_))
+                | Err(Error::InvalidParentLayerPath(_)) => {}
+                Err(e) => return Err(e).wrap_with_cost(cost),
+            }
+        }
+        Ok(()).wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{make_test_grovedb, TEST_LEAF};
+
+    #[test]
+    fn test_strict_mode_rejects_implicit_overwrites() {
+        let db = make_test_grovedb();
+        db.insert(
+            [TEST_LEAF],
+            b"key",
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+        db.set_strict_mode(true);
+
+        assert!(matches!(
+            db.insert(
+                [TEST_LEAF],
+                b"key",
+                Element::new_item(b"other".to_vec()),
+                None,
+                None,
+            )
+            .unwrap(),
+            Err(Error::OverrideNotAllowed(_))
+        ));
+        db.insert(
+            [TEST_LEAF],
+            b"new_key",
+            Element::new_item(b"value".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert new item");
+        db.replace(
+            [TEST_LEAF],
+            b"key",
+            Element::new_item(b"replaced".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should replace item");
+        db.insert(
+            [TEST_LEAF],
+            b"key",
+            Element::new_item(b"overwritten".to_vec()),
+            Some(InsertOptions {
+                overwrite: true,
+                ..Default::default()
+            }),
+            None,
+        )
+        .unwrap()
+        .expect("should overwrite item");
+        assert_eq!(
+            db.get([TEST_LEAF], b"key", None)
+                .unwrap()
+                .expect("should get item"),
+            Element::new_item(b"overwritten".to_vec())
+        );
+
+        let path = vec![TEST_LEAF.to_vec()];
+        assert!(matches!(
+            db.apply_batch(
+                vec![GroveDbOp::insert_op(
+                    path.clone(),
+                    b"key".to_vec(),
+                    Element::new_item(b"batch".to_vec())
+                )],
+                None,
+                None
+            )
+            .unwrap(),
+            Err(Error::OverrideNotAllowed(_))
+        ));
+        db.apply_batch(
+            vec![
+                GroveDbOp::replace_op(
+                    path.clone(),
+                    b"key".to_vec(),
+                    Element::new_item(b"batch".to_vec()),
+                ),
+                GroveDbOp::insert_op(path.clone(), b"tree".to_vec(), Element::empty_tree()),
+                GroveDbOp::insert_op(
+                    vec![TEST_LEAF.to_vec(), b"tree".to_vec()],
+                    b"key".to_vec(),
+                    Element::new_item(b"nested".to_vec()),
+                ),
+                GroveDbOp::insert_op(
+                    vec![TEST_LEAF.to_vec(), b"tree".to_vec()],
+                    b"inner".to_vec(),
+                    Element::empty_tree(),
+                ),
+                GroveDbOp::insert_op(
+                    vec![TEST_LEAF.to_vec(), b"tree".to_vec(), b"inner".to_vec()],
+                    b"key".to_vec(),
+                    Element::new_item(b"deeply nested".to_vec()),
+                ),
+            ],
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should apply batch with overwrite intent");
+
+        db.set_strict_mode(false);
+        db.insert(
+            [TEST_LEAF],
+            b"key",
+            Element::new_item(b"lenient".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should overwrite outside of strict mode");
+    }
+}
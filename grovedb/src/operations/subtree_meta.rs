@@ -0,0 +1,294 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Per subtree metadata
+//! Metadata set on a few subtrees only, like quotas and reservations, is kept
+//! under one meta storage key per subtree, made of the key of its kind and the
+//! path of the subtree, so an operation reads and writes only the entries of
+//! the subtrees it touches. The paths having an entry are listed under the key
+//! of the kind itself, to find the entries of deleted subtrees. Whether a kind
+//! has any entry is kept in memory, so operations of a database where it was
+//! never used don't read the meta storage at all.
+
+#[cfg(feature = "full")]
+use std::{
+    collections::BTreeSet,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+#[cfg(feature = "full")]
+use bincode::Options;
+#[cfg(feature = "full")]
+use costs::{
+    cost_return_on_error, cost_return_on_error_no_add, CostResult, CostsExt, OperationCost,
+};
+#[cfg(feature = "full")]
+use integer_encoding::VarInt;
+#[cfg(feature = "full")]
+use serde::{de::DeserializeOwned, Serialize};
+#[cfg(feature = "full")]
+use storage::StorageContext;
+
+#[cfg(feature = "full")]
+use crate::{
+    operations::{
+        metadata::{get_internal_meta, put_internal_meta},
+        quota::SUBTREE_QUOTAS_KEY,
+        read_only::READ_ONLY_SUBTREES_KEY,
+        reservation::SUBTREE_RESERVATIONS_KEY,
+    },
+    Error,
+};
+
+#[cfg(feature = "full")]
+/// Separates the key of a kind of per subtree metadata from the path of the
+/// subtree in the key of an entry
+pub(crate) const SUBTREE_META_SEPARATOR: u8 = b'/';
+
+#[cfg(feature = "full")]
+/// Keys of the kinds of per subtree metadata
+pub(crate) const SUBTREE_META_KINDS: [&[u8]; 2] = [SUBTREE_QUOTAS_KEY, SUBTREE_RESERVATIONS_KEY];
+
+#[cfg(feature = "full")]
+/// Path of a subtree having an entry
+pub(crate) type SubtreeMetaPath = Vec<Vec<u8>>;
+
+#[cfg(feature = "full")]
+#[derive(Default)]
+/// Kinds of per subtree metadata in use, read only subtrees included, set
+/// when an entry is found on open
+/// or added later. A flag is not cleared when the last entry goes, until the
+/// database is opened again.
+pub(crate) struct SubtreeMetaFlags {
+    /// Subtree quotas may be set
+    pub(crate) quotas: AtomicBool,
+    /// Subtree reservations may be set
+    pub(crate) reservations: AtomicBool,
+    /// Subtrees may be read only
+    pub(crate) read_only: AtomicBool,
+}
+
+#[cfg(feature = "full")]
+impl SubtreeMetaFlags {
+    /// Sets the flags of the kinds having entries in the meta storage
+    pub(crate) fn load<'db, S: StorageContext<'db>>(
+        &self,
+        meta_storage: &S,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+        for (kind_key, flag) in [
+            (SUBTREE_QUOTAS_KEY, &self.quotas),
+            (SUBTREE_RESERVATIONS_KEY, &self.reservations),
+        ] {
+            let paths =
+                cost_return_on_error!(&mut cost, get_subtree_meta_paths(meta_storage, kind_key));
+            if !paths.is_empty() {
+                flag.store(true, Ordering::Relaxed);
+            }
+        }
+        let read_only = cost_return_on_error!(
+            &mut cost,
+            get_internal_meta(meta_storage, READ_ONLY_SUBTREES_KEY)
+        );
+        if read_only.is_some() {
+            self.read_only.store(true, Ordering::Relaxed);
+        }
+        Ok(()).wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::default()
+        .with_varint_encoding()
+        .reject_trailing_bytes()
+}
+
+#[cfg(feature = "full")]
+/// Meta storage key of the entry of the subtree at `path`, every segment is
+/// prefixed with its length so distinct paths never share a key
+pub(crate) fn subtree_meta_key(kind_key: &[u8], path: &[Vec<u8>]) -> Vec<u8> {
+    let mut key = kind_key.to_vec();
+    key.push(SUBTREE_META_SEPARATOR);
+    for segment in path {
+        key.extend(segment.len().encode_var_vec());
+        key.extend_from_slice(segment);
+    }
+    key
+}
+
+#[cfg(feature = "full")]
+/// Whether the meta storage key is the key of a per subtree entry
+pub(crate) fn is_subtree_meta_key(key: &[u8]) -> bool {
+    SUBTREE_META_KINDS.iter().any(|kind_key| {
+        key.starts_with(kind_key) && key.get(kind_key.len()) == Some(&SUBTREE_META_SEPARATOR)
+    })
+}
+
+#[cfg(feature = "full")]
+/// Reads the paths of the subtrees having an entry of the kind
+pub(crate) fn get_subtree_meta_paths<'db, S: StorageContext<'db>>(
+    meta_storage: &S,
+    kind_key: &[u8],
+) -> CostResult<BTreeSet<SubtreeMetaPath>, Error> {
+    let mut cost = OperationCost::default();
+    let maybe_bytes = cost_return_on_error!(&mut cost, get_internal_meta(meta_storage, kind_key));
+    match maybe_bytes {
+        Some(bytes) => bincode_options().deserialize(&bytes).map_err(|_| {
+            Error::CorruptedData(format!(
+                "paths of {} are corrupted",
+                String::from_utf8_lossy(kind_key)
+            ))
+        }),
+        None => Ok(BTreeSet::new()),
+    }
+    .wrap_with_cost(cost)
+}
+
+#[cfg(feature = "full")]
+/// Puts the paths of the subtrees having an entry of the kind, or deletes
+/// them when none is left
+pub(crate) fn put_subtree_meta_paths<'db, S: StorageContext<'db>>(
+    meta_storage: &S,
+    kind_key: &[u8],
+    paths: &BTreeSet<SubtreeMetaPath>,
+) -> CostResult<(), Error> {
+    if paths.is_empty() {
+        return meta_storage
+            .delete_meta(kind_key, None)
+            .map_err(Error::StorageError);
+    }
+    let cost = OperationCost::default();
+    let bytes = cost_return_on_error_no_add!(
+        &cost,
+        bincode_options().serialize(paths).map_err(|_| {
+            Error::CorruptedData(format!(
+                "unable to serialize paths of {}",
+                String::from_utf8_lossy(kind_key)
+            ))
+        })
+    );
+    put_internal_meta(meta_storage, kind_key, bytes)
+}
+
+#[cfg(feature = "full")]
+/// Reads the entry of the kind of the subtree at `path`
+pub(crate) fn get_subtree_meta<'db, S: StorageContext<'db>, T: DeserializeOwned>(
+    meta_storage: &S,
+    kind_key: &[u8],
+    path: &[Vec<u8>],
+) -> CostResult<Option<T>, Error> {
+    let mut cost = OperationCost::default();
+    let key = subtree_meta_key(kind_key, path);
+    let maybe_bytes = cost_return_on_error!(&mut cost, get_internal_meta(meta_storage, &key));
+    maybe_bytes
+        .map(|bytes| {
+            bincode_options().deserialize(&bytes).map_err(|_| {
+                Error::CorruptedData(format!(
+                    "entry of {} is corrupted",
+                    String::from_utf8_lossy(kind_key)
+                ))
+            })
+        })
+        .transpose()
+        .wrap_with_cost(cost)
+}
+
+#[cfg(feature = "full")]
+/// Puts the entry of the kind of the subtree at `path`
+pub(crate) fn put_subtree_meta<'db, S: StorageContext<'db>, T: Serialize>(
+    meta_storage: &S,
+    kind_key: &[u8],
+    path: &[Vec<u8>],
+    value: &T,
+) -> CostResult<(), Error> {
+    let cost = OperationCost::default();
+    let bytes = cost_return_on_error_no_add!(
+        &cost,
+        bincode_options().serialize(value).map_err(|_| {
+            Error::CorruptedData(format!(
+                "unable to serialize entry of {}",
+                String::from_utf8_lossy(kind_key)
+            ))
+        })
+    );
+    put_internal_meta(meta_storage, &subtree_meta_key(kind_key, path), bytes)
+}
+
+#[cfg(feature = "full")]
+/// Deletes the entry of the kind of the subtree at `path`
+pub(crate) fn delete_subtree_meta<'db, S: StorageContext<'db>>(
+    meta_storage: &S,
+    kind_key: &[u8],
+    path: &[Vec<u8>],
+) -> CostResult<(), Error> {
+    meta_storage
+        .delete_meta(subtree_meta_key(kind_key, path), None)
+        .map_err(Error::StorageError)
+}
+
+#[cfg(feature = "full")]
+/// Checks the integrity hash of the paths of the kind and of every entry
+pub(crate) fn verify_subtree_meta<'db, S: StorageContext<'db>>(
+    meta_storage: &S,
+    kind_key: &[u8],
+) -> CostResult<(), Error> {
+    let mut cost = OperationCost::default();
+    let paths = cost_return_on_error!(&mut cost, get_subtree_meta_paths(meta_storage, kind_key));
+    for path in paths {
+        cost_return_on_error!(
+            &mut cost,
+            get_internal_meta(meta_storage, &subtree_meta_key(kind_key, &path))
+        );
+    }
+    Ok(()).wrap_with_cost(cost)
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subtree_meta_keys_are_distinct() {
+        let key = |path: &[&[u8]]| {
+            subtree_meta_key(
+                SUBTREE_QUOTAS_KEY,
+                &path
+                    .iter()
+                    .map(|segment| segment.to_vec())
+                    .collect::<Vec<_>>(),
+            )
+        };
+        assert_ne!(key(&[b"ab", b"c"]), key(&[b"a", b"bc"]));
+        assert_ne!(key(&[b"ab"]), key(&[b"a", b"b"]));
+        assert_ne!(key(&[]), key(&[b""]));
+        assert!(is_subtree_meta_key(&key(&[b"a"])));
+        assert!(!is_subtree_meta_key(SUBTREE_QUOTAS_KEY));
+    }
+}
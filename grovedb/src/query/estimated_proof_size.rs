@@ -0,0 +1,262 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Estimated proof size of a path query
+
+use merk::{
+    estimated_costs::average_case_costs::{EstimatedLayerInformation, EstimatedLayerSizes},
+    proofs::{query::SubqueryBranch, Query},
+    tree::HASH_LENGTH,
+};
+
+use crate::{Error, PathQuery};
+
+/// Size of an encoded proof node holding a key, a value and a value hash,
+/// not counting the key and the value themselves
+const KV_NODE_OVERHEAD: u32 = 4 + HASH_LENGTH as u32;
+
+/// Size of an encoded hash node
+const HASH_NODE_SIZE: u32 = 1 + HASH_LENGTH as u32;
+
+/// Proof token type and a varint length prefix of a merk proof
+const MERK_PROOF_HEADER_SIZE: u32 = 1 + 9;
+
+/// Size of a tree element value not counting its root key: enum, option,
+/// key length, sum and flags length
+const TREE_VALUE_OVERHEAD: u32 = 4 + 9;
+
+impl PathQuery {
+    /// Returns an upper bound on the size in bytes of a proof for this path
+    /// query without touching the storage.
+    /// `layer_info` describes subtrees by their depth: the first element is
+    /// for the root tree, the second is for subtrees right under the root and
+    /// so on, it must cover every layer the path query goes through.
+    pub fn estimated_proof_size(
+        &self,
+        layer_info: &[EstimatedLayerInformation],
+    ) -> Result<u64, Error> {
+        let mut size: u64 = 0;
+
+        // The path is proved with a single key proof on each layer
+        for depth in 0..self.path.len() {
+            size = size.saturating_add(merk_proof_size_bound(layer_info, depth, 1)?);
+        }
+
+        size = size.saturating_add(query_proof_size_bound(
+            &self.query.query,
+            self.query.limit,
+            self.query.offset,
+            self.path.len(),
+            layer_info,
+        )?);
+
+        Ok(size)
+    }
+}
+
+/// Upper bound of a proof of the query applied to a subtree at the depth,
+/// including its subqueries
+fn query_proof_size_bound(
+    query: &Query,
+    limit: Option<u16>,
+    offset: Option<u16>,
+    depth: usize,
+    layer_info: &[EstimatedLayerInformation],
+) -> Result<u64, Error> {
+    let layer = layer_at(layer_info, depth)?;
+    let max_elements = max_elements_count(layer);
+
+    let mut results = query
+        .items
+        .iter()
+        .fold(0u64, |acc, item| {
+            acc.saturating_add(if item.is_key() { 1 } else { max_elements })
+        })
+        .min(max_elements);
+    // Subtrees with subqueries are proved without limit and offset, only leaf
+    // subtrees are narrowed by them, skipped elements being proved as well
+    if let (Some(limit), false) = (limit, query.has_subquery()) {
+        results = results.min(limit as u64 + offset.unwrap_or_default() as u64);
+    }
+
+    let mut size = merk_proof_size_bound(layer_info, depth, results)?;
+
+    // Every result may be a subtree queried by the heaviest subquery branch
+    let mut subquery_size = subquery_branch_size_bound(
+        &query.default_subquery_branch,
+        limit,
+        offset,
+        depth + 1,
+        layer_info,
+    )?;
+    if let Some(conditional_branches) = &query.conditional_subquery_branches {
+        for branch in conditional_branches.values() {
+            subquery_size = subquery_size.max(subquery_branch_size_bound(
+                branch,
+                limit,
+                offset,
+                depth + 1,
+                layer_info,
+            )?);
+        }
+    }
+    size = size.saturating_add(results.saturating_mul(subquery_size));
+
+    Ok(size)
+}
+
+/// Upper bound of proofs produced by a subquery branch applied to a subtree
+/// at the depth
+fn subquery_branch_size_bound(
+    branch: &SubqueryBranch,
+    limit: Option<u16>,
+    offset: Option<u16>,
+    mut depth: usize,
+    layer_info: &[EstimatedLayerInformation],
+) -> Result<u64, Error> {
+    let mut size: u64 = 0;
+    match (&branch.subquery_path, &branch.subquery) {
+        (None, None) => {}
+        (Some(subquery_path), None) => {
+            // The last key of a subquery path becomes a single key query
+            for _ in subquery_path.iter() {
+                size = size.saturating_add(merk_proof_size_bound(layer_info, depth, 1)?);
+                depth += 1;
+            }
+        }
+        (subquery_path, Some(subquery)) => {
+            for _ in subquery_path.iter().flatten() {
+                size = size.saturating_add(merk_proof_size_bound(layer_info, depth, 1)?);
+                depth += 1;
+            }
+            size = size.saturating_add(query_proof_size_bound(
+                subquery, limit, offset, depth, layer_info,
+            )?);
+        }
+    }
+    Ok(size)
+}
+
+/// Upper bound of a single merk proof for the number of results in a subtree
+/// at the depth: every result and both boundaries come with a path to the
+/// root of the subtree and sibling hashes along the way
+fn merk_proof_size_bound(
+    layer_info: &[EstimatedLayerInformation],
+    depth: usize,
+    results: u64,
+) -> Result<u64, Error> {
+    let layer = layer_at(layer_info, depth)?;
+    let levels = layer.estimated_layer_count.estimate_levels() as u64;
+    if layer.estimated_layer_count.estimated_to_be_empty() || levels == 0 {
+        // An empty tree is proved with a single token
+        return Ok(1);
+    }
+
+    let child_key_size = layer_info
+        .get(depth + 1)
+        .map(|child| max_key_size(&child.estimated_layer_sizes))
+        .unwrap_or(u8::MAX as u32);
+    let node_size = KV_NODE_OVERHEAD
+        + max_key_size(&layer.estimated_layer_sizes)
+        + max_value_size(&layer.estimated_layer_sizes, child_key_size);
+    // Each visited node is pushed along with a sibling hash and two ops
+    let visited_node_size = (node_size + HASH_NODE_SIZE + 2) as u64;
+
+    Ok(results
+        .saturating_add(2)
+        .saturating_mul(levels)
+        .saturating_mul(visited_node_size)
+        .saturating_add(MERK_PROOF_HEADER_SIZE as u64))
+}
+
+fn layer_at(
+    layer_info: &[EstimatedLayerInformation],
+    depth: usize,
+) -> Result<&EstimatedLayerInformation, Error> {
+    layer_info.get(depth).ok_or(Error::InvalidInput(
+        "estimated layer information doesn't cover every layer of the path query",
+    ))
+}
+
+/// Max number of elements in a balanced tree of estimated height
+fn max_elements_count(layer: &EstimatedLayerInformation) -> u64 {
+    let levels = layer.estimated_layer_count.estimate_levels().min(63);
+    (1u64 << levels) - 1
+}
+
+fn max_key_size(sizes: &EstimatedLayerSizes) -> u32 {
+    match sizes {
+        EstimatedLayerSizes::AllSubtrees(key_size, ..)
+        | EstimatedLayerSizes::AllItems(key_size, ..)
+        | EstimatedLayerSizes::AllReference(key_size, ..) => *key_size as u32,
+        EstimatedLayerSizes::Mix {
+            subtrees_size,
+            items_size,
+            references_size,
+        } => subtrees_size
+            .iter()
+            .map(|(key_size, ..)| *key_size)
+            .chain(items_size.iter().map(|(key_size, ..)| *key_size))
+            .chain(references_size.iter().map(|(key_size, ..)| *key_size))
+            .max()
+            .unwrap_or_default() as u32,
+    }
+}
+
+/// Unlike average case costs this takes the biggest of mixed element sizes,
+/// references are counted as items since proofs include referenced values
+fn max_value_size(sizes: &EstimatedLayerSizes, child_key_size: u32) -> u32 {
+    let tree_size = |flags_size: &Option<u32>| {
+        TREE_VALUE_OVERHEAD + child_key_size + flags_size.unwrap_or_default()
+    };
+    let item_size = |value_size: &u32, flags_size: &Option<u32>| {
+        value_size + flags_size.unwrap_or_default() + 5
+    };
+    match sizes {
+        EstimatedLayerSizes::AllSubtrees(_, _, flags_size) => tree_size(flags_size),
+        EstimatedLayerSizes::AllItems(_, value_size, flags_size)
+        | EstimatedLayerSizes::AllReference(_, value_size, flags_size) => {
+            item_size(value_size, flags_size)
+        }
+        EstimatedLayerSizes::Mix {
+            subtrees_size,
+            items_size,
+            references_size,
+        } => subtrees_size
+            .iter()
+            .map(|(_, _, flags_size, _)| tree_size(flags_size))
+            .chain(
+                items_size
+                    .iter()
+                    .chain(references_size.iter())
+                    .map(|(_, value_size, flags_size, _)| item_size(value_size, flags_size)),
+            )
+            .max()
+            .unwrap_or_default(),
+    }
+}
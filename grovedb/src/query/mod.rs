@@ -27,14 +27,34 @@
 // DEALINGS IN THE SOFTWARE.
 
 //! Queries
+//! Path queries, along with the query and proof types of the underlying trees
+//! re-exported for consumers to depend on grovedb only. The re-exported set is
+//! part of the stable API of grovedb, anything else merk exposes is not.
+
+#[cfg(any(feature = "full", feature = "verify"))]
+mod estimated_proof_size;
+#[cfg(feature = "full")]
+mod pagination;
+#[cfg(any(feature = "full", feature = "verify"))]
+mod version;
 
 use std::cmp::Ordering;
 
 #[cfg(any(feature = "full", feature = "verify"))]
-use merk::proofs::query::query_item::QueryItem;
-use merk::proofs::query::SubqueryBranch;
+pub use merk::proofs::query::query_item::QueryItem;
+#[cfg(any(feature = "full", feature = "verify"))]
+pub use merk::proofs::query::SubqueryBranch;
+#[cfg(any(feature = "full", feature = "verify"))]
+pub use merk::proofs::query::{ProofNodeCounts, ProofVerificationResult, ProvedKeyValue};
 #[cfg(any(feature = "full", feature = "verify"))]
-use merk::proofs::Query;
+pub use merk::proofs::Query;
+#[cfg(any(feature = "full", feature = "verify"))]
+pub use merk::CryptoHash;
+
+#[cfg(feature = "full")]
+pub use pagination::{PaginationKey, PaginationToken, PAGINATION_TOKEN_VERSION};
+#[cfg(any(feature = "full", feature = "verify"))]
+pub use version::QueryVersion;
 
 #[cfg(any(feature = "full", feature = "verify"))]
 use crate::query_result_type::PathKey;
@@ -123,6 +143,33 @@ impl PathQuery {
         Self { path, query }
     }
 
+    /// New path query on the root tree, the root leaves themselves are the
+    /// queried keys and subqueries descend into each of them. Proved root
+    /// leaves carry the value hash which commits to their subtree root hash.
+    pub const fn new_root(query: SizedQuery) -> Self {
+        Self {
+            path: vec![],
+            query,
+        }
+    }
+
+    /// Returns true if the path query is applied to the root tree
+    pub fn is_root(&self) -> bool {
+        self.path.is_empty()
+    }
+
+    /// Returns the path query without its limit and offset
+    pub fn without_limit_and_offset(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            query: SizedQuery {
+                query: self.query.query.clone(),
+                limit: None,
+                offset: None,
+            },
+        }
+    }
+
     /// Gets the path of all terminal keys
     pub fn terminal_keys(&self, max_results: usize) -> Result<Vec<PathKey>, Error> {
         let mut result: Vec<(Vec<Vec<u8>>, Vec<u8>)> = vec![];
@@ -279,12 +326,20 @@ impl PathQuery {
 mod tests {
     use std::ops::RangeFull;
 
-    use merk::proofs::{query::query_item::QueryItem, Query};
+    use merk::{
+        estimated_costs::average_case_costs::{
+            EstimatedLayerCount::{ApproximateElements, EstimatedLevel},
+            EstimatedLayerInformation,
+            EstimatedLayerSizes::{AllItems, AllSubtrees},
+            EstimatedSumTrees::NoSumTrees,
+        },
+        proofs::{query::query_item::QueryItem, Query},
+    };
 
     use crate::{
         query_result_type::QueryResultType,
-        tests::{common::compare_result_tuples, make_deep_tree, TEST_LEAF},
-        Element, GroveDb, PathQuery,
+        tests::{common::compare_result_tuples, make_deep_tree, make_test_grovedb, TEST_LEAF},
+        Element, Error, GroveDb, PathQuery, SizedQuery,
     };
 
     #[test]
@@ -842,4 +897,114 @@ mod tests {
             .expect("should execute proof");
         assert_eq!(result_set.len(), 4);
     }
+
+    #[test]
+    fn test_estimated_proof_size_is_an_upper_bound() {
+        let db = make_test_grovedb();
+        for i in 0u8..10 {
+            db.insert(
+                [TEST_LEAF],
+                &[b'k', i],
+                Element::new_item(vec![i; 8]),
+                None,
+                None,
+            )
+            .unwrap()
+            .expect("successful item insert");
+        }
+
+        let layer_info = vec![
+            EstimatedLayerInformation {
+                is_sum_tree: false,
+                estimated_layer_count: EstimatedLevel(2, false),
+                estimated_layer_sizes: AllSubtrees(17, NoSumTrees, None),
+            },
+            EstimatedLayerInformation {
+                is_sum_tree: false,
+                estimated_layer_count: ApproximateElements(10),
+                estimated_layer_sizes: AllItems(2, 8, None),
+            },
+        ];
+
+        let mut query = Query::new();
+        query.insert_all();
+        let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query.clone());
+        let estimated_size = path_query
+            .estimated_proof_size(&layer_info)
+            .expect("should estimate proof size");
+        let proof = db.prove_query(&path_query).unwrap().unwrap();
+        assert!(proof.len() as u64 <= estimated_size);
+
+        // A limit narrows the estimate
+        let limited_path_query = PathQuery::new(
+            vec![TEST_LEAF.to_vec()],
+            SizedQuery::new(query, Some(2), None),
+        );
+        let limited_estimated_size = limited_path_query
+            .estimated_proof_size(&layer_info)
+            .expect("should estimate proof size");
+        let limited_proof = db.prove_query(&limited_path_query).unwrap().unwrap();
+        assert!(limited_proof.len() as u64 <= limited_estimated_size);
+        assert!(limited_estimated_size < estimated_size);
+
+        // Layers the query goes through must be described
+        assert!(matches!(
+            path_query.estimated_proof_size(&layer_info[..1]),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_estimated_proof_size_bounds_limited_subqueries() {
+        let db = make_test_grovedb();
+        for i in 0u8..4 {
+            db.insert([TEST_LEAF], &[b's', i], Element::empty_tree(), None, None)
+                .unwrap()
+                .expect("successful subtree insert");
+            for j in 0u8..5 {
+                db.insert(
+                    [TEST_LEAF, &[b's', i]],
+                    &[b'k', j],
+                    Element::new_item(vec![j; 8]),
+                    None,
+                    None,
+                )
+                .unwrap()
+                .expect("successful item insert");
+            }
+        }
+
+        let layer_info = vec![
+            EstimatedLayerInformation {
+                is_sum_tree: false,
+                estimated_layer_count: EstimatedLevel(2, false),
+                estimated_layer_sizes: AllSubtrees(17, NoSumTrees, None),
+            },
+            EstimatedLayerInformation {
+                is_sum_tree: false,
+                estimated_layer_count: ApproximateElements(4),
+                estimated_layer_sizes: AllSubtrees(2, NoSumTrees, None),
+            },
+            EstimatedLayerInformation {
+                is_sum_tree: false,
+                estimated_layer_count: ApproximateElements(5),
+                estimated_layer_sizes: AllItems(2, 8, None),
+            },
+        ];
+
+        let mut subquery = Query::new();
+        subquery.insert_all();
+        let mut query = Query::new();
+        query.insert_all();
+        query.set_subquery(subquery);
+        let path_query = PathQuery::new(
+            vec![TEST_LEAF.to_vec()],
+            SizedQuery::new(query, Some(3), Some(2)),
+        );
+        let estimated_size = path_query
+            .estimated_proof_size(&layer_info)
+            .expect("should estimate proof size");
+        let proof = db.prove_query(&path_query).unwrap().unwrap();
+        assert!(proof.len() as u64 <= estimated_size);
+    }
 }
@@ -0,0 +1,317 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Pagination tokens
+//! Continuation tokens for paginated public endpoints. A token carries the key
+//! to resume from, the root hash the previous page was read at and the hash of
+//! the path query, authenticated with a key only the embedder knows, so that
+//! clients can't forge arbitrary continuation states. Blake3 in keyed mode is
+//! used as the MAC. Creating and validating tokens is charged as hashing.
+
+use costs::{CostContext, CostResult, CostsExt, OperationCost};
+use integer_encoding::VarInt;
+use merk::{
+    proofs::{query::SubqueryBranch, Query},
+    tree::value_hash,
+    CryptoHash,
+};
+
+use crate::{Error, PathQuery};
+
+/// Version of the pagination token encoding
+pub const PAGINATION_TOKEN_VERSION: u8 = 0;
+
+/// Length of the authentication tag of a token
+const TAG_LENGTH: usize = 32;
+
+/// Length of the version, root hash and query hash of an encoded token
+const MESSAGE_HEADER_LENGTH: usize = 1 + 32 + 32;
+
+/// Length of an encoded token without its start key
+const TOKEN_HEADER_LENGTH: usize = MESSAGE_HEADER_LENGTH + TAG_LENGTH;
+
+/// Secret key authenticating pagination tokens, provided by the embedder
+#[derive(Clone)]
+pub struct PaginationKey([u8; 32]);
+
+impl PaginationKey {
+    /// New pagination key from secret bytes
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+
+    fn tag(&self, message: &[u8]) -> CostContext<[u8; TAG_LENGTH]> {
+        let tag = *blake3::keyed_hash(&self.0, message).as_bytes();
+        tag.wrap_with_cost(OperationCost {
+            hash_node_calls: (1 + (message.len().max(1) - 1) / 64) as u16,
+            ..Default::default()
+        })
+    }
+}
+
+/// Continuation state of a paginated query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaginationToken {
+    /// Key the next page starts after
+    pub start_key: Vec<u8>,
+    /// Root hash the previous page was read at
+    pub root_hash: CryptoHash,
+    /// Hash of the paginated path query
+    pub query_hash: CryptoHash,
+}
+
+impl PaginationToken {
+    /// Continuation state of the path query after `start_key`
+    pub fn new(
+        path_query: &PathQuery,
+        start_key: Vec<u8>,
+        root_hash: CryptoHash,
+    ) -> CostContext<Self> {
+        path_query.query_hash().map(|query_hash| Self {
+            start_key,
+            root_hash,
+            query_hash,
+        })
+    }
+
+    /// Encodes the token along with its authentication tag
+    pub fn sign(&self, key: &PaginationKey) -> CostContext<Vec<u8>> {
+        let mut message = Vec::with_capacity(MESSAGE_HEADER_LENGTH + self.start_key.len());
+        message.push(PAGINATION_TOKEN_VERSION);
+        message.extend_from_slice(&self.root_hash);
+        message.extend_from_slice(&self.query_hash);
+        message.extend_from_slice(&self.start_key);
+        key.tag(&message).map(|tag| {
+            let mut bytes = Vec::with_capacity(TOKEN_HEADER_LENGTH + self.start_key.len());
+            bytes.extend_from_slice(&message[..MESSAGE_HEADER_LENGTH]);
+            bytes.extend_from_slice(&tag);
+            bytes.extend_from_slice(&self.start_key);
+            bytes
+        })
+    }
+
+    /// Decodes a token, checking it was signed with the key and issued for
+    /// the path query
+    pub fn verify(
+        bytes: &[u8],
+        key: &PaginationKey,
+        path_query: &PathQuery,
+    ) -> CostResult<Self, Error> {
+        let mut cost = OperationCost::default();
+
+        if bytes.len() < TOKEN_HEADER_LENGTH || bytes[0] != PAGINATION_TOKEN_VERSION {
+            return Err(Error::InvalidInput("malformed pagination token")).wrap_with_cost(cost);
+        }
+        let (header, start_key) = bytes.split_at(TOKEN_HEADER_LENGTH);
+        let mut message = Vec::with_capacity(bytes.len() - TAG_LENGTH);
+        message.extend_from_slice(&header[..MESSAGE_HEADER_LENGTH]);
+        message.extend_from_slice(start_key);
+        let tag = key.tag(&message).unwrap_add_cost(&mut cost);
+        // constant time comparison, the tag must not leak byte by byte
+        let difference = tag
+            .iter()
+            .zip(&header[MESSAGE_HEADER_LENGTH..])
+            .fold(0u8, |difference, (a, b)| difference | (a ^ b));
+        if difference != 0 {
+            return Err(Error::InvalidInput("pagination token signature mismatch"))
+                .wrap_with_cost(cost);
+        }
+
+        let mut root_hash = CryptoHash::default();
+        root_hash.copy_from_slice(&header[1..33]);
+        let mut query_hash = CryptoHash::default();
+        query_hash.copy_from_slice(&header[33..MESSAGE_HEADER_LENGTH]);
+        if query_hash != path_query.query_hash().unwrap_add_cost(&mut cost) {
+            return Err(Error::InvalidInput(
+                "pagination token was issued for another query",
+            ))
+            .wrap_with_cost(cost);
+        }
+
+        Ok(Self {
+            start_key: start_key.to_vec(),
+            root_hash,
+            query_hash,
+        })
+        .wrap_with_cost(cost)
+    }
+}
+
+impl PathQuery {
+    /// Hash of a canonical encoding of the path query, query items are
+    /// encoded by their bounds so equivalent items hash the same
+    pub fn query_hash(&self) -> CostContext<CryptoHash> {
+        let mut bytes = Vec::new();
+        encode_path(&self.path, &mut bytes);
+        encode_optional_u16(self.query.limit, &mut bytes);
+        encode_optional_u16(self.query.offset, &mut bytes);
+        encode_query(&self.query.query, &mut bytes);
+        value_hash(&bytes)
+    }
+}
+
+fn encode_bytes(value: &[u8], bytes: &mut Vec<u8>) {
+    bytes.extend_from_slice(&value.len().encode_var_vec());
+    bytes.extend_from_slice(value);
+}
+
+fn encode_path(path: &[Vec<u8>], bytes: &mut Vec<u8>) {
+    bytes.extend_from_slice(&path.len().encode_var_vec());
+    path.iter().for_each(|segment| encode_bytes(segment, bytes));
+}
+
+fn encode_optional_u16(value: Option<u16>, bytes: &mut Vec<u8>) {
+    match value {
+        Some(value) => {
+            bytes.push(1);
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+        None => bytes.push(0),
+    }
+}
+
+fn encode_bound((bound, flag): (Option<&[u8]>, bool), bytes: &mut Vec<u8>) {
+    match bound {
+        Some(bound) => {
+            bytes.push(1 + flag as u8);
+            encode_bytes(bound, bytes);
+        }
+        None => bytes.push(0),
+    }
+}
+
+fn encode_query(query: &Query, bytes: &mut Vec<u8>) {
+    bytes.push(query.left_to_right as u8);
+    bytes.extend_from_slice(&query.items.len().encode_var_vec());
+    for item in &query.items {
+        encode_bound(item.lower_bound(), bytes);
+        encode_bound(item.upper_bound(), bytes);
+    }
+    encode_subquery_branch(&query.default_subquery_branch, bytes);
+    let conditional_branches = query.conditional_subquery_branches.iter().flatten();
+    let conditional_branch_count = query
+        .conditional_subquery_branches
+        .as_ref()
+        .map_or(0, |branches| branches.len());
+    bytes.extend_from_slice(&conditional_branch_count.encode_var_vec());
+    for (item, branch) in conditional_branches {
+        encode_bound(item.lower_bound(), bytes);
+        encode_bound(item.upper_bound(), bytes);
+        encode_subquery_branch(branch, bytes);
+    }
+}
+
+fn encode_subquery_branch(branch: &SubqueryBranch, bytes: &mut Vec<u8>) {
+    match &branch.subquery_path {
+        Some(path) => {
+            bytes.push(1);
+            encode_path(path, bytes);
+        }
+        None => bytes.push(0),
+    }
+    match &branch.subquery {
+        Some(subquery) => {
+            bytes.push(1);
+            encode_query(subquery, bytes);
+        }
+        None => bytes.push(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{QueryItem, SizedQuery};
+
+    fn path_query(limit: u16) -> PathQuery {
+        let mut query = Query::new();
+        query.insert_range_from(b"a".to_vec()..);
+        PathQuery::new(
+            vec![b"leaf".to_vec()],
+            SizedQuery::new(query, Some(limit), None),
+        )
+    }
+
+    #[test]
+    fn test_pagination_token_round_trip() {
+        let key = PaginationKey::new([7; 32]);
+        let token = PaginationToken::new(&path_query(10), b"next".to_vec(), [3; 32]).unwrap();
+        let signed = token.sign(&key);
+        assert!(signed.cost.hash_node_calls > 0);
+
+        let verified = PaginationToken::verify(&signed.value, &key, &path_query(10))
+            .unwrap()
+            .expect("should verify token");
+        assert_eq!(verified, token);
+    }
+
+    #[test]
+    fn test_pagination_token_rejects_forgeries() {
+        let key = PaginationKey::new([7; 32]);
+        let token = PaginationToken::new(&path_query(10), b"next".to_vec(), [3; 32]).unwrap();
+        let signed = token.sign(&key).unwrap();
+
+        let mut forged = signed.clone();
+        let last = forged.len() - 1;
+        forged[last] ^= 1;
+        assert!(matches!(
+            PaginationToken::verify(&forged, &key, &path_query(10)).unwrap(),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            PaginationToken::verify(&signed, &PaginationKey::new([8; 32]), &path_query(10))
+                .unwrap(),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            PaginationToken::verify(&signed, &key, &path_query(11)).unwrap(),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            PaginationToken::verify(&signed[..10], &key, &path_query(10)).unwrap(),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_query_hash_is_canonical() {
+        let single_key = PathQuery::new_single_key(vec![b"leaf".to_vec()], b"k".to_vec());
+        let inclusive_range = PathQuery::new_single_query_item(
+            vec![b"leaf".to_vec()],
+            QueryItem::RangeInclusive(b"k".to_vec()..=b"k".to_vec()),
+        );
+        assert_eq!(
+            single_key.query_hash().unwrap(),
+            inclusive_range.query_hash().unwrap()
+        );
+        assert_ne!(
+            path_query(10).query_hash().unwrap(),
+            path_query(11).query_hash().unwrap()
+        );
+    }
+}
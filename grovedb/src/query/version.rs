@@ -0,0 +1,298 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Query versions
+//! Semantics of queries that changed over protocol upgrades. Queries, proofs
+//! and verification all take the version the query was made under, so proofs
+//! made before an upgrade stay verifiable with the semantics of their time.
+//! Versions differ in limit semantics and result ordering. References are
+//! followed the same way under every version: a proof carries the element a
+//! reference points to, checked by the same hashes whatever the version, so
+//! there is no behavior a version could change without breaking proofs made
+//! before it.
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use std::borrow::Cow;
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use crate::{Error, PathQuery, Query};
+
+#[cfg(any(feature = "full", feature = "verify"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Version of query semantics
+pub enum QueryVersion {
+    /// Original semantics: a limit of zero gives no results and results come
+    /// in the order subtrees were traversed, every subquery in its own
+    /// direction
+    V0,
+    /// A limit of zero is rejected and results are ordered by path, then by
+    /// key, in the direction of the query, the keys of every subtree in the
+    /// ordering it declares. Subqueries are traversed in the direction of the
+    /// query, which yields that order, so the offset and limit still bound the
+    /// traversal and the proof.
+    V1,
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl QueryVersion {
+    /// Latest version of query semantics
+    pub const LATEST: QueryVersion = QueryVersion::V1;
+
+    /// Checks that the path query is valid under this version
+    pub fn validate(&self, path_query: &PathQuery) -> Result<(), Error> {
+        match self {
+            QueryVersion::V0 => Ok(()),
+            QueryVersion::V1 => {
+                if path_query.query.limit == Some(0) {
+                    Err(Error::InvalidQuery("limit must be greater than zero"))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Whether results are ordered by path and key rather than traversal
+    pub fn orders_results_by_path(&self) -> bool {
+        match self {
+            QueryVersion::V0 => false,
+            QueryVersion::V1 => true,
+        }
+    }
+
+    /// Path query to traverse, query and prove under this version, the proof
+    /// of a versioned query is verified against it too
+    pub fn traversal_query<'a>(&self, path_query: &'a PathQuery) -> Cow<'a, PathQuery> {
+        if !self.orders_results_by_path() {
+            return Cow::Borrowed(path_query);
+        }
+        let mut path_query = path_query.clone();
+        let left_to_right = path_query.query.query.left_to_right;
+        set_subqueries_direction(&mut path_query.query.query, left_to_right);
+        Cow::Owned(path_query)
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Gives every subquery of the query the direction, so subtrees are traversed
+/// in the same direction at every depth
+fn set_subqueries_direction(query: &mut Query, left_to_right: bool) {
+    let branches = std::iter::once(&mut query.default_subquery_branch).chain(
+        query
+            .conditional_subquery_branches
+            .iter_mut()
+            .flat_map(|branches| branches.values_mut()),
+    );
+    for branch in branches {
+        if let Some(subquery) = branch.subquery.as_mut() {
+            subquery.left_to_right = left_to_right;
+            set_subqueries_direction(subquery, left_to_right);
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        query_result_type::QueryResultType,
+        tests::{make_deep_tree, make_test_grovedb, DEEP_LEAF, TEST_LEAF},
+        Element, GroveDb, KeyOrdering, SizedQuery,
+    };
+
+    #[test]
+    fn test_query_versions() {
+        let db = make_deep_tree();
+        let mut subquery = Query::new_with_direction(false);
+        subquery.insert_all();
+        let mut query = Query::new();
+        query.insert_all();
+        query.set_subquery(subquery);
+        let path_query =
+            PathQuery::new_unsized(vec![DEEP_LEAF.to_vec(), b"deep_node_1".to_vec()], query);
+        let v0_proof = db
+            .prove_query_with_version(&path_query, QueryVersion::V0)
+            .unwrap()
+            .expect("should prove");
+        let v1_proof = db
+            .prove_query_with_version(&path_query, QueryVersion::V1)
+            .unwrap()
+            .expect("should prove");
+
+        let (_, v0_results) =
+            GroveDb::verify_query_with_version(&v0_proof, &path_query, QueryVersion::V0)
+                .expect("should verify");
+        let (_, v1_results) =
+            GroveDb::verify_query_with_version(&v1_proof, &path_query, QueryVersion::V1)
+                .expect("should verify");
+        let mut sorted_results = v0_results.clone();
+        sorted_results.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        assert_ne!(v0_results, sorted_results);
+        assert_eq!(v1_results, sorted_results);
+        // a proof only verifies under the version it was made for
+        assert!(
+            GroveDb::verify_query_with_version(&v0_proof, &path_query, QueryVersion::V1).is_err()
+        );
+
+        let (elements, _) = db
+            .query_with_version(
+                &path_query,
+                true,
+                QueryResultType::QueryPathKeyElementTrioResultType,
+                QueryVersion::V1,
+                None,
+            )
+            .unwrap()
+            .expect("should query");
+        assert_eq!(
+            elements
+                .to_path_key_elements()
+                .into_iter()
+                .map(|(path, key, element)| (path, key, Some(element)))
+                .collect::<Vec<_>>(),
+            sorted_results
+        );
+
+        // the limit and offset apply to the ordered results
+        let limited_path_query = PathQuery::new(
+            path_query.path.clone(),
+            SizedQuery::new(path_query.query.query.clone(), Some(2), Some(1)),
+        );
+        let proof = db
+            .prove_query_with_version(&limited_path_query, QueryVersion::V1)
+            .unwrap()
+            .expect("should prove");
+        let (_, v1_limited_results) =
+            GroveDb::verify_query_with_version(&proof, &limited_path_query, QueryVersion::V1)
+                .expect("should verify");
+        assert_eq!(v1_limited_results, sorted_results[1..3].to_vec());
+        let (elements, skipped) = db
+            .query_with_version(
+                &limited_path_query,
+                true,
+                QueryResultType::QueryPathKeyElementTrioResultType,
+                QueryVersion::V1,
+                None,
+            )
+            .unwrap()
+            .expect("should query");
+        assert_eq!(skipped, 1);
+        assert_eq!(
+            elements
+                .to_path_key_elements()
+                .into_iter()
+                .map(|(path, key, element)| (path, key, Some(element)))
+                .collect::<Vec<_>>(),
+            sorted_results[1..3].to_vec()
+        );
+
+        let mut query = Query::new();
+        query.insert_all();
+        let zero_limit_query = PathQuery::new(
+            vec![DEEP_LEAF.to_vec()],
+            SizedQuery::new(query, Some(0), None),
+        );
+        assert!(db
+            .query_with_version(
+                &zero_limit_query,
+                true,
+                QueryResultType::QueryElementResultType,
+                QueryVersion::V0,
+                None,
+            )
+            .unwrap()
+            .is_ok());
+        assert!(matches!(
+            db.prove_query_with_version(&zero_limit_query, QueryVersion::V1)
+                .unwrap(),
+            Err(Error::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    fn test_query_version_orders_by_subtree_key_ordering() {
+        let db = make_test_grovedb();
+        db.insert(
+            [TEST_LEAF],
+            b"ordered",
+            Element::empty_ordered_tree(KeyOrdering::ReverseLexicographic),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert ordered tree");
+        for key in [b"a", b"b", b"c", b"d"] {
+            db.insert(
+                [TEST_LEAF, b"ordered"],
+                key,
+                Element::new_item(key.to_vec()),
+                None,
+                None,
+            )
+            .unwrap()
+            .expect("should insert item");
+        }
+
+        let mut query = Query::new();
+        query.insert_all();
+        let path_query = PathQuery::new(
+            vec![TEST_LEAF.to_vec(), b"ordered".to_vec()],
+            SizedQuery::new(query, Some(2), Some(1)),
+        );
+        let expected_keys = vec![b"c".to_vec(), b"b".to_vec()];
+
+        let (elements, _) = db
+            .query_with_version(
+                &path_query,
+                true,
+                QueryResultType::QueryKeyElementPairResultType,
+                QueryVersion::V1,
+                None,
+            )
+            .unwrap()
+            .expect("should query");
+        assert_eq!(elements.to_keys(), expected_keys);
+
+        let proof = db
+            .prove_query_with_version(&path_query, QueryVersion::V1)
+            .unwrap()
+            .expect("should prove");
+        let (root_hash, results) =
+            GroveDb::verify_query_with_version(&proof, &path_query, QueryVersion::V1)
+                .expect("should verify");
+        assert_eq!(root_hash, db.root_hash(None).unwrap().unwrap());
+        assert_eq!(
+            results
+                .into_iter()
+                .map(|(_, key, _)| key)
+                .collect::<Vec<_>>(),
+            expected_keys
+        );
+    }
+}
@@ -207,6 +207,15 @@ pub enum QueryResultElement {
 
 #[cfg(feature = "full")]
 impl QueryResultElement {
+    /// Element of the result
+    pub fn element(&self) -> &Element {
+        match self {
+            QueryResultElement::ElementResultItem(element)
+            | QueryResultElement::KeyElementPairResultItem((_, element))
+            | QueryResultElement::PathKeyElementTrioResultItem((_, _, element)) => element,
+        }
+    }
+
     /// Map element
     pub fn map_element(
         self,
@@ -28,7 +28,7 @@
 
 //! Reference path
 
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 use std::fmt;
 
 #[cfg(feature = "full")]
@@ -38,6 +38,8 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "full")]
 use visualize::visualize_to_vec;
 
+#[cfg(any(feature = "full", feature = "verify"))]
+use crate::Element;
 #[cfg(feature = "full")]
 use crate::Error;
 
@@ -86,6 +88,30 @@ impl fmt::Debug for ReferencePathType {
     }
 }
 
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Part of a reference chain followed before giving up, for diagnosing and
+/// repairing bad chains
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct PartialReferenceChain {
+    /// Qualified paths of the elements read, in the order they were read
+    pub visited: Vec<Vec<Vec<u8>>>,
+    /// Last element read, a reference
+    pub last_element: Option<Element>,
+    /// Qualified path the last element points to, which wasn't read
+    pub next_path: Option<Vec<Vec<u8>>>,
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl fmt::Debug for PartialReferenceChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("PartialReferenceChain");
+        debug.field("visited", &self.visited);
+        #[cfg(feature = "full")]
+        debug.field("last_element", &self.last_element);
+        debug.field("next_path", &self.next_path).finish()
+    }
+}
+
 #[cfg(feature = "full")]
 /// Given the reference path type and the current qualified path (path+key),
 /// this computes the absolute path of the item the reference is pointing to.
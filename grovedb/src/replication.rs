@@ -31,13 +31,17 @@
 use std::{
     collections::VecDeque,
     iter::{empty, once},
+    sync::Arc,
 };
 
 use merk::{
     proofs::{Node, Op},
     Merk, TreeFeatureType,
 };
-use storage::{rocksdb_storage::PrefixedRocksDbStorageContext, Storage, StorageContext};
+use storage::{
+    rocksdb_storage::{PhysicalChanges, PrefixedRocksDbStorageContext, ReplicationSink},
+    Storage, StorageContext,
+};
 
 use crate::{Element, Error, GroveDb, Hash};
 
@@ -48,6 +52,21 @@ impl GroveDb {
     pub fn chunks(&self) -> SubtreeChunkProducer {
         SubtreeChunkProducer::new(self)
     }
+
+    /// Sets a sink which receives raw storage changes of every commit, in
+    /// commit order. Setting `None` stops the stream. The sink is shared by
+    /// every handle of the database.
+    pub fn set_replication_sink(&self, sink: Option<Arc<dyn ReplicationSink>>) {
+        self.db.set_replication_sink(sink);
+    }
+
+    /// Applies raw storage changes received from another GroveDb replication
+    /// stream. Changes must be applied in the order they were committed.
+    pub fn apply_physical_changes(&self, changes: &PhysicalChanges) -> Result<(), Error> {
+        self.db
+            .apply_physical_changes(changes)
+            .map_err(Error::StorageError)
+    }
 }
 
 /// Subtree chunks producer.
@@ -205,9 +224,10 @@ impl<'db> Restorer<'db> {
                     value_hash,
                     feature_type,
                 )) => {
-                    if let Element::Tree(root_key, _) | Element::SumTree(root_key, ..) =
-                        Element::deserialize(value_bytes)
-                            .map_err(|e| RestorerError(e.to_string()))?
+                    if let Element::Tree(root_key, _)
+                    | Element::SumTree(root_key, ..)
+                    | Element::OrderedTree(root_key, ..) = Element::deserialize(value_bytes)
+                        .map_err(|e| RestorerError(e.to_string()))?
                     {
                         if root_key.is_none() || self.current_merk_path.last() == Some(key) {
                             // We add only subtrees of the current subtree to queue, skipping
@@ -338,7 +358,9 @@ impl<'db> SiblingsChunkProducer<'db> {
         }
 
         while let Some(element) = siblings_iter.next_element().unwrap()? {
-            if let (key, Element::Tree(..)) | (key, Element::SumTree(..)) = element {
+            if let (key, Element::Tree(..) | Element::SumTree(..) | Element::OrderedTree(..)) =
+                element
+            {
                 siblings_keys.push_back(key);
             }
         }
@@ -436,6 +458,8 @@ impl<'db> BufferedRestorer<'db> {
 
 #[cfg(test)]
 mod test {
+    use std::sync::Mutex;
+
     use rand::RngCore;
     use tempfile::TempDir;
 
@@ -963,4 +987,122 @@ mod test {
             Element::new_item(b"ayyb".to_vec())
         );
     }
+
+    #[test]
+    fn test_physical_replication_stream() {
+        let db = make_test_grovedb();
+        let replica_tempdir = TempDir::new().unwrap();
+        let replica_db = GroveDb::open(replica_tempdir.path()).unwrap();
+
+        // Bring the replica to the same starting point
+        replica_db
+            .insert([], TEST_LEAF, Element::empty_tree(), None, None)
+            .unwrap()
+            .unwrap();
+        replica_db
+            .insert([], ANOTHER_TEST_LEAF, Element::empty_tree(), None, None)
+            .unwrap()
+            .unwrap();
+
+        let stream: Arc<Mutex<Vec<PhysicalChanges>>> = Default::default();
+        let sink_stream = stream.clone();
+        // the sink is set through another handle of the same database
+        let handle = GroveDb::clone(&db);
+        handle.set_replication_sink(Some(Arc::new(move |changes: PhysicalChanges| {
+            sink_stream.lock().unwrap().push(changes)
+        })));
+
+        db.insert(
+            [TEST_LEAF],
+            b"key1",
+            Element::new_item(b"ayy".to_vec()),
+            None,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+
+        let tx = db.start_transaction();
+        db.insert(
+            [TEST_LEAF],
+            b"innertree",
+            Element::empty_tree(),
+            None,
+            Some(&tx),
+        )
+        .unwrap()
+        .unwrap();
+        db.insert(
+            [TEST_LEAF, b"innertree"],
+            b"key2",
+            Element::new_item(b"ayyb".to_vec()),
+            None,
+            Some(&tx),
+        )
+        .unwrap()
+        .unwrap();
+        let stream_len_before_commit = stream.lock().unwrap().len();
+        db.commit_transaction(tx).unwrap().unwrap();
+        assert_eq!(stream.lock().unwrap().len(), stream_len_before_commit + 1);
+
+        let tx = db.start_transaction();
+        db.insert(
+            [ANOTHER_TEST_LEAF],
+            b"rolled_back",
+            Element::new_item(b"nope".to_vec()),
+            None,
+            Some(&tx),
+        )
+        .unwrap()
+        .unwrap();
+        db.rollback_transaction(&tx).unwrap();
+        drop(tx);
+
+        // writes of a dry run within a committed transaction are not streamed
+        let tx = db.start_transaction();
+        db.dry_run(Some(&tx), |tx| {
+            db.insert(
+                [ANOTHER_TEST_LEAF],
+                b"dry_run",
+                Element::new_item(b"nope".to_vec()),
+                None,
+                Some(tx),
+            )
+        })
+        .unwrap()
+        .unwrap();
+        db.commit_transaction(tx).unwrap().unwrap();
+
+        db.delete([TEST_LEAF], b"key1", None, None)
+            .unwrap()
+            .unwrap();
+
+        for changes in stream.lock().unwrap().iter() {
+            replica_db.apply_physical_changes(changes).unwrap();
+        }
+
+        assert_eq!(
+            db.root_hash(None).unwrap().unwrap(),
+            replica_db.root_hash(None).unwrap().unwrap()
+        );
+        assert_eq!(
+            replica_db
+                .get([TEST_LEAF, b"innertree"], b"key2", None)
+                .unwrap()
+                .unwrap(),
+            Element::new_item(b"ayyb".to_vec())
+        );
+        assert!(matches!(
+            replica_db
+                .get([ANOTHER_TEST_LEAF], b"rolled_back", None)
+                .unwrap(),
+            Err(Error::PathKeyNotFound(_))
+        ));
+        assert!(matches!(
+            replica_db
+                .get([ANOTHER_TEST_LEAF], b"dry_run", None)
+                .unwrap(),
+            Err(Error::PathKeyNotFound(_))
+        ));
+    }
 }
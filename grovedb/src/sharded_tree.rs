@@ -0,0 +1,401 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Sharded trees
+//! A logical subtree can be split across a fixed number of shard subtrees,
+//! every key going to the shard picked by the hash of the key. Accessors hide
+//! the sharding while proofs expose the extra shard level, keeping each merk
+//! tree shallow for collections of hundreds of millions of keys. The shard
+//! count is stored in the sharded tree next to its shards, so a tree can't be
+//! opened with another count.
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use costs::CostContext;
+#[cfg(feature = "full")]
+use costs::{
+    cost_return_on_error, cost_return_on_error_no_add, CostResult, CostsExt, OperationCost,
+};
+#[cfg(any(feature = "full", feature = "verify"))]
+use merk::tree::value_hash;
+
+#[cfg(feature = "full")]
+use crate::{
+    batch::GroveDbOp,
+    operations::{delete::DeleteOptions, insert::InsertOptions},
+    query_result_type::{KeyElementPair, QueryResultType},
+    Element, GroveDb, TransactionArg,
+};
+#[cfg(any(feature = "full", feature = "verify"))]
+use crate::{Error, PathQuery, Query, SizedQuery};
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Key of the item holding the shard count in the sharded tree, shard indexes
+/// are below `u16::MAX` so no shard has this key
+pub const SHARD_COUNT_KEY: [u8; 2] = u16::MAX.to_be_bytes();
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// A logical subtree split across shard subtrees by key hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardedTree {
+    path: Vec<Vec<u8>>,
+    shard_count: u16,
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl ShardedTree {
+    /// New sharded tree rooted at the given path, which must be a subtree.
+    /// Use [`ShardedTree::open`] for sharded trees created before.
+    pub fn new(path: Vec<Vec<u8>>, shard_count: u16) -> Result<Self, Error> {
+        if shard_count == 0 {
+            return Err(Error::InvalidInput(
+                "a sharded tree needs at least one shard",
+            ));
+        }
+        Ok(Self { path, shard_count })
+    }
+
+    /// Path of the subtree holding the shards
+    pub fn path(&self) -> &[Vec<u8>] {
+        &self.path
+    }
+
+    /// Number of shards
+    pub fn shard_count(&self) -> u16 {
+        self.shard_count
+    }
+
+    /// Key of a shard subtree, big endian so shards are ordered by index
+    pub fn shard_key(shard_index: u16) -> Vec<u8> {
+        shard_index.to_be_bytes().to_vec()
+    }
+
+    /// Index of the shard holding the key
+    pub fn shard_index(&self, key: &[u8]) -> CostContext<u16> {
+        value_hash(key).map(|hash| {
+            let mut prefix = [0u8; 8];
+            prefix.copy_from_slice(&hash[..8]);
+            (u64::from_be_bytes(prefix) % self.shard_count as u64) as u16
+        })
+    }
+
+    /// Path of the shard subtree holding the key
+    pub fn shard_path(&self, key: &[u8]) -> CostContext<Vec<Vec<u8>>> {
+        self.shard_index(key).map(|shard_index| {
+            let mut shard_path = self.path.clone();
+            shard_path.push(Self::shard_key(shard_index));
+            shard_path
+        })
+    }
+
+    /// Path query for a single key, targeting its shard only
+    pub fn key_path_query(&self, key: &[u8]) -> CostContext<PathQuery> {
+        self.shard_path(key)
+            .map(|shard_path| PathQuery::new_single_key(shard_path, key.to_vec()))
+    }
+
+    /// Path query applying the query to every shard. Results come shard after
+    /// shard, so a limit doesn't select the smallest keys across shards.
+    pub fn path_query(&self, query: Query, limit: Option<u16>) -> PathQuery {
+        let mut shards_query = Query::new();
+        shards_query.insert_range_to(..SHARD_COUNT_KEY.to_vec());
+        shards_query.set_subquery(query);
+        PathQuery::new(
+            self.path.clone(),
+            SizedQuery::new(shards_query, limit, None),
+        )
+    }
+}
+
+#[cfg(feature = "full")]
+impl ShardedTree {
+    /// Inserts the empty shard subtrees and the shard count, the sharded tree
+    /// path must exist
+    pub fn create(&self, db: &GroveDb, transaction: TransactionArg) -> CostResult<(), Error> {
+        let mut ops: Vec<GroveDbOp> = (0..self.shard_count)
+            .map(|shard_index| {
+                GroveDbOp::insert_op(
+                    self.path.clone(),
+                    Self::shard_key(shard_index),
+                    Element::empty_tree(),
+                )
+            })
+            .collect();
+        ops.push(GroveDbOp::insert_op(
+            self.path.clone(),
+            SHARD_COUNT_KEY.to_vec(),
+            Element::new_item(self.shard_count.to_be_bytes().to_vec()),
+        ));
+        db.apply_batch(ops, None, transaction)
+    }
+
+    /// Opens the sharded tree created at the given path, failing if it was
+    /// created with another shard count
+    pub fn open(
+        db: &GroveDb,
+        path: Vec<Vec<u8>>,
+        shard_count: u16,
+        transaction: TransactionArg,
+    ) -> CostResult<Self, Error> {
+        let mut cost = OperationCost::default();
+        let sharded_tree = cost_return_on_error_no_add!(&cost, Self::new(path, shard_count));
+        let element = cost_return_on_error!(
+            &mut cost,
+            db.get_raw(
+                sharded_tree.path.iter().map(|segment| segment.as_slice()),
+                &SHARD_COUNT_KEY,
+                transaction,
+            )
+        );
+        let stored_shard_count = match element {
+            Element::Item(bytes, _) if bytes.len() == 2 => u16::from_be_bytes([bytes[0], bytes[1]]),
+            _ => {
+                return Err(Error::CorruptedData(
+                    "sharded tree shard count is corrupted".to_owned(),
+                ))
+                .wrap_with_cost(cost)
+            }
+        };
+        if stored_shard_count != shard_count {
+            return Err(Error::InvalidInput(
+                "sharded tree was created with another shard count",
+            ))
+            .wrap_with_cost(cost);
+        }
+        Ok(sharded_tree).wrap_with_cost(cost)
+    }
+
+    /// Inserts an element into the shard of its key
+    pub fn insert(
+        &self,
+        db: &GroveDb,
+        key: &[u8],
+        element: Element,
+        options: Option<InsertOptions>,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+        let shard_path = self.shard_path(key).unwrap_add_cost(&mut cost);
+        db.insert(
+            shard_path.iter().map(|segment| segment.as_slice()),
+            key,
+            element,
+            options,
+            transaction,
+        )
+        .add_cost(cost)
+    }
+
+    /// Gets an element from the shard of its key, following references
+    pub fn get(
+        &self,
+        db: &GroveDb,
+        key: &[u8],
+        transaction: TransactionArg,
+    ) -> CostResult<Element, Error> {
+        let mut cost = OperationCost::default();
+        let shard_path = self.shard_path(key).unwrap_add_cost(&mut cost);
+        db.get(
+            shard_path.iter().map(|segment| segment.as_slice()),
+            key,
+            transaction,
+        )
+        .add_cost(cost)
+    }
+
+    /// Deletes an element from the shard of its key
+    pub fn delete(
+        &self,
+        db: &GroveDb,
+        key: &[u8],
+        options: Option<DeleteOptions>,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error> {
+        let mut cost = OperationCost::default();
+        let shard_path = self.shard_path(key).unwrap_add_cost(&mut cost);
+        db.delete(
+            shard_path.iter().map(|segment| segment.as_slice()),
+            key,
+            options,
+            transaction,
+        )
+        .add_cost(cost)
+    }
+
+    /// Queries every shard and returns the key element pairs ordered by key in
+    /// the direction of the query. The offset and limit apply to the merged
+    /// results, each shard is asked for at most as many results as the
+    /// offset and limit cover.
+    pub fn query(
+        &self,
+        db: &GroveDb,
+        query: Query,
+        limit: Option<u16>,
+        offset: Option<u16>,
+        transaction: TransactionArg,
+    ) -> CostResult<Vec<KeyElementPair>, Error> {
+        let mut cost = OperationCost::default();
+
+        let offset = offset.unwrap_or_default();
+        let shard_limit = limit.and_then(|limit| limit.checked_add(offset));
+        let left_to_right = query.left_to_right;
+        let mut key_elements = Vec::new();
+        for shard_index in 0..self.shard_count {
+            let mut shard_path = self.path.clone();
+            shard_path.push(Self::shard_key(shard_index));
+            let (elements, _) = cost_return_on_error!(
+                &mut cost,
+                db.query_raw(
+                    &PathQuery::new(
+                        shard_path,
+                        SizedQuery::new(query.clone(), shard_limit, None)
+                    ),
+                    true,
+                    QueryResultType::QueryKeyElementPairResultType,
+                    transaction,
+                )
+            );
+            key_elements.extend(elements.to_key_elements());
+        }
+        if left_to_right {
+            key_elements.sort_by(|(a, _), (b, _)| a.cmp(b));
+        } else {
+            key_elements.sort_by(|(a, _), (b, _)| b.cmp(a));
+        }
+        let key_elements = key_elements
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit.map_or(usize::MAX, |limit| limit as usize))
+            .collect();
+        Ok(key_elements).wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use crate::{
+        sharded_tree::ShardedTree,
+        tests::{make_test_grovedb, TEST_LEAF},
+        Element, Error, GroveDb, Query,
+    };
+
+    #[test]
+    fn test_sharded_tree_hides_shards_but_proves_them() {
+        assert!(matches!(
+            ShardedTree::new(vec![TEST_LEAF.to_vec()], 0),
+            Err(Error::InvalidInput(_))
+        ));
+
+        let db = make_test_grovedb();
+        db.insert([TEST_LEAF], b"sharded", Element::empty_tree(), None, None)
+            .unwrap()
+            .expect("should insert sharded tree");
+        let sharded_tree =
+            ShardedTree::new(vec![TEST_LEAF.to_vec(), b"sharded".to_vec()], 4).unwrap();
+        sharded_tree
+            .create(&db, None)
+            .unwrap()
+            .expect("should create shards");
+
+        let keys: Vec<Vec<u8>> = (0u8..20).map(|i| vec![i]).collect();
+        for key in &keys {
+            sharded_tree
+                .insert(&db, key, Element::new_item(key.clone()), None, None)
+                .unwrap()
+                .expect("should insert into shard");
+        }
+        assert_eq!(
+            sharded_tree.get(&db, &[7], None).unwrap().unwrap(),
+            Element::new_item(vec![7])
+        );
+        assert!(sharded_tree.shard_index(&[7]).unwrap() < 4);
+
+        let mut query = Query::new();
+        query.insert_all();
+        let key_elements = sharded_tree
+            .query(&db, query.clone(), None, None, None)
+            .unwrap()
+            .expect("should query shards");
+        assert_eq!(
+            key_elements
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>(),
+            keys
+        );
+
+        let limited = sharded_tree
+            .query(&db, query.clone(), Some(3), Some(2), None)
+            .unwrap()
+            .expect("should query shards with limit and offset");
+        assert_eq!(
+            limited.into_iter().map(|(key, _)| key).collect::<Vec<_>>(),
+            keys[2..5].to_vec()
+        );
+        let mut right_to_left_query = query.clone();
+        right_to_left_query.left_to_right = false;
+        let limited = sharded_tree
+            .query(&db, right_to_left_query, Some(3), Some(2), None)
+            .unwrap()
+            .expect("should query shards right to left");
+        assert_eq!(
+            limited.into_iter().map(|(key, _)| key).collect::<Vec<_>>(),
+            vec![vec![17], vec![16], vec![15]]
+        );
+
+        assert_eq!(
+            ShardedTree::open(&db, sharded_tree.path().to_vec(), 4, None)
+                .unwrap()
+                .expect("should open with the same shard count"),
+            sharded_tree
+        );
+        assert!(matches!(
+            ShardedTree::open(&db, sharded_tree.path().to_vec(), 8, None).unwrap(),
+            Err(Error::InvalidInput(_))
+        ));
+
+        let path_query = sharded_tree.path_query(query, None);
+        let proof = db.prove_query(&path_query).unwrap().expect("should prove");
+        let (root_hash, result_set) =
+            GroveDb::verify_query(&proof, &path_query).expect("should verify");
+        assert_eq!(root_hash, db.root_hash(None).unwrap().unwrap());
+        assert_eq!(result_set.len(), keys.len());
+        assert!(result_set
+            .iter()
+            .all(|(path, ..)| path.len() == 3 && path[..2] == sharded_tree.path()[..]));
+
+        sharded_tree
+            .delete(&db, &[7], None, None)
+            .unwrap()
+            .expect("should delete from shard");
+        assert!(matches!(
+            sharded_tree.get(&db, &[7], None).unwrap(),
+            Err(Error::PathKeyNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,422 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+//! Subtree path
+//! Typed paths to subtrees. A `SubtreePath` borrows its segments so deriving a
+//! parent is free, a `SubtreePathBuilder` owns the segments added on top of
+//! borrowed base segments so deriving a child doesn't copy the whole path.
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use std::iter::FusedIterator;
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Segments borrowed by a subtree path
+#[derive(Debug, Clone, Copy)]
+enum Segments<'b> {
+    Slices(&'b [&'b [u8]]),
+    Vecs(&'b [Vec<u8>]),
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl<'b> Segments<'b> {
+    fn len(&self) -> usize {
+        match self {
+            Segments::Slices(segments) => segments.len(),
+            Segments::Vecs(segments) => segments.len(),
+        }
+    }
+
+    fn get(&self, index: usize) -> &'b [u8] {
+        match self {
+            Segments::Slices(segments) => segments[index],
+            Segments::Vecs(segments) => segments[index].as_slice(),
+        }
+    }
+
+    fn split_last(&self) -> Option<(Segments<'b>, &'b [u8])> {
+        match self {
+            Segments::Slices(segments) => segments
+                .split_last()
+                .map(|(last, parent)| (Segments::Slices(parent), *last)),
+            Segments::Vecs(segments) => segments
+                .split_last()
+                .map(|(last, parent)| (Segments::Vecs(parent), last.as_slice())),
+        }
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Borrowed path to a subtree, an empty path is the root tree
+#[derive(Debug, Clone, Copy)]
+pub struct SubtreePath<'b> {
+    base: Segments<'b>,
+    relative: &'b [Vec<u8>],
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+#[allow(clippy::len_without_is_empty)]
+impl<'b> SubtreePath<'b> {
+    /// Path of the root tree
+    pub fn root() -> Self {
+        SubtreePath {
+            base: Segments::Slices(&[]),
+            relative: &[],
+        }
+    }
+
+    /// Number of segments
+    pub fn len(&self) -> usize {
+        self.base.len() + self.relative.len()
+    }
+
+    /// Returns true if the path is the root tree path
+    pub fn is_root(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over the segments from the root
+    pub fn iter(&self) -> SubtreePathIter<'b> {
+        SubtreePathIter {
+            path: *self,
+            front: 0,
+            back: self.len(),
+        }
+    }
+
+    /// Splits the path into the parent path and the last segment, returns
+    /// `None` for the root tree path. Doesn't allocate.
+    pub fn derive_parent(&self) -> Option<(SubtreePath<'b>, &'b [u8])> {
+        if let Some((last, relative)) = self.relative.split_last() {
+            Some((
+                SubtreePath {
+                    base: self.base,
+                    relative,
+                },
+                last.as_slice(),
+            ))
+        } else {
+            self.base.split_last().map(|(base, last)| {
+                (
+                    SubtreePath {
+                        base,
+                        relative: &[],
+                    },
+                    last,
+                )
+            })
+        }
+    }
+
+    /// Path of the child subtree under `segment`, the borrowed base segments
+    /// aren't copied
+    pub fn derive_owned_with_child(&self, segment: &[u8]) -> SubtreePathBuilder<'b> {
+        let mut relative = Vec::with_capacity(self.relative.len() + 1);
+        relative.extend_from_slice(self.relative);
+        relative.push(segment.to_vec());
+        SubtreePathBuilder {
+            base: self.base,
+            relative,
+        }
+    }
+
+    /// Copies the segments into an owned path
+    pub fn to_vec(&self) -> Vec<Vec<u8>> {
+        self.iter().map(|segment| segment.to_vec()).collect()
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl Default for SubtreePath<'_> {
+    fn default() -> Self {
+        SubtreePath::root()
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl PartialEq for SubtreePath<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl Eq for SubtreePath<'_> {}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl<'b> From<&'b [&'b [u8]]> for SubtreePath<'b> {
+    fn from(segments: &'b [&'b [u8]]) -> Self {
+        SubtreePath {
+            base: Segments::Slices(segments),
+            relative: &[],
+        }
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl<'b, const N: usize> From<&'b [&'b [u8]; N]> for SubtreePath<'b> {
+    fn from(segments: &'b [&'b [u8]; N]) -> Self {
+        SubtreePath::from(segments.as_slice())
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl<'b> From<&'b [Vec<u8>]> for SubtreePath<'b> {
+    fn from(segments: &'b [Vec<u8>]) -> Self {
+        SubtreePath {
+            base: Segments::Vecs(segments),
+            relative: &[],
+        }
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl<'b> From<&'b Vec<Vec<u8>>> for SubtreePath<'b> {
+    fn from(segments: &'b Vec<Vec<u8>>) -> Self {
+        SubtreePath::from(segments.as_slice())
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl<'s, 'b: 's> From<&'s SubtreePathBuilder<'b>> for SubtreePath<'s> {
+    fn from(builder: &'s SubtreePathBuilder<'b>) -> Self {
+        builder.as_path()
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl<'b> IntoIterator for SubtreePath<'b> {
+    type IntoIter = SubtreePathIter<'b>;
+    type Item = &'b [u8];
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Iterator over the segments of a subtree path
+#[derive(Debug, Clone)]
+pub struct SubtreePathIter<'b> {
+    path: SubtreePath<'b>,
+    front: usize,
+    back: usize,
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl<'b> SubtreePathIter<'b> {
+    fn segment(&self, index: usize) -> &'b [u8] {
+        let base_len = self.path.base.len();
+        if index < base_len {
+            self.path.base.get(index)
+        } else {
+            self.path.relative[index - base_len].as_slice()
+        }
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl<'b> Iterator for SubtreePathIter<'b> {
+    type Item = &'b [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        let segment = self.segment(self.front);
+        self.front += 1;
+        Some(segment)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl DoubleEndedIterator for SubtreePathIter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        Some(self.segment(self.back))
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl ExactSizeIterator for SubtreePathIter<'_> {}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl FusedIterator for SubtreePathIter<'_> {}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Path to a subtree owning the segments added on top of borrowed base
+/// segments
+#[derive(Debug, Clone)]
+pub struct SubtreePathBuilder<'b> {
+    base: Segments<'b>,
+    relative: Vec<Vec<u8>>,
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl Default for SubtreePathBuilder<'_> {
+    fn default() -> Self {
+        SubtreePathBuilder {
+            base: Segments::Slices(&[]),
+            relative: Vec::new(),
+        }
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+#[allow(clippy::len_without_is_empty)]
+impl<'b> SubtreePathBuilder<'b> {
+    /// Builder starting at the root tree
+    pub fn new() -> Self {
+        SubtreePathBuilder::default()
+    }
+
+    /// Borrows the builder as a subtree path
+    pub fn as_path(&self) -> SubtreePath<'_> {
+        SubtreePath {
+            base: self.base,
+            relative: self.relative.as_slice(),
+        }
+    }
+
+    /// Number of segments
+    pub fn len(&self) -> usize {
+        self.base.len() + self.relative.len()
+    }
+
+    /// Returns true if the path is the root tree path
+    pub fn is_root(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends a segment to the path
+    pub fn push_segment(&mut self, segment: &[u8]) {
+        self.relative.push(segment.to_vec());
+    }
+
+    /// Path of the child subtree under `segment` borrowing this path
+    pub fn derive_owned_with_child(&self, segment: &[u8]) -> SubtreePathBuilder<'_> {
+        self.as_path().derive_owned_with_child(segment)
+    }
+
+    /// Copies the segments into an owned path
+    pub fn to_vec(&self) -> Vec<Vec<u8>> {
+        self.as_path().to_vec()
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl<'b> From<SubtreePath<'b>> for SubtreePathBuilder<'b> {
+    fn from(path: SubtreePath<'b>) -> Self {
+        SubtreePathBuilder {
+            base: path.base,
+            relative: path.relative.to_vec(),
+        }
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl From<Vec<Vec<u8>>> for SubtreePathBuilder<'_> {
+    fn from(segments: Vec<Vec<u8>>) -> Self {
+        SubtreePathBuilder {
+            base: Segments::Slices(&[]),
+            relative: segments,
+        }
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl<S: AsRef<[u8]>> Extend<S> for SubtreePathBuilder<'_> {
+    fn extend<T: IntoIterator<Item = S>>(&mut self, segments: T) {
+        self.relative.extend(
+            segments
+                .into_iter()
+                .map(|segment| segment.as_ref().to_vec()),
+        );
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl PartialEq for SubtreePathBuilder<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_path() == other.as_path()
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl Eq for SubtreePathBuilder<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derive_parent_and_child() {
+        let base: &[&[u8]] = &[b"a", b"b"];
+        let path = SubtreePath::from(base);
+        let mut child = path.derive_owned_with_child(b"c");
+        child.push_segment(b"d");
+        assert_eq!(child.len(), 4);
+        assert_eq!(
+            child.to_vec(),
+            vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"d".to_vec()]
+        );
+
+        let (parent, last) = child.as_path().derive_parent().expect("should have parent");
+        assert_eq!(last, b"d");
+        let (parent, last) = parent.derive_parent().expect("should have parent");
+        assert_eq!(last, b"c");
+        assert_eq!(parent, path);
+        let (parent, _) = parent.derive_parent().expect("should have parent");
+        let (parent, _) = parent.derive_parent().expect("should have parent");
+        assert!(parent.is_root());
+        assert!(parent.derive_parent().is_none());
+    }
+
+    #[test]
+    fn test_iteration_from_both_ends() {
+        let base = vec![b"a".to_vec()];
+        let path = SubtreePath::from(&base);
+        let mut builder = path.derive_owned_with_child(b"b");
+        builder.extend([b"c"]);
+
+        let mut iter = builder.as_path().into_iter();
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.next_back(), Some(b"c".as_slice()));
+        assert_eq!(iter.next(), Some(b"a".as_slice()));
+        assert_eq!(iter.len(), 1);
+        assert_eq!(iter.next(), Some(b"b".as_slice()));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+}
@@ -0,0 +1,238 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Key ordering tests
+
+use merk::proofs::{query::QueryItem, Query};
+
+use crate::{
+    batch::GroveDbOp,
+    query_result_type::QueryResultType::QueryKeyElementPairResultType,
+    tests::{make_test_grovedb, TempGroveDb, TEST_LEAF},
+    Element, Error, GroveDb, KeyOrdering, PathQuery, SizedQuery,
+};
+
+fn make_ordered_tree_db(key_ordering: KeyOrdering, keys: &[Vec<u8>]) -> TempGroveDb {
+    let db = make_test_grovedb();
+    db.insert(
+        [TEST_LEAF],
+        b"ordered",
+        Element::empty_ordered_tree(key_ordering),
+        None,
+        None,
+    )
+    .unwrap()
+    .expect("should insert ordered tree");
+    for (i, key) in keys.iter().enumerate() {
+        db.insert(
+            [TEST_LEAF, b"ordered"],
+            key,
+            Element::new_item(vec![i as u8]),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("should insert item");
+    }
+    db
+}
+
+fn all_keys_query() -> Query {
+    let mut query = Query::new();
+    query.insert_all();
+    query
+}
+
+fn query_keys(db: &GroveDb, path_query: &PathQuery) -> Vec<Vec<u8>> {
+    let (elements, _) = db
+        .query_raw(path_query, true, QueryKeyElementPairResultType, None)
+        .unwrap()
+        .expect("should query");
+    elements.to_keys()
+}
+
+fn proved_keys(db: &GroveDb, path_query: &PathQuery) -> Vec<Vec<u8>> {
+    let proof = db
+        .prove_query(path_query)
+        .unwrap()
+        .expect("should generate proof");
+    let (root_hash, result_set) =
+        GroveDb::verify_query_raw(&proof, path_query).expect("should verify proof");
+    assert_eq!(root_hash, db.root_hash(None).unwrap().unwrap());
+    result_set.into_iter().map(|result| result.key).collect()
+}
+
+#[test]
+fn test_reverse_ordered_tree_queries_and_proofs() {
+    let keys: Vec<Vec<u8>> = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"d".to_vec()];
+    let db = make_ordered_tree_db(KeyOrdering::ReverseLexicographic, &keys);
+
+    let element = db
+        .get([TEST_LEAF], b"ordered", None)
+        .unwrap()
+        .expect("should get ordered tree");
+    assert_eq!(element.key_ordering(), KeyOrdering::ReverseLexicographic);
+
+    // the latest keys come first without inverting the key bytes
+    let path_query = PathQuery::new(
+        vec![TEST_LEAF.to_vec(), b"ordered".to_vec()],
+        SizedQuery::new(all_keys_query(), Some(2), None),
+    );
+    let expected = vec![b"d".to_vec(), b"c".to_vec()];
+    assert_eq!(query_keys(&db, &path_query), expected);
+    assert_eq!(proved_keys(&db, &path_query), expected);
+
+    // ranges are expressed in the declared ordering
+    let mut query = Query::new();
+    query.insert_range(b"c".to_vec()..b"a".to_vec());
+    let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec(), b"ordered".to_vec()], query);
+    let expected = vec![b"c".to_vec(), b"b".to_vec()];
+    assert_eq!(query_keys(&db, &path_query), expected);
+    assert_eq!(proved_keys(&db, &path_query), expected);
+
+    // the ordering also applies when the tree is reached through a subquery
+    let mut query = Query::new();
+    query.insert_key(b"ordered".to_vec());
+    let mut subquery = Query::new();
+    subquery.insert_item(QueryItem::RangeTo(..b"b".to_vec()));
+    query.set_subquery(subquery);
+    let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+    let expected = vec![b"d".to_vec(), b"c".to_vec()];
+    assert_eq!(query_keys(&db, &path_query), expected);
+    assert_eq!(proved_keys(&db, &path_query), expected);
+}
+
+#[test]
+fn test_proof_must_declare_the_subtree_key_ordering() {
+    let keys: Vec<Vec<u8>> = vec![b"a".to_vec(), b"b".to_vec()];
+    let db = make_ordered_tree_db(KeyOrdering::ReverseLexicographic, &keys);
+
+    let path_query = PathQuery::new(
+        vec![TEST_LEAF.to_vec(), b"ordered".to_vec()],
+        SizedQuery::new(all_keys_query(), Some(1), None),
+    );
+    let proof = db
+        .prove_query(&path_query)
+        .unwrap()
+        .expect("should generate proof");
+
+    // dropping the declared ordering makes the proof invalid
+    let key_ordering_bytes = KeyOrdering::ReverseLexicographic
+        .serialize()
+        .expect("should serialize");
+    let stripped_proof = &proof[2 + key_ordering_bytes.len()..];
+    assert!(matches!(
+        GroveDb::verify_query_raw(stripped_proof, &path_query),
+        Err(Error::InvalidProof(_))
+    ));
+}
+
+#[test]
+fn test_fixed_width_numeric_ordered_tree() {
+    let keys: Vec<Vec<u8>> = [3u64, 20, 100, 256]
+        .iter()
+        .map(|key| key.to_be_bytes().to_vec())
+        .collect();
+    let db = make_ordered_tree_db(KeyOrdering::FixedWidthNumeric(8), &keys);
+
+    assert!(matches!(
+        db.insert(
+            [TEST_LEAF, b"ordered"],
+            &7u32.to_be_bytes(),
+            Element::new_item(vec![0]),
+            None,
+            None,
+        )
+        .unwrap(),
+        Err(Error::InvalidInput(_))
+    ));
+
+    let mut query = Query::new();
+    query.insert_range_from(20u64.to_be_bytes().to_vec()..);
+    let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec(), b"ordered".to_vec()], query);
+    let expected = keys[1..].to_vec();
+    assert_eq!(query_keys(&db, &path_query), expected);
+    assert_eq!(proved_keys(&db, &path_query), expected);
+}
+
+#[test]
+fn test_batch_ops_honor_fixed_width_numeric_ordering() {
+    let db = make_ordered_tree_db(
+        KeyOrdering::FixedWidthNumeric(8),
+        &[3u64.to_be_bytes().to_vec()],
+    );
+    let ordered_path = vec![TEST_LEAF.to_vec(), b"ordered".to_vec()];
+
+    let narrow_key_ops = [
+        GroveDbOp::insert_op(ordered_path.clone(), vec![1], Element::new_item(vec![0])),
+        GroveDbOp::replace_op(ordered_path.clone(), vec![1], Element::new_item(vec![0])),
+        GroveDbOp::patch_op(ordered_path.clone(), vec![1], Element::new_item(vec![0]), 0),
+        GroveDbOp::rekey_op(ordered_path.clone(), 3u64.to_be_bytes().to_vec(), vec![1]),
+    ];
+    for op in narrow_key_ops {
+        assert!(matches!(
+            db.apply_batch(vec![op], None, None).unwrap(),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    let ops = vec![
+        GroveDbOp::insert_op(
+            vec![TEST_LEAF.to_vec()],
+            b"new_ordered".to_vec(),
+            Element::empty_ordered_tree(KeyOrdering::FixedWidthNumeric(4)),
+        ),
+        GroveDbOp::insert_op(
+            vec![TEST_LEAF.to_vec(), b"new_ordered".to_vec()],
+            7u64.to_be_bytes().to_vec(),
+            Element::new_item(vec![0]),
+        ),
+    ];
+    assert!(matches!(
+        db.apply_batch(ops, None, None).unwrap(),
+        Err(Error::InvalidInput(_))
+    ));
+
+    db.apply_batch(
+        vec![GroveDbOp::rekey_op(
+            ordered_path.clone(),
+            3u64.to_be_bytes().to_vec(),
+            4u64.to_be_bytes().to_vec(),
+        )],
+        None,
+        None,
+    )
+    .unwrap()
+    .expect("should rekey within the fixed width");
+    assert_eq!(
+        db.get([TEST_LEAF, b"ordered"], &4u64.to_be_bytes(), None)
+            .unwrap()
+            .expect("should get rekeyed element"),
+        Element::new_item(vec![0])
+    );
+}
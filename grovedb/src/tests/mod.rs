@@ -30,6 +30,8 @@
 
 pub mod common;
 
+mod key_ordering_tests;
+
 mod query_tests;
 
 mod sum_tree_tests;
@@ -54,7 +56,7 @@ pub const TEST_LEAF: &[u8] = b"test_leaf";
 
 pub const ANOTHER_TEST_LEAF: &[u8] = b"test_leaf2";
 
-const DEEP_LEAF: &[u8] = b"deep_leaf";
+pub const DEEP_LEAF: &[u8] = b"deep_leaf";
 
 /// GroveDB wrapper to keep temp directory alive
 pub struct TempGroveDb {
@@ -83,9 +85,12 @@ impl Visualize for TempGroveDb {
 }
 
 /// A helper method to create an empty GroveDB
+/// Propagation is eager, as costs of operations in transactions are checked
+/// against estimated costs, which always propagate to the root
 pub fn make_empty_grovedb() -> TempGroveDb {
     let tmp_dir = TempDir::new().unwrap();
     let db = GroveDb::open(tmp_dir.path()).unwrap();
+    db.set_eager_propagation(true);
     TempGroveDb {
         _tmp_dir: tmp_dir,
         grove_db: db,
@@ -100,6 +105,7 @@ pub fn make_test_grovedb() -> TempGroveDb {
     //  another_test_leaf
     let tmp_dir = TempDir::new().unwrap();
     let mut db = GroveDb::open(tmp_dir.path()).unwrap();
+    db.set_eager_propagation(true);
     add_test_leaves(&mut db);
     TempGroveDb {
         _tmp_dir: tmp_dir,
@@ -757,6 +763,188 @@ fn test_follow_references() {
     );
 }
 
+#[test]
+fn test_follow_references_with_resolution_cache() {
+    let db = make_test_grovedb();
+    let element = Element::new_item(b"ayy".to_vec());
+
+    db.insert([TEST_LEAF], b"key1", element.clone(), None, None)
+        .unwrap()
+        .expect("successful value insert");
+    // A chain of two references ending up at the item
+    db.insert(
+        [TEST_LEAF],
+        b"reference_1",
+        Element::new_reference(ReferencePathType::AbsolutePathReference(vec![
+            TEST_LEAF.to_vec(),
+            b"key1".to_vec(),
+        ])),
+        None,
+        None,
+    )
+    .unwrap()
+    .expect("successful reference insert");
+    let reference_2_path = vec![TEST_LEAF.to_vec(), b"reference_1".to_vec()];
+
+    let mut reference_cache = ReferenceResolutionCache::new();
+    let first_resolution = db
+        .follow_reference_with_cache(reference_2_path.clone(), true, &mut reference_cache, None)
+        .unwrap_add_cost(&mut Default::default())
+        .expect("should follow reference");
+    assert_eq!(first_resolution, element);
+    assert_eq!(reference_cache.len(), 2);
+
+    // Second resolution within the same operation doesn't touch the storage
+    let second_resolution =
+        db.follow_reference_with_cache(reference_2_path.clone(), true, &mut reference_cache, None);
+    assert_eq!(second_resolution.cost().seek_count, 0);
+    assert_eq!(
+        second_resolution.unwrap().expect("should follow reference"),
+        element
+    );
+
+    // Without a shared cache the storage is read again
+    assert_ne!(
+        db.follow_reference(reference_2_path, true, None)
+            .cost()
+            .seek_count,
+        0
+    );
+}
+
+#[test]
+fn test_resolve_references_in_key_order() {
+    let db = make_test_grovedb();
+    db.insert([TEST_LEAF], b"index", Element::empty_tree(), None, None)
+        .unwrap()
+        .expect("successful subtree insert");
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"doc_a",
+        Element::new_item(b"a".to_vec()),
+        None,
+        None,
+    )
+    .unwrap()
+    .expect("successful value insert");
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"doc_b",
+        Element::new_item(b"b".to_vec()),
+        None,
+        None,
+    )
+    .unwrap()
+    .expect("successful value insert");
+    let reference_to = |key: &[u8]| {
+        Element::new_reference(ReferencePathType::AbsolutePathReference(vec![
+            ANOTHER_TEST_LEAF.to_vec(),
+            key.to_vec(),
+        ]))
+    };
+    // Index entries sharing a target document next to a plain item
+    for (key, element) in [
+        (b"1".as_slice(), reference_to(b"doc_b")),
+        (b"2".as_slice(), reference_to(b"doc_a")),
+        (b"3".as_slice(), reference_to(b"doc_b")),
+        (b"4".as_slice(), Element::new_item(b"plain".to_vec())),
+    ] {
+        db.insert([TEST_LEAF, b"index"], key, element, None, None)
+            .unwrap()
+            .expect("successful index insert");
+    }
+    // An entry resolving through a second reference
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"doc_c",
+        Element::new_reference(ReferencePathType::SiblingReference(b"doc_a".to_vec())),
+        None,
+        None,
+    )
+    .unwrap()
+    .expect("successful reference insert");
+    db.insert(
+        [TEST_LEAF, b"index"],
+        b"5",
+        reference_to(b"doc_c"),
+        None,
+        None,
+    )
+    .unwrap()
+    .expect("successful index insert");
+
+    let keys: [&[u8]; 6] = [b"3", b"1", b"4", b"5", b"2", b"3"];
+    let resolved = db
+        .resolve_references([TEST_LEAF, b"index"], keys, None)
+        .unwrap()
+        .expect("should resolve references");
+    assert_eq!(
+        resolved,
+        vec![
+            Element::new_item(b"b".to_vec()),
+            Element::new_item(b"b".to_vec()),
+            Element::new_item(b"plain".to_vec()),
+            Element::new_item(b"a".to_vec()),
+            Element::new_item(b"a".to_vec()),
+            Element::new_item(b"b".to_vec()),
+        ]
+    );
+    for (key, element) in keys.into_iter().zip(resolved) {
+        assert_eq!(
+            db.get([TEST_LEAF, b"index"], key, None)
+                .unwrap()
+                .expect("successful get"),
+            element
+        );
+    }
+
+    // A missing target fails the whole resolution
+    db.insert(
+        [TEST_LEAF, b"index"],
+        b"6",
+        reference_to(b"doc_x"),
+        None,
+        None,
+    )
+    .unwrap()
+    .expect("successful index insert");
+    assert!(matches!(
+        db.resolve_references(
+            [TEST_LEAF, b"index"],
+            [b"1".as_slice(), b"6".as_slice()],
+            None
+        )
+        .unwrap(),
+        Err(Error::CorruptedReferencePathKeyNotFound(_))
+    ));
+    assert!(matches!(
+        db.resolve_references([TEST_LEAF, b"index"], [b"7".as_slice()], None)
+            .unwrap(),
+        Err(Error::PathKeyNotFound(_))
+    ));
+}
+
+#[test]
+fn test_resolve_references_detects_cycles() {
+    let db = make_test_grovedb();
+    for (key, target) in [(b"ref_a", b"ref_b"), (b"ref_b", b"ref_a")] {
+        db.insert(
+            [TEST_LEAF],
+            key,
+            Element::new_reference(ReferencePathType::SiblingReference(target.to_vec())),
+            None,
+            None,
+        )
+        .unwrap()
+        .expect("successful reference insert");
+    }
+    assert!(matches!(
+        db.resolve_references([TEST_LEAF], [b"ref_a".as_slice()], None)
+            .unwrap(),
+        Err(Error::CyclicReference)
+    ));
+}
+
 #[test]
 fn test_reference_must_point_to_item() {
     let db = make_test_grovedb();
@@ -827,7 +1015,34 @@ fn test_too_many_indirections() {
         .get([TEST_LEAF], &keygen(MAX_REFERENCE_HOPS + 1), None)
         .unwrap();
 
-    assert!(matches!(result, Err(Error::ReferenceLimit)));
+    match result {
+        Err(Error::ReferenceLimit(chain)) => {
+            assert_eq!(chain.visited.len(), MAX_REFERENCE_HOPS);
+            assert_eq!(
+                chain.visited[0],
+                vec![TEST_LEAF.to_vec(), keygen(MAX_REFERENCE_HOPS)]
+            );
+            assert_eq!(chain.next_path, Some(vec![TEST_LEAF.to_vec(), keygen(0)]));
+            assert!(matches!(chain.last_element, Some(Element::Reference(..))));
+        }
+        _ => panic!("expected the reference limit to be exceeded"),
+    }
+
+    // the chain can still be walked one hop at a time
+    let mut step = db
+        .resolve_reference_step(vec![TEST_LEAF.to_vec(), keygen(1)], None)
+        .unwrap()
+        .expect("should resolve reference step");
+    let mut hops = 1;
+    while let Some(next_path) = step.next_path {
+        step = db
+            .resolve_reference_step(next_path, None)
+            .unwrap()
+            .expect("should resolve reference step");
+        hops += 1;
+    }
+    assert_eq!(hops, 2);
+    assert!(matches!(step.element, Element::Item(..)));
 }
 
 #[test]
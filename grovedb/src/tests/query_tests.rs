@@ -37,7 +37,8 @@ use crate::{
     query_result_type::{PathKeyOptionalElementTrio, QueryResultType},
     reference_path::ReferencePathType,
     tests::{
-        common::compare_result_sets, make_deep_tree, make_test_grovedb, TempGroveDb, TEST_LEAF,
+        common::compare_result_sets, make_deep_tree, make_test_grovedb, TempGroveDb,
+        ANOTHER_TEST_LEAF, TEST_LEAF,
     },
     Element, Error, GroveDb, PathQuery, SizedQuery,
 };
@@ -2400,3 +2401,149 @@ fn test_chained_path_query_verification() {
         )
     );
 }
+
+#[test]
+fn test_root_path_query_with_subquery() {
+    let db = make_deep_tree();
+
+    let mut query = Query::new();
+    query.insert_all();
+
+    // the root leaves themselves
+    let path_query = PathQuery::new_root(SizedQuery::new(query.clone(), None, None));
+    assert!(path_query.is_root());
+    let (elements, _) = db
+        .query_raw(
+            &path_query,
+            true,
+            QueryResultType::QueryKeyElementPairResultType,
+            None,
+        )
+        .unwrap()
+        .expect("should query root leaves");
+    assert_eq!(elements.len(), 3);
+
+    let proof = db.prove_query(&path_query).unwrap().unwrap();
+    let (hash, result_set) =
+        GroveDb::verify_query_raw(proof.as_slice(), &path_query).expect("should verify proof");
+    assert_eq!(hash, db.root_hash(None).unwrap().unwrap());
+    assert_eq!(result_set.len(), 3);
+
+    // subqueries descend through the subtrees of root leaves down to their items
+    let mut query = Query::new();
+    query.insert_key(TEST_LEAF.to_vec());
+    query.insert_key(ANOTHER_TEST_LEAF.to_vec());
+    let mut subquery = Query::new();
+    subquery.insert_all();
+    let mut items_query = Query::new();
+    items_query.insert_all();
+    subquery.set_subquery(items_query);
+    query.set_subquery(subquery);
+    let path_query = PathQuery::new_root(SizedQuery::new(query, None, None));
+    let (elements, _) = db
+        .query_raw(
+            &path_query,
+            true,
+            QueryResultType::QueryPathKeyElementTrioResultType,
+            None,
+        )
+        .unwrap()
+        .expect("should query root leaves subtrees");
+    assert_eq!(elements.len(), 7);
+
+    let proof = db.prove_query(&path_query).unwrap().unwrap();
+    let (hash, result_set) =
+        GroveDb::verify_query_raw(proof.as_slice(), &path_query).expect("should verify proof");
+    assert_eq!(hash, db.root_hash(None).unwrap().unwrap());
+    assert_eq!(result_set.len(), 7);
+    assert_eq!(
+        result_set
+            .iter()
+            .map(|result| (result.path.clone(), result.key.clone()))
+            .collect::<Vec<_>>(),
+        elements
+            .to_path_key_elements()
+            .into_iter()
+            .map(|(path, key, _)| (path, key))
+            .collect::<Vec<_>>()
+    );
+
+    // a verbose root proof can be verified against a subset query
+    let proof = db.prove_verbose(&path_query).unwrap().unwrap();
+    let mut subset_query = Query::new();
+    subset_query.insert_all();
+    let subset_path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], subset_query);
+    let (hash, result_set) = GroveDb::verify_subset_query(proof.as_slice(), &subset_path_query)
+        .expect("should verify subset proof");
+    assert_eq!(hash, db.root_hash(None).unwrap().unwrap());
+    assert_eq!(result_set.len(), 2);
+}
+
+#[test]
+fn test_verify_query_rejects_proof_in_other_direction() {
+    let db = make_deep_tree();
+
+    let mut query = Query::new_with_direction(false);
+    query.insert_all();
+    let path = vec![TEST_LEAF.to_vec(), b"innertree".to_vec()];
+    let right_to_left_path_query = PathQuery::new_unsized(path.clone(), query.clone());
+
+    let proof = db.prove_query(&right_to_left_path_query).unwrap().unwrap();
+    let (hash, result_set) =
+        GroveDb::verify_query_raw(&proof, &right_to_left_path_query).expect("should verify proof");
+    assert_eq!(hash, db.root_hash(None).unwrap().unwrap());
+    assert_eq!(
+        result_set
+            .iter()
+            .map(|result| result.key.clone())
+            .collect::<Vec<_>>(),
+        vec![b"key3".to_vec(), b"key2".to_vec(), b"key1".to_vec()]
+    );
+
+    // the results are ordered the other way than what a left to right query
+    // asked for
+    query.left_to_right = true;
+    let left_to_right_path_query = PathQuery::new_unsized(path, query);
+    assert!(matches!(
+        GroveDb::verify_query_raw(&proof, &left_to_right_path_query),
+        Err(Error::InvalidProof(_))
+    ));
+}
+
+#[test]
+fn test_verify_query_with_stats_sums_every_layer() {
+    let db = make_deep_tree();
+
+    let mut query = Query::new();
+    query.insert_all();
+    let inner_path_query = PathQuery::new_unsized(
+        vec![TEST_LEAF.to_vec(), b"innertree".to_vec()],
+        query.clone(),
+    );
+    let proof = db.prove_query(&inner_path_query).unwrap().unwrap();
+    let (hash, result_set, inner_stats) =
+        GroveDb::verify_query_with_stats(&proof, &inner_path_query).expect("should verify proof");
+    assert_eq!(hash, db.root_hash(None).unwrap().unwrap());
+    assert_eq!(result_set.len(), 3);
+    // the queried layer and the two layers proving its path
+    assert!(inner_stats.node_counts.total() >= result_set.len() + 2);
+    assert!(inner_stats.proof_bytes > 0 && inner_stats.proof_bytes < proof.len());
+
+    let mut subquery_query = Query::new();
+    subquery_query.insert_all();
+    subquery_query.set_subquery(query);
+    let subquery_path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], subquery_query);
+    let proof = db.prove_query(&subquery_path_query).unwrap().unwrap();
+    let (_, result_set, subquery_stats) =
+        GroveDb::verify_query_with_stats(&proof, &subquery_path_query)
+            .expect("should verify proof");
+    assert_eq!(
+        GroveDb::verify_query(&proof, &subquery_path_query)
+            .expect("should verify proof")
+            .1,
+        result_set
+    );
+    assert!(subquery_stats.node_counts.total() > inner_stats.node_counts.total());
+    assert!(subquery_stats.proof_bytes > inner_stats.proof_bytes);
+    assert!(subquery_stats.proof_bytes < proof.len());
+}
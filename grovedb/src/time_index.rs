@@ -0,0 +1,515 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Time indexes
+//! A time index keeps the elements of a primary subtree ordered by a
+//! timestamp in a secondary subtree. Index keys are the big endian timestamp
+//! followed by the key of the element in the primary subtree, and index
+//! entries are references to the primary elements, so range by time queries
+//! and their proofs return the elements themselves. Re-indexing an element
+//! takes its previous timestamp, so that a single entry points to it. Entries
+//! older than a cutoff are pruned in batches of bounded size, each with its own
+//! cost.
+
+#[cfg(feature = "full")]
+use std::collections::BTreeSet;
+
+#[cfg(feature = "full")]
+use costs::{cost_return_on_error, CostResult, CostsExt, OperationCost};
+
+#[cfg(feature = "full")]
+use crate::{
+    batch::GroveDbOp, query_result_type::QueryResultType, reference_path::ReferencePathType,
+    Element, GroveDb, TransactionArg,
+};
+#[cfg(any(feature = "full", feature = "verify"))]
+use crate::{Error, PathQuery, Query, QueryItem, SizedQuery};
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Length of the timestamp prefix of index keys
+pub const TIMESTAMP_LENGTH: usize = 8;
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// A primary subtree indexed by timestamp in a secondary subtree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeIndex {
+    primary_path: Vec<Vec<u8>>,
+    index_path: Vec<Vec<u8>>,
+}
+
+#[cfg(feature = "full")]
+/// An element of the primary subtree found through the time index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeIndexEntry {
+    /// Timestamp of the element
+    pub timestamp: u64,
+    /// Key of the element in the primary subtree
+    pub key: Vec<u8>,
+    /// The element
+    pub element: Element,
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl TimeIndex {
+    /// New time index of the primary subtree kept in the index subtree
+    pub fn new(primary_path: Vec<Vec<u8>>, index_path: Vec<Vec<u8>>) -> Self {
+        Self {
+            primary_path,
+            index_path,
+        }
+    }
+
+    /// Path of the indexed subtree
+    pub fn primary_path(&self) -> &[Vec<u8>] {
+        &self.primary_path
+    }
+
+    /// Path of the index subtree
+    pub fn index_path(&self) -> &[Vec<u8>] {
+        &self.index_path
+    }
+
+    /// Index key of a primary key at a timestamp
+    pub fn index_key(timestamp: u64, key: &[u8]) -> Vec<u8> {
+        let mut index_key = Vec::with_capacity(TIMESTAMP_LENGTH + key.len());
+        index_key.extend_from_slice(&timestamp.to_be_bytes());
+        index_key.extend_from_slice(key);
+        index_key
+    }
+
+    /// Splits an index key into the timestamp and the primary key
+    pub fn split_index_key(index_key: &[u8]) -> Result<(u64, &[u8]), Error> {
+        if index_key.len() < TIMESTAMP_LENGTH {
+            return Err(Error::CorruptedData(
+                "time index key is shorter than a timestamp".to_owned(),
+            ));
+        }
+        let (timestamp, key) = index_key.split_at(TIMESTAMP_LENGTH);
+        let mut timestamp_bytes = [0u8; TIMESTAMP_LENGTH];
+        timestamp_bytes.copy_from_slice(timestamp);
+        Ok((u64::from_be_bytes(timestamp_bytes), key))
+    }
+
+    /// Path query for the elements with a timestamp from `from` included up to
+    /// `to` excluded, oldest first unless `left_to_right` is false
+    pub fn range_path_query(
+        &self,
+        from: u64,
+        to: u64,
+        limit: Option<u16>,
+        left_to_right: bool,
+    ) -> PathQuery {
+        let query = Query::new_single_query_item_with_direction(
+            QueryItem::Range(Self::index_key(from, &[])..Self::index_key(to, &[])),
+            left_to_right,
+        );
+        PathQuery::new(self.index_path.clone(), SizedQuery::new(query, limit, None))
+    }
+
+    /// Path query for the oldest elements with a timestamp before `cutoff`
+    pub fn before_path_query(&self, cutoff: u64, limit: Option<u16>) -> PathQuery {
+        let query =
+            Query::new_single_query_item(QueryItem::RangeTo(..Self::index_key(cutoff, &[])));
+        PathQuery::new(self.index_path.clone(), SizedQuery::new(query, limit, None))
+    }
+}
+
+#[cfg(feature = "full")]
+impl TimeIndex {
+    /// Inserts the empty index subtree, its parent must exist
+    pub fn create(&self, db: &GroveDb, transaction: TransactionArg) -> CostResult<(), Error> {
+        let mut parent_path = self.index_path.clone();
+        let index_key = match parent_path.pop() {
+            Some(index_key) => index_key,
+            None => {
+                return Err(Error::InvalidPath(
+                    "the time index can't be the root tree".to_owned(),
+                ))
+                .wrap_with_cost(OperationCost::default())
+            }
+        };
+        db.apply_batch(
+            vec![GroveDbOp::insert_op(
+                parent_path,
+                index_key,
+                Element::empty_tree(),
+            )],
+            None,
+            transaction,
+        )
+    }
+
+    /// Operations inserting the element into the primary subtree and
+    /// indexing it at the timestamp. An element that is already indexed must
+    /// be given its `previous_timestamp`, its stale index entry is deleted.
+    pub fn insert_ops(
+        &self,
+        key: &[u8],
+        timestamp: u64,
+        previous_timestamp: Option<u64>,
+        element: Element,
+    ) -> Vec<GroveDbOp> {
+        let mut primary_element_path = self.primary_path.clone();
+        primary_element_path.push(key.to_vec());
+        let mut ops = vec![
+            GroveDbOp::insert_op(self.primary_path.clone(), key.to_vec(), element),
+            GroveDbOp::insert_op(
+                self.index_path.clone(),
+                Self::index_key(timestamp, key),
+                Element::new_reference(ReferencePathType::AbsolutePathReference(
+                    primary_element_path,
+                )),
+            ),
+        ];
+        if let Some(previous_timestamp) = previous_timestamp.filter(|t| *t != timestamp) {
+            ops.push(GroveDbOp::delete_op(
+                self.index_path.clone(),
+                Self::index_key(previous_timestamp, key),
+            ));
+        }
+        ops
+    }
+
+    /// Operations deleting the element indexed at the timestamp from both
+    /// subtrees
+    pub fn delete_ops(&self, key: &[u8], timestamp: u64) -> Vec<GroveDbOp> {
+        vec![
+            GroveDbOp::delete_op(self.primary_path.clone(), key.to_vec()),
+            GroveDbOp::delete_op(self.index_path.clone(), Self::index_key(timestamp, key)),
+        ]
+    }
+
+    /// Inserts the element into the primary subtree and indexes it at the
+    /// timestamp, see [`TimeIndex::insert_ops`]
+    pub fn insert(
+        &self,
+        db: &GroveDb,
+        key: &[u8],
+        timestamp: u64,
+        previous_timestamp: Option<u64>,
+        element: Element,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error> {
+        db.apply_batch(
+            self.insert_ops(key, timestamp, previous_timestamp, element),
+            None,
+            transaction,
+        )
+    }
+
+    /// Deletes the element indexed at the timestamp from both subtrees
+    pub fn delete(
+        &self,
+        db: &GroveDb,
+        key: &[u8],
+        timestamp: u64,
+        transaction: TransactionArg,
+    ) -> CostResult<(), Error> {
+        db.apply_batch(self.delete_ops(key, timestamp), None, transaction)
+    }
+
+    /// Returns the elements with a timestamp from `from` included up to `to`
+    /// excluded, see [`TimeIndex::range_path_query`]
+    pub fn query_range(
+        &self,
+        db: &GroveDb,
+        from: u64,
+        to: u64,
+        limit: Option<u16>,
+        left_to_right: bool,
+        transaction: TransactionArg,
+    ) -> CostResult<Vec<TimeIndexEntry>, Error> {
+        self.query_entries(
+            db,
+            &self.range_path_query(from, to, limit, left_to_right),
+            transaction,
+        )
+    }
+
+    /// Proves the elements with a timestamp from `from` included up to `to`
+    /// excluded, the proof verifies against
+    /// [`TimeIndex::range_path_query`] with the same arguments
+    pub fn prove_range(
+        &self,
+        db: &GroveDb,
+        from: u64,
+        to: u64,
+        limit: Option<u16>,
+        left_to_right: bool,
+    ) -> CostResult<Vec<u8>, Error> {
+        db.prove_query(&self.range_path_query(from, to, limit, left_to_right))
+    }
+
+    /// Deletes at most `max_entries` of the oldest elements with a timestamp
+    /// before `cutoff` in a single batch and returns how many were deleted,
+    /// so that pruning can be spread out and its cost metered per batch
+    pub fn prune_batch(
+        &self,
+        db: &GroveDb,
+        cutoff: u64,
+        max_entries: u16,
+        transaction: TransactionArg,
+    ) -> CostResult<usize, Error> {
+        let mut cost = OperationCost::default();
+
+        // expired entries are pruned as well, so the read isn't filtered
+        let (elements, _) = cost_return_on_error!(
+            &mut cost,
+            Element::get_raw_path_query(
+                &db.db,
+                &self.before_path_query(cutoff, Some(max_entries)),
+                true,
+                QueryResultType::QueryKeyElementPairResultType,
+                transaction,
+            )
+        );
+        let index_keys = elements.to_keys();
+        let pruned = index_keys.len();
+        let mut primary_keys = BTreeSet::new();
+        let mut ops = Vec::with_capacity(pruned * 2);
+        for index_key in index_keys {
+            let (_, key) = cost_return_on_error!(
+                &mut cost,
+                Self::split_index_key(&index_key).wrap_with_cost(OperationCost::default())
+            );
+            if primary_keys.insert(key.to_vec()) {
+                ops.push(GroveDbOp::delete_op(
+                    self.primary_path.clone(),
+                    key.to_vec(),
+                ));
+            }
+            ops.push(GroveDbOp::delete_op(self.index_path.clone(), index_key));
+        }
+        if pruned > 0 {
+            cost_return_on_error!(&mut cost, db.apply_batch(ops, None, transaction));
+        }
+        Ok(pruned).wrap_with_cost(cost)
+    }
+
+    /// Deletes every element with a timestamp before `cutoff`, in batches of
+    /// at most `batch_size` elements, and returns how many were deleted
+    pub fn prune(
+        &self,
+        db: &GroveDb,
+        cutoff: u64,
+        batch_size: u16,
+        transaction: TransactionArg,
+    ) -> CostResult<usize, Error> {
+        let mut cost = OperationCost::default();
+
+        if batch_size == 0 {
+            return Err(Error::InvalidInput("pruning batches can't be empty")).wrap_with_cost(cost);
+        }
+        let mut total = 0;
+        loop {
+            let pruned = cost_return_on_error!(
+                &mut cost,
+                self.prune_batch(db, cutoff, batch_size, transaction)
+            );
+            total += pruned;
+            if pruned < batch_size as usize {
+                return Ok(total).wrap_with_cost(cost);
+            }
+        }
+    }
+
+    fn query_entries(
+        &self,
+        db: &GroveDb,
+        path_query: &PathQuery,
+        transaction: TransactionArg,
+    ) -> CostResult<Vec<TimeIndexEntry>, Error> {
+        let mut cost = OperationCost::default();
+
+        let (elements, _) = cost_return_on_error!(
+            &mut cost,
+            db.query(
+                path_query,
+                true,
+                QueryResultType::QueryKeyElementPairResultType,
+                transaction,
+            )
+        );
+        let entries: Result<Vec<TimeIndexEntry>, Error> = elements
+            .to_key_elements()
+            .into_iter()
+            .map(|(index_key, element)| {
+                Self::split_index_key(&index_key).map(|(timestamp, key)| TimeIndexEntry {
+                    timestamp,
+                    key: key.to_vec(),
+                    element,
+                })
+            })
+            .collect();
+        entries.wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{make_test_grovedb, ANOTHER_TEST_LEAF, TEST_LEAF};
+
+    #[test]
+    fn test_time_index_range_queries_and_pruning() {
+        let db = make_test_grovedb();
+        let time_index = TimeIndex::new(
+            vec![TEST_LEAF.to_vec()],
+            vec![ANOTHER_TEST_LEAF.to_vec(), b"by_time".to_vec()],
+        );
+        time_index
+            .create(&db, None)
+            .unwrap()
+            .expect("should create index subtree");
+        for (key, timestamp) in [(b"d", 400u64), (b"a", 100), (b"c", 300), (b"b", 200)] {
+            time_index
+                .insert(
+                    &db,
+                    key,
+                    timestamp,
+                    None,
+                    Element::new_item(key.to_vec()),
+                    None,
+                )
+                .unwrap()
+                .expect("should insert indexed element");
+        }
+
+        let entries = time_index
+            .query_range(&db, 150, 400, None, true, None)
+            .unwrap()
+            .expect("should query range");
+        assert_eq!(
+            entries,
+            vec![
+                TimeIndexEntry {
+                    timestamp: 200,
+                    key: b"b".to_vec(),
+                    element: Element::new_item(b"b".to_vec()),
+                },
+                TimeIndexEntry {
+                    timestamp: 300,
+                    key: b"c".to_vec(),
+                    element: Element::new_item(b"c".to_vec()),
+                },
+            ]
+        );
+
+        let proof = time_index
+            .prove_range(&db, 150, 400, None, true)
+            .unwrap()
+            .expect("should prove range");
+        let (root_hash, result_set) =
+            GroveDb::verify_query(&proof, &time_index.range_path_query(150, 400, None, true))
+                .expect("should verify range proof");
+        assert_eq!(root_hash, db.root_hash(None).unwrap().unwrap());
+        assert_eq!(result_set.len(), 2);
+
+        let pruned = time_index
+            .prune(&db, 350, 1, None)
+            .unwrap()
+            .expect("should prune");
+        assert_eq!(pruned, 3);
+        let remaining = time_index
+            .query_range(&db, 0, u64::MAX, None, true, None)
+            .unwrap()
+            .expect("should query range");
+        assert_eq!(
+            remaining
+                .into_iter()
+                .map(|entry| entry.key)
+                .collect::<Vec<_>>(),
+            vec![b"d".to_vec()]
+        );
+        assert!(matches!(
+            db.get([TEST_LEAF], b"a", None).unwrap(),
+            Err(Error::PathKeyNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_time_index_reindexed_element_is_kept_by_pruning() {
+        let db = make_test_grovedb();
+        let time_index = TimeIndex::new(
+            vec![TEST_LEAF.to_vec()],
+            vec![ANOTHER_TEST_LEAF.to_vec(), b"by_time".to_vec()],
+        );
+        time_index
+            .create(&db, None)
+            .unwrap()
+            .expect("should create index subtree");
+        time_index
+            .insert(
+                &db,
+                b"a",
+                100,
+                None,
+                Element::new_item(b"old".to_vec()),
+                None,
+            )
+            .unwrap()
+            .expect("should insert indexed element");
+        time_index
+            .insert(
+                &db,
+                b"a",
+                500,
+                Some(100),
+                Element::new_item(b"new".to_vec()),
+                None,
+            )
+            .unwrap()
+            .expect("should re-index element");
+
+        let entries = time_index
+            .query_range(&db, 0, u64::MAX, None, true, None)
+            .unwrap()
+            .expect("should query range");
+        assert_eq!(
+            entries,
+            vec![TimeIndexEntry {
+                timestamp: 500,
+                key: b"a".to_vec(),
+                element: Element::new_item(b"new".to_vec()),
+            }]
+        );
+
+        let pruned = time_index
+            .prune(&db, 300, 10, None)
+            .unwrap()
+            .expect("should prune");
+        assert_eq!(pruned, 0);
+        assert_eq!(
+            db.get([TEST_LEAF], b"a", None)
+                .unwrap()
+                .expect("re-indexed element should be kept"),
+            Element::new_item(b"new".to_vec())
+        );
+    }
+}
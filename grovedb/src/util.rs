@@ -80,7 +80,7 @@ macro_rules! storage_context_with_parent_optional_tx {
                         })
                     );
                     match element {
-                        Element::Tree(root_key, _) => {
+                        Element::Tree(root_key, _) | Element::OrderedTree(root_key, ..) => {
                             let $root_key = root_key;
                             let $is_sum_tree = false;
                             $($body)*
@@ -121,7 +121,7 @@ macro_rules! storage_context_with_parent_optional_tx {
                         })
                     );
                     match element {
-                        Element::Tree(root_key, _) => {
+                        Element::Tree(root_key, _) | Element::OrderedTree(root_key, ..) => {
                             let $root_key = root_key;
                             let $is_sum_tree = false;
                             $($body)*
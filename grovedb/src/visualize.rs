@@ -71,6 +71,10 @@ impl Visualize for Element {
                 drawer.write(b"sum_tree: ")?;
                 drawer = root_key.as_deref().visualize(drawer)?;
             }
+            Element::OrderedTree(root_key, ..) => {
+                drawer.write(b"ordered_tree: ")?;
+                drawer = root_key.as_deref().visualize(drawer)?;
+            }
         }
         Ok(drawer)
     }
@@ -162,7 +166,7 @@ impl GroveDb {
                     drawer = key.visualize(drawer)?;
                     drawer.write(b" ")?;
                     match element {
-                        Element::Tree(..) => {
+                        Element::Tree(..) | Element::OrderedTree(..) => {
                             drawer.write(b"Merk root is: ")?;
                             drawer = element.visualize(drawer)?;
                             drawer.down();
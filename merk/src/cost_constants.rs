@@ -0,0 +1,214 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Cost constants
+//! Byte accounting constants used by cost calculations, kept in a table
+//! versioned by cost epoch. The epoch in use is selected at compile time with
+//! the `GROVEDB_COST_EPOCH` environment variable and defaults to the first one,
+//! so costs can evolve across protocol versions while the previous epochs stay
+//! available. Costs can also be calculated with other constants at runtime with
+//! [`with_cost_constants`], for instance to charge a past epoch.
+
+#[cfg(any(feature = "full", feature = "verify"))]
+use std::cell::Cell;
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Version of the cost constants
+pub type CostEpoch = u16;
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Byte accounting constants of a cost epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CostConstants {
+    /// Size of the prefix of storage keys, derived from the subtree path
+    pub key_prefix_size: u32,
+    /// Size of a node hash or of a value hash
+    pub node_hash_size: u32,
+    /// Size of a parent to child hook besides the child key and hash:
+    /// 1 byte for the key length, 2 bytes for the child heights and 1 byte for
+    /// the sum option
+    pub parent_hook_size: u32,
+    /// Size of the sum in the parent to child hooks of sum trees
+    pub parent_hook_sum_size: u32,
+    /// Size of the feature type of a node of a basic tree
+    pub basic_feature_size: u32,
+    /// Size of the feature type of a node of a sum tree
+    pub summed_feature_size: u32,
+    /// Size of a subtree layer: 1 byte for the element type, 1 byte for the
+    /// root key option and 1 byte for the flags option
+    pub layer_cost_size: u32,
+    /// Extra size of the sum value of sum items and sum trees
+    pub sum_value_extra_cost: u32,
+    /// Biggest key size, without its prefix
+    pub max_key_size: u32,
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl CostConstants {
+    /// Size of the feature type of a node
+    pub const fn feature_size(&self, is_sum_node: bool) -> u32 {
+        if is_sum_node {
+            self.summed_feature_size
+        } else {
+            self.basic_feature_size
+        }
+    }
+
+    /// Biggest key size, with its prefix
+    pub const fn max_prefixed_key_size(&self) -> u32 {
+        self.key_prefix_size + self.max_key_size
+    }
+
+    /// Size of a summed subtree layer
+    pub const fn sum_layer_cost_size(&self) -> u32 {
+        self.layer_cost_size + self.sum_value_extra_cost
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Cost constants of every cost epoch, indexed by epoch
+pub const COST_CONSTANTS_TABLE: [CostConstants; 1] = [CostConstants {
+    key_prefix_size: 32,
+    node_hash_size: 32,
+    parent_hook_size: 4,
+    parent_hook_sum_size: 8,
+    basic_feature_size: 1,
+    summed_feature_size: 9,
+    layer_cost_size: 3,
+    sum_value_extra_cost: 9,
+    max_key_size: 256,
+}];
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Cost epoch selected at compile time
+pub const COST_EPOCH: CostEpoch = parse_cost_epoch(option_env!("GROVEDB_COST_EPOCH"));
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Cost constants of the cost epoch selected at compile time
+pub const COST_CONSTANTS: CostConstants = COST_CONSTANTS_TABLE[COST_EPOCH as usize];
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Returns the cost constants of a cost epoch, if it exists
+pub fn cost_constants(epoch: CostEpoch) -> Option<&'static CostConstants> {
+    COST_CONSTANTS_TABLE.get(epoch as usize)
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+thread_local! {
+    /// Constants set for the current thread by [`with_cost_constants`]
+    static COST_CONSTANTS_OVERRIDE: Cell<Option<CostConstants>> = const { Cell::new(None) };
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Restores the previous constants of the thread, even on panic
+struct CostConstantsGuard(Option<CostConstants>);
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl Drop for CostConstantsGuard {
+    fn drop(&mut self) {
+        COST_CONSTANTS_OVERRIDE.with(|constants| constants.set(self.0));
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Runs `f` with every merk cost of the current thread calculated from
+/// `constants` rather than from the ones selected at compile time
+pub fn with_cost_constants<T>(constants: CostConstants, f: impl FnOnce() -> T) -> T {
+    let previous = COST_CONSTANTS_OVERRIDE.with(|current| current.replace(Some(constants)));
+    let _guard = CostConstantsGuard(previous);
+    f()
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Cost constants of the current thread, the ones of the cost epoch selected
+/// at compile time unless overridden by [`with_cost_constants`]
+pub fn active_cost_constants() -> CostConstants {
+    COST_CONSTANTS_OVERRIDE
+        .with(Cell::get)
+        .unwrap_or(COST_CONSTANTS)
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+const fn parse_cost_epoch(maybe_epoch: Option<&str>) -> CostEpoch {
+    let bytes = match maybe_epoch {
+        Some(epoch) => epoch.as_bytes(),
+        None => return 0,
+    };
+    assert!(!bytes.is_empty(), "GROVEDB_COST_EPOCH must be a number");
+    let mut epoch: CostEpoch = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        assert!(
+            bytes[i].is_ascii_digit(),
+            "GROVEDB_COST_EPOCH must be a number"
+        );
+        epoch = epoch * 10 + (bytes[i] - b'0') as CostEpoch;
+        i += 1;
+    }
+    epoch
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tree::kv::KV, HASH_LENGTH_U32};
+
+    #[test]
+    fn test_cost_epochs() {
+        assert_eq!(parse_cost_epoch(None), 0);
+        assert_eq!(parse_cost_epoch(Some("12")), 12);
+        assert_eq!(cost_constants(COST_EPOCH), Some(&COST_CONSTANTS));
+        assert_eq!(
+            cost_constants(COST_CONSTANTS_TABLE.len() as CostEpoch),
+            None
+        );
+
+        let first_epoch = cost_constants(0).expect("first epoch should exist");
+        assert_eq!(first_epoch.node_hash_size, HASH_LENGTH_U32);
+        assert_eq!(first_epoch.max_prefixed_key_size(), 288);
+    }
+
+    #[test]
+    fn test_cost_constants_override() {
+        let overridden = CostConstants {
+            key_prefix_size: 20,
+            ..COST_CONSTANTS
+        };
+        assert_eq!(active_cost_constants(), COST_CONSTANTS);
+        let key_cost = with_cost_constants(overridden, || {
+            assert_eq!(active_cost_constants(), overridden);
+            let nested = with_cost_constants(COST_CONSTANTS, active_cost_constants);
+            assert_eq!(nested, COST_CONSTANTS);
+            assert_eq!(active_cost_constants(), overridden);
+            KV::node_key_byte_cost_size(10)
+        });
+        assert_eq!(active_cost_constants(), COST_CONSTANTS);
+        assert_eq!(key_cost + 12, KV::node_key_byte_cost_size(10));
+    }
+}
@@ -35,26 +35,26 @@ use integer_encoding::VarInt;
 
 #[cfg(feature = "full")]
 use crate::{
+    cost_constants::active_cost_constants,
     error::Error,
-    estimated_costs::LAYER_COST_SIZE,
     tree::{kv::KV, Link, Tree},
-    HASH_BLOCK_SIZE, HASH_BLOCK_SIZE_U32, HASH_LENGTH, HASH_LENGTH_U32,
+    HASH_BLOCK_SIZE, HASH_BLOCK_SIZE_U32, HASH_LENGTH,
 };
 
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 /// Average key size
 pub type AverageKeySize = u8;
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 /// Average value size
 pub type AverageValueSize = u32;
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 /// Average flags size
 pub type AverageFlagsSize = u32;
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 /// Weight
 pub type Weight = u8;
 
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 #[derive(Clone, PartialEq, Eq, Debug)]
 /// Estimated number of sum trees
 pub enum EstimatedSumTrees {
@@ -71,7 +71,7 @@ pub enum EstimatedSumTrees {
     AllSumTrees,
 }
 
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 impl Default for EstimatedSumTrees {
     fn default() -> Self {
         EstimatedSumTrees::NoSumTrees
@@ -94,7 +94,7 @@ impl EstimatedSumTrees {
     }
 }
 
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 #[derive(Clone, PartialEq, Eq, Debug)]
 /// Estimated layer sizes
 pub enum EstimatedLayerSizes {
@@ -252,17 +252,17 @@ impl EstimatedLayerSizes {
     }
 }
 
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 /// Approximate element count
 pub type ApproximateElementCount = u32;
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 /// Estimated level number
 pub type EstimatedLevelNumber = u32;
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 /// Estimated to be empty
 pub type EstimatedToBeEmpty = bool;
 
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 #[derive(Clone, PartialEq, Eq, Debug)]
 /// Information on an estimated layer
 pub struct EstimatedLayerInformation {
@@ -274,10 +274,10 @@ pub struct EstimatedLayerInformation {
     pub estimated_layer_sizes: EstimatedLayerSizes,
 }
 
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 impl EstimatedLayerInformation {}
 
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 #[derive(Clone, PartialEq, Eq, Debug)]
 /// Estimated elements and level number of a layer
 pub enum EstimatedLayerCount {
@@ -289,7 +289,7 @@ pub enum EstimatedLayerCount {
     EstimatedLevel(EstimatedLevelNumber, EstimatedToBeEmpty),
 }
 
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 impl EstimatedLayerCount {
     /// Returns true if the tree is estimated to be empty.
     pub fn estimated_to_be_empty(&self) -> bool {
@@ -328,8 +328,11 @@ impl Tree {
         // two option values for the left and right link
         // the actual left and right link encoding size
         // the encoded kv node size
-        2 + (2 * Link::encoded_link_size(not_prefixed_key_len, is_sum_node))
-            + KV::encoded_kv_node_size(estimated_element_size, is_sum_node)
+        2 + (2 * Link::encoded_link_size(
+            not_prefixed_key_len,
+            is_sum_node,
+            &active_cost_constants(),
+        )) + KV::encoded_kv_node_size(estimated_element_size, is_sum_node)
     }
 }
 
@@ -382,7 +385,8 @@ pub fn add_average_case_merk_replace_layered(
     // then let's add the combine hash
     cost.hash_node_calls += 1;
     // then let's add the kv_digest_to_kv_hash hash call
-    let hashed_size = key_len.encode_var_vec().len() as u32 + key_len + HASH_LENGTH_U32;
+    let hashed_size =
+        key_len.encode_var_vec().len() as u32 + key_len + active_cost_constants().node_hash_size;
     cost.hash_node_calls += 1 + ((hashed_size - 1) / HASH_BLOCK_SIZE_U32) as u16;
     // then let's add the two block hashes for the node hash call
     cost.hash_node_calls += 2;
@@ -463,9 +467,9 @@ pub fn add_average_case_merk_propagate(
         ) => {
             let flags_len = average_flags_size.unwrap_or(0);
 
-            // it is normal to have LAYER_COST_SIZE here, as we add estimated sum tree
+            // it is normal to have the layer cost size here, as we add estimated sum tree
             // additions right after
-            let value_len = LAYER_COST_SIZE + flags_len;
+            let value_len = active_cost_constants().layer_cost_size + flags_len;
             // in order to simplify calculations we get the estimated size and remove the
             // cost for the basic merk
             let sum_tree_addition = estimated_sum_trees.estimated_size()?;
@@ -518,7 +522,7 @@ pub fn add_average_case_merk_propagate(
                     None => 0,
                     Some((average_key_size, estimated_sum_trees, average_flags_size, weight)) => {
                         let flags_len = average_flags_size.unwrap_or(0);
-                        let value_len = LAYER_COST_SIZE + flags_len;
+                        let value_len = active_cost_constants().layer_cost_size + flags_len;
                         let sum_tree_addition = estimated_sum_trees.estimated_size()?;
                         let cost = KV::value_byte_cost_size_for_key_and_raw_value_lengths(
                             *average_key_size as u32,
@@ -582,7 +586,7 @@ pub fn add_average_case_merk_propagate(
             average_flags_size,
         ) => {
             let flags_len = average_flags_size.unwrap_or(0);
-            let value_len = LAYER_COST_SIZE + flags_len;
+            let value_len = active_cost_constants().layer_cost_size + flags_len;
             let sum_tree_addition = estimated_sum_trees.estimated_size()?;
             nodes_updated
                 * KV::layered_node_byte_cost_size_for_key_and_value_lengths(
@@ -634,7 +638,7 @@ pub fn add_average_case_merk_propagate(
                     .map(
                         |(average_key_size, estimated_sum_trees, average_flags_size, weight)| {
                             let flags_len = average_flags_size.unwrap_or(0);
-                            let value_len = LAYER_COST_SIZE + flags_len;
+                            let value_len = active_cost_constants().layer_cost_size + flags_len;
                             let sum_tree_addition = estimated_sum_trees.estimated_size()?;
                             let cost = KV::layered_node_byte_cost_size_for_key_and_value_lengths(
                                 *average_key_size as u32,
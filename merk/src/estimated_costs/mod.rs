@@ -33,10 +33,12 @@ use costs::OperationCost;
 #[cfg(feature = "full")]
 use integer_encoding::VarInt;
 
+#[cfg(any(feature = "full", feature = "verify"))]
+use crate::cost_constants::COST_CONSTANTS;
 #[cfg(feature = "full")]
-use crate::{tree::kv::KV, HASH_BLOCK_SIZE_U32, HASH_LENGTH_U32};
+use crate::{cost_constants::active_cost_constants, tree::kv::KV, HASH_BLOCK_SIZE_U32};
 
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 pub mod average_case_costs;
 
 #[cfg(feature = "full")]
@@ -48,28 +50,32 @@ pub mod worst_case_costs;
 /// 1 byte for the element type
 /// 1 byte for the root key option
 /// 1 byte for the flag option
-pub const LAYER_COST_SIZE: u32 = 3;
+/// Value of the cost epoch selected at compile time, costs are calculated with
+/// the `layer_cost_size` of `active_cost_constants()`
+pub const LAYER_COST_SIZE: u32 = COST_CONSTANTS.layer_cost_size;
 
 #[cfg(any(feature = "full", feature = "verify"))]
-/// The cost of a sum value
-pub const SUM_VALUE_EXTRA_COST: u32 = 9;
+/// The cost of a sum value, of the cost epoch selected at compile time
+pub const SUM_VALUE_EXTRA_COST: u32 = COST_CONSTANTS.sum_value_extra_cost;
 
 #[cfg(feature = "full")]
 /// The cost of a summed subtree layer
-/// This is the layer size + 9 for the encoded value
-pub const SUM_LAYER_COST_SIZE: u32 = LAYER_COST_SIZE + SUM_VALUE_EXTRA_COST;
+/// This is the layer size + 9 for the encoded value, of the cost epoch
+/// selected at compile time
+pub const SUM_LAYER_COST_SIZE: u32 = COST_CONSTANTS.sum_layer_cost_size();
 
 #[cfg(feature = "full")]
 impl KV {
     fn encoded_kv_node_size(element_size: u32, is_sum_node: bool) -> u32 {
         // We always charge 8 bytes for the sum node (even though
         // it could theoretically be 9 bytes
-        let sum_node_feature_size = if is_sum_node { 9 } else { 1 };
+        let cost_constants = active_cost_constants();
+        let sum_node_feature_size = cost_constants.feature_size(is_sum_node);
         // KV holds the state of a node
         // 32 bytes to encode the hash of the node
         // 32 bytes to encode the value hash
         // max_element_size to encode the worst case value size
-        HASH_LENGTH_U32 + HASH_LENGTH_U32 + element_size + sum_node_feature_size
+        cost_constants.node_hash_size * 2 + element_size + sum_node_feature_size
     }
 }
 
@@ -91,7 +97,8 @@ pub fn add_cost_case_merk_insert(
     // first lets add the value hash
     cost.hash_node_calls += 1 + ((value_len - 1) / HASH_BLOCK_SIZE_U32) as u16;
     // then let's add the kv_digest_to_kv_hash hash call
-    let hashed_size = key_len.encode_var_vec().len() as u32 + key_len + HASH_LENGTH_U32;
+    let hashed_size =
+        key_len.encode_var_vec().len() as u32 + key_len + active_cost_constants().node_hash_size;
     cost.hash_node_calls += 1 + ((hashed_size - 1) / HASH_BLOCK_SIZE_U32) as u16;
     // then let's add the two block hashes for the node hash call
     cost.hash_node_calls += 2;
@@ -117,7 +124,8 @@ pub fn add_cost_case_merk_insert_layered(
     // then let's add the combine hash
     cost.hash_node_calls += 1;
     // then let's add the kv_digest_to_kv_hash hash call
-    let hashed_size = key_len.encode_var_vec().len() as u32 + key_len + HASH_LENGTH_U32;
+    let hashed_size =
+        key_len.encode_var_vec().len() as u32 + key_len + active_cost_constants().node_hash_size;
     cost.hash_node_calls += 1 + ((hashed_size - 1) / HASH_BLOCK_SIZE_U32) as u16;
     // then let's add the two block hashes for the node hash call
     cost.hash_node_calls += 2;
@@ -139,7 +147,8 @@ pub fn add_cost_case_merk_replace(
     // first lets add the value hash
     cost.hash_node_calls += 1 + ((value_len - 1) / HASH_BLOCK_SIZE_U32) as u16;
     // then let's add the kv_digest_to_kv_hash hash call
-    let hashed_size = key_len.encode_var_vec().len() as u32 + key_len + HASH_LENGTH_U32;
+    let hashed_size =
+        key_len.encode_var_vec().len() as u32 + key_len + active_cost_constants().node_hash_size;
     cost.hash_node_calls += 1 + ((hashed_size - 1) / HASH_BLOCK_SIZE_U32) as u16;
     // then let's add the two block hashes for the node hash call
     cost.hash_node_calls += 2;
@@ -164,7 +173,8 @@ pub fn add_cost_case_merk_replace_same_size(
     // first lets add the value hash
     cost.hash_node_calls += 1 + ((value_len - 1) / HASH_BLOCK_SIZE_U32) as u16;
     // then let's add the kv_digest_to_kv_hash hash call
-    let hashed_size = key_len.encode_var_vec().len() as u32 + key_len + HASH_LENGTH_U32;
+    let hashed_size =
+        key_len.encode_var_vec().len() as u32 + key_len + active_cost_constants().node_hash_size;
     cost.hash_node_calls += 1 + ((hashed_size - 1) / HASH_BLOCK_SIZE_U32) as u16;
     // then let's add the two block hashes for the node hash call
     cost.hash_node_calls += 2;
@@ -190,7 +200,8 @@ pub fn add_cost_case_merk_replace_layered(
     // then let's add the combine hash
     cost.hash_node_calls += 1;
     // then let's add the kv_digest_to_kv_hash hash call
-    let hashed_size = key_len.encode_var_vec().len() as u32 + key_len + HASH_LENGTH_U32;
+    let hashed_size =
+        key_len.encode_var_vec().len() as u32 + key_len + active_cost_constants().node_hash_size;
     cost.hash_node_calls += 1 + ((hashed_size - 1) / HASH_BLOCK_SIZE_U32) as u16;
     // then let's add the two block hashes for the node hash call
     cost.hash_node_calls += 2;
@@ -235,7 +246,8 @@ pub fn add_cost_case_merk_patch(
     // first lets add the value hash
     cost.hash_node_calls += 1 + ((value_len - 1) / HASH_BLOCK_SIZE_U32) as u16;
     // then let's add the kv_digest_to_kv_hash hash call
-    let hashed_size = key_len.encode_var_vec().len() as u32 + key_len + HASH_LENGTH_U32;
+    let hashed_size =
+        key_len.encode_var_vec().len() as u32 + key_len + active_cost_constants().node_hash_size;
     cost.hash_node_calls += 1 + ((hashed_size - 1) / HASH_BLOCK_SIZE_U32) as u16;
     // then let's add the two block hashes for the node hash call
     cost.hash_node_calls += 2;
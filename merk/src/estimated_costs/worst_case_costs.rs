@@ -35,8 +35,8 @@ use costs::{CostResult, CostsExt, OperationCost};
 
 #[cfg(feature = "full")]
 use crate::{
+    cost_constants::{active_cost_constants, COST_CONSTANTS},
     error::Error,
-    merk::defaults::MAX_PREFIXED_KEY_SIZE,
     tree::{kv::KV, Link, Tree},
     HASH_BLOCK_SIZE, HASH_BLOCK_SIZE_U32, HASH_LENGTH,
 };
@@ -62,8 +62,11 @@ impl Tree {
         // two option values for the left and right link
         // the actual left and right link encoding size
         // the encoded kv node size
-        2 + (2 * Link::encoded_link_size(not_prefixed_key_len, is_sum_node))
-            + KV::encoded_kv_node_size(max_element_size, is_sum_node)
+        2 + (2 * Link::encoded_link_size(
+            not_prefixed_key_len,
+            is_sum_node,
+            &active_cost_constants(),
+        )) + KV::encoded_kv_node_size(max_element_size, is_sum_node)
     }
 }
 
@@ -161,8 +164,8 @@ pub fn add_worst_case_merk_root_hash(cost: &mut OperationCost) {
 /// Merk biggest value size
 pub const MERK_BIGGEST_VALUE_SIZE: u32 = u16::MAX as u32;
 #[cfg(feature = "full")]
-/// Merk biggest key size
-pub const MERK_BIGGEST_KEY_SIZE: u32 = 256;
+/// Merk biggest key size, of the cost epoch selected at compile time
+pub const MERK_BIGGEST_KEY_SIZE: u32 = COST_CONSTANTS.max_key_size;
 
 #[cfg(feature = "full")]
 /// Worst case cost of a merk propagation
@@ -207,7 +210,8 @@ pub fn add_worst_case_merk_propagate(
 
     // todo: verify these numbers
     cost.storage_cost.replaced_bytes += nodes_updated * MERK_BIGGEST_VALUE_SIZE;
-    cost.storage_loaded_bytes += nodes_updated * (MERK_BIGGEST_VALUE_SIZE + MERK_BIGGEST_KEY_SIZE);
+    cost.storage_loaded_bytes +=
+        nodes_updated * (MERK_BIGGEST_VALUE_SIZE + active_cost_constants().max_key_size);
     cost.seek_count += nodes_updated as u16;
     cost.hash_node_calls += (nodes_updated as u16) * 2;
     Ok(())
@@ -220,7 +224,8 @@ pub fn add_worst_case_cost_for_is_empty_tree_except(
     except_keys_count: u16,
 ) {
     cost.seek_count += except_keys_count + 1;
-    cost.storage_loaded_bytes += MAX_PREFIXED_KEY_SIZE * (except_keys_count as u32 + 1);
+    cost.storage_loaded_bytes +=
+        active_cost_constants().max_prefixed_key_size() * (except_keys_count as u32 + 1);
 }
 
 /// Add average case cost for is_empty_tree_except
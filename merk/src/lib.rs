@@ -38,7 +38,12 @@ extern crate core;
 mod merk;
 
 #[cfg(feature = "full")]
-pub use crate::merk::{chunks::ChunkProducer, options::MerkOptions, restore::Restorer};
+pub use crate::merk::{
+    chunk_tree::{ChunkSource, ChunkTree},
+    chunks::ChunkProducer,
+    options::MerkOptions,
+    restore::Restorer,
+};
 
 /// Provides a container type that allows temporarily taking ownership of a
 /// value.
@@ -61,6 +66,10 @@ pub mod tree;
 #[cfg(any(feature = "full", feature = "verify"))]
 pub mod error;
 
+/// Cost constants
+#[cfg(any(feature = "full", feature = "verify"))]
+pub mod cost_constants;
+
 /// Estimated costs
 #[cfg(any(feature = "full", feature = "verify"))]
 pub mod estimated_costs;
@@ -78,11 +87,12 @@ pub use proofs::query::execute_proof;
 pub use proofs::query::verify_query;
 #[cfg(feature = "full")]
 pub use tree::{
-    BatchEntry, Link, MerkBatch, Op, PanicSource, HASH_BLOCK_SIZE, HASH_BLOCK_SIZE_U32,
-    HASH_LENGTH, HASH_LENGTH_U32, HASH_LENGTH_U32_X2,
+    encoded_node_version, BatchEntry, Link, MerkBatch, Op, PanicSource, HASH_BLOCK_SIZE,
+    HASH_BLOCK_SIZE_U32, HASH_LENGTH, HASH_LENGTH_U32, HASH_LENGTH_U32_X2, LEGACY_NODE_VERSION,
+    NODE_VERSION,
 };
 #[cfg(any(feature = "full", feature = "verify"))]
-pub use tree::{CryptoHash, TreeFeatureType};
+pub use tree::{with_hash_backend, Blake3Backend, CryptoHash, HashBackend, TreeFeatureType};
 
 #[cfg(feature = "full")]
 pub use crate::merk::{
@@ -0,0 +1,333 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Provides `ChunkTree`, a read only Merk hydrated in memory from the chunk
+//! proofs of a tree, which answers proofs without restoring the tree into
+//! storage.
+
+#[cfg(feature = "full")]
+use std::collections::BTreeMap;
+
+#[cfg(feature = "full")]
+use costs::{CostResult, CostsExt, OperationCost};
+
+#[cfg(feature = "full")]
+use crate::{
+    error::Error,
+    merk::{restore::encode_chunk_nodes, ProofConstructionResult},
+    proofs::{
+        chunk::{verify_leaf, verify_trunk, MIN_TRUNK_HEIGHT},
+        encode_into, Op, Query,
+    },
+    tree::{Fetch, Link, RefWalker, Tree, NULL_HASH},
+    CryptoHash,
+};
+
+#[cfg(feature = "full")]
+/// A Merk tree rebuilt from its chunks, in the order a `ChunkProducer` gives
+/// them. Chunks are verified against the expected root hash like a `Restorer`
+/// does, but nodes are kept in memory so that archived trees can be proved
+/// from cold storage. Child heights of the trunk nodes aren't rewritten, they
+/// only matter to balance the tree, which is never written.
+pub struct ChunkTree {
+    nodes: BTreeMap<Vec<u8>, Vec<u8>>,
+    root_key: Option<Vec<u8>>,
+    root_hash: CryptoHash,
+}
+
+#[cfg(feature = "full")]
+/// Source fetching the nodes of a `ChunkTree`
+#[derive(Clone, Copy)]
+pub struct ChunkSource<'a> {
+    nodes: &'a BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+#[cfg(feature = "full")]
+impl Fetch for ChunkSource<'_> {
+    fn fetch(&self, link: &Link) -> CostResult<Tree, Error> {
+        let mut cost = OperationCost::default();
+        match self.nodes.get(link.key()) {
+            Some(bytes) => {
+                cost.seek_count += 1;
+                cost.storage_loaded_bytes += bytes.len() as u32;
+                Tree::decode_raw(bytes, link.key().to_vec())
+            }
+            None => Err(Error::KeyNotFoundError("Key not found for fetch")),
+        }
+        .wrap_with_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+impl ChunkTree {
+    /// Verifies the chunks of a tree against its root hash and keeps its
+    /// nodes. No chunk at all is an empty tree.
+    pub fn from_chunks<I>(chunks: I, expected_root_hash: CryptoHash) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = Vec<Op>>,
+    {
+        let mut chunks = chunks.into_iter();
+        let trunk_ops = match chunks.next() {
+            Some(trunk_ops) => trunk_ops,
+            None if expected_root_hash == NULL_HASH => {
+                return Ok(ChunkTree {
+                    nodes: BTreeMap::new(),
+                    root_key: None,
+                    root_hash: NULL_HASH,
+                })
+            }
+            None => {
+                return Err(Error::ChunkRestoringError(
+                    "Missing trunk chunk of a non empty tree".to_string(),
+                ))
+            }
+        };
+
+        let (trunk, height) = verify_trunk(trunk_ops.into_iter().map(Ok)).unwrap()?;
+        let root_hash = trunk.hash().unwrap();
+        if root_hash != expected_root_hash {
+            return Err(Error::ChunkRestoringError(format!(
+                "Proof did not match expected hash\n\tExpected: {:?}\n\tActual: {:?}",
+                expected_root_hash, root_hash
+            )));
+        }
+
+        let trunk_height = height / 2;
+        let (leaf_hashes, parent_keys) = if trunk_height >= MIN_TRUNK_HEIGHT {
+            (
+                trunk
+                    .layer(trunk_height)
+                    .map(|node| node.hash().unwrap())
+                    .collect(),
+                trunk
+                    .layer(trunk_height - 1)
+                    .map(|node| node.key().to_vec())
+                    .collect(),
+            )
+        } else {
+            (Vec::new(), Vec::<Vec<u8>>::new())
+        };
+
+        let mut nodes = BTreeMap::new();
+        encode_chunk_nodes(&trunk, &mut |key, bytes| {
+            nodes.insert(key.to_vec(), bytes);
+            Ok(())
+        })?;
+
+        for (index, leaf_hash) in leaf_hashes.into_iter().enumerate() {
+            let leaf_ops = chunks.next().ok_or_else(|| {
+                Error::ChunkRestoringError("Received less chunks than expected".to_string())
+            })?;
+            let leaf = verify_leaf(leaf_ops.into_iter().map(Ok), leaf_hash).unwrap()?;
+
+            // leaves come in layer order, each parent has its left leaf first
+            let parent_key = &parent_keys[index / 2];
+            let mut parent = nodes
+                .get(parent_key)
+                .ok_or_else(|| {
+                    Error::ChunkRestoringError("Missing parent of leaf chunk".to_string())
+                })
+                .and_then(|bytes| Tree::decode_raw(bytes, parent_key.clone()))?;
+            match parent.link_mut(index % 2 == 0) {
+                Some(Link::Reference { key, .. }) => *key = leaf.key().to_vec(),
+                _ => {
+                    return Err(Error::ChunkRestoringError(
+                        "Expected parent links to be type Link::Reference".to_string(),
+                    ))
+                }
+            }
+            nodes.insert(parent_key.clone(), parent.encode());
+
+            encode_chunk_nodes(&leaf, &mut |key, bytes| {
+                nodes.insert(key.to_vec(), bytes);
+                Ok(())
+            })?;
+        }
+        if chunks.next().is_some() {
+            return Err(Error::ChunkRestoringError(
+                "Received more chunks than expected".to_string(),
+            ));
+        }
+
+        Ok(ChunkTree {
+            nodes,
+            root_key: Some(trunk.key().to_vec()),
+            root_hash,
+        })
+    }
+
+    /// Root hash of the tree
+    pub fn root_hash(&self) -> CryptoHash {
+        self.root_hash
+    }
+
+    /// Source fetching the nodes of the tree
+    pub fn source(&self) -> ChunkSource {
+        ChunkSource { nodes: &self.nodes }
+    }
+
+    /// Value stored under the key
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.nodes
+            .get(key)
+            .map(|bytes| {
+                Tree::decode_raw(bytes, key.to_vec()).map(|tree| tree.value_as_slice().to_vec())
+            })
+            .transpose()
+    }
+
+    /// Creates a proof for the query, encoded the same way as
+    /// `Merk::prove` proofs so they are verified the same way
+    pub fn prove(
+        &self,
+        query: Query,
+        limit: Option<u16>,
+        offset: Option<u16>,
+    ) -> CostResult<ProofConstructionResult, Error> {
+        let mut cost = OperationCost::default();
+
+        let root_key = match &self.root_key {
+            Some(root_key) => root_key,
+            None => {
+                return Err(Error::CorruptedCodeExecution(
+                    "Cannot create proof for empty tree",
+                ))
+                .wrap_with_cost(cost)
+            }
+        };
+        let mut root = match self
+            .source()
+            .fetch(&Link::Reference {
+                hash: self.root_hash,
+                child_heights: (0, 0),
+                key: root_key.clone(),
+                sum: None,
+            })
+            .unwrap_add_cost(&mut cost)
+        {
+            Ok(root) => root,
+            Err(error) => return Err(error).wrap_with_cost(cost),
+        };
+
+        RefWalker::new(&mut root, self.source())
+            .create_proof(query.items.as_slice(), limit, offset, query.left_to_right)
+            .map_ok(|(proof, _, limit, offset, ..)| {
+                let mut bytes = Vec::with_capacity(128);
+                encode_into(proof.iter(), &mut bytes);
+                ProofConstructionResult::new(bytes, limit, offset)
+            })
+            .add_cost(cost)
+    }
+}
+
+#[cfg(feature = "full")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        proofs::query::query_item::QueryItem, test_utils::*, verify_query, TreeFeatureType,
+    };
+
+    fn chunk_tree_test(node_count: u64) {
+        let mut original = TempMerk::new();
+        original
+            .apply::<Vec<_>, Vec<_>>(&make_batch_seq(0..node_count), &[], None)
+            .unwrap()
+            .unwrap();
+        let chunks: Vec<Vec<Op>> = original
+            .chunks()
+            .unwrap()
+            .into_iter()
+            .map(|chunk| chunk.unwrap())
+            .collect();
+        let root_hash = original.root_hash().unwrap();
+
+        let chunk_tree =
+            ChunkTree::from_chunks(chunks.clone(), root_hash).expect("chunks should verify");
+        assert_eq!(chunk_tree.root_hash(), root_hash);
+        assert_eq!(
+            chunk_tree.get(&seq_key(node_count / 2)).unwrap(),
+            Some(vec![123; 60])
+        );
+
+        let mut query = Query::new();
+        query.insert_item(QueryItem::Range(
+            seq_key(node_count / 3).to_vec()..seq_key(node_count / 3 + 5).to_vec(),
+        ));
+        query.insert_key(seq_key(node_count + 1).to_vec());
+        let proof = chunk_tree
+            .prove(query.clone(), Some(3), None)
+            .unwrap()
+            .expect("should prove from chunks");
+        let expected = original
+            .prove(query.clone(), Some(3), None)
+            .unwrap()
+            .expect("should prove from storage");
+        assert_eq!(proof.proof, expected.proof);
+        let result = verify_query(&proof.proof, &query, Some(3), None, true, root_hash)
+            .unwrap()
+            .expect("proof should verify");
+        assert_eq!(
+            result.result_set.len(),
+            3.min((node_count - node_count / 3) as usize)
+        );
+
+        let mut tampered = chunks;
+        tampered.pop();
+        assert!(ChunkTree::from_chunks(tampered, root_hash).is_err());
+        assert!(ChunkTree::from_chunks(Vec::new(), root_hash).is_err());
+    }
+
+    #[test]
+    fn chunk_tree_3() {
+        chunk_tree_test(3);
+    }
+
+    #[test]
+    fn chunk_tree_10000() {
+        chunk_tree_test(10_000);
+    }
+
+    #[test]
+    fn chunk_tree_rejects_wrong_root_hash() {
+        let mut original = TempMerk::new();
+        original
+            .apply::<Vec<_>, Vec<_>>(
+                &[(
+                    vec![1],
+                    crate::tree::Op::Put(vec![2], TreeFeatureType::BasicMerk),
+                )],
+                &[],
+                None,
+            )
+            .unwrap()
+            .unwrap();
+        let chunks = original.chunks().unwrap().into_iter().map(|c| c.unwrap());
+        assert!(ChunkTree::from_chunks(chunks, [1; 32]).is_err());
+    }
+}
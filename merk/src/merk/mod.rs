@@ -28,6 +28,8 @@
 
 //! Merk
 
+pub mod chunk_tree;
+
 pub mod chunks;
 
 pub(crate) mod defaults;
@@ -132,38 +132,8 @@ impl<'db, S: StorageContext<'db>> Restorer<S> {
     fn write_chunk(&mut self, tree: ProofTree) -> Result<(), Error> {
         let mut batch = self.merk.storage.new_batch();
 
-        tree.visit_refs(&mut |proof_node| {
-            if let Some((mut node, key)) = match &proof_node.node {
-                Node::KV(key, value) => Some((
-                    Tree::new(key.clone(), value.clone(), None, BasicMerk).unwrap(),
-                    key,
-                )),
-                Node::KVValueHash(key, value, value_hash) => Some((
-                    Tree::new_with_value_hash(key.clone(), value.clone(), *value_hash, BasicMerk)
-                        .unwrap(),
-                    key,
-                )),
-                Node::KVValueHashFeatureType(key, value, value_hash, feature_type) => Some((
-                    Tree::new_with_value_hash(
-                        key.clone(),
-                        value.clone(),
-                        *value_hash,
-                        *feature_type,
-                    )
-                    .unwrap(),
-                    key,
-                )),
-                _ => None,
-            } {
-                // TODO: encode tree node without cloning key/value
-                *node.slot_mut(true) = proof_node.left.as_ref().map(Child::as_link);
-                *node.slot_mut(false) = proof_node.right.as_ref().map(Child::as_link);
-
-                let bytes = node.encode();
-                batch.put(key, &bytes, None, None).map_err(CostsError)
-            } else {
-                Ok(())
-            }
+        encode_chunk_nodes(&tree, &mut |key, bytes| {
+            batch.put(key, &bytes, None, None).map_err(CostsError)
         })?;
 
         self.merk
@@ -347,6 +317,43 @@ impl<'db, S: StorageContext<'db>> Restorer<S> {
     }
 }
 
+#[cfg(feature = "full")]
+/// Encodes the nodes of a verified chunk as they are stored, passing each key
+/// with its encoded node to `store`. Links to the roots of leaf chunks don't
+/// have a key yet, it is written once the leaf chunk is verified.
+pub(crate) fn encode_chunk_nodes(
+    tree: &ProofTree,
+    store: &mut impl FnMut(&[u8], Vec<u8>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    tree.visit_refs(&mut |proof_node| {
+        if let Some((mut node, key)) = match &proof_node.node {
+            Node::KV(key, value) => Some((
+                Tree::new(key.clone(), value.clone(), None, BasicMerk).unwrap(),
+                key,
+            )),
+            Node::KVValueHash(key, value, value_hash) => Some((
+                Tree::new_with_value_hash(key.clone(), value.clone(), *value_hash, BasicMerk)
+                    .unwrap(),
+                key,
+            )),
+            Node::KVValueHashFeatureType(key, value, value_hash, feature_type) => Some((
+                Tree::new_with_value_hash(key.clone(), value.clone(), *value_hash, *feature_type)
+                    .unwrap(),
+                key,
+            )),
+            _ => None,
+        } {
+            // TODO: encode tree node without cloning key/value
+            *node.slot_mut(true) = proof_node.left.as_ref().map(Child::as_link);
+            *node.slot_mut(false) = proof_node.right.as_ref().map(Child::as_link);
+
+            store(key, node.encode())
+        } else {
+            Ok(())
+        }
+    })
+}
+
 #[cfg(feature = "full")]
 impl<'db, S: StorageContext<'db>> Merk<S> {
     /// Creates a new `Restorer`, which can be used to verify chunk proofs to
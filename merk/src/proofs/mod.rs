@@ -37,7 +37,7 @@ pub mod query;
 #[cfg(any(feature = "full", feature = "verify"))]
 pub mod tree;
 
-#[cfg(feature = "full")]
+#[cfg(any(feature = "full", feature = "verify"))]
 pub use encoding::encode_into;
 #[cfg(any(feature = "full", feature = "verify"))]
 pub use encoding::Decoder;
@@ -82,6 +82,18 @@ pub enum Op {
     ChildInverted,
 }
 
+#[cfg(any(feature = "full", feature = "verify"))]
+impl Op {
+    /// Returns true for the operators of proofs generated from right to left,
+    /// so the direction of a proof can be told from any of its operators
+    pub fn is_inverted(&self) -> bool {
+        matches!(
+            self,
+            Op::PushInverted(_) | Op::ParentInverted | Op::ChildInverted
+        )
+    }
+}
+
 #[cfg(any(feature = "full", feature = "verify"))]
 /// A selected piece of data about a single tree node, to be contained in a
 /// `Push` operator in a proof.
@@ -59,7 +59,9 @@ pub use query_item::QueryItem;
 #[cfg(any(feature = "full", feature = "verify"))]
 use verify::ProofAbsenceLimitOffset;
 #[cfg(any(feature = "full", feature = "verify"))]
-pub use verify::{execute_proof, verify_query, ProofVerificationResult, ProvedKeyValue};
+pub use verify::{
+    execute_proof, verify_query, ProofNodeCounts, ProofVerificationResult, ProvedKeyValue,
+};
 #[cfg(feature = "full")]
 use {super::Op, std::collections::LinkedList};
 
@@ -1043,6 +1045,33 @@ mod test {
         verify_keys_test(vec![vec![5]], vec![Some(vec![5])]);
     }
 
+    #[test]
+    fn verify_reports_proof_telemetry() {
+        let mut tree = make_3_node_tree();
+        let mut walker = RefWalker::new(&mut tree, PanicSource {});
+        let (proof, ..) = walker
+            .create_full_proof(&[QueryItem::Key(vec![5])], None, None, true)
+            .unwrap()
+            .expect("failed to create proof");
+        let mut bytes = vec![];
+        encode_into(proof.iter(), &mut bytes);
+
+        let mut query = Query::new();
+        query.insert_key(vec![5]);
+        let (_, result) = execute_proof(bytes.as_slice(), &query, None, None, true)
+            .unwrap()
+            .expect("verify failed");
+
+        // the queried root between the hashes of its two subtrees
+        assert_eq!(result.node_counts.hash, 2);
+        assert_eq!(result.node_counts.total(), 3);
+        assert_eq!(result.proof_bytes, bytes.len());
+
+        let mut summed = result.node_counts;
+        summed += result.node_counts;
+        assert_eq!(summed.total(), 6);
+    }
+
     #[test]
     fn single_verify() {
         verify_keys_test(vec![vec![3]], vec![Some(vec![3])]);
@@ -2041,6 +2070,57 @@ mod test {
         );
     }
 
+    #[test]
+    fn proof_direction_must_match_query_direction() {
+        let mut tree = make_tree_seq(10);
+        let mut walker = RefWalker::new(&mut tree, PanicSource {});
+
+        let queryitems = vec![QueryItem::Range(
+            vec![0, 0, 0, 0, 0, 0, 0, 5]..vec![0, 0, 0, 0, 0, 0, 0, 7],
+        )];
+        let mut query = Query::new();
+        for item in queryitems.iter() {
+            query.insert_item(item.clone());
+        }
+
+        // a right to left proof can't be verified as left to right
+        let (proof, ..) = walker
+            .create_full_proof(queryitems.as_slice(), None, None, false)
+            .unwrap()
+            .expect("create_proof errored");
+        let mut bytes = vec![];
+        encode_into(proof.iter(), &mut bytes);
+        assert!(verify_query(
+            bytes.as_slice(),
+            &query,
+            None,
+            None,
+            true,
+            tree.hash().unwrap(),
+        )
+        .unwrap()
+        .is_err());
+
+        // and the other way around
+        let mut walker = RefWalker::new(&mut tree, PanicSource {});
+        let (proof, ..) = walker
+            .create_full_proof(queryitems.as_slice(), None, None, true)
+            .unwrap()
+            .expect("create_proof errored");
+        let mut bytes = vec![];
+        encode_into(proof.iter(), &mut bytes);
+        assert!(verify_query(
+            bytes.as_slice(),
+            &query,
+            None,
+            None,
+            false,
+            tree.hash().unwrap(),
+        )
+        .unwrap()
+        .is_err());
+    }
+
     #[test]
     fn range_proof_inclusive() {
         let mut tree = make_tree_seq(10);
@@ -1,4 +1,4 @@
-use std::collections::LinkedList;
+use std::{collections::LinkedList, ops::AddAssign};
 
 use costs::{cost_return_on_error, CostResult, CostsExt, OperationCost};
 
@@ -48,6 +48,9 @@ pub fn verify(bytes: &[u8], expected_hash: MerkHash) -> CostResult<Map, Error> {
 /// list will contain 2 elements, the value of `A` and the value of `B`. Keys
 /// proven to be absent in the tree will have an entry of `None`, keys that have
 /// a proven value will have an entry of `Some(value)`.
+///
+/// Returns `Err` if the proof wasn't generated in the `left_to_right`
+/// direction.
 pub fn execute_proof(
     bytes: &[u8],
     query: &Query,
@@ -64,7 +67,17 @@ pub fn execute_proof(
     let mut current_limit = limit;
     let mut current_offset = offset;
 
-    let ops = Decoder::new(bytes);
+    // the direction a proof was generated in is told by its operators, a proof
+    // generated in the other direction would order results differently than
+    // the query asked for
+    let ops = Decoder::new(bytes).map(|op| match op {
+        Ok(op) if op.is_inverted() == left_to_right => Err(Error::InvalidProofError(
+            "Proof direction doesn't match the query direction".to_string(),
+        )),
+        op => op,
+    });
+
+    let mut node_counts = ProofNodeCounts::default();
 
     let root_wrapped = execute(ops, true, |node| {
         let mut execute_node = |key: &Vec<u8>,
@@ -241,6 +254,8 @@ pub fn execute_proof(
             Ok(())
         };
 
+        node_counts.record(node);
+
         if let Node::KV(key, value) = node {
             execute_node(key, Some(value), value_hash(value).unwrap())?;
         } else if let Node::KVValueHash(key, value, value_hash) = node {
@@ -294,6 +309,8 @@ pub fn execute_proof(
             result_set: output,
             limit: current_limit,
             offset: current_offset,
+            node_counts,
+            proof_bytes: bytes.len(),
         },
     ))
     .wrap_with_cost(cost)
@@ -321,6 +338,71 @@ pub struct ProofVerificationResult {
     pub limit: Option<u16>,
     /// Offset
     pub offset: Option<u16>,
+    /// Nodes of each type pushed by the proof
+    pub node_counts: ProofNodeCounts,
+    /// Size of the encoded proof in bytes
+    pub proof_bytes: usize,
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+#[derive(PartialEq, Eq, Debug, Default, Clone, Copy)]
+/// Number of nodes of each type pushed by a proof, summed across proofs with
+/// `+=` when a proof spans several trees
+pub struct ProofNodeCounts {
+    /// `Node::Hash` nodes
+    pub hash: usize,
+    /// `Node::KVHash` nodes
+    pub kv_hash: usize,
+    /// `Node::KVDigest` nodes
+    pub kv_digest: usize,
+    /// `Node::KV` nodes
+    pub kv: usize,
+    /// `Node::KVValueHash` nodes
+    pub kv_value_hash: usize,
+    /// `Node::KVValueHashFeatureType` nodes
+    pub kv_value_hash_feature_type: usize,
+    /// `Node::KVRefValueHash` nodes
+    pub kv_ref_value_hash: usize,
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl ProofNodeCounts {
+    /// Total number of nodes
+    pub fn total(&self) -> usize {
+        self.hash
+            + self.kv_hash
+            + self.kv_digest
+            + self.kv
+            + self.kv_value_hash
+            + self.kv_value_hash_feature_type
+            + self.kv_ref_value_hash
+    }
+
+    fn record(&mut self, node: &Node) {
+        let count = match node {
+            Node::Hash(_) => &mut self.hash,
+            Node::KVHash(_) => &mut self.kv_hash,
+            Node::KVDigest(..) => &mut self.kv_digest,
+            Node::KV(..) => &mut self.kv,
+            Node::KVValueHash(..) => &mut self.kv_value_hash,
+            Node::KVValueHashFeatureType(..) => &mut self.kv_value_hash_feature_type,
+            Node::KVRefValueHash(..) => &mut self.kv_ref_value_hash,
+        };
+        *count += 1;
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl AddAssign for ProofNodeCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.hash += other.hash;
+        self.kv_hash += other.kv_hash;
+        self.kv_digest += other.kv_digest;
+        self.kv += other.kv;
+        self.kv_value_hash += other.kv_value_hash;
+        self.kv_value_hash_feature_type += other.kv_value_hash_feature_type;
+        self.kv_ref_value_hash += other.kv_ref_value_hash;
+    }
 }
 
 #[cfg(any(feature = "full", feature = "verify"))]
@@ -27,6 +27,14 @@
 // DEALINGS IN THE SOFTWARE.
 
 //! Merk tree encoding
+//! The first byte of an encoded node is a header carrying the option tag of the
+//! left link in its lowest bit and the node version in the remaining bits.
+//! Legacy nodes were written with a plain option tag, which reads as version
+//! zero, so nodes of any known version decode and are rewritten with the
+//! current version whenever they are next written.
+
+#[cfg(feature = "full")]
+use std::io::Read;
 
 #[cfg(feature = "full")]
 use costs::{
@@ -46,6 +54,36 @@ use crate::{
     Error::StorageError,
 };
 
+#[cfg(feature = "full")]
+/// Version of nodes written before the node header carried a version
+pub const LEGACY_NODE_VERSION: u8 = 0;
+
+#[cfg(feature = "full")]
+/// Version of newly encoded nodes
+pub const NODE_VERSION: u8 = 1;
+
+#[cfg(feature = "full")]
+/// Returns the version of an encoded node, `None` for empty input
+pub fn encoded_node_version(bytes: &[u8]) -> Option<u8> {
+    bytes.first().map(|header| header >> 1)
+}
+
+#[cfg(feature = "full")]
+/// Strips the version from the node header, leaving the left link option tag
+fn strip_node_version(input: &[u8]) -> ed::Result<[u8; 1]> {
+    match input.first() {
+        Some(header) if header >> 1 <= NODE_VERSION => Ok([header & 1]),
+        Some(header) => Err(ed::Error::UnexpectedByte(*header)),
+        None => Err(ed::Error::IOError(std::io::ErrorKind::UnexpectedEof.into())),
+    }
+}
+
+#[cfg(feature = "full")]
+fn decode_tree_inner(input: &[u8]) -> ed::Result<TreeInner> {
+    let left_option_tag = strip_node_version(input)?;
+    Decode::decode(Read::chain(&left_option_tag[..], &input[1..]))
+}
+
 #[cfg(feature = "full")]
 impl Tree {
     /// Decode given bytes and set as Tree fields. Set key to value of given
@@ -79,15 +117,18 @@ impl Tree {
     #[inline]
     /// Encode
     pub fn encode(&self) -> Vec<u8> {
-        // operation is infallible so it's ok to unwrap
-        Encode::encode(&self.inner).unwrap()
+        let mut bytes = Vec::with_capacity(self.encoding_length());
+        self.encode_into(&mut bytes);
+        bytes
     }
 
     #[inline]
     /// Encode to destination writer
     pub fn encode_into(&self, dest: &mut Vec<u8>) {
+        let header_index = dest.len();
         // operation is infallible so it's ok to unwrap
-        Encode::encode_into(&self.inner, dest).unwrap()
+        Encode::encode_into(&self.inner, dest).unwrap();
+        dest[header_index] |= NODE_VERSION << 1;
     }
 
     #[inline]
@@ -112,7 +153,7 @@ impl Tree {
     #[inline]
     /// Decode bytes from reader, set as Tree fields and set key to given key
     pub fn decode_into(&mut self, key: Vec<u8>, input: &[u8]) -> ed::Result<()> {
-        let mut tree_inner: TreeInner = Decode::decode(input)?;
+        let mut tree_inner = decode_tree_inner(input)?;
         tree_inner.kv.key = key;
         self.inner = Box::new(tree_inner);
         Ok(())
@@ -121,7 +162,7 @@ impl Tree {
     #[inline]
     /// Decode input and set as Tree fields. Set the key as the given key.
     pub fn decode(key: Vec<u8>, input: &[u8]) -> ed::Result<Self> {
-        let mut tree_inner: TreeInner = Decode::decode(input)?;
+        let mut tree_inner = decode_tree_inner(input)?;
         tree_inner.kv.key = key;
         Ok(Tree::new_with_tree_inner(tree_inner))
     }
@@ -144,7 +185,7 @@ mod tests {
         assert_eq!(
             tree.encode(),
             vec![
-                0, 0, 0, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55,
+                2, 0, 0, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55,
                 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 32, 34, 236, 157, 87, 27,
                 167, 116, 207, 158, 131, 208, 25, 73, 98, 245, 209, 227, 170, 26, 72, 212, 134,
                 166, 126, 39, 98, 166, 199, 149, 144, 21, 1
@@ -190,7 +231,7 @@ mod tests {
         assert_eq!(
             tree.encode(),
             vec![
-                1, 1, 2, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66,
+                3, 1, 2, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66,
                 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 123, 124, 0, 0, 0, 55, 55,
                 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55,
                 55, 55, 55, 55, 55, 55, 55, 55, 55, 32, 34, 236, 157, 87, 27, 167, 116, 207, 158,
@@ -219,7 +260,7 @@ mod tests {
         assert_eq!(
             tree.encode(),
             vec![
-                1, 1, 2, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66,
+                3, 1, 2, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66,
                 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 123, 124, 1, 20, 0, 1, 10,
                 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55,
                 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 32, 34, 236, 157, 87, 27, 167, 116,
@@ -257,7 +298,7 @@ mod tests {
         assert_eq!(
             tree.encode(),
             vec![
-                1, 1, 2, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66,
+                3, 1, 2, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66,
                 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 66, 123, 124, 0, 0, 0, 55, 55,
                 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55,
                 55, 55, 55, 55, 55, 55, 55, 55, 55, 32, 34, 236, 157, 87, 27, 167, 116, 207, 158,
@@ -308,6 +349,52 @@ mod tests {
         }
     }
 
+    #[test]
+    fn decode_legacy_and_current_nodes() {
+        let tree = Tree::from_fields(
+            vec![0],
+            vec![1],
+            [55; 32],
+            Some(Link::Reference {
+                hash: [66; 32],
+                sum: None,
+                child_heights: (123, 124),
+                key: vec![2],
+            }),
+            None,
+            BasicMerk,
+        )
+        .unwrap();
+        let bytes = tree.encode();
+        assert_eq!(encoded_node_version(&bytes), Some(NODE_VERSION));
+
+        let mut legacy_bytes = bytes.clone();
+        legacy_bytes[0] = 1;
+        assert_eq!(
+            encoded_node_version(&legacy_bytes),
+            Some(LEGACY_NODE_VERSION)
+        );
+
+        for bytes in [bytes, legacy_bytes] {
+            let decoded = Tree::decode(vec![0], bytes.as_slice()).expect("should decode");
+            assert_eq!(decoded.value_as_slice(), &[1]);
+            assert!(matches!(decoded.link(true), Some(Link::Reference { key, .. }) if key == &[2]));
+            assert_eq!(encoded_node_version(&decoded.encode()), Some(NODE_VERSION));
+        }
+    }
+
+    #[test]
+    fn decode_unknown_node_version() {
+        let mut bytes = Tree::from_fields(vec![0], vec![1], [55; 32], None, None, BasicMerk)
+            .unwrap()
+            .encode();
+        bytes[0] = (NODE_VERSION + 1) << 1;
+        assert!(matches!(
+            Tree::decode(vec![0], bytes.as_slice()),
+            Err(ed::Error::UnexpectedByte(_))
+        ));
+    }
+
     #[test]
     fn decode_invalid_bytes_as_tree() {
         let bytes = vec![2, 3, 4, 5];
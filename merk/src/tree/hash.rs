@@ -28,6 +28,9 @@
 
 //! Merk tree hash
 
+#[cfg(any(feature = "full", feature = "verify"))]
+use std::{cell::RefCell, sync::Arc};
+
 #[cfg(any(feature = "full", feature = "verify"))]
 use costs::{CostContext, CostsExt, OperationCost};
 #[cfg(any(feature = "full", feature = "verify"))]
@@ -60,23 +63,83 @@ pub const NULL_HASH: CryptoHash = [0; HASH_LENGTH];
 #[cfg(any(feature = "full", feature = "verify"))]
 pub type CryptoHash = [u8; HASH_LENGTH];
 
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Implementation of the BLAKE3 hash function used for merk hashes. Any
+/// backend, be it hardware accelerated or provided by the platform, has to
+/// give the exact same digests as the default one, otherwise root hashes won't
+/// match.
+pub trait HashBackend: Send + Sync {
+    /// BLAKE3 digest of the concatenation of `parts`
+    fn hash(&self, parts: &[&[u8]]) -> CryptoHash;
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Default hash backend, using the `blake3` crate
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Blake3Backend;
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl HashBackend for Blake3Backend {
+    fn hash(&self, parts: &[&[u8]]) -> CryptoHash {
+        let mut hasher = blake3::Hasher::new();
+        for part in parts {
+            hasher.update(part);
+        }
+        let mut hash: CryptoHash = Default::default();
+        hash.copy_from_slice(hasher.finalize().as_bytes());
+        hash
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+thread_local! {
+    /// Backend set for the current thread by [`with_hash_backend`]
+    static HASH_BACKEND: RefCell<Option<Arc<dyn HashBackend>>> = RefCell::new(None);
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Restores the previous backend of the thread, even on panic
+struct HashBackendGuard(Option<Arc<dyn HashBackend>>);
+
+#[cfg(any(feature = "full", feature = "verify"))]
+impl Drop for HashBackendGuard {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        HASH_BACKEND.with(|backend| *backend.borrow_mut() = previous);
+    }
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Runs `f` with every merk hash of the current thread computed by `backend`
+pub fn with_hash_backend<T>(backend: Arc<dyn HashBackend>, f: impl FnOnce() -> T) -> T {
+    let previous = HASH_BACKEND.with(|current| current.borrow_mut().replace(backend));
+    let _guard = HashBackendGuard(previous);
+    f()
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Hashes the concatenation of `parts` with the backend of the thread
+fn hash_parts(parts: &[&[u8]]) -> CryptoHash {
+    HASH_BACKEND.with(|backend| match backend.borrow().as_ref() {
+        Some(backend) => backend.hash(parts),
+        None => Blake3Backend.hash(parts),
+    })
+}
+
+#[cfg(any(feature = "full", feature = "verify"))]
+/// Number of hash blocks needed for `len` bytes
+fn hash_blocks(len: usize) -> u16 {
+    (1 + (len.max(1) - 1) / 64) as u16
+}
+
 #[cfg(any(feature = "full", feature = "verify"))]
 /// Hashes a value
 pub fn value_hash(value: &[u8]) -> CostContext<CryptoHash> {
-    // TODO: make generic to allow other hashers
-    let mut hasher = blake3::Hasher::new();
-
     let val_length = value.len().encode_var_vec();
-    hasher.update(val_length.as_slice());
-    hasher.update(value);
-
-    let hashes = 1 + (hasher.count() - 1) / 64;
+    let hash = hash_parts(&[val_length.as_slice(), value]);
 
-    let res = hasher.finalize();
-    let mut hash: CryptoHash = Default::default();
-    hash.copy_from_slice(res.as_bytes());
     hash.wrap_with_cost(OperationCost {
-        hash_node_calls: hashes as u16,
+        hash_node_calls: hash_blocks(val_length.len() + value.len()),
         ..Default::default()
     })
 }
@@ -88,44 +151,20 @@ pub fn value_hash(value: &[u8]) -> CostContext<CryptoHash> {
 pub fn kv_hash(key: &[u8], value: &[u8]) -> CostContext<CryptoHash> {
     let mut cost = OperationCost::default();
 
-    // TODO: make generic to allow other hashers
-    let mut hasher = blake3::Hasher::new();
+    let value_hash = value_hash(value).unwrap_add_cost(&mut cost);
+    let hash = kv_digest_to_kv_hash(key, &value_hash).unwrap_add_cost(&mut cost);
 
-    let key_length = key.len().encode_var_vec();
-    hasher.update(key_length.as_slice());
-    hasher.update(key);
-
-    let value_hash = value_hash(value);
-    hasher.update(value_hash.unwrap_add_cost(&mut cost).as_slice());
-
-    let hashes = 1 + (hasher.count() - 1) / 64;
-
-    let res = hasher.finalize();
-    let mut hash: CryptoHash = Default::default();
-    hash.copy_from_slice(res.as_bytes());
-
-    cost.hash_node_calls += hashes as u16;
     hash.wrap_with_cost(cost)
 }
 
 #[cfg(any(feature = "full", feature = "verify"))]
 /// Computes the kv hash given a kv digest
 pub fn kv_digest_to_kv_hash(key: &[u8], value_hash: &CryptoHash) -> CostContext<CryptoHash> {
-    let mut hasher = blake3::Hasher::new();
-
     let key_length = key.len().encode_var_vec();
-    hasher.update(key_length.as_slice());
-    hasher.update(key);
-
-    hasher.update(value_hash.as_slice());
+    let hash = hash_parts(&[key_length.as_slice(), key, value_hash.as_slice()]);
 
-    let hashes = 1 + (hasher.count() - 1) / 64;
-
-    let res = hasher.finalize();
-    let mut hash: CryptoHash = Default::default();
-    hash.copy_from_slice(res.as_bytes());
     hash.wrap_with_cost(OperationCost {
-        hash_node_calls: hashes as u16,
+        hash_node_calls: hash_blocks(key_length.len() + key.len() + value_hash.len()),
         ..Default::default()
     })
 }
@@ -138,20 +177,11 @@ pub fn node_hash(
     left: &CryptoHash,
     right: &CryptoHash,
 ) -> CostContext<CryptoHash> {
-    // TODO: make generic to allow other hashers
-    let mut hasher = blake3::Hasher::new();
-    hasher.update(kv);
-    hasher.update(left);
-    hasher.update(right);
+    let hash = hash_parts(&[kv, left, right]);
 
     // hashes will always be 2
-    let hashes = 2; // 1 + (hasher.count() - 1) / 64;
-
-    let res = hasher.finalize();
-    let mut hash: CryptoHash = Default::default();
-    hash.copy_from_slice(res.as_bytes());
     hash.wrap_with_cost(OperationCost {
-        hash_node_calls: hashes,
+        hash_node_calls: 2,
         ..Default::default()
     })
 }
@@ -159,13 +189,8 @@ pub fn node_hash(
 #[cfg(any(feature = "full", feature = "verify"))]
 /// Combines two hash values into one
 pub fn combine_hash(hash_one: &CryptoHash, hash_two: &CryptoHash) -> CostContext<CryptoHash> {
-    let mut hasher = blake3::Hasher::new();
-    hasher.update(hash_one);
-    hasher.update(hash_two);
+    let hash = hash_parts(&[hash_one, hash_two]);
 
-    let res = hasher.finalize();
-    let mut hash: CryptoHash = Default::default();
-    hash.copy_from_slice(res.as_bytes());
     hash.wrap_with_cost(OperationCost {
         hash_node_calls: 1, // as this will fit on exactly 1 block
         ..Default::default()
@@ -43,11 +43,12 @@ use super::hash::{CryptoHash, HASH_LENGTH, NULL_HASH};
 use crate::tree::kv::ValueDefinedCostType::{LayeredValueDefinedCost, SpecializedValueDefinedCost};
 #[cfg(feature = "full")]
 use crate::{
+    cost_constants::active_cost_constants,
     tree::{
         hash::{combine_hash, kv_digest_to_kv_hash, value_hash, HASH_LENGTH_X2},
         tree_feature_type::{TreeFeatureType, TreeFeatureType::BasicMerk},
     },
-    Link, HASH_LENGTH_U32, HASH_LENGTH_U32_X2,
+    Link,
 };
 
 // TODO: maybe use something similar to Vec but without capacity field,
@@ -292,9 +293,10 @@ impl KV {
     /// Get the key costs for the node, this has the parent to child hooks
     #[inline]
     pub fn node_key_byte_cost_size(not_prefixed_key_len: u32) -> u32 {
-        HASH_LENGTH_U32
+        let key_prefix_size = active_cost_constants().key_prefix_size;
+        key_prefix_size
             + not_prefixed_key_len
-            + (not_prefixed_key_len + HASH_LENGTH_U32).required_space() as u32
+            + (not_prefixed_key_len + key_prefix_size).required_space() as u32
     }
 
     /// Get the key costs for the node, this has the parent to child hooks
@@ -306,12 +308,14 @@ impl KV {
     ) -> u32 {
         // Sum trees are either 1 or 9 bytes. While they might be more or less on disk,
         // costs can not take advantage of the varint aspect of the feature.
-        let feature_len = if is_sum_node { 9 } else { 1 };
+        let cost_constants = active_cost_constants();
+        let feature_len = cost_constants.feature_size(is_sum_node);
 
-        let value_size = raw_value_len + HASH_LENGTH_U32_X2 + feature_len;
+        let value_size = raw_value_len + cost_constants.node_hash_size * 2 + feature_len;
         // The node will be a child of another node which stores it's key and hash
         // That will be added during propagation
-        let parent_to_child_cost = Link::encoded_link_size(not_prefixed_key_len, is_sum_node);
+        let parent_to_child_cost =
+            Link::encoded_link_size(not_prefixed_key_len, is_sum_node, &cost_constants);
 
         value_size + value_size.required_space() as u32 + parent_to_child_cost
     }
@@ -339,21 +343,23 @@ impl KV {
     ) -> u32 {
         // Sum trees are either 1 or 9 bytes. While they might be more or less on disk,
         // costs can not take advantage of the varint aspect of the feature.
-        let feature_len = if is_sum_node { 9 } else { 1 };
+        let cost_constants = active_cost_constants();
+        let feature_len = cost_constants.feature_size(is_sum_node);
 
         // Each node stores the key and value, and the node hash
         // the value hash on a layered node is not stored directly in the node
         // The required space is set to 2, even though it could be potentially 1
-        let node_value_size = value_len + feature_len + HASH_LENGTH_U32 + 2;
+        let node_value_size = value_len + feature_len + cost_constants.node_hash_size + 2;
         // Hash length is for the key prefix
-        let node_key_size = HASH_LENGTH_U32
+        let node_key_size = cost_constants.key_prefix_size
             + not_prefixed_key_len
-            + (not_prefixed_key_len + HASH_LENGTH_U32).required_space() as u32;
+            + (not_prefixed_key_len + cost_constants.key_prefix_size).required_space() as u32;
 
         let node_size = node_value_size + node_key_size;
         // The node will be a child of another node which stores it's key and hash
         // That will be added during propagation
-        let parent_to_child_cost = Link::encoded_link_size(not_prefixed_key_len, is_sum_node);
+        let parent_to_child_cost =
+            Link::encoded_link_size(not_prefixed_key_len, is_sum_node, &cost_constants);
         node_size + parent_to_child_cost
     }
 
@@ -367,7 +373,8 @@ impl KV {
     ) -> u32 {
         // Sum trees are either 1 or 9 bytes. While they might be more or less on disk,
         // costs can not take advantage of the varint aspect of the feature.
-        let feature_len = if is_sum_node { 9 } else { 1 };
+        let cost_constants = active_cost_constants();
+        let feature_len = cost_constants.feature_size(is_sum_node);
         // Each node stores the key and value, and the node hash
         // the value hash on a layered node is not stored directly in the node
         // The required space is set to 2. However in reality it could be 1 or 2.
@@ -376,10 +383,11 @@ impl KV {
         // There is no point to pay for the value_hash because it is already being paid
         // by the parent to child reference hook of the root of the underlying
         // tree
-        let node_value_size = value_len + feature_len + HASH_LENGTH_U32 + 2;
+        let node_value_size = value_len + feature_len + cost_constants.node_hash_size + 2;
         // The node will be a child of another node which stores it's key and hash
         // That will be added during propagation
-        let parent_to_child_cost = Link::encoded_link_size(not_prefixed_key_len, is_sum_node);
+        let parent_to_child_cost =
+            Link::encoded_link_size(not_prefixed_key_len, is_sum_node, &cost_constants);
         node_value_size + parent_to_child_cost
     }
 
@@ -392,13 +400,15 @@ impl KV {
     ) -> u32 {
         // Sum trees are either 1 or 9 bytes. While they might be more or less on disk,
         // costs can not take advantage of the varint aspect of the feature.
-        let feature_len = if is_sum_node { 9 } else { 1 };
+        let cost_constants = active_cost_constants();
+        let feature_len = cost_constants.feature_size(is_sum_node);
         // Each node stores the key and value, and the node hash and the value hash
-        let node_value_size = inner_value_len + feature_len + HASH_LENGTH_U32_X2;
+        let node_value_size = inner_value_len + feature_len + cost_constants.node_hash_size * 2;
         let node_value_size = node_value_size + node_value_size.required_space() as u32;
         // The node will be a child of another node which stores it's key and hash
         // That will be added during propagation
-        let parent_to_child_cost = Link::encoded_link_size(not_prefixed_key_len, is_sum_node);
+        let parent_to_child_cost =
+            Link::encoded_link_size(not_prefixed_key_len, is_sum_node, &cost_constants);
         node_value_size + parent_to_child_cost
     }
 
@@ -417,7 +427,7 @@ impl KV {
         // however we do need the varint required space for the cost of the key in
         // rocks_db
         let parent_to_child_reference_len =
-            Link::encoded_link_size(not_prefixed_key_len, is_sum_node);
+            Link::encoded_link_size(not_prefixed_key_len, is_sum_node, &active_cost_constants());
         value_len + value_len.required_space() as u32 + parent_to_child_reference_len
     }
 
@@ -429,8 +439,10 @@ impl KV {
         raw_value_len: u32,
         is_sum_node: bool,
     ) -> u32 {
-        let sum_tree_len = if is_sum_node { 9 } else { 1 }; // 1 for option, 0 or 9 for sum feature
-        let value_len = raw_value_len + HASH_LENGTH_U32_X2 + sum_tree_len;
+        // 1 for option, 0 or 9 for sum feature
+        let cost_constants = active_cost_constants();
+        let sum_tree_len = cost_constants.feature_size(is_sum_node);
+        let value_len = raw_value_len + cost_constants.node_hash_size * 2 + sum_tree_len;
         Self::value_byte_cost_size_for_key_and_value_lengths(
             not_prefixed_key_len,
             value_len,
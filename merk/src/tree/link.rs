@@ -39,7 +39,7 @@ use integer_encoding::{VarInt, VarIntReader, VarIntWriter};
 #[cfg(feature = "full")]
 use super::{hash::CryptoHash, Tree};
 #[cfg(feature = "full")]
-use crate::HASH_LENGTH_U32;
+use crate::cost_constants::CostConstants;
 
 // TODO: optimize memory footprint
 
@@ -279,8 +279,16 @@ impl Link {
     // Costs for operations within a single merk
     #[inline]
     /// Encoded link size
-    pub const fn encoded_link_size(not_prefixed_key_len: u32, is_sum_tree: bool) -> u32 {
-        let sum_tree_cost = if is_sum_tree { 8 } else { 0 };
+    pub const fn encoded_link_size(
+        not_prefixed_key_len: u32,
+        is_sum_tree: bool,
+        cost_constants: &CostConstants,
+    ) -> u32 {
+        let sum_tree_cost = if is_sum_tree {
+            cost_constants.parent_hook_sum_size
+        } else {
+            0
+        };
         // Links are optional values that represent the right or left node for a given
         // 1 byte to represent key_length (this is a u8)
         // key_length to represent the actual key
@@ -288,7 +296,10 @@ impl Link {
         // 1 byte for the left child height
         // 1 byte for the right child height
         // 1 byte for the sum tree option
-        not_prefixed_key_len + HASH_LENGTH_U32 + 4 + sum_tree_cost
+        not_prefixed_key_len
+            + cost_constants.node_hash_size
+            + cost_constants.parent_hook_size
+            + sum_tree_cost
     }
 
     /// The encoding cost is always 8 bytes for the sum instead of a varint
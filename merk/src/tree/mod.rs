@@ -54,6 +54,8 @@ mod walk;
 #[cfg(feature = "full")]
 use std::cmp::{max, Ordering};
 
+#[cfg(feature = "full")]
+use crate::cost_constants::active_cost_constants;
 #[cfg(feature = "full")]
 pub use commit::{Commit, NoopCommit};
 #[cfg(feature = "full")]
@@ -68,10 +70,12 @@ use costs::{
 };
 #[cfg(feature = "full")]
 use ed::{Decode, Encode, Terminated};
+#[cfg(feature = "full")]
+pub use encoding::{encoded_node_version, LEGACY_NODE_VERSION, NODE_VERSION};
 #[cfg(any(feature = "full", feature = "verify"))]
 pub use hash::{
-    combine_hash, kv_digest_to_kv_hash, kv_hash, node_hash, value_hash, CryptoHash, HASH_LENGTH,
-    NULL_HASH,
+    combine_hash, kv_digest_to_kv_hash, kv_hash, node_hash, value_hash, with_hash_backend,
+    Blake3Backend, CryptoHash, HashBackend, HASH_LENGTH, NULL_HASH,
 };
 #[cfg(feature = "full")]
 pub use hash::{HASH_BLOCK_SIZE, HASH_BLOCK_SIZE_U32, HASH_LENGTH_U32, HASH_LENGTH_U32_X2};
@@ -416,10 +420,13 @@ impl Tree {
     /// Returns a the size of node's child key and sum on the given side, if
     /// any. If there is no child, returns `None`.
     pub fn child_ref_and_sum_size(&self, left: bool) -> Option<(u32, u32)> {
+        let cost_constants = active_cost_constants();
         self.link(left).map(|link| {
             (
-                // 36 = 32 Hash + 1 key length + 2 child heights + 1 feature type
-                link.key().len() as u32 + 36,
+                // 32 Hash + 1 key length + 2 child heights + 1 feature type
+                link.key().len() as u32
+                    + cost_constants.node_hash_size
+                    + cost_constants.parent_hook_size,
                 link.sum()
                     .map(|s| s.encode_var_vec().len() as u32)
                     .unwrap_or_default(),
@@ -52,7 +52,10 @@ use Op::*;
 #[cfg(feature = "full")]
 use super::{Fetch, Link, Tree, Walker};
 #[cfg(feature = "full")]
-use crate::{error::Error, tree::tree_feature_type::TreeFeatureType, CryptoHash, HASH_LENGTH_U32};
+use crate::{
+    cost_constants::active_cost_constants, error::Error, tree::tree_feature_type::TreeFeatureType,
+    CryptoHash,
+};
 use crate::{merk::KeyUpdates, tree::kv::ValueDefinedCostType::SpecializedValueDefinedCost};
 
 #[cfg(feature = "full")]
@@ -422,7 +425,7 @@ where
                     let key = self.tree().key().to_vec();
                     let key_len = key.len() as u32;
 
-                    let prefixed_key_len = HASH_LENGTH_U32 + key_len;
+                    let prefixed_key_len = active_cost_constants().key_prefix_size + key_len;
                     let total_key_len = prefixed_key_len + prefixed_key_len.required_space() as u32;
                     let value = self.tree().value_ref();
 
@@ -1,8 +1,58 @@
 const { promisify } = require('util');
 const { join: pathJoin } = require('path');
 
+// Version of the native module interface and of the GroveDB storage format
+// this wrapper is written against, see `groveDbBindingInfo` in ./src/lib.rs
+const EXPECTED_ABI_VERSION = 1;
+const EXPECTED_FORMAT_VERSION = 1;
+
 // This file is crated when run `npm run build`. The actual source file that
 // exports those functions is ./src/lib.rs
+const native = require('neon-load-or-build')({
+  dir: pathJoin(__dirname, '..'),
+});
+
+/**
+ * Makes sure the loaded native module, possibly a prebuilt binary, is the one
+ * this wrapper expects and can run on this Node.JS release
+ *
+ * @param {Object} nativeModule
+ * @returns {BindingInfo}
+ */
+function checkBindingInfo(nativeModule) {
+  if (typeof nativeModule.groveDbBindingInfo !== 'function') {
+    throw new Error('GroveDB native module is too old for this wrapper, please rebuild it');
+  }
+
+  const info = nativeModule.groveDbBindingInfo();
+
+  if (info.abiVersion !== EXPECTED_ABI_VERSION) {
+    throw new Error(
+      `GroveDB native module ABI version ${info.abiVersion} doesn't match `
+      + `the expected version ${EXPECTED_ABI_VERSION}, please rebuild it`,
+    );
+  }
+
+  if (info.formatVersion !== EXPECTED_FORMAT_VERSION) {
+    throw new Error(
+      `GroveDB native module format version ${info.formatVersion} doesn't match `
+      + `the expected version ${EXPECTED_FORMAT_VERSION}`,
+    );
+  }
+
+  const nodeNapiVersion = Number(process.versions.napi);
+  if (nodeNapiVersion < info.napiVersion) {
+    throw new Error(
+      `GroveDB native module requires N-API version ${info.napiVersion}, `
+      + `this Node.JS release supports version ${nodeNapiVersion}`,
+    );
+  }
+
+  return info;
+}
+
+const bindingInfo = checkBindingInfo(native);
+
 const {
   groveDbOpen,
   groveDbGet,
@@ -21,9 +71,7 @@ const {
   groveDbGetAux,
   groveDbGetPathQuery,
   groveDbRootHash,
-} = require('neon-load-or-build')({
-  dir: pathJoin(__dirname, '..'),
-});
+} = native;
 
 // Convert the DB methods from using callbacks to returning promises
 const groveDbGetAsync = promisify(groveDbGet);
@@ -246,4 +294,14 @@ class GroveDB {
  * @property {boolean| null} leftToRight
  */
 
+/**
+ * @typedef BindingInfo
+ * @property {number} abiVersion
+ * @property {number} napiVersion
+ * @property {number} formatVersion
+ * @property {string} version
+ */
+
+GroveDB.bindingInfo = bindingInfo;
+
 module.exports = GroveDB;
@@ -31,6 +31,12 @@ describe('GroveDB', () => {
     fs.rmSync(TEST_DATA_PATH, { recursive: true });
   });
 
+  it('should load a native module matching the wrapper', () => {
+    expect(GroveDB.bindingInfo.abiVersion).to.equal(1);
+    expect(GroveDB.bindingInfo.formatVersion).to.equal(1);
+    expect(GroveDB.bindingInfo.napiVersion).to.be.at.most(Number(process.versions.napi));
+  });
+
   it('should store and retrieve a value', async () => {
     // Making a subtree to insert items into
     await groveDb.insert(
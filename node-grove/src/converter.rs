@@ -38,6 +38,7 @@ fn element_to_string(element: Element) -> String {
         Element::Reference(..) => "reference".to_string(),
         Element::Tree(..) => "tree".to_string(),
         Element::SumTree(..) => "sum_tree".to_string(),
+        Element::OrderedTree(..) => "ordered_tree".to_string(),
     }
 }
 
@@ -92,6 +93,7 @@ pub fn element_to_js_object<'a, C: Context<'a>>(
         Element::Reference(..) => nested_vecs_to_js(vec![], cx)?,
         Element::Tree(..) => nested_vecs_to_js(vec![], cx)?,
         Element::SumTree(..) => nested_vecs_to_js(vec![], cx)?,
+        Element::OrderedTree(..) => nested_vecs_to_js(vec![], cx)?,
     };
 
     js_object.set(cx, "value", js_value)?;
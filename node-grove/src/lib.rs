@@ -37,6 +37,22 @@ use std::{option::Option::None, path::Path, sync::mpsc, thread};
 use grovedb::{GroveDb, Transaction, TransactionArg};
 use neon::prelude::*;
 
+#[cfg(not(feature = "napi-4"))]
+compile_error!("one of the napi-* features selecting the targeted N-API version is required");
+
+/// Version of the interface between the native module and `index.js`, bumped
+/// whenever exported functions are added, removed or change their arguments
+const BINDING_ABI_VERSION: u32 = 1;
+
+/// N-API version the native module is built for
+const NAPI_VERSION: u32 = if cfg!(feature = "napi-6") {
+    6
+} else if cfg!(feature = "napi-5") {
+    5
+} else {
+    4
+};
+
 type DbCallback = Box<dyn for<'a> FnOnce(&'a GroveDb, TransactionArg, &Channel) + Send>;
 type UnitCallback = Box<dyn FnOnce(&Channel) + Send>;
 
@@ -789,8 +805,26 @@ impl GroveDbWrapper {
     }
 }
 
+// Describes the native module, `index.js` checks it matches what it expects
+// before using anything else
+fn js_binding_info(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let info = cx.empty_object();
+
+    let abi_version = cx.number(BINDING_ABI_VERSION);
+    info.set(&mut cx, "abiVersion", abi_version)?;
+    let napi_version = cx.number(NAPI_VERSION);
+    info.set(&mut cx, "napiVersion", napi_version)?;
+    let format_version = cx.number(grovedb::NODE_VERSION);
+    info.set(&mut cx, "formatVersion", format_version)?;
+    let version = cx.string(env!("CARGO_PKG_VERSION"));
+    info.set(&mut cx, "version", version)?;
+
+    Ok(info)
+}
+
 #[neon::main]
 fn main(mut cx: ModuleContext) -> NeonResult<()> {
+    cx.export_function("groveDbBindingInfo", js_binding_info)?;
     cx.export_function("groveDbOpen", GroveDbWrapper::js_open)?;
     cx.export_function("groveDbInsert", GroveDbWrapper::js_insert)?;
     cx.export_function(
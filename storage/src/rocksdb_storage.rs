@@ -27,6 +27,9 @@
 // DEALINGS IN THE SOFTWARE.
 
 //! GroveDB storage layer implemented over RocksDB backend.
+#[cfg(feature = "fault_injection")]
+mod fault_injection;
+mod replication;
 mod storage;
 mod storage_context;
 pub mod test_utils;
@@ -40,4 +43,9 @@ pub use storage_context::{
     PrefixedRocksDbStorageContext, PrefixedRocksDbTransactionContext,
 };
 
-pub use self::storage::RocksDbStorage;
+#[cfg(feature = "fault_injection")]
+pub use self::fault_injection::{CrashPoint, FaultInjector};
+pub use self::{
+    replication::{PhysicalChanges, PhysicalColumnFamily, PhysicalOperation, ReplicationSink},
+    storage::{RocksDbStorage, RocksDbTransaction, RocksDbWriteBatch},
+};
@@ -0,0 +1,130 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+//! Fault injection.
+//!
+//! A [`FaultInjector`] set on a [`RocksDbStorage`](super::RocksDbStorage)
+//! crashes it at a chosen write batch boundary, either right before the batch
+//! reaches RocksDB or right after it was written. Once crashed every
+//! following write fails, as if the process had died, so the only way forward
+//! is reopening the storage, which is what crash recovery tests exercise.
+
+use std::sync::{Arc, Mutex};
+
+use crate::error::Error::{self, RocksDBError, StorageError};
+
+/// Point of a write batch at which the storage crashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrashPoint {
+    /// The write batch is lost
+    BeforeWrite,
+    /// The write batch is written but the write never returns
+    AfterWrite,
+}
+
+#[derive(Debug, Default)]
+struct FaultState {
+    /// Write batches to let through before crashing, and where to crash
+    countdown: Option<(u64, CrashPoint)>,
+    /// Write batches that reached RocksDB
+    writes: u64,
+    crashed: bool,
+}
+
+/// Handle crashing a storage at a chosen write batch boundary, shared between
+/// the storage and the test driving it.
+#[derive(Debug, Clone, Default)]
+pub struct FaultInjector {
+    state: Arc<Mutex<FaultState>>,
+}
+
+impl FaultInjector {
+    /// Create an injector which doesn't crash until armed
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Crash at the write batch following the next `write_batches` ones
+    pub fn crash_at(&self, write_batches: u64, point: CrashPoint) {
+        self.lock().countdown = Some((write_batches, point));
+    }
+
+    /// Cancel a pending crash
+    pub fn disarm(&self) {
+        self.lock().countdown = None;
+    }
+
+    /// Returns true once the storage has crashed
+    pub fn crashed(&self) -> bool {
+        self.lock().crashed
+    }
+
+    /// Number of write batches that reached RocksDB
+    pub fn writes(&self) -> u64 {
+        self.lock().writes
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<FaultState> {
+        self.state.lock().expect("fault injector lock is poisoned")
+    }
+
+    /// Performs a write batch unless the storage crashes at it
+    pub(crate) fn write<T>(
+        injector: Option<&FaultInjector>,
+        write: impl FnOnce() -> Result<T, rocksdb::Error>,
+    ) -> Result<T, Error> {
+        let injector = match injector {
+            Some(injector) => injector,
+            None => return write().map_err(RocksDBError),
+        };
+        let mut state = injector.lock();
+        if state.crashed {
+            return Err(crashed());
+        }
+        match state.countdown {
+            Some((0, point)) => {
+                state.countdown = None;
+                state.crashed = true;
+                if point == CrashPoint::AfterWrite {
+                    write().map_err(RocksDBError)?;
+                    state.writes += 1;
+                }
+                Err(crashed())
+            }
+            countdown => {
+                state.countdown = countdown.map(|(left, point)| (left - 1, point));
+                let result = write().map_err(RocksDBError)?;
+                state.writes += 1;
+                Ok(result)
+            }
+        }
+    }
+}
+
+fn crashed() -> Error {
+    StorageError("storage crashed by fault injection".to_owned())
+}
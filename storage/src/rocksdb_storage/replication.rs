@@ -0,0 +1,210 @@
+// MIT LICENSE
+//
+// Copyright (c) 2021 Dash Core Group
+//
+// Permission is hereby granted, free of charge, to any
+// person obtaining a copy of this software and associated
+// documentation files (the "Software"), to deal in the
+// Software without restriction, including without
+// limitation the rights to use, copy, modify, merge,
+// publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice
+// shall be included in all copies or substantial portions
+// of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
+// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
+// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
+// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
+// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+//! Physical replication stream.
+//!
+//! Every commit to the underlying RocksDB can be reported to a
+//! [`ReplicationSink`] as a list of raw key/value changes, exactly as they are
+//! written (prefixed keys, encoded merk nodes). Applying these changes in
+//! order to another storage keeps it a byte-for-byte replica without any
+//! knowledge of GroveDB semantics.
+
+use std::sync::{Arc, Mutex};
+
+use crate::AbstractBatchOperation;
+
+/// Column family a physical operation is applied to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhysicalColumnFamily {
+    /// Default column family holding subtrees data
+    Default,
+    /// Auxiliary data column family
+    Aux,
+    /// Subtrees roots column family
+    Roots,
+    /// Metadata column family
+    Meta,
+}
+
+/// Raw write to the storage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PhysicalOperation {
+    /// Put a value under a key
+    Put {
+        /// Column family
+        column_family: PhysicalColumnFamily,
+        /// Prefixed key
+        key: Vec<u8>,
+        /// Value
+        value: Vec<u8>,
+    },
+    /// Delete a key
+    Delete {
+        /// Column family
+        column_family: PhysicalColumnFamily,
+        /// Prefixed key
+        key: Vec<u8>,
+    },
+}
+
+impl PhysicalOperation {
+    /// Converts a deferred batch operation into the raw write it results in
+    pub(crate) fn from_batch_operation(operation: &AbstractBatchOperation) -> Self {
+        use PhysicalColumnFamily::*;
+        match operation {
+            AbstractBatchOperation::Put { key, value, .. } => {
+                PhysicalOperation::put(Default, key, value)
+            }
+            AbstractBatchOperation::PutAux { key, value, .. } => {
+                PhysicalOperation::put(Aux, key, value)
+            }
+            AbstractBatchOperation::PutRoot { key, value, .. } => {
+                PhysicalOperation::put(Roots, key, value)
+            }
+            AbstractBatchOperation::PutMeta { key, value, .. } => {
+                PhysicalOperation::put(Meta, key, value)
+            }
+            AbstractBatchOperation::Delete { key, .. } => PhysicalOperation::delete(Default, key),
+            AbstractBatchOperation::DeleteAux { key, .. } => PhysicalOperation::delete(Aux, key),
+            AbstractBatchOperation::DeleteRoot { key, .. } => PhysicalOperation::delete(Roots, key),
+            AbstractBatchOperation::DeleteMeta { key, .. } => PhysicalOperation::delete(Meta, key),
+        }
+    }
+
+    pub(crate) fn put(column_family: PhysicalColumnFamily, key: &[u8], value: &[u8]) -> Self {
+        PhysicalOperation::Put {
+            column_family,
+            key: key.to_vec(),
+            value: value.to_vec(),
+        }
+    }
+
+    pub(crate) fn delete(column_family: PhysicalColumnFamily, key: &[u8]) -> Self {
+        PhysicalOperation::Delete {
+            column_family,
+            key: key.to_vec(),
+        }
+    }
+}
+
+/// Physical changes of a single commit, in the order they were written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PhysicalChanges {
+    /// Raw writes
+    pub operations: Vec<PhysicalOperation>,
+}
+
+/// Receiver of the physical replication stream.
+pub trait ReplicationSink: Send + Sync {
+    /// Called after every successful commit with the changes it made
+    fn on_commit(&self, changes: PhysicalChanges);
+}
+
+impl<F> ReplicationSink for F
+where
+    F: Fn(PhysicalChanges) + Send + Sync,
+{
+    fn on_commit(&self, changes: PhysicalChanges) {
+        self(changes)
+    }
+}
+
+/// Collects physical operations of a transaction until it is committed.
+pub(crate) struct ReplicationRecorder {
+    sink: Arc<dyn ReplicationSink>,
+    operations: Mutex<Vec<PhysicalOperation>>,
+    savepoints: Mutex<Vec<usize>>,
+}
+
+impl ReplicationRecorder {
+    pub(crate) fn new(sink: Arc<dyn ReplicationSink>) -> Self {
+        ReplicationRecorder {
+            sink,
+            operations: Mutex::new(Vec::new()),
+            savepoints: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn record(&self, operations: impl IntoIterator<Item = PhysicalOperation>) {
+        self.operations
+            .lock()
+            .expect("replication recorder lock is poisoned")
+            .extend(operations);
+    }
+
+    /// Drops operations recorded so far
+    pub(crate) fn clear(&self) {
+        self.operations
+            .lock()
+            .expect("replication recorder lock is poisoned")
+            .clear();
+        self.savepoints
+            .lock()
+            .expect("replication recorder lock is poisoned")
+            .clear();
+    }
+
+    /// Remembers how many operations were recorded so far
+    pub(crate) fn set_savepoint(&self) {
+        let recorded = self
+            .operations
+            .lock()
+            .expect("replication recorder lock is poisoned")
+            .len();
+        self.savepoints
+            .lock()
+            .expect("replication recorder lock is poisoned")
+            .push(recorded);
+    }
+
+    /// Drops operations recorded since the last savepoint
+    pub(crate) fn rollback_to_savepoint(&self) {
+        let recorded = self
+            .savepoints
+            .lock()
+            .expect("replication recorder lock is poisoned")
+            .pop()
+            .unwrap_or_default();
+        self.operations
+            .lock()
+            .expect("replication recorder lock is poisoned")
+            .truncate(recorded);
+    }
+
+    /// Sends everything recorded so far to the sink as one commit
+    pub(crate) fn emit(&self) {
+        let operations = std::mem::take(
+            &mut *self
+                .operations
+                .lock()
+                .expect("replication recorder lock is poisoned"),
+        );
+        if !operations.is_empty() {
+            self.sink.on_commit(PhysicalChanges { operations });
+        }
+    }
+}
@@ -28,7 +28,12 @@
 
 //! Implementation for a storage abstraction over RocksDB.
 
-use std::{ops::AddAssign, path::Path};
+use std::{
+    collections::BTreeSet,
+    ops::{AddAssign, Deref},
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard, RwLock},
+};
 
 use costs::{
     cost_return_on_error, cost_return_on_error_no_add,
@@ -39,11 +44,17 @@ use error::Error;
 use integer_encoding::VarInt;
 use lazy_static::lazy_static;
 use rocksdb::{
-    checkpoint::Checkpoint, ColumnFamily, ColumnFamilyDescriptor, OptimisticTransactionDB,
-    Transaction, WriteBatchWithTransaction,
+    checkpoint::Checkpoint, ColumnFamily, ColumnFamilyDescriptor, FlushOptions,
+    OptimisticTransactionDB, Transaction, WriteBatchWithTransaction,
 };
 
+#[cfg(feature = "fault_injection")]
+use super::FaultInjector;
 use super::{
+    replication::{
+        PhysicalChanges, PhysicalColumnFamily, PhysicalOperation, ReplicationRecorder,
+        ReplicationSink,
+    },
     PrefixedRocksDbBatchStorageContext, PrefixedRocksDbBatchTransactionContext,
     PrefixedRocksDbStorageContext, PrefixedRocksDbTransactionContext,
 };
@@ -84,15 +95,162 @@ lazy_static! {
     };
 }
 
+/// RocksDB property holding the size of active and unflushed memtables
+const MEMTABLES_SIZE_PROPERTY: &str = "rocksdb.cur-size-all-mem-tables";
+
 /// Type alias for a database
 pub(crate) type Db = OptimisticTransactionDB;
 
 /// Type alias for a transaction
-pub(crate) type Tx<'db> = Transaction<'db, Db>;
+pub(crate) type Tx<'db> = RocksDbTransaction<'db>;
+
+/// Type alias for a RocksDB transaction
+pub(crate) type RawTx<'db> = Transaction<'db, Db>;
 
 /// Storage which uses RocksDB as its backend.
 pub struct RocksDbStorage {
     db: OptimisticTransactionDB,
+    replication_sink: RwLock<Option<Arc<dyn ReplicationSink>>>,
+    #[cfg(feature = "fault_injection")]
+    fault_injector: RwLock<Option<FaultInjector>>,
+}
+
+/// Transaction of RocksDB storage.
+/// Derefs to RocksDB transaction, additionally records physical changes for
+/// the replication stream if a sink is set.
+pub struct RocksDbTransaction<'db> {
+    transaction: RawTx<'db>,
+    replication: Option<ReplicationRecorder>,
+    #[cfg(feature = "fault_injection")]
+    fault_injector: Option<FaultInjector>,
+    /// Subtrees changed in the transaction whose root hash is not yet
+    /// carried to their ancestors, maintained by the layer above
+    pending_subtrees: Mutex<PendingSubtrees>,
+}
+
+/// Paths of pending subtrees, along with their snapshots at savepoints
+#[derive(Default)]
+struct PendingSubtrees {
+    paths: BTreeSet<Vec<Vec<u8>>>,
+    savepoints: Vec<BTreeSet<Vec<Vec<u8>>>>,
+}
+
+impl<'db> RocksDbTransaction<'db> {
+    /// Commits the transaction
+    pub fn commit(self) -> Result<(), Error> {
+        #[cfg(feature = "fault_injection")]
+        FaultInjector::write(self.fault_injector.as_ref(), || self.transaction.commit())?;
+        #[cfg(not(feature = "fault_injection"))]
+        self.transaction.commit().map_err(RocksDBError)?;
+        if let Some(replication) = self.replication {
+            replication.emit();
+        }
+        Ok(())
+    }
+
+    /// Rollbacks the transaction to its initial state
+    pub fn rollback(&self) -> Result<(), Error> {
+        self.transaction.rollback().map_err(RocksDBError)?;
+        if let Some(replication) = &self.replication {
+            replication.clear();
+        }
+        *self.lock_pending_subtrees() = PendingSubtrees::default();
+        Ok(())
+    }
+
+    /// Sets a savepoint the transaction can be rolled back to
+    pub fn set_savepoint(&self) {
+        self.transaction.set_savepoint();
+        if let Some(replication) = &self.replication {
+            replication.set_savepoint();
+        }
+        let mut pending_subtrees = self.lock_pending_subtrees();
+        let paths = pending_subtrees.paths.clone();
+        pending_subtrees.savepoints.push(paths);
+    }
+
+    /// Rollbacks the transaction to the last savepoint
+    pub fn rollback_to_savepoint(&self) -> Result<(), Error> {
+        self.transaction
+            .rollback_to_savepoint()
+            .map_err(RocksDBError)?;
+        if let Some(replication) = &self.replication {
+            replication.rollback_to_savepoint();
+        }
+        let mut pending_subtrees = self.lock_pending_subtrees();
+        if let Some(paths) = pending_subtrees.savepoints.pop() {
+            pending_subtrees.paths = paths;
+        }
+        Ok(())
+    }
+
+    /// Returns true if physical changes have to be recorded
+    pub(crate) fn is_replicated(&self) -> bool {
+        self.replication.is_some()
+    }
+
+    /// Records physical operations to be emitted on commit
+    pub(crate) fn record(&self, operations: impl FnOnce() -> Vec<PhysicalOperation>) {
+        if let Some(replication) = &self.replication {
+            replication.record(operations());
+        }
+    }
+
+    fn lock_pending_subtrees(&self) -> MutexGuard<'_, PendingSubtrees> {
+        self.pending_subtrees
+            .lock()
+            .expect("pending subtrees lock is poisoned")
+    }
+
+    /// Marks subtrees whose root hash is not yet carried to their ancestors
+    pub fn add_pending_subtrees(&self, paths: impl IntoIterator<Item = Vec<Vec<u8>>>) {
+        self.lock_pending_subtrees().paths.extend(paths);
+    }
+
+    /// Unmarks a subtree whose root hash was carried to its ancestors
+    pub fn remove_pending_subtree(&self, path: &[Vec<u8>]) {
+        self.lock_pending_subtrees().paths.remove(path);
+    }
+
+    /// Returns the marked subtrees
+    pub fn pending_subtrees(&self) -> BTreeSet<Vec<Vec<u8>>> {
+        self.lock_pending_subtrees().paths.clone()
+    }
+
+    /// Returns true if any marked subtree satisfies `filter`
+    pub fn has_pending_subtrees(&self, filter: impl Fn(&[Vec<u8>]) -> bool) -> bool {
+        self.lock_pending_subtrees()
+            .paths
+            .iter()
+            .any(|path| filter(path))
+    }
+
+    /// Unmarks and returns the marked subtrees satisfying `filter`
+    pub fn take_pending_subtrees(
+        &self,
+        filter: impl Fn(&[Vec<u8>]) -> bool,
+    ) -> BTreeSet<Vec<Vec<u8>>> {
+        let mut pending_subtrees = self.lock_pending_subtrees();
+        let (taken, kept) = std::mem::take(&mut pending_subtrees.paths)
+            .into_iter()
+            .partition(|path| filter(path));
+        pending_subtrees.paths = kept;
+        taken
+    }
+}
+
+impl<'db> Deref for RocksDbTransaction<'db> {
+    type Target = RawTx<'db>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.transaction
+    }
+}
+
+/// RocksDB write batch built from a storage batch
+pub struct RocksDbWriteBatch {
+    batch: WriteBatchWithTransaction<true>,
+    physical_operations: Option<Vec<PhysicalOperation>>,
 }
 
 impl RocksDbStorage {
@@ -109,7 +267,129 @@ impl RocksDbStorage {
         )
         .map_err(RocksDBError)?;
 
-        Ok(RocksDbStorage { db })
+        Ok(RocksDbStorage {
+            db,
+            replication_sink: RwLock::new(None),
+            #[cfg(feature = "fault_injection")]
+            fault_injector: RwLock::new(None),
+        })
+    }
+
+    /// Sets a sink receiving physical changes of every commit, `None` stops
+    /// the replication stream. Transactions started before keep the sink they
+    /// were started with.
+    pub fn set_replication_sink(&self, sink: Option<Arc<dyn ReplicationSink>>) {
+        *self
+            .replication_sink
+            .write()
+            .expect("replication sink lock is poisoned") = sink;
+    }
+
+    fn replication_sink(&self) -> Option<Arc<dyn ReplicationSink>> {
+        self.replication_sink
+            .read()
+            .expect("replication sink lock is poisoned")
+            .clone()
+    }
+
+    /// Sets an injector crashing the storage at a chosen write batch, `None`
+    /// removes it. Transactions started before keep the injector they were
+    /// started with.
+    #[cfg(feature = "fault_injection")]
+    pub fn set_fault_injector(&self, injector: Option<FaultInjector>) {
+        *self
+            .fault_injector
+            .write()
+            .expect("fault injector lock is poisoned") = injector;
+    }
+
+    #[cfg(feature = "fault_injection")]
+    fn fault_injector(&self) -> Option<FaultInjector> {
+        self.fault_injector
+            .read()
+            .expect("fault injector lock is poisoned")
+            .clone()
+    }
+
+    /// Applies physical changes received from a replication stream of another
+    /// storage atomically
+    pub fn apply_physical_changes(&self, changes: &PhysicalChanges) -> Result<(), Error> {
+        let mut db_batch = WriteBatchWithTransaction::<true>::default();
+        for operation in changes.operations.iter() {
+            match operation {
+                PhysicalOperation::Put {
+                    column_family,
+                    key,
+                    value,
+                } => match self.physical_column_family(*column_family) {
+                    Some(cf) => db_batch.put_cf(cf, key, value),
+                    None => db_batch.put(key, value),
+                },
+                PhysicalOperation::Delete { column_family, key } => {
+                    match self.physical_column_family(*column_family) {
+                        Some(cf) => db_batch.delete_cf(cf, key),
+                        None => db_batch.delete(key),
+                    }
+                }
+            }
+        }
+        self.write(db_batch)?;
+        if let Some(sink) = self.replication_sink() {
+            sink.on_commit(changes.clone());
+        }
+        Ok(())
+    }
+
+    /// Flushes the memtables of all column families, optionally without
+    /// waiting for the flushes to complete
+    fn flush_memtables(&self, wait: bool) -> Result<(), Error> {
+        let mut flush_options = FlushOptions::default();
+        flush_options.set_wait(wait);
+        self.db.flush_opt(&flush_options).map_err(RocksDBError)?;
+        for cf in [cf_aux(&self.db), cf_roots(&self.db), cf_meta(&self.db)] {
+            self.db
+                .flush_cf_opt(cf, &flush_options)
+                .map_err(RocksDBError)?;
+        }
+        Ok(())
+    }
+
+    /// Approximate size in bytes of the memtables of all column families
+    pub fn memtables_size(&self) -> Result<u64, Error> {
+        let mut size = self
+            .db
+            .property_int_value(MEMTABLES_SIZE_PROPERTY)
+            .map_err(RocksDBError)?
+            .unwrap_or_default();
+        for cf in [cf_aux(&self.db), cf_roots(&self.db), cf_meta(&self.db)] {
+            size += self
+                .db
+                .property_int_value_cf(cf, MEMTABLES_SIZE_PROPERTY)
+                .map_err(RocksDBError)?
+                .unwrap_or_default();
+        }
+        Ok(size)
+    }
+
+    /// Writes a batch to RocksDB
+    #[cfg(not(feature = "fault_injection"))]
+    fn write(&self, db_batch: WriteBatchWithTransaction<true>) -> Result<(), Error> {
+        self.db.write(db_batch).map_err(RocksDBError)
+    }
+
+    /// Writes a batch to RocksDB, unless the fault injector crashes it
+    #[cfg(feature = "fault_injection")]
+    fn write(&self, db_batch: WriteBatchWithTransaction<true>) -> Result<(), Error> {
+        FaultInjector::write(self.fault_injector().as_ref(), || self.db.write(db_batch))
+    }
+
+    fn physical_column_family(&self, column_family: PhysicalColumnFamily) -> Option<&ColumnFamily> {
+        match column_family {
+            PhysicalColumnFamily::Default => None,
+            PhysicalColumnFamily::Aux => Some(cf_aux(&self.db)),
+            PhysicalColumnFamily::Roots => Some(cf_roots(&self.db)),
+            PhysicalColumnFamily::Meta => Some(cf_meta(&self.db)),
+        }
     }
 
     fn build_prefix_body<'a, P>(path: P) -> (Vec<u8>, usize)
@@ -161,8 +441,11 @@ impl RocksDbStorage {
     pub fn build_write_batch(
         &self,
         storage_batch: StorageBatch,
-    ) -> CostResult<(WriteBatchWithTransaction<true>, OperationCost), Error> {
-        let mut db_batch = WriteBatchWithTransaction::<true>::default();
+    ) -> CostResult<(RocksDbWriteBatch, OperationCost), Error> {
+        let mut db_batch = RocksDbWriteBatch {
+            batch: WriteBatchWithTransaction::<true>::default(),
+            physical_operations: self.replication_sink().map(|_| Vec::new()),
+        };
         self.continue_write_batch(&mut db_batch, storage_batch)
             .map_ok(|operation_cost| (db_batch, operation_cost))
     }
@@ -172,7 +455,7 @@ impl RocksDbStorage {
     /// write of the write batch.
     pub fn continue_write_batch(
         &self,
-        db_batch: &mut WriteBatchWithTransaction<true>,
+        db_batch: &mut RocksDbWriteBatch,
         storage_batch: StorageBatch,
     ) -> CostResult<OperationCost, Error> {
         let mut cost = OperationCost::default();
@@ -180,7 +463,15 @@ impl RocksDbStorage {
         // of early termination).
         let mut pending_costs = OperationCost::default();
 
+        let RocksDbWriteBatch {
+            batch: db_batch,
+            physical_operations,
+        } = db_batch;
+
         for op in storage_batch.into_iter() {
+            if let Some(physical_operations) = physical_operations.as_mut() {
+                physical_operations.push(PhysicalOperation::from_batch_operation(&op));
+            }
             match op {
                 AbstractBatchOperation::Put {
                     key,
@@ -387,21 +678,36 @@ impl RocksDbStorage {
     /// Commits a write batch
     pub fn commit_db_write_batch(
         &self,
-        db_batch: WriteBatchWithTransaction<true>,
+        db_batch: RocksDbWriteBatch,
         pending_costs: OperationCost,
         transaction: Option<&<RocksDbStorage as Storage>::Transaction>,
     ) -> CostResult<(), Error> {
+        let RocksDbWriteBatch {
+            batch: db_batch,
+            physical_operations,
+        } = db_batch;
         let result = match transaction {
-            None => self.db.write(db_batch),
-            Some(transaction) => transaction.rebuild_from_writebatch(&db_batch),
+            None => self.write(db_batch),
+            Some(transaction) => transaction
+                .rebuild_from_writebatch(&db_batch)
+                .map_err(RocksDBError),
         };
 
         if result.is_ok() {
-            result.map_err(RocksDBError).wrap_with_cost(pending_costs)
+            if let Some(operations) = physical_operations {
+                match transaction {
+                    None => {
+                        if let Some(sink) = self.replication_sink() {
+                            sink.on_commit(PhysicalChanges { operations });
+                        }
+                    }
+                    Some(transaction) => transaction.record(|| operations),
+                }
+            }
+
+            result.wrap_with_cost(pending_costs)
         } else {
-            result
-                .map_err(RocksDBError)
-                .wrap_with_cost(OperationCost::default())
+            result.wrap_with_cost(OperationCost::default())
         }
     }
 }
@@ -414,30 +720,43 @@ impl<'db> Storage<'db> for RocksDbStorage {
     type TransactionalStorageContext = PrefixedRocksDbTransactionContext<'db>;
 
     fn start_transaction(&'db self) -> Self::Transaction {
-        self.db.transaction()
+        RocksDbTransaction {
+            transaction: self.db.transaction(),
+            replication: self.replication_sink().map(ReplicationRecorder::new),
+            #[cfg(feature = "fault_injection")]
+            fault_injector: self.fault_injector(),
+            pending_subtrees: Mutex::new(PendingSubtrees::default()),
+        }
     }
 
     fn commit_transaction(&self, transaction: Self::Transaction) -> CostResult<(), Error> {
         // All transaction costs were provided on method calls
-        transaction
-            .commit()
-            .map_err(RocksDBError)
-            .wrap_with_cost(Default::default())
+        transaction.commit().wrap_with_cost(Default::default())
     }
 
     fn rollback_transaction(&self, transaction: &Self::Transaction) -> Result<(), Error> {
-        transaction.rollback().map_err(RocksDBError)
+        transaction.rollback()
     }
 
     fn flush(&self) -> Result<(), Error> {
-        self.db.flush().map_err(RocksDBError)
+        self.flush_memtables(true)
+    }
+
+    fn schedule_flush(&self) -> Result<(), Error> {
+        self.flush_memtables(false)
     }
 
     fn get_storage_context<'p, P>(&'db self, path: P) -> CostContext<Self::StorageContext>
     where
         P: IntoIterator<Item = &'p [u8]>,
     {
-        Self::build_prefix(path).map(|prefix| PrefixedRocksDbStorageContext::new(&self.db, prefix))
+        Self::build_prefix(path).map(|prefix| {
+            let context =
+                PrefixedRocksDbStorageContext::new(&self.db, prefix, self.replication_sink());
+            #[cfg(feature = "fault_injection")]
+            let context = context.with_fault_injector(self.fault_injector());
+            context
+        })
     }
 
     fn get_transactional_storage_context<'p, P>(
@@ -41,9 +41,19 @@ pub use context_batch_tx::PrefixedRocksDbBatchTransactionContext;
 pub use context_no_tx::PrefixedRocksDbStorageContext;
 pub use context_tx::PrefixedRocksDbTransactionContext;
 pub use raw_iterator::PrefixedRocksDbRawIterator;
+use rocksdb::ReadOptions;
 
 /// Make prefixed key
 pub fn make_prefixed_key<K: AsRef<[u8]>>(mut prefix: Vec<u8>, key: K) -> Vec<u8> {
     prefix.extend_from_slice(key.as_ref());
     prefix
 }
+
+/// Read options of scans: blocks are read ahead by `readahead_size` bytes and
+/// aren't put in the block cache, so a large scan doesn't evict hot blocks
+fn scan_read_options(readahead_size: usize) -> ReadOptions {
+    let mut read_options = ReadOptions::default();
+    read_options.fill_cache(false);
+    read_options.set_readahead_size(readahead_size);
+    read_options
+}
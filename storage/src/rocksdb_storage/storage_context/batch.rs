@@ -35,7 +35,10 @@ use integer_encoding::VarInt;
 use rocksdb::{ColumnFamily, WriteBatchWithTransaction};
 
 use super::make_prefixed_key;
-use crate::{Batch, StorageBatch};
+use crate::{
+    rocksdb_storage::replication::{PhysicalColumnFamily, PhysicalOperation},
+    Batch, StorageBatch,
+};
 
 /// Wrapper to RocksDB batch.
 /// All calls go to RocksDB batch, but wrapper handles prefixes and column
@@ -50,6 +53,18 @@ pub struct PrefixedRocksDbBatch<'db> {
     /// what it will do, we collect costs at the moment we append something to
     /// the batch.
     pub(crate) cost_acc: OperationCost,
+
+    /// Raw writes of the batch, collected only if the replication stream is
+    /// enabled
+    pub(crate) physical_operations: Option<Vec<PhysicalOperation>>,
+}
+
+impl<'db> PrefixedRocksDbBatch<'db> {
+    fn replicate(&mut self, operation: impl FnOnce() -> PhysicalOperation) {
+        if let Some(physical_operations) = self.physical_operations.as_mut() {
+            physical_operations.push(operation());
+        }
+    }
 }
 
 /// Batch with no backing storage_cost (it's not a RocksDB batch, but our own
@@ -89,7 +104,10 @@ impl<'db> Batch for PrefixedRocksDbBatch<'db> {
             updated_cost_info,
         )?;
 
-        self.batch.put(prefixed_key, value);
+        self.batch.put(&prefixed_key, value);
+        self.replicate(|| {
+            PhysicalOperation::put(PhysicalColumnFamily::Default, &prefixed_key, value)
+        });
         Ok(())
     }
 
@@ -109,7 +127,8 @@ impl<'db> Batch for PrefixedRocksDbBatch<'db> {
             cost_info,
         )?;
 
-        self.batch.put_cf(self.cf_aux, prefixed_key, value);
+        self.batch.put_cf(self.cf_aux, &prefixed_key, value);
+        self.replicate(|| PhysicalOperation::put(PhysicalColumnFamily::Aux, &prefixed_key, value));
         Ok(())
     }
 
@@ -132,7 +151,10 @@ impl<'db> Batch for PrefixedRocksDbBatch<'db> {
             )?;
         }
 
-        self.batch.put_cf(self.cf_roots, prefixed_key, value);
+        self.batch.put_cf(self.cf_roots, &prefixed_key, value);
+        self.replicate(|| {
+            PhysicalOperation::put(PhysicalColumnFamily::Roots, &prefixed_key, value)
+        });
         Ok(())
     }
 
@@ -145,7 +167,8 @@ impl<'db> Batch for PrefixedRocksDbBatch<'db> {
             self.cost_acc.storage_cost.removed_bytes += removed_bytes.combined_removed_bytes();
         }
 
-        self.batch.delete(prefixed_key);
+        self.batch.delete(&prefixed_key);
+        self.replicate(|| PhysicalOperation::delete(PhysicalColumnFamily::Default, &prefixed_key));
     }
 
     fn delete_aux<K: AsRef<[u8]>>(&mut self, key: K, cost_info: Option<KeyValueStorageCost>) {
@@ -157,7 +180,8 @@ impl<'db> Batch for PrefixedRocksDbBatch<'db> {
             self.cost_acc.storage_cost.removed_bytes += removed_bytes.combined_removed_bytes();
         }
 
-        self.batch.delete_cf(self.cf_aux, prefixed_key);
+        self.batch.delete_cf(self.cf_aux, &prefixed_key);
+        self.replicate(|| PhysicalOperation::delete(PhysicalColumnFamily::Aux, &prefixed_key));
     }
 
     fn delete_root<K: AsRef<[u8]>>(&mut self, key: K, cost_info: Option<KeyValueStorageCost>) {
@@ -169,7 +193,8 @@ impl<'db> Batch for PrefixedRocksDbBatch<'db> {
             self.cost_acc.storage_cost.removed_bytes += removed_bytes.combined_removed_bytes();
         }
 
-        self.batch.delete_cf(self.cf_roots, prefixed_key);
+        self.batch.delete_cf(self.cf_roots, &prefixed_key);
+        self.replicate(|| PhysicalOperation::delete(PhysicalColumnFamily::Roots, &prefixed_key));
     }
 }
 
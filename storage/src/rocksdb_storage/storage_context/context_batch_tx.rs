@@ -39,7 +39,7 @@ use super::{batch::PrefixedMultiContextBatchPart, make_prefixed_key, PrefixedRoc
 use crate::{
     error,
     error::Error::RocksDBError,
-    rocksdb_storage::storage::{Db, Tx, AUX_CF_NAME, META_CF_NAME, ROOTS_CF_NAME},
+    rocksdb_storage::storage::{Db, RawTx, Tx, AUX_CF_NAME, META_CF_NAME, ROOTS_CF_NAME},
     RawIterator, StorageBatch, StorageContext,
 };
 
@@ -114,7 +114,7 @@ impl<'db> PrefixedRocksDbBatchTransactionContext<'db> {
 
 impl<'db> StorageContext<'db> for PrefixedRocksDbBatchTransactionContext<'db> {
     type Batch = PrefixedMultiContextBatchPart;
-    type RawIterator = PrefixedRocksDbRawIterator<DBRawIteratorWithThreadMode<'db, Tx<'db>>>;
+    type RawIterator = PrefixedRocksDbRawIterator<DBRawIteratorWithThreadMode<'db, RawTx<'db>>>;
 
     fn put<K: AsRef<[u8]>>(
         &self,
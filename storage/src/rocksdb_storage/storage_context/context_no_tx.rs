@@ -28,6 +28,8 @@
 
 //! Storage context implementation without a transaction
 
+use std::sync::Arc;
+
 use costs::{
     cost_return_on_error, cost_return_on_error_no_add,
     storage_cost::{
@@ -38,11 +40,18 @@ use costs::{
 use error::Error;
 use rocksdb::{ColumnFamily, DBRawIteratorWithThreadMode, WriteBatchWithTransaction};
 
-use super::{make_prefixed_key, PrefixedRocksDbBatch, PrefixedRocksDbRawIterator};
+use super::{
+    make_prefixed_key, scan_read_options, PrefixedRocksDbBatch, PrefixedRocksDbRawIterator,
+};
+#[cfg(feature = "fault_injection")]
+use crate::rocksdb_storage::FaultInjector;
 use crate::{
     error,
     error::Error::{CostError, RocksDBError},
-    rocksdb_storage::storage::{Db, AUX_CF_NAME, META_CF_NAME, ROOTS_CF_NAME},
+    rocksdb_storage::{
+        replication::{PhysicalChanges, PhysicalColumnFamily, PhysicalOperation, ReplicationSink},
+        storage::{Db, AUX_CF_NAME, META_CF_NAME, ROOTS_CF_NAME},
+    },
     StorageContext,
 };
 
@@ -52,12 +61,44 @@ pub struct PrefixedRocksDbStorageContext<'db> {
     storage: &'db Db,
     /// ze prefix
     pub prefix: Vec<u8>,
+    replication_sink: Option<Arc<dyn ReplicationSink>>,
+    #[cfg(feature = "fault_injection")]
+    fault_injector: Option<FaultInjector>,
 }
 
 impl<'db> PrefixedRocksDbStorageContext<'db> {
     /// Create a new prefixed storage context instance
-    pub fn new(storage: &'db Db, prefix: Vec<u8>) -> Self {
-        PrefixedRocksDbStorageContext { storage, prefix }
+    pub fn new(
+        storage: &'db Db,
+        prefix: Vec<u8>,
+        replication_sink: Option<Arc<dyn ReplicationSink>>,
+    ) -> Self {
+        PrefixedRocksDbStorageContext {
+            storage,
+            prefix,
+            replication_sink,
+            #[cfg(feature = "fault_injection")]
+            fault_injector: None,
+        }
+    }
+
+    /// Crash the batch writes of the context with the injector
+    #[cfg(feature = "fault_injection")]
+    pub(crate) fn with_fault_injector(mut self, fault_injector: Option<FaultInjector>) -> Self {
+        self.fault_injector = fault_injector;
+        self
+    }
+
+    /// Writes a batch to RocksDB
+    #[cfg(not(feature = "fault_injection"))]
+    fn write(&self, batch: WriteBatchWithTransaction<true>) -> Result<(), Error> {
+        self.storage.write(batch).map_err(RocksDBError)
+    }
+
+    /// Writes a batch to RocksDB, unless the fault injector crashes it
+    #[cfg(feature = "fault_injection")]
+    fn write(&self, batch: WriteBatchWithTransaction<true>) -> Result<(), Error> {
+        FaultInjector::write(self.fault_injector.as_ref(), || self.storage.write(batch))
     }
 }
 
@@ -82,6 +123,22 @@ impl<'db> PrefixedRocksDbStorageContext<'db> {
             .cf_handle(META_CF_NAME)
             .expect("meta column family must exist")
     }
+
+    /// Every direct write is a commit on its own, so it is reported to the
+    /// replication sink right away
+    fn replicate(
+        &self,
+        result: Result<(), rocksdb::Error>,
+        operation: impl FnOnce() -> PhysicalOperation,
+    ) -> Result<(), Error> {
+        result.map_err(RocksDBError)?;
+        if let Some(sink) = &self.replication_sink {
+            sink.on_commit(PhysicalChanges {
+                operations: vec![operation()],
+            });
+        }
+        Ok(())
+    }
 }
 
 impl<'db> StorageContext<'db> for PrefixedRocksDbStorageContext<'db> {
@@ -109,10 +166,12 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbStorageContext<'db> {
             )
             .map_err(CostError)
         );
-        self.storage
-            .put(make_prefixed_key(self.prefix.clone(), &key), value)
-            .map_err(RocksDBError)
-            .wrap_with_cost(cost)
+        let prefixed_key = make_prefixed_key(self.prefix.clone(), &key);
+        let result = self.storage.put(&prefixed_key, value);
+        self.replicate(result, || {
+            PhysicalOperation::put(PhysicalColumnFamily::Default, &prefixed_key, value)
+        })
+        .wrap_with_cost(cost)
     }
 
     fn put_aux<K: AsRef<[u8]>>(
@@ -135,14 +194,12 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbStorageContext<'db> {
             )
             .map_err(CostError)
         );
-        self.storage
-            .put_cf(
-                self.cf_aux(),
-                make_prefixed_key(self.prefix.clone(), &key),
-                value,
-            )
-            .map_err(RocksDBError)
-            .wrap_with_cost(cost)
+        let prefixed_key = make_prefixed_key(self.prefix.clone(), &key);
+        let result = self.storage.put_cf(self.cf_aux(), &prefixed_key, value);
+        self.replicate(result, || {
+            PhysicalOperation::put(PhysicalColumnFamily::Aux, &prefixed_key, value)
+        })
+        .wrap_with_cost(cost)
     }
 
     fn put_root<K: AsRef<[u8]>>(
@@ -165,14 +222,12 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbStorageContext<'db> {
             )
             .map_err(CostError)
         );
-        self.storage
-            .put_cf(
-                self.cf_roots(),
-                make_prefixed_key(self.prefix.clone(), &key),
-                value,
-            )
-            .map_err(RocksDBError)
-            .wrap_with_cost(cost)
+        let prefixed_key = make_prefixed_key(self.prefix.clone(), &key);
+        let result = self.storage.put_cf(self.cf_roots(), &prefixed_key, value);
+        self.replicate(result, || {
+            PhysicalOperation::put(PhysicalColumnFamily::Roots, &prefixed_key, value)
+        })
+        .wrap_with_cost(cost)
     }
 
     fn put_meta<K: AsRef<[u8]>>(
@@ -195,14 +250,12 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbStorageContext<'db> {
             )
             .map_err(CostError)
         );
-        self.storage
-            .put_cf(
-                self.cf_meta(),
-                make_prefixed_key(self.prefix.clone(), &key),
-                value,
-            )
-            .map_err(RocksDBError)
-            .wrap_with_cost(cost)
+        let prefixed_key = make_prefixed_key(self.prefix.clone(), &key);
+        let result = self.storage.put_cf(self.cf_meta(), &prefixed_key, value);
+        self.replicate(result, || {
+            PhysicalOperation::put(PhysicalColumnFamily::Meta, &prefixed_key, value)
+        })
+        .wrap_with_cost(cost)
     }
 
     fn delete<K: AsRef<[u8]>>(
@@ -224,10 +277,12 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbStorageContext<'db> {
             cost.seek_count += 2;
         }
 
-        self.storage
-            .delete(make_prefixed_key(self.prefix.clone(), key))
-            .map_err(RocksDBError)
-            .wrap_with_cost(cost)
+        let prefixed_key = make_prefixed_key(self.prefix.clone(), key);
+        let result = self.storage.delete(&prefixed_key);
+        self.replicate(result, || {
+            PhysicalOperation::delete(PhysicalColumnFamily::Default, &prefixed_key)
+        })
+        .wrap_with_cost(cost)
     }
 
     fn delete_aux<K: AsRef<[u8]>>(
@@ -249,10 +304,12 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbStorageContext<'db> {
             cost.seek_count += 2;
         }
 
-        self.storage
-            .delete_cf(self.cf_aux(), make_prefixed_key(self.prefix.clone(), key))
-            .map_err(RocksDBError)
-            .wrap_with_cost(cost)
+        let prefixed_key = make_prefixed_key(self.prefix.clone(), key);
+        let result = self.storage.delete_cf(self.cf_aux(), &prefixed_key);
+        self.replicate(result, || {
+            PhysicalOperation::delete(PhysicalColumnFamily::Aux, &prefixed_key)
+        })
+        .wrap_with_cost(cost)
     }
 
     fn delete_root<K: AsRef<[u8]>>(
@@ -274,10 +331,12 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbStorageContext<'db> {
             cost.seek_count += 2;
         }
 
-        self.storage
-            .delete_cf(self.cf_roots(), make_prefixed_key(self.prefix.clone(), key))
-            .map_err(RocksDBError)
-            .wrap_with_cost(cost)
+        let prefixed_key = make_prefixed_key(self.prefix.clone(), key);
+        let result = self.storage.delete_cf(self.cf_roots(), &prefixed_key);
+        self.replicate(result, || {
+            PhysicalOperation::delete(PhysicalColumnFamily::Roots, &prefixed_key)
+        })
+        .wrap_with_cost(cost)
     }
 
     fn delete_meta<K: AsRef<[u8]>>(
@@ -299,10 +358,12 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbStorageContext<'db> {
             cost.seek_count += 2;
         }
 
-        self.storage
-            .delete_cf(self.cf_meta(), make_prefixed_key(self.prefix.clone(), key))
-            .map_err(RocksDBError)
-            .wrap_with_cost(cost)
+        let prefixed_key = make_prefixed_key(self.prefix.clone(), key);
+        let result = self.storage.delete_cf(self.cf_meta(), &prefixed_key);
+        self.replicate(result, || {
+            PhysicalOperation::delete(PhysicalColumnFamily::Meta, &prefixed_key)
+        })
+        .wrap_with_cost(cost)
     }
 
     fn get<K: AsRef<[u8]>>(&self, key: K) -> CostResult<Option<Vec<u8>>, Error> {
@@ -376,6 +437,7 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbStorageContext<'db> {
             cf_aux: self.cf_aux(),
             cf_roots: self.cf_roots(),
             cost_acc: Default::default(),
+            physical_operations: self.replication_sink.as_ref().map(|_| Vec::new()),
         }
     }
 
@@ -383,7 +445,12 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbStorageContext<'db> {
         let cost = OperationCost::default();
 
         // On unsuccessul batch commit only deletion finalization cost will be returned.
-        cost_return_on_error_no_add!(&cost, self.storage.write(batch.batch).map_err(RocksDBError));
+        cost_return_on_error_no_add!(&cost, self.write(batch.batch));
+
+        if let (Some(sink), Some(operations)) = (&self.replication_sink, batch.physical_operations)
+        {
+            sink.on_commit(PhysicalChanges { operations });
+        }
 
         Ok(()).wrap_with_cost(cost).add_cost(batch.cost_acc)
     }
@@ -395,3 +462,16 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbStorageContext<'db> {
         }
     }
 }
+
+impl<'db> PrefixedRocksDbStorageContext<'db> {
+    /// Raw iterator for large sequential scans, reading ahead `readahead_size`
+    /// bytes without filling the block cache
+    pub fn scan_iter(&self, readahead_size: usize) -> <Self as StorageContext<'db>>::RawIterator {
+        PrefixedRocksDbRawIterator {
+            prefix: self.prefix.clone(),
+            raw_iterator: self
+                .storage
+                .raw_iterator_opt(scan_read_options(readahead_size)),
+        }
+    }
+}
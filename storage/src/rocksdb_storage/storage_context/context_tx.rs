@@ -38,11 +38,16 @@ use costs::{
 use error::Error;
 use rocksdb::{ColumnFamily, DBRawIteratorWithThreadMode, WriteBatchWithTransaction};
 
-use super::{make_prefixed_key, PrefixedRocksDbBatch, PrefixedRocksDbRawIterator};
+use super::{
+    make_prefixed_key, scan_read_options, PrefixedRocksDbBatch, PrefixedRocksDbRawIterator,
+};
 use crate::{
     error,
     error::Error::{CostError, RocksDBError},
-    rocksdb_storage::storage::{Db, Tx, AUX_CF_NAME, META_CF_NAME, ROOTS_CF_NAME},
+    rocksdb_storage::{
+        replication::{PhysicalColumnFamily, PhysicalOperation},
+        storage::{Db, RawTx, Tx, AUX_CF_NAME, META_CF_NAME, ROOTS_CF_NAME},
+    },
     StorageContext,
 };
 
@@ -86,11 +91,22 @@ impl<'db> PrefixedRocksDbTransactionContext<'db> {
             .cf_handle(META_CF_NAME)
             .expect("meta column family must exist")
     }
+
+    /// Records a successful write to be replicated on transaction commit
+    fn replicate(
+        &self,
+        result: Result<(), rocksdb::Error>,
+        operation: impl FnOnce() -> PhysicalOperation,
+    ) -> Result<(), Error> {
+        result.map_err(RocksDBError)?;
+        self.transaction.record(|| vec![operation()]);
+        Ok(())
+    }
 }
 
 impl<'db> StorageContext<'db> for PrefixedRocksDbTransactionContext<'db> {
     type Batch = PrefixedRocksDbBatch<'db>;
-    type RawIterator = PrefixedRocksDbRawIterator<DBRawIteratorWithThreadMode<'db, Tx<'db>>>;
+    type RawIterator = PrefixedRocksDbRawIterator<DBRawIteratorWithThreadMode<'db, RawTx<'db>>>;
 
     fn put<K: AsRef<[u8]>>(
         &self,
@@ -113,10 +129,12 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbTransactionContext<'db> {
             )
             .map_err(CostError)
         );
-        self.transaction
-            .put(make_prefixed_key(self.prefix.clone(), &key), value)
-            .map_err(RocksDBError)
-            .wrap_with_cost(cost)
+        let prefixed_key = make_prefixed_key(self.prefix.clone(), &key);
+        let result = self.transaction.put(&prefixed_key, value);
+        self.replicate(result, || {
+            PhysicalOperation::put(PhysicalColumnFamily::Default, &prefixed_key, value)
+        })
+        .wrap_with_cost(cost)
     }
 
     fn put_aux<K: AsRef<[u8]>>(
@@ -139,14 +157,12 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbTransactionContext<'db> {
             )
             .map_err(CostError)
         );
-        self.transaction
-            .put_cf(
-                self.cf_aux(),
-                make_prefixed_key(self.prefix.clone(), &key),
-                value,
-            )
-            .map_err(RocksDBError)
-            .wrap_with_cost(cost)
+        let prefixed_key = make_prefixed_key(self.prefix.clone(), &key);
+        let result = self.transaction.put_cf(self.cf_aux(), &prefixed_key, value);
+        self.replicate(result, || {
+            PhysicalOperation::put(PhysicalColumnFamily::Aux, &prefixed_key, value)
+        })
+        .wrap_with_cost(cost)
     }
 
     fn put_root<K: AsRef<[u8]>>(
@@ -169,14 +185,14 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbTransactionContext<'db> {
             )
             .map_err(CostError)
         );
-        self.transaction
-            .put_cf(
-                self.cf_roots(),
-                make_prefixed_key(self.prefix.clone(), &key),
-                value,
-            )
-            .map_err(RocksDBError)
-            .wrap_with_cost(cost)
+        let prefixed_key = make_prefixed_key(self.prefix.clone(), &key);
+        let result = self
+            .transaction
+            .put_cf(self.cf_roots(), &prefixed_key, value);
+        self.replicate(result, || {
+            PhysicalOperation::put(PhysicalColumnFamily::Roots, &prefixed_key, value)
+        })
+        .wrap_with_cost(cost)
     }
 
     fn put_meta<K: AsRef<[u8]>>(
@@ -199,14 +215,14 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbTransactionContext<'db> {
             )
             .map_err(CostError)
         );
-        self.transaction
-            .put_cf(
-                self.cf_meta(),
-                make_prefixed_key(self.prefix.clone(), &key),
-                value,
-            )
-            .map_err(RocksDBError)
-            .wrap_with_cost(cost)
+        let prefixed_key = make_prefixed_key(self.prefix.clone(), &key);
+        let result = self
+            .transaction
+            .put_cf(self.cf_meta(), &prefixed_key, value);
+        self.replicate(result, || {
+            PhysicalOperation::put(PhysicalColumnFamily::Meta, &prefixed_key, value)
+        })
+        .wrap_with_cost(cost)
     }
 
     fn delete<K: AsRef<[u8]>>(
@@ -229,10 +245,12 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbTransactionContext<'db> {
             cost.seek_count += 2;
         }
 
-        self.transaction
-            .delete(make_prefixed_key(self.prefix.clone(), key))
-            .map_err(RocksDBError)
-            .wrap_with_cost(cost)
+        let prefixed_key = make_prefixed_key(self.prefix.clone(), key);
+        let result = self.transaction.delete(&prefixed_key);
+        self.replicate(result, || {
+            PhysicalOperation::delete(PhysicalColumnFamily::Default, &prefixed_key)
+        })
+        .wrap_with_cost(cost)
     }
 
     fn delete_aux<K: AsRef<[u8]>>(
@@ -254,10 +272,12 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbTransactionContext<'db> {
             cost.seek_count += 2;
         }
 
-        self.transaction
-            .delete_cf(self.cf_aux(), make_prefixed_key(self.prefix.clone(), key))
-            .map_err(RocksDBError)
-            .wrap_with_cost(cost)
+        let prefixed_key = make_prefixed_key(self.prefix.clone(), key);
+        let result = self.transaction.delete_cf(self.cf_aux(), &prefixed_key);
+        self.replicate(result, || {
+            PhysicalOperation::delete(PhysicalColumnFamily::Aux, &prefixed_key)
+        })
+        .wrap_with_cost(cost)
     }
 
     fn delete_root<K: AsRef<[u8]>>(
@@ -279,10 +299,12 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbTransactionContext<'db> {
             cost.seek_count += 2;
         }
 
-        self.transaction
-            .delete_cf(self.cf_roots(), make_prefixed_key(self.prefix.clone(), key))
-            .map_err(RocksDBError)
-            .wrap_with_cost(cost)
+        let prefixed_key = make_prefixed_key(self.prefix.clone(), key);
+        let result = self.transaction.delete_cf(self.cf_roots(), &prefixed_key);
+        self.replicate(result, || {
+            PhysicalOperation::delete(PhysicalColumnFamily::Roots, &prefixed_key)
+        })
+        .wrap_with_cost(cost)
     }
 
     fn delete_meta<K: AsRef<[u8]>>(
@@ -304,10 +326,12 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbTransactionContext<'db> {
             cost.seek_count += 2;
         }
 
-        self.transaction
-            .delete_cf(self.cf_meta(), make_prefixed_key(self.prefix.clone(), key))
-            .map_err(RocksDBError)
-            .wrap_with_cost(cost)
+        let prefixed_key = make_prefixed_key(self.prefix.clone(), key);
+        let result = self.transaction.delete_cf(self.cf_meta(), &prefixed_key);
+        self.replicate(result, || {
+            PhysicalOperation::delete(PhysicalColumnFamily::Meta, &prefixed_key)
+        })
+        .wrap_with_cost(cost)
     }
 
     fn get<K: AsRef<[u8]>>(&self, key: K) -> CostResult<Option<Vec<u8>>, Error> {
@@ -381,6 +405,7 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbTransactionContext<'db> {
             cf_aux: self.cf_aux(),
             cf_roots: self.cf_roots(),
             cost_acc: Default::default(),
+            physical_operations: self.transaction.is_replicated().then(Vec::new),
         }
     }
 
@@ -395,6 +420,10 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbTransactionContext<'db> {
                 .map_err(RocksDBError)
         );
 
+        if let Some(operations) = batch.physical_operations {
+            self.transaction.record(|| operations);
+        }
+
         Ok(()).wrap_with_cost(cost).add_cost(batch.cost_acc)
     }
 
@@ -405,3 +434,16 @@ impl<'db> StorageContext<'db> for PrefixedRocksDbTransactionContext<'db> {
         }
     }
 }
+
+impl<'db> PrefixedRocksDbTransactionContext<'db> {
+    /// Raw iterator for large sequential scans, reading ahead `readahead_size`
+    /// bytes without filling the block cache
+    pub fn scan_iter(&self, readahead_size: usize) -> <Self as StorageContext<'db>>::RawIterator {
+        PrefixedRocksDbRawIterator {
+            prefix: self.prefix.clone(),
+            raw_iterator: self
+                .transaction
+                .raw_iterator_opt(scan_read_options(readahead_size)),
+        }
+    }
+}
@@ -81,6 +81,9 @@ pub trait Storage<'db> {
     /// Forces data to be written
     fn flush(&self) -> Result<(), Error>;
 
+    /// Starts writing data without waiting for it to complete
+    fn schedule_flush(&self) -> Result<(), Error>;
+
     /// Make storage_cost context for a subtree with path
     fn get_storage_context<'p, P>(&'db self, path: P) -> CostContext<Self::StorageContext>
     where
@@ -334,6 +337,30 @@ impl StorageBatch {
             + operations.meta.len()
     }
 
+    /// Approximate size in bytes of the keys and values held by the batch
+    pub fn size_in_bytes(&self) -> usize {
+        let operations = self.operations.borrow();
+        [
+            &operations.data,
+            &operations.roots,
+            &operations.aux,
+            &operations.meta,
+        ]
+        .into_iter()
+        .flat_map(|operations| operations.values())
+        .map(|operation| match operation {
+            AbstractBatchOperation::Put { key, value, .. }
+            | AbstractBatchOperation::PutAux { key, value, .. }
+            | AbstractBatchOperation::PutRoot { key, value, .. }
+            | AbstractBatchOperation::PutMeta { key, value, .. } => key.len() + value.len(),
+            AbstractBatchOperation::Delete { key, .. }
+            | AbstractBatchOperation::DeleteAux { key, .. }
+            | AbstractBatchOperation::DeleteRoot { key, .. }
+            | AbstractBatchOperation::DeleteMeta { key, .. } => key.len(),
+        })
+        .sum()
+    }
+
     /// Add deferred `put` operation
     pub fn put(
         &self,